@@ -0,0 +1,141 @@
+// src/ambiguous.rs
+// What `sketch --ambiguous` does with bases other than A, C, G and T. The default
+// (drop) removes them and joins the bases around them, as lash always has. The
+// other policies read the record in uppercase and
+// - skip-kmer: leave out every k-mer overlapping an ambiguous base,
+// - expand: also sketch every resolution of k-mers holding IUPAC codes, as long as
+//   a k-mer has at most MAX_EXPANSIONS of them,
+// - random: resolve each IUPAC code to one of its bases, drawn from the run seed
+//   and the position, so a genome sketches the same way every time.
+// Characters outside the IUPAC code always break k-mers under these policies.
+
+use crate::utils::filter_out_n;
+use crate::weighted::KmerRng;
+
+// resolutions of one k-mer sketched under expand, 2 Ns or 4 two-base codes
+const MAX_EXPANSIONS: usize = 16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AmbiguityPolicy {
+    #[default]
+    Drop,
+    SkipKmer,
+    Expand,
+    Random,
+}
+
+impl AmbiguityPolicy {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "drop" => Ok(AmbiguityPolicy::Drop),
+            "skip-kmer" => Ok(AmbiguityPolicy::SkipKmer),
+            "expand" => Ok(AmbiguityPolicy::Expand),
+            "random" => Ok(AmbiguityPolicy::Random),
+            _ => Err(format!("unknown ambiguity policy {}, choose from drop, skip-kmer, expand, random", text)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AmbiguityPolicy::Drop => "drop",
+            AmbiguityPolicy::SkipKmer => "skip-kmer",
+            AmbiguityPolicy::Expand => "expand",
+            AmbiguityPolicy::Random => "random",
+        }
+    }
+}
+
+// bases an uppercase IUPAC code stands for, None outside the code
+fn resolutions(code: u8) -> Option<&'static [u8]> {
+    Some(match code {
+        b'A' => b"A",
+        b'C' => b"C",
+        b'G' => b"G",
+        b'T' => b"T",
+        b'R' => b"AG",
+        b'Y' => b"CT",
+        b'S' => b"CG",
+        b'W' => b"AT",
+        b'K' => b"GT",
+        b'M' => b"AC",
+        b'B' => b"CGT",
+        b'D' => b"AGT",
+        b'H' => b"ACT",
+        b'V' => b"ACG",
+        b'N' => b"ACGT",
+        _ => return None,
+    })
+}
+
+fn is_base(c: u8) -> bool {
+    matches!(c, b'A' | b'C' | b'G' | b'T')
+}
+
+// bases of a record that are not A, C, G or T once uppercased
+pub fn count_ambiguous(seq: &[u8]) -> u64 {
+    seq.iter().filter(|c| !is_base(c.to_ascii_uppercase())).count() as u64
+}
+
+// the stretches of a record k-mers are taken from, ACGT only
+pub fn resolve(seq: &[u8], policy: AmbiguityPolicy, seed: u64) -> Vec<Vec<u8>> {
+    if policy == AmbiguityPolicy::Drop {
+        return vec![filter_out_n(seq)];
+    }
+    let mut upper = seq.to_ascii_uppercase();
+    if policy == AmbiguityPolicy::Random {
+        for (i, c) in upper.iter_mut().enumerate() {
+            if let Some(bases) = resolutions(*c).filter(|b| b.len() > 1) {
+                let draw = KmerRng(seed ^ (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)).next_u64();
+                *c = bases[(draw % bases.len() as u64) as usize];
+            }
+        }
+    }
+    upper
+        .split(|&c| !is_base(c))
+        .filter(|stretch| !stretch.is_empty())
+        .map(|stretch| stretch.to_vec())
+        .collect()
+}
+
+// under expand, every resolution of the k-long windows holding IUPAC codes; the
+// windows without any are already in `resolve`'s stretches
+pub fn expanded_windows(seq: &[u8], kmer_length: usize) -> Vec<Vec<u8>> {
+    let upper = seq.to_ascii_uppercase();
+    let mut windows = Vec::new();
+    if upper.len() < kmer_length || kmer_length == 0 {
+        return windows;
+    }
+    // starts of the windows covering an ambiguous base
+    let mut starts: Vec<usize> = Vec::new();
+    for (p, _) in upper.iter().enumerate().filter(|(_, &c)| !is_base(c)) {
+        let first = (p + 1).saturating_sub(kmer_length).max(starts.last().map_or(0, |&s| s + 1));
+        starts.extend(first..=p.min(upper.len() - kmer_length));
+    }
+    for start in starts {
+        let window = &upper[start..start + kmer_length];
+        let choices: Option<Vec<&[u8]>> = window.iter().map(|&c| resolutions(c)).collect();
+        let choices = match choices {
+            Some(choices) => choices,
+            None => continue,
+        };
+        let combinations = choices.iter().try_fold(1usize, |n, bases| n.checked_mul(bases.len()));
+        if combinations.is_none_or(|n| n > MAX_EXPANSIONS) {
+            continue;
+        }
+        let mut resolved: Vec<Vec<u8>> = vec![Vec::with_capacity(kmer_length)];
+        for bases in choices {
+            resolved = resolved
+                .into_iter()
+                .flat_map(|prefix| {
+                    bases.iter().map(move |&b| {
+                        let mut next = prefix.clone();
+                        next.push(b);
+                        next
+                    })
+                })
+                .collect();
+        }
+        windows.extend(resolved);
+    }
+    windows
+}
@@ -0,0 +1,146 @@
+// src/archive.rs
+// Tar archives of genomes (.tar, .tar.gz/.tgz, .tar.zst, .tar.xz, .tar.lz4) given in place of a
+// FASTA/FASTQ path, local or by URL, as reference collections such as GTDB are
+// shipped. The archive is read once, front to back, and its FASTA/FASTQ members
+// (compressed or not, by extension) are written to a temporary directory that
+// is removed once sketching is done. In a file list every member is a sample of
+// its own, named <archive>/<member path>; on a manifest line all members are
+// unioned into the line's one sample.
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::MultiGzDecoder;
+
+use crate::compression;
+use crate::remote;
+use crate::utils::{is_sequence_file, Sample};
+
+pub fn is_archive(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    [".tar", ".tar.gz", ".tgz", ".tar.zst", ".tar.zstd", ".tar.xz", ".txz", ".tar.lz4"].iter().any(|ext| path.ends_with(ext))
+}
+
+// directory of the extracted members, removed when dropped
+pub struct Extracted {
+    dir: PathBuf,
+}
+
+impl Drop for Extracted {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn open(path: &str) -> io::Result<Box<dyn Read>> {
+    let raw: Box<dyn Read + Send> = match remote::is_remote(path) {
+        true => Box::new(remote::open(path)?),
+        false => Box::new(BufReader::new(File::open(path)?)),
+    };
+    let lower = path.to_ascii_lowercase();
+    Ok(if lower.ends_with(".gz") || lower.ends_with(".tgz") {
+        Box::new(MultiGzDecoder::new(raw))
+    } else if lower.ends_with(".zst") || lower.ends_with(".zstd") {
+        Box::new(zstd::stream::Decoder::new(raw)?)
+    } else {
+        compression::decoded(raw)?
+    })
+}
+
+// sequence members of the archive written under `dir`, their paths within the
+// archive and on disk in archive order
+fn extract(path: &str, dir: &Path) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut members = Vec::new();
+    let mut archive = tar::Archive::new(open(path)?);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        // members outside the archive's own tree are not written
+        let inside = path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        let member: PathBuf = path.components().filter(|c| matches!(c, Component::Normal(_))).collect();
+        let hidden = member.file_name().and_then(|n| n.to_str()).is_none_or(|n| n.starts_with("._"));
+        if hidden || !inside || !is_sequence_file(&member) {
+            continue;
+        }
+        let target = dir.join(&member);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(&target)?)?;
+        members.push((member.to_string_lossy().into_owned(), target.to_string_lossy().into_owned()));
+    }
+    if members.is_empty() {
+        return Err(format!("no FASTA/FASTQ files in archive {}", path).into());
+    }
+    Ok(members)
+}
+
+// archives among the samples replaced by their members; the returned directory
+// holds them until it is dropped
+pub fn expand(samples: Vec<Sample>) -> Result<(Vec<Sample>, Option<Extracted>), Box<dyn Error>> {
+    if !samples.iter().any(|s| is_archive(&s.path) || s.more.iter().any(|p| is_archive(p))) {
+        return Ok((samples, None));
+    }
+    let extracted = Extracted {
+        dir: std::env::temp_dir().join(format!("lash_archives_{}", std::process::id())),
+    };
+    let mut archives = 0;
+    let mut expanded = Vec::new();
+    for sample in samples {
+        let listed = sample.name == sample.path && sample.more.is_empty();
+        if listed && is_archive(&sample.path) {
+            let members = extract(&sample.path, &extracted.dir.join(archives.to_string()))?;
+            archives += 1;
+            expanded.extend(members.into_iter().map(|(member, path)| {
+                let name = format!("{}/{}", sample.name, member);
+                Sample { source: Some(name.clone()), name, path, more: Vec::new() }
+            }));
+            continue;
+        }
+        // a manifest sample: all files of all its archives in one sketch
+        let source = sample.source.or_else(|| is_archive(&sample.path).then(|| sample.path.clone()));
+        let mut paths = Vec::new();
+        for path in std::iter::once(sample.path).chain(sample.more) {
+            match is_archive(&path) {
+                true => {
+                    let members = extract(&path, &extracted.dir.join(archives.to_string()))?;
+                    archives += 1;
+                    paths.extend(members.into_iter().map(|(_, path)| path));
+                }
+                false => paths.push(path),
+            }
+        }
+        let path = paths.remove(0);
+        expanded.push(Sample { name: sample.name, path, more: paths, source });
+    }
+    println!("{} archives read, {} samples to sketch.", archives, expanded.len());
+    Ok((expanded, Some(extracted)))
+}
+
+// tests/data/small.tar.xz and small.tar.lz4 hold sample.fa, the 600 bytes the
+// lz4 fixtures of compression.rs decompress to, written by tar, xz and lz4
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_archives_extract() {
+        let expected: Vec<u8> = (0..600u64).map(|i| b"ACGT"[((i * i + i / 3) % 4) as usize]).collect();
+        for name in ["small.tar.xz", "small.tar.lz4"] {
+            let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/").to_string() + name;
+            assert!(is_archive(&path));
+            let extracted = Extracted {
+                dir: std::env::temp_dir().join(format!("lash_archive_test_{}_{}", name, std::process::id())),
+            };
+            let members = extract(&path, &extracted.dir).unwrap();
+            assert_eq!(members.len(), 1);
+            assert_eq!(members[0].0, "sample.fa");
+            assert_eq!(fs::read(&members[0].1).unwrap(), expected);
+        }
+    }
+}
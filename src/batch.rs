@@ -0,0 +1,157 @@
+// src/batch.rs
+// `lash run`: several sketch/dist jobs from one TOML file, run one after the
+// other on the global thread pool with a consolidated report at the end
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::time::Instant;
+
+use log::info;
+use serde_json::json;
+use toml::{Table, Value};
+
+use crate::notify::{run_report, Notifier};
+
+// outcome of one job for the report
+struct JobReport {
+    name: String,
+    command: String,
+    status: &'static str,
+    seconds: f64,
+    message: String,
+}
+
+// turn one [[job]] table into the argument list of its subcommand, keys are
+// the long option names and `true` booleans become bare flags
+fn job_args(job: &Table) -> Result<(String, Vec<String>), Box<dyn Error>> {
+    let command = job
+        .get("command")
+        .and_then(Value::as_str)
+        .ok_or("every job needs a `command` key")?
+        .to_string();
+    if command == "run" {
+        return Err("jobs cannot start another `run`".into());
+    }
+
+    let mut args = vec!["lash".to_string(), command.clone()];
+    for (key, value) in job {
+        if key == "command" || key == "name" {
+            continue;
+        }
+        let flag = format!("--{}", key);
+        match value {
+            Value::Boolean(true) => args.push(flag),
+            Value::Boolean(false) => {}
+            Value::String(v) => args.extend([flag, v.clone()]),
+            Value::Integer(v) => args.extend([flag, v.to_string()]),
+            Value::Float(v) => args.extend([flag, v.to_string()]),
+            Value::Array(values) => {
+                let joined: Vec<String> = values
+                    .iter()
+                    .map(|v| match v {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect();
+                args.extend([flag, joined.join(",")]);
+            }
+            _ => return Err(format!("unsupported value for `{}`", key).into()),
+        }
+    }
+    Ok((command, args))
+}
+
+// run every job of `config` in order, a failing job is reported and does not stop the
+// others, the run fails once all are done if any did
+pub fn run_jobs(config: &str, report: &str, notifier: &Notifier) -> Result<(), Box<dyn Error>> {
+    let batch_start = Instant::now();
+    let table: Table = fs::read_to_string(config)?.parse()?;
+    let jobs = match table.get("job") {
+        Some(Value::Array(jobs)) => jobs.clone(),
+        _ => return Err(format!("{} has no [[job]] tables", config).into()),
+    };
+
+    let mut reports = Vec::with_capacity(jobs.len());
+    for (i, job) in jobs.iter().enumerate() {
+        let job = job.as_table().ok_or("[[job]] entries must be tables")?;
+        let name = job
+            .get("name")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("job{}", i + 1));
+
+        let start = Instant::now();
+        let (command, outcome) = match job_args(job) {
+            Ok((command, args)) => {
+                info!("job {}: {}", name, args.join(" "));
+                let outcome = match crate::cli().try_get_matches_from(&args) {
+                    Ok(matches) => {
+                        let outcome = crate::run_command(&matches).map_err(|e| e.to_string());
+                        // webhook and hook keys of the job report the job itself
+                        if let Some((_, job_matches)) = matches.subcommand() {
+                            let mut report = run_report(&command, outcome.as_ref().err().cloned(), start.elapsed().as_secs_f64());
+                            report["job"] = json!(name);
+                            report["args"] = json!(args);
+                            Notifier::from_matches(job_matches).notify(&report);
+                        }
+                        outcome
+                    }
+                    Err(e) => Err(e.to_string()),
+                };
+                (command, outcome)
+            }
+            Err(e) => (String::new(), Err(e.to_string())),
+        };
+
+        let seconds = start.elapsed().as_secs_f64();
+        let (status, message) = match outcome {
+            Ok(()) => ("OK", String::new()),
+            Err(e) => ("FAILED", e.lines().next().unwrap_or("").to_string()),
+        };
+        println!("job {} ({}): {} in {:.2}s", name, command, status, seconds);
+        reports.push(JobReport { name, command, status, seconds, message });
+    }
+
+    let mut file = File::create(report)?;
+    writeln!(file, "Job\tCommand\tStatus\tSeconds\tMessage")?;
+    for r in &reports {
+        writeln!(
+            file,
+            "{}\t{}\t{}\t{:.3}\t{}",
+            r.name, r.command, r.status, r.seconds, r.message
+        )?;
+    }
+
+    let failed = reports.iter().filter(|r| r.status != "OK").count();
+
+    let mut summary = run_report(
+        "run",
+        (failed > 0).then(|| format!("{} of {} jobs failed", failed, reports.len())),
+        batch_start.elapsed().as_secs_f64(),
+    );
+    summary["jobs"] = reports
+        .iter()
+        .map(|r| {
+            json!({
+                "name": r.name,
+                "command": r.command,
+                "status": r.status,
+                "seconds": r.seconds,
+                "message": r.message,
+            })
+        })
+        .collect();
+    notifier.notify(&summary);
+
+    println!(
+        "{} jobs done, {} failed, report written to {}.",
+        reports.len(),
+        failed,
+        report
+    );
+    if failed > 0 {
+        return Err(format!("{} of {} jobs failed, see {}", failed, reports.len(), report).into());
+    }
+    Ok(())
+}
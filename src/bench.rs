@@ -0,0 +1,147 @@
+// src/bench.rs
+// `lash bench`: sketch the same genomes with every algorithm/precision asked for
+// and report time, memory and size, to help choose parameters. Every run is a
+// separate `lash sketch` process so its peak memory is its own; the peak is read
+// from /proc while the child runs and is NA where /proc is not available.
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+pub struct BenchOptions {
+    pub file_list: String,
+    pub kmer_length: usize,
+    pub algorithms: Vec<String>,
+    pub precisions: Vec<u32>,
+    pub threads: usize,
+    pub output: String,
+}
+
+struct BenchRun {
+    algorithm: String,
+    precision: Option<u32>,
+    seconds: f64,
+    peak_rss_kb: Option<u64>,
+    sketch_bytes: u64,
+}
+
+// VmHWM of a running process, in kB
+fn peak_rss_kb(pid: u32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status
+        .lines()
+        .find(|l| l.starts_with("VmHWM:"))?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+fn run_sketch(
+    opts: &BenchOptions,
+    algorithm: &str,
+    precision: Option<u32>,
+    prefix: &str,
+) -> Result<BenchRun, Box<dyn Error>> {
+    let mut cmd = Command::new(std::env::current_exe()?);
+    cmd.args(["sketch", "-f", &opts.file_list, "-o", prefix, "-a", algorithm])
+        .args(["-k", &opts.kmer_length.to_string(), "-t", &opts.threads.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    if let Some(p) = precision {
+        cmd.args(["-p", &p.to_string()]);
+    }
+
+    let start = Instant::now();
+    let mut child = cmd.spawn()?;
+    let mut peak = None;
+    // poll until the child exits, the last reading is its peak
+    while child.try_wait()?.is_none() {
+        peak = peak_rss_kb(child.id()).or(peak);
+        thread::sleep(Duration::from_millis(10));
+    }
+    let status = child.wait()?;
+    let seconds = start.elapsed().as_secs_f64();
+    if !status.success() {
+        return Err(format!("lash sketch exited with {}", status).into());
+    }
+
+    let sketch_file = format!("{}_sketches.bin", prefix);
+    let sketch_bytes = fs::metadata(&sketch_file)?.len();
+    for suffix in ["_sketches.bin", "_files.json", "_parameters.json"] {
+        let _ = fs::remove_file(format!("{}{}", prefix, suffix));
+    }
+    Ok(BenchRun {
+        algorithm: algorithm.to_string(),
+        precision,
+        seconds,
+        peak_rss_kb: peak,
+        sketch_bytes,
+    })
+}
+
+pub fn bench(opts: &BenchOptions) -> Result<(), Box<dyn Error>> {
+    let genomes = crate::utils::read_file_list(&opts.file_list)?.len();
+    if genomes == 0 {
+        return Err(format!("{} lists no files", opts.file_list).into());
+    }
+    let prefix = std::env::temp_dir()
+        .join(format!("lash_bench_{}", std::process::id()))
+        .to_string_lossy()
+        .into_owned();
+
+    let mut runs = Vec::new();
+    for algorithm in &opts.algorithms {
+        // only hll and ull take a precision, the others run once with their defaults
+        let precisions: Vec<Option<u32>> = if crate::utils::size_key(algorithm) != Some("precision") {
+            vec![None]
+        } else {
+            opts.precisions.iter().copied().map(Some).collect()
+        };
+        for precision in precisions {
+            let label = match precision {
+                Some(p) => format!("{} p={}", algorithm, p),
+                None => algorithm.clone(),
+            };
+            match run_sketch(opts, algorithm, precision, &prefix) {
+                Ok(run) => {
+                    println!("{}: {:.2}s", label, run.seconds);
+                    runs.push(run);
+                }
+                Err(e) => warn!("{} failed: {}", label, e),
+            }
+        }
+    }
+
+    let mut out = BufWriter::new(File::create(&opts.output)?);
+    writeln!(
+        out,
+        "# lash={} k={} genomes={} threads={}",
+        env!("CARGO_PKG_VERSION"),
+        opts.kmer_length,
+        genomes,
+        opts.threads
+    )?;
+    writeln!(out, "Algorithm\tPrecision\tSeconds\tPeakRSS_MB\tSketchBytes\tBytesPerGenome")?;
+    for run in &runs {
+        writeln!(
+            out,
+            "{}\t{}\t{:.3}\t{}\t{}\t{:.0}",
+            run.algorithm,
+            run.precision.map_or("NA".to_string(), |p| p.to_string()),
+            run.seconds,
+            run.peak_rss_kb.map_or("NA".to_string(), |kb| format!("{:.1}", kb as f64 / 1024.0)),
+            run.sketch_bytes,
+            run.sketch_bytes as f64 / genomes as f64
+        )?;
+    }
+    out.flush()?;
+
+    println!("{} runs on {} genomes written to {}.", runs.len(), genomes, opts.output);
+    Ok(())
+}
@@ -0,0 +1,69 @@
+// src/bloom.rs
+// Blocked Bloom filter for `--min-count 2`: a k-mer of a FASTQ file reaches the
+// sketch only on its second occurrence, so k-mers seen once, mostly sequencing
+// errors, stay out. Each k-mer sets 4 bits within one 512-bit block, a single
+// cache line. False positives let a few singletons through, never drop a repeat.
+// `sketch --blocklist` looks k-mers up in one filled from the blocked sequences.
+
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+// bits per expected k-mer, about 2% false positives with 4 probes per block
+const BITS_PER_KMER: u64 = 10;
+const MIN_BYTES: u64 = 1 << 16;
+const MAX_BYTES: u64 = 1 << 28;
+// used when the input size is unknown (stdin)
+const DEFAULT_BYTES: u64 = 1 << 26;
+const PROBES: u32 = 4;
+
+pub struct BloomFilter {
+    // 8 words per 512-bit block
+    blocks: Vec<[u64; 8]>,
+    seed: u64,
+}
+
+impl BloomFilter {
+    // sized for a sequence file of `input_bytes`, one k-mer per byte at most,
+    // between 64 KiB and 256 MiB
+    pub fn for_input(input_bytes: Option<u64>, seed: u64) -> Self {
+        let bytes = input_bytes
+            .map_or(DEFAULT_BYTES, |b| (b * BITS_PER_KMER / 8).clamp(MIN_BYTES, MAX_BYTES))
+            .next_power_of_two();
+        BloomFilter { blocks: vec![[0; 8]; (bytes / 64) as usize], seed: seed ^ 0x626C_6F6F_6D00_0000 }
+    }
+
+    // for a blocklist of up to `kmers` k-mers, 16 bits each (about 0.3% false
+    // positives), between 64 KiB and 2 GiB
+    pub fn for_kmers(kmers: u64, seed: u64) -> Self {
+        let bytes = (kmers * 2).clamp(MIN_BYTES, 1 << 31).next_power_of_two();
+        BloomFilter { blocks: vec![[0; 8]; (bytes / 64) as usize], seed: seed ^ 0x626C_6F6F_6D00_0000 }
+    }
+
+    // block index and the (word, bit) of each probe
+    fn probes(&self, masked: u64) -> (usize, [(usize, u64); PROBES as usize]) {
+        let hash = xxh3_64_with_seed(&masked.to_le_bytes(), self.seed);
+        let index = (hash >> 40) as usize & (self.blocks.len() - 1);
+        let probes = std::array::from_fn(|probe| {
+            let bit = (hash >> (9 * probe)) & 511;
+            ((bit >> 6) as usize, 1u64 << (bit & 63))
+        });
+        (index, probes)
+    }
+
+    // add a k-mer, true if it was (probably) seen before
+    pub fn insert(&mut self, masked: u64) -> bool {
+        let (index, probes) = self.probes(masked);
+        let block = &mut self.blocks[index];
+        let mut present = true;
+        for (word, mask) in probes {
+            present &= block[word] & mask != 0;
+            block[word] |= mask;
+        }
+        present
+    }
+
+    // whether a k-mer was (probably) added
+    pub fn contains(&self, masked: u64) -> bool {
+        let (index, probes) = self.probes(masked);
+        probes.iter().all(|&(word, mask)| self.blocks[index][word] & mask != 0)
+    }
+}
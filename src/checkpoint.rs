@@ -0,0 +1,200 @@
+// src/checkpoint.rs
+// Checkpoints of long `sketch` runs: the sketches of each sample (every record
+// with --per-contig, every k-mer length, Count-Min sketches too) are written to
+// <output>_checkpoint/<sample index>.bin as soon as the sample is done. A run
+// restarted with --resume reads those back instead of sketching the samples
+// again; the directory is removed once the sets are written. A fingerprint of
+// the inputs and parameters kept next to them makes --resume refuse the
+// checkpoint of a different run, and a run without --resume refuses to start
+// over one, so an interrupted run is never thrown away by accident.
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use zstd::stream::{Decoder, Encoder};
+
+use crate::countmin::CountMin;
+use crate::format;
+use crate::utils::{KmerSketch, Sketched};
+
+// sketches of one sample: per record id, one (sketch, Count-Min) per k-mer length
+pub type SampleSketches<S> = Vec<(String, Sketched<S>)>;
+
+pub struct Checkpoint {
+    dir: PathBuf,
+}
+
+impl Checkpoint {
+    pub fn open(output: &str, fingerprint: u64, resume: bool) -> Result<(Self, usize), Box<dyn Error>> {
+        let dir = PathBuf::from(format!("{}_checkpoint", output));
+        let fingerprint_file = dir.join("fingerprint");
+        let mut done = 0;
+        if dir.is_dir() {
+            if !resume {
+                return Err(format!(
+                    "{} holds the checkpoint of an interrupted run, add --resume to continue it or remove the directory",
+                    dir.display()
+                )
+                .into());
+            }
+            if fs::read_to_string(&fingerprint_file).ok().as_deref() != Some(&fingerprint.to_string()) {
+                return Err(format!(
+                    "{} holds the checkpoint of a run with other inputs or parameters, remove it to start over",
+                    dir.display()
+                )
+                .into());
+            }
+            done = fs::read_dir(&dir)?
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_name().to_string_lossy().ends_with(".bin"))
+                .count();
+        } else {
+            fs::create_dir_all(&dir)?;
+            fs::write(&fingerprint_file, fingerprint.to_string())?;
+        }
+        Ok((Checkpoint { dir }, done))
+    }
+
+    fn file(&self, index: usize) -> PathBuf {
+        self.dir.join(format!("{}.bin", index))
+    }
+
+    // the sketches of sample `index` and its counters, if they were checkpointed
+    pub fn load<S: KmerSketch>(&self, index: usize, kmer_lengths: usize) -> Option<(SampleSketches<S>, [u64; 3])> {
+        let file = File::open(self.file(index)).ok()?;
+        match read_sample(&mut Decoder::new(BufReader::new(file)).ok()?, kmer_lengths) {
+            Ok(sample) => Some(sample),
+            Err(e) => {
+                warn!("checkpoint of sample {} is unreadable ({}), sketching it again", index, e);
+                None
+            }
+        }
+    }
+
+    // written under a temporary name first, a crash midway leaves no partial file
+    pub fn save<S: KmerSketch>(&self, index: usize, entries: &SampleSketches<S>, counters: [u64; 3]) {
+        let partial = self.dir.join(format!("{}.part", index));
+        let written = write_file(&partial, entries, counters).and_then(|_| Ok(fs::rename(&partial, self.file(index))?));
+        if let Err(e) = written {
+            warn!("sample {} could not be checkpointed: {}", index, e);
+        }
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        fs::remove_dir_all(&self.dir)
+    }
+}
+
+fn write_file<S: KmerSketch>(path: &Path, entries: &SampleSketches<S>, counters: [u64; 3]) -> Result<(), Box<dyn Error>> {
+    let mut encoder = Encoder::new(BufWriter::new(File::create(path)?), 1)?;
+    write_sample(&mut encoder, entries, counters)?;
+    encoder.finish()?.flush()?;
+    Ok(())
+}
+
+fn write_sample<S: KmerSketch, W: Write>(writer: &mut W, entries: &SampleSketches<S>, counters: [u64; 3]) -> Result<(), Box<dyn Error>> {
+    for counter in counters {
+        writer.write_all(&counter.to_le_bytes())?;
+    }
+    writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+    for (id, per_k) in entries {
+        writer.write_all(&(id.len() as u64).to_le_bytes())?;
+        writer.write_all(id.as_bytes())?;
+        for (sketch, counts) in per_k {
+            sketch.save(writer)?;
+            match counts {
+                Some(counts) => {
+                    writer.write_all(&[1])?;
+                    format::write_countmin(writer, counts)?;
+                }
+                None => writer.write_all(&[0])?,
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_sample<S: KmerSketch, R: Read>(reader: &mut R, kmer_lengths: usize) -> io::Result<(SampleSketches<S>, [u64; 3])> {
+    let counters = [read_u64(reader)?, read_u64(reader)?, read_u64(reader)?];
+    let records = read_u64(reader)?;
+    let mut entries = Vec::new();
+    for _ in 0..records {
+        let mut id = vec![0u8; read_u64(reader)? as usize];
+        reader.read_exact(&mut id)?;
+        let mut per_k = Vec::with_capacity(kmer_lengths);
+        for _ in 0..kmer_lengths {
+            let sketch = S::load(reader)?;
+            let mut flag = [0u8];
+            reader.read_exact(&mut flag)?;
+            let counts: Option<CountMin> = match flag[0] {
+                1 => Some(format::read_countmin(reader)?),
+                _ => None,
+            };
+            per_k.push((sketch, counts));
+        }
+        entries.push((String::from_utf8_lossy(&id).into_owned(), per_k));
+    }
+    Ok((entries, counters))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::minhash::MinHash;
+    use crate::utils::TempDir;
+
+    fn sample(kmers: std::ops::Range<u64>, counts: bool) -> SampleSketches<MinHash> {
+        let mut sketch = MinHash::new(Some(64));
+        let mut count_min = CountMin::new(64);
+        for kmer in kmers {
+            sketch.add_kmer(kmer, 42);
+            count_min.add_kmer(kmer, 42);
+        }
+        vec![("record".to_string(), vec![(sketch.clone(), None), (sketch, counts.then_some(count_min))])]
+    }
+
+    fn bytes(entries: &SampleSketches<MinHash>, counters: [u64; 3]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_sample(&mut bytes, entries, counters).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn resume_reads_back_saved_samples() {
+        let dir = TempDir::new("checkpoint_resume");
+        let output = dir.path("set");
+        let (first, third) = (sample(0..1000, false), sample(500..1500, true));
+        let (checkpoint, done) = Checkpoint::open(&output, 7, false).unwrap();
+        assert_eq!(done, 0);
+        checkpoint.save(0, &first, [1, 2, 3]);
+        checkpoint.save(2, &third, [0, 0, 4]);
+
+        // the same run again has to ask for --resume, another run is refused
+        assert!(Checkpoint::open(&output, 7, false).is_err());
+        assert!(Checkpoint::open(&output, 8, true).is_err());
+        let (checkpoint, done) = Checkpoint::open(&output, 7, true).unwrap();
+        assert_eq!(done, 2);
+        let (loaded, counters) = checkpoint.load::<MinHash>(0, 2).unwrap();
+        assert_eq!(bytes(&loaded, counters), bytes(&first, [1, 2, 3]));
+        let (loaded, counters) = checkpoint.load::<MinHash>(2, 2).unwrap();
+        assert_eq!(bytes(&loaded, counters), bytes(&third, [0, 0, 4]));
+        assert!(checkpoint.load::<MinHash>(1, 2).is_none());
+
+        // a damaged sample is sketched again
+        fs::write(checkpoint.file(2), b"damaged").unwrap();
+        assert!(checkpoint.load::<MinHash>(2, 2).is_none());
+
+        checkpoint.finish().unwrap();
+        assert!(!Path::new(&format!("{}_checkpoint", output)).exists());
+        assert_eq!(Checkpoint::open(&output, 8, false).unwrap().1, 0);
+    }
+}
@@ -0,0 +1,163 @@
+// src/classify.rs
+// `lash classify`: taxonomy of each query from its best reference hit. The best
+// hit's lineage is kept down to the lowest rank whose distance threshold the hit
+// passes (e.g. <= 0.05 for species), deeper ranks are cut off.
+//
+// The taxonomy is a two-column TSV of reference name and GTDB-style lineage,
+// `d__Bacteria;p__...;s__Escherichia coli`. Reference names are matched as they
+// appear in `_files.json`, or by file name without the directories.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use hashbrown::HashMap;
+use log::warn;
+use rayon::prelude::*;
+
+use crate::pairwise::{sketch_distance, SketchSet};
+use crate::utils::{check_same_params, load_sketches, metadata_header, with_sketch_type, KmerSketch};
+
+// lineage levels, in the order they appear in a lineage string
+pub const RANKS: [&str; 7] = ["domain", "phylum", "class", "order", "family", "genus", "species"];
+
+// "species=0.05,genus=0.2": distance thresholds per rank
+pub fn parse_thresholds(spec: &str) -> Result<Vec<(usize, f64)>, String> {
+    let mut thresholds = Vec::new();
+    for item in spec.split(',').filter(|s| !s.trim().is_empty()) {
+        let (rank, value) = item
+            .split_once('=')
+            .ok_or_else(|| format!("threshold {} is not rank=distance", item))?;
+        let level = RANKS
+            .iter()
+            .position(|r| *r == rank.trim())
+            .ok_or_else(|| format!("unknown rank {}, expected one of {}", rank, RANKS.join(",")))?;
+        let value: f64 = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid distance in {}", item))?;
+        thresholds.push((level, value));
+    }
+    // deepest rank first, the first one passed is the assignment
+    thresholds.sort_by_key(|t| std::cmp::Reverse(t.0));
+    Ok(thresholds)
+}
+
+pub fn read_taxonomy(file_name: &str) -> Result<HashMap<String, Vec<String>>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(file_name)?);
+    let mut taxonomy = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, lineage) = line
+            .split_once('\t')
+            .ok_or_else(|| format!("{} line {}: expected <name>\\t<lineage>", file_name, i + 1))?;
+        let levels: Vec<String> = lineage.trim().split(';').map(|l| l.trim().to_string()).collect();
+        taxonomy.insert(name.to_string(), levels);
+    }
+    Ok(taxonomy)
+}
+
+fn lineage_of<'a>(taxonomy: &'a HashMap<String, Vec<String>>, name: &str) -> Option<&'a Vec<String>> {
+    taxonomy.get(name).or_else(|| {
+        let base = Path::new(name).file_name()?.to_str()?;
+        taxonomy.get(base)
+    })
+}
+
+// (reference index, distance), None when nothing was compared
+type BestHit = Option<(usize, f64)>;
+
+// best annotated reference of every query
+fn best_hits_with<S: KmerSketch + Sync>(
+    queries: &SketchSet,
+    references: &SketchSet,
+    annotated: &[usize],
+    equation: u8,
+) -> Result<Vec<BestHit>, Box<dyn Error>> {
+    let query_sketches: Vec<S> = load_sketches(&queries.sketch_file, queries.names.len())?;
+    let ref_sketches: Vec<S> = load_sketches(&references.sketch_file, references.names.len())?;
+    Ok(query_sketches
+        .par_iter()
+        .map(|q| {
+            annotated
+                .iter()
+                .map(|&j| (j, sketch_distance(&ref_sketches[j], q, references.kmer_length, equation)))
+                .min_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)))
+        })
+        .collect())
+}
+
+pub fn classify(
+    query: &str,
+    reference: &str,
+    taxonomy_file: &str,
+    thresholds: &[(usize, f64)],
+    output: &str,
+    equation: u8,
+    metadata: bool,
+) -> Result<(), Box<dyn Error>> {
+    let queries = SketchSet::open(query)?;
+    let references = SketchSet::open(reference)?;
+    check_same_params(query, &queries.params, reference, &references.params)?;
+    let taxonomy = read_taxonomy(taxonomy_file)?;
+
+    let lineages: Vec<Option<&Vec<String>>> = references.names.iter().map(|n| lineage_of(&taxonomy, n)).collect();
+    let annotated: Vec<usize> = (0..lineages.len()).filter(|&j| lineages[j].is_some()).collect();
+    if annotated.is_empty() {
+        return Err(format!("no reference of {} is listed in {}", reference, taxonomy_file).into());
+    }
+    if annotated.len() < references.names.len() {
+        warn!(
+            "{} of {} references have no lineage in {} and are not used",
+            references.names.len() - annotated.len(),
+            references.names.len(),
+            taxonomy_file
+        );
+    }
+
+    let hits = with_sketch_type!(
+        references.params["algorithm"].as_str(),
+        best_hits_with(&queries, &references, &annotated, equation)
+    )?;
+
+    let mut out = BufWriter::new(File::create(output)?);
+    if metadata {
+        writeln!(out, "{}", metadata_header(&references.params, equation))?;
+    }
+    writeln!(out, "Query\tBestReference\tDistance\tRank\tLineage")?;
+    let mut classified = 0;
+    for (q_name, hit) in queries.names.iter().zip(&hits) {
+        let (j, d) = hit.expect("at least one annotated reference");
+        let lineage = lineages[j].expect("annotated");
+        // deepest rank whose threshold is passed, and that the lineage goes down to
+        match thresholds.iter().find(|(level, max)| d <= *max && *level < lineage.len()) {
+            Some(&(level, _)) => {
+                classified += 1;
+                writeln!(
+                    out,
+                    "{}\t{}\t{:.6}\t{}\t{}",
+                    q_name,
+                    references.names[j],
+                    d,
+                    RANKS[level],
+                    lineage[..=level].join(";")
+                )?;
+            }
+            None => writeln!(out, "{}\t{}\t{:.6}\tunclassified\tNA", q_name, references.names[j], d)?,
+        }
+    }
+    out.flush()?;
+
+    println!(
+        "{} of {} queries classified against {} annotated references, written to {}.",
+        classified,
+        queries.names.len(),
+        annotated.len(),
+        output
+    );
+    Ok(())
+}
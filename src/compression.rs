@@ -0,0 +1,128 @@
+// src/compression.rs
+// Input codecs on top of the ones the FASTA/FASTQ parser recognizes by itself
+// (gzip, bzip2, zstd): xz, as several public archives ship assemblies, read
+// across concatenated streams like gzip members, and lz4 frames (plus the
+// legacy format of `lz4 -l`), decoded by lz4_flex with their checksums verified.
+// Like the parser, the codec is told by its magic bytes, not by the file
+// extension, so local files, URLs and standard input are all handled alike.
+
+use std::io::{self, BufRead, BufReader, Cursor, ErrorKind, Read};
+
+use liblzma::read::XzDecoder;
+use lz4_flex::frame::FrameDecoder;
+
+const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0x00];
+const LZ4_MAGIC: u32 = 0x184d_2204;
+const LZ4_LEGACY_MAGIC: u32 = 0x184c_2102;
+
+// `reader`, decompressed when it is xz or lz4, as it is otherwise
+pub fn decoded<'a, R: Read + Send + 'a>(mut reader: R) -> io::Result<Box<dyn Read + Send + 'a>> {
+    let mut magic = [0u8; 6];
+    let mut filled = 0;
+    while filled < magic.len() {
+        match reader.read(&mut magic[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    let head = magic[..filled].to_vec();
+    let reader = Cursor::new(head).chain(reader);
+    let first = u32::from_le_bytes([magic[0], magic[1], magic[2], magic[3]]);
+    let decoder: Box<dyn Read + Send + 'a> = if filled == magic.len() && magic == XZ_MAGIC {
+        Box::new(XzDecoder::new_multi_decoder(reader))
+    } else if filled >= 4 && (first == LZ4_MAGIC || first == LZ4_LEGACY_MAGIC || is_skippable(first)) {
+        Box::new(FrameDecoder::new(reader))
+    } else {
+        return Ok(Box::new(reader));
+    };
+    // a corrupt start fails here with the codec's error, the parser would only
+    // see an empty file
+    let mut decoder = BufReader::new(decoder);
+    decoder.fill_buf()?;
+    Ok(Box::new(decoder))
+}
+
+// skippable frames carry metadata, magic 0x184D2A50 to 0x184D2A5F
+fn is_skippable(magic: u32) -> bool {
+    magic & 0xffff_fff0 == 0x184d_2a50
+}
+
+// Fixtures in tests/data were written by the lz4 1.9.4 and xz command line tools
+// from `sample`: small.lz4 (defaults, content checksum), small_bx.lz4 (-BX
+// --content-size, block checksums), small_legacy.lz4 (-l) and linked.lz4 (-B4 -BD,
+// 64 KiB blocks whose matches reach into the previous block) of 600 and 150000 bytes.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/small.lz4"));
+    const SMALL_BX: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/small_bx.lz4"));
+    const SMALL_LEGACY: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/small_legacy.lz4"));
+    const LINKED: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/linked.lz4"));
+
+    fn sample(n: u64) -> Vec<u8> {
+        (0..n).map(|i| b"ACGT"[((i * i + i / 3) % 4) as usize]).collect()
+    }
+
+    fn decode(input: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        decoded(Cursor::new(input.to_vec()))?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn lz4_frames_decode() {
+        assert_eq!(decode(SMALL).unwrap(), sample(600));
+        assert_eq!(decode(SMALL_BX).unwrap(), sample(600));
+        assert_eq!(decode(SMALL_LEGACY).unwrap(), sample(600));
+        assert_eq!(decode(LINKED).unwrap(), sample(150000));
+    }
+
+    #[test]
+    fn lz4_frames_concatenate() {
+        let skippable = [0x50, 0x2a, 0x4d, 0x18, 3, 0, 0, 0, 1, 2, 3];
+        let input = [&skippable[..], SMALL, SMALL_BX, SMALL, SMALL_LEGACY].concat();
+        assert_eq!(decode(&input).unwrap(), sample(600).repeat(4));
+    }
+
+    #[test]
+    fn plain_input_passes_through() {
+        assert_eq!(decode(b">r\nACGT\n").unwrap(), b">r\nACGT\n");
+        assert_eq!(decode(b"AC").unwrap(), b"AC");
+    }
+
+    #[test]
+    fn truncated_lz4_fails() {
+        for fixture in [SMALL, SMALL_BX, LINKED] {
+            for end in 4..fixture.len() {
+                assert!(decode(&fixture[..end]).is_err(), "cut at {}", end);
+            }
+        }
+        // legacy frames have no end mark, only a cut within a block shows
+        for end in 9..SMALL_LEGACY.len() {
+            assert!(decode(&SMALL_LEGACY[..end]).is_err(), "legacy cut at {}", end);
+        }
+        let skippable = [0x50, 0x2a, 0x4d, 0x18, 3, 0, 0, 0, 1];
+        assert!(decode(&skippable).is_err());
+    }
+
+    #[test]
+    fn corrupt_lz4_fails() {
+        // a flipped bit past the magic number, in the header, block sizes, data or
+        // checksums, fails; only the match length bits of the last token of a block are
+        // unused, so flipping them may decode the same data, never other data
+        for fixture in [SMALL, SMALL_BX] {
+            for i in 4..fixture.len() {
+                for bit in 0..8 {
+                    let mut corrupt = fixture.to_vec();
+                    corrupt[i] ^= 1 << bit;
+                    if let Ok(out) = decode(&corrupt) {
+                        assert_eq!(out, sample(600), "bit {} of byte {} flipped", bit, i);
+                    }
+                }
+            }
+        }
+    }
+}
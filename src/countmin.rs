@@ -0,0 +1,236 @@
+// src/countmin.rs
+// Count-Min sketches of k-mer abundances, built next to the cardinality sketch
+// when `lash sketch --count-min <width>` is given and written to `_counts.bin` in
+// the order of `_files.json`. Counters use conservative update, so each holds an
+// upper bound of the count of every k-mer hashed to it.
+//
+// `dist --abundance` compares two samples by the cosine of their count vectors,
+// with inner products corrected for collisions (count-mean-min, Deng & Rafiei
+// 2007); `stats` reports the k-mer
+// frequency spectrum, tracked while sketching since k-mers cannot be listed from
+// the counters. Both are exact up to collisions, which grow once the number of
+// distinct k-mers approaches the width.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+
+use rayon::prelude::*;
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+use zstd::stream::{Decoder, Encoder};
+
+use crate::format;
+use crate::utils::{find_files, is_fastq, metadata_header, read_names, read_params};
+
+// rows of every sketch, the estimate of a k-mer is its smallest counter
+pub const DEPTH: usize = 4;
+
+// highest abundance of the spectrum, more frequent k-mers are counted in its last bin
+pub const MAX_ABUNDANCE: usize = 10000;
+
+#[derive(Clone)]
+pub struct CountMin {
+    pub width: usize,
+    pub table: Vec<u32>,
+    // spectrum[i]: k-mers seen i times, spectrum[0] is unused
+    pub spectrum: Vec<u64>,
+}
+
+impl CountMin {
+    pub fn new(width: usize) -> Self {
+        assert!(width > 0, "Count-Min width must be at least 1");
+        CountMin { width, table: vec![0; DEPTH * width], spectrum: vec![0; 2] }
+    }
+
+    // counter of every row for a k-mer hash, by double hashing
+    fn cells(&self, hash: u64) -> [usize; DEPTH] {
+        let (h1, h2) = (hash & 0xFFFF_FFFF, (hash >> 32) | 1);
+        let mut cells = [0; DEPTH];
+        for (row, cell) in cells.iter_mut().enumerate() {
+            *cell = row * self.width + (h1.wrapping_add(row as u64 * h2) % self.width as u64) as usize;
+        }
+        cells
+    }
+
+    pub fn add_kmer(&mut self, masked: u64, seed: u64) {
+        let cells = self.cells(xxh3_64_with_seed(&masked.to_le_bytes(), seed));
+        let count = cells.iter().map(|&c| self.table[c]).min().expect("DEPTH > 0");
+        let updated = count.saturating_add(1);
+        for &c in &cells {
+            self.table[c] = self.table[c].max(updated);
+        }
+        // the k-mer moves from bin `count` to bin `count + 1`
+        let (from, to) = ((count as usize).min(MAX_ABUNDANCE), (updated as usize).min(MAX_ABUNDANCE));
+        if from != to {
+            if self.spectrum.len() <= to {
+                self.spectrum.resize(to + 1, 0);
+            }
+            if from > 0 {
+                self.spectrum[from] = self.spectrum[from].saturating_sub(1);
+            }
+            self.spectrum[to] += 1;
+        }
+    }
+
+    // inner product of the count vectors: per row, the expected contribution of
+    // colliding k-mers (row sums / width) is subtracted, then the median row is taken
+    fn inner_product(&self, other: &Self) -> f64 {
+        let w = self.width as f64;
+        let mut rows: Vec<f64> = self
+            .table
+            .chunks(self.width)
+            .zip(other.table.chunks(other.width))
+            .map(|(a, b)| {
+                let product: f64 = a.iter().zip(b).map(|(&x, &y)| x as f64 * y as f64).sum();
+                let (sum_a, sum_b): (f64, f64) =
+                    (a.iter().map(|&x| x as f64).sum(), b.iter().map(|&y| y as f64).sum());
+                if self.width == 1 {
+                    return product;
+                }
+                (product - sum_a * sum_b / w) * w / (w - 1.0)
+            })
+            .collect();
+        rows.sort_by(|x, y| x.total_cmp(y));
+        (rows[DEPTH / 2 - 1] + rows[DEPTH / 2]) / 2.0
+    }
+
+    // distinct k-mers of the genome a read set covers: the spectrum from its first
+    // valley on, leaving out the low-count k-mers of sequencing errors; without a
+    // valley (too shallow to tell errors apart) the k-mers seen at least twice
+    pub fn solid_kmers(&self) -> u64 {
+        let valley = (1..self.spectrum.len().saturating_sub(1))
+            .find(|&a| self.spectrum[a + 1] > self.spectrum[a])
+            .unwrap_or(2);
+        self.spectrum.iter().skip(valley).sum()
+    }
+
+    // cosine similarity of the k-mer count vectors, 1 for identical abundance profiles
+    pub fn cosine(&self, other: &Self) -> f64 {
+        assert_eq!(self.width, other.width, "Count-Min sketches with different widths");
+        let norms = self.inner_product(self) * other.inner_product(other);
+        if norms <= 0.0 {
+            return 0.0;
+        }
+        (self.inner_product(other) / norms.sqrt()).clamp(0.0, 1.0)
+    }
+}
+
+// the Count-Min sketches of a sketch set, None if it was sketched without --count-min
+pub fn load_counts(prefix: &str, count: usize) -> Result<Option<Vec<CountMin>>, Box<dyn Error>> {
+    let counts_file = format!("{}_counts.bin", prefix);
+    if !std::path::Path::new(&counts_file).is_file() {
+        return Ok(None);
+    }
+    let mut decoder = Decoder::new(BufReader::new(File::open(&counts_file)?))?;
+    let counts = (0..count)
+        .map(|_| format::read_countmin(&mut decoder))
+        .collect::<std::io::Result<Vec<CountMin>>>()?;
+    Ok(Some(counts))
+}
+
+pub fn write_counts(prefix: &str, counts: &[CountMin], threads: u32) -> Result<(), Box<dyn Error>> {
+    let writer = BufWriter::new(File::create(format!("{}_counts.bin", prefix))?);
+    let mut encoder = Encoder::new(writer, 3)?;
+    encoder.multithread(threads)?;
+    for sketch in counts {
+        format::write_countmin(&mut encoder, sketch)?;
+    }
+    encoder.finish()?;
+    Ok(())
+}
+
+fn require_counts(prefix: &str, count: usize) -> Result<Vec<CountMin>, Box<dyn Error>> {
+    load_counts(prefix, count)?.ok_or_else(|| {
+        format!("{} has no {}_counts.bin, sketch it again with --count-min", prefix, prefix).into()
+    })
+}
+
+// `dist --abundance`: abundance-weighted similarity of every reference/query pair
+pub fn abundance_similarity(
+    reference: &str,
+    query: &str,
+    output: &str,
+    equation: u8,
+    metadata: bool,
+) -> Result<(), Box<dyn Error>> {
+    let ref_files = find_files(reference)?;
+    let query_files = find_files(query)?;
+    let params = read_params(&ref_files["params"])?;
+    let query_params = read_params(&query_files["params"])?;
+    if params.get("count_min") != query_params.get("count_min") {
+        return Err(format!("{} and {} were sketched with different --count-min widths", reference, query).into());
+    }
+    let ref_names = read_names(&ref_files["files"])?;
+    let query_names = read_names(&query_files["files"])?;
+    let ref_counts = require_counts(reference, ref_names.len())?;
+    let query_counts = require_counts(query, query_names.len())?;
+
+    let rows: Vec<(usize, usize, f64)> = ref_counts
+        .par_iter()
+        .enumerate()
+        .flat_map_iter(|(i, r)| query_counts.iter().enumerate().map(move |(j, q)| (i, j, r.cosine(q))))
+        .collect();
+
+    let mut out = BufWriter::new(File::create(output)?);
+    if metadata {
+        writeln!(out, "{}", metadata_header(&params, equation))?;
+    }
+    writeln!(out, "Reference\tQuery\tAbundanceCosine")?;
+    for (i, j, cosine) in &rows {
+        writeln!(out, "{}\t{}\t{:.6}", ref_names[*i], query_names[*j], cosine)?;
+    }
+    out.flush()?;
+    println!("Abundance-weighted similarities written to {}.", output);
+    Ok(())
+}
+
+// `stats`: k-mer frequency spectrum of every read set, one row per non-empty bin
+pub fn write_spectra(
+    names: &[String],
+    counts: &[CountMin],
+    output: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut out = BufWriter::new(File::create(output)?);
+    writeln!(out, "Genome\tAbundance\tKmers")?;
+    for (name, sketch) in names.iter().zip(counts) {
+        if !is_fastq(name) {
+            continue;
+        }
+        for (abundance, &kmers) in sketch.spectrum.iter().enumerate().skip(1) {
+            if kmers > 0 {
+                writeln!(out, "{}\t{}\t{}", name, abundance, kmers)?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_spectrum(spectrum: &[u64]) -> CountMin {
+        CountMin { spectrum: spectrum.to_vec(), ..CountMin::new(1) }
+    }
+
+    #[test]
+    fn solid_kmers_skip_errors() {
+        // errors at counts 1-2, the genome's k-mers around coverage 6
+        assert_eq!(with_spectrum(&[0, 5000, 300, 40, 60, 200, 400, 200, 50, 10]).solid_kmers(), 960);
+        // no valley: k-mers seen twice or more
+        assert_eq!(with_spectrum(&[0, 900, 80, 10, 1]).solid_kmers(), 91);
+    }
+
+    #[test]
+    fn spectrum_counts_distinct_kmers() {
+        let mut counts = CountMin::new(1 << 16);
+        for kmer in 0..1000u64 {
+            for _ in 0..=(kmer % 3) {
+                counts.add_kmer(kmer, 1);
+            }
+        }
+        assert_eq!(&counts.spectrum[1..4], &[334, 333, 333]);
+        assert_eq!(counts.solid_kmers(), 666);
+    }
+}
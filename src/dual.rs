@@ -0,0 +1,71 @@
+// src/dual.rs
+// Two sketches of the same k-mers built in one pass (`-a hmh+hll`) and stored one
+// after the other for each genome. HyperMinHash gives the more accurate Jaccard
+// index, so `dist` goes through it; HLL gives cardinalities and unions with the
+// usual HLL error, so `stats`, `merge` and the set operations use it. Neither has
+// to be sketched again when the other estimate is wanted.
+
+use std::error::Error;
+
+use hyperminhash::Sketch;
+use streaming_algorithms::HyperLogLog;
+
+use crate::utils::KmerSketch;
+
+#[derive(Clone)]
+pub struct Dual<A, B> {
+    pub similarity: A,
+    pub cardinality: B,
+}
+
+pub type HmhHll = Dual<Sketch, HyperLogLog<i64>>;
+
+impl<A: KmerSketch, B: KmerSketch> KmerSketch for Dual<A, B> {
+    // the size parameter goes to both, HMH ignores it
+    fn new(precision: Option<u32>) -> Self {
+        Dual { similarity: A::new(precision), cardinality: B::new(precision) }
+    }
+
+    fn add_kmer(&mut self, masked: u64, seed: u64) {
+        self.similarity.add_kmer(masked, seed);
+        self.cardinality.add_kmer(masked, seed);
+    }
+
+    fn add_hashed(&mut self, masked: u64, hash: u64, seed: u64) {
+        self.similarity.add_hashed(masked, hash, seed);
+        self.cardinality.add_hashed(masked, hash, seed);
+    }
+
+    fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        self.similarity.save(writer)?;
+        self.cardinality.save(writer)
+    }
+
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let similarity = A::load(reader)?;
+        Ok(Dual { similarity, cardinality: B::load(reader)? })
+    }
+
+    fn cardinality(&self) -> f64 {
+        self.cardinality.cardinality()
+    }
+
+    fn merged(&self, other: &Self) -> Self {
+        Dual {
+            similarity: self.similarity.merged(&other.similarity),
+            cardinality: self.cardinality.merged(&other.cardinality),
+        }
+    }
+
+    fn jaccard(&self, other: &Self) -> f64 {
+        self.similarity.jaccard(&other.similarity)
+    }
+
+    fn containment(&self, other: &Self) -> f64 {
+        self.similarity.containment(&other.similarity)
+    }
+
+    fn registers(&self) -> Vec<u16> {
+        self.similarity.registers()
+    }
+}
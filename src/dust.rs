@@ -0,0 +1,24 @@
+// src/dust.rs
+// Low-complexity k-mers for `sketch --filter-low-complexity`, scored as DUST does
+// (Morgulis et al. 2006): the triplets of a k-mer are counted and the score is
+// sum c(c - 1) / 2 over triplet counts, divided by the number of triplets less one.
+// Poly-A scores about k / 2, dinucleotide repeats about k / 4, random sequence
+// well below 1; k-mers above the DUST default of level 20 (score 2) are left out,
+// so repeat-rich genomes do not look alike only for their simple repeats.
+
+const LEVEL: f64 = 2.0;
+
+// whether a k-mer packed 2 bits per base is low-complexity; the reverse complement
+// has the complemented triplets, so both strands of a k-mer score the same
+pub fn low_complexity(masked: u64, kmer_length: usize) -> bool {
+    if kmer_length < 5 {
+        return false;
+    }
+    let triplets = kmer_length - 2;
+    let mut counts = [0u32; 64];
+    for i in 0..triplets {
+        counts[((masked >> (2 * (triplets - 1 - i))) & 63) as usize] += 1;
+    }
+    let pairs: u32 = counts.iter().map(|&c| c * c.saturating_sub(1) / 2).sum();
+    pairs as f64 / (triplets - 1) as f64 > LEVEL
+}
@@ -0,0 +1,137 @@
+// src/ehll.rs
+// ExtendedHyperLogLog (`ehll`, Ohayon 2021): an HLL register plus one bit telling
+// whether the next lower rank was also seen, 7 bits per register against HLL's 6
+// and ULL's 8. The extra bit recovers part of the information HLL throws away, so
+// at the same precision the error falls between HLL and ULL.
+//
+// Cardinalities are maximum-likelihood estimates under the Poisson model, found by
+// bisection on the score; Jaccard goes through inclusion-exclusion with the union
+// of the registers, as for the other register sketches.
+
+use std::error::Error;
+
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+use crate::format;
+use crate::utils::KmerSketch;
+
+#[derive(Clone)]
+pub struct ExtendedHll {
+    pub p: u8,
+    // rank << 1 | bit for rank - 1, 0 for an empty register
+    pub registers: Vec<u8>,
+}
+
+impl ExtendedHll {
+    pub fn from_registers(p: u8, registers: Vec<u8>) -> Self {
+        ExtendedHll { p, registers }
+    }
+
+    // highest rank a hash can reach
+    fn max_rank(&self) -> u8 {
+        65 - self.p
+    }
+
+    fn add_rank(register: &mut u8, rank: u8) {
+        let current = *register >> 1;
+        *register = if rank > current {
+            (rank << 1) | u8::from(current > 0 && current + 1 == rank)
+        } else if rank + 1 == current {
+            *register | 1
+        } else {
+            *register
+        };
+    }
+
+    // score (derivative of the log-likelihood) at a rate of `lambda` k-mers per
+    // register: each rank r is hit with rate lambda * 2^-r
+    fn score(&self, lambda: f64) -> f64 {
+        let seen = |a: f64| a / (a * lambda).exp_m1();
+        self.registers
+            .iter()
+            .map(|&register| {
+                let rank = (register >> 1) as i32;
+                if rank == 0 {
+                    return -1.0;
+                }
+                // the highest rank also takes every hash beyond it
+                let a = 2f64.powi(-rank);
+                let mut score = if rank == self.max_rank() as i32 { seen(2.0 * a) } else { -a + seen(a) };
+                if rank >= 2 {
+                    let b = 2f64.powi(1 - rank);
+                    score += if register & 1 == 1 { seen(b) } else { -b };
+                }
+                score
+            })
+            .sum()
+    }
+}
+
+impl KmerSketch for ExtendedHll {
+    fn new(precision: Option<u32>) -> Self {
+        let p = precision.expect("EHLL needs precision") as u8;
+        assert!((4..=16).contains(&p), "EHLL precision must be between 4 and 16");
+        ExtendedHll { p, registers: vec![0; 1 << p] }
+    }
+
+    fn add_kmer(&mut self, masked: u64, seed: u64) {
+        self.add_hashed(masked, xxh3_64_with_seed(&masked.to_le_bytes(), seed), seed);
+    }
+
+    fn add_hashed(&mut self, _masked: u64, hash: u64, _seed: u64) {
+        let index = (hash >> (64 - self.p)) as usize;
+        let rank = ((hash << self.p).leading_zeros() as u8 + 1).min(self.max_rank());
+        Self::add_rank(&mut self.registers[index], rank);
+    }
+
+    fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        Ok(format::write_ehll(writer, self)?)
+    }
+
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        format::read_ehll(reader)
+    }
+
+    fn cardinality(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        if self.registers.iter().all(|&r| r == 0) {
+            return 0.0;
+        }
+        // the score falls with lambda; bracket its root, then bisect in log space
+        let (mut low, mut high) = (1e-6f64, 1.0f64);
+        while self.score(high) > 0.0 && high < 1e20 {
+            high *= 2.0;
+        }
+        for _ in 0..100 {
+            let mid = (low * high).sqrt();
+            if self.score(mid) > 0.0 {
+                low = mid;
+            } else {
+                high = mid;
+            }
+            if high / low < 1.0 + 1e-9 {
+                break;
+            }
+        }
+        m * (low * high).sqrt()
+    }
+
+    fn merged(&self, other: &Self) -> Self {
+        assert_eq!(self.p, other.p, "EHLL sketches with different precisions");
+        let mut merged = self.clone();
+        for (register, &theirs) in merged.registers.iter_mut().zip(&other.registers) {
+            let rank = theirs >> 1;
+            if rank > 0 {
+                Self::add_rank(register, rank);
+                if theirs & 1 == 1 {
+                    Self::add_rank(register, rank - 1);
+                }
+            }
+        }
+        merged
+    }
+
+    fn registers(&self) -> Vec<u16> {
+        self.registers.iter().map(|&r| u16::from(r)).collect()
+    }
+}
@@ -0,0 +1,167 @@
+// src/fetchdb.rs
+// `lash fetchdb`: download a prebuilt sketch database listed in a catalog. A
+// database is an ordinary sketch set, its three files are downloaded next to
+// each other and only renamed into place once their SHA-256 matches the catalog.
+//
+// The catalog is a TOML file (local path or URL) with one table per database
+// version:
+//
+//   [[database]]
+//   name = "gtdb-reps"
+//   version = "r220"
+//   description = "GTDB r220 species representatives, hmh k=16"
+//   url = "https://example.org/lash/gtdb_r220"   # <url>_parameters.json, _files.json, _sketches.bin
+//   parameters_sha256 = "..."
+//   files_sha256 = "..."
+//   sketches_sha256 = "..."
+//
+// Several versions of one name may be listed, the last one is the default.
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::time::Duration;
+
+use log::info;
+use sha2::{Digest, Sha256};
+use toml::{Table, Value};
+
+use crate::format::format_version;
+use crate::sketchset;
+use crate::utils::read_params;
+
+// the files of a sketch set, by suffix
+const SET_FILES: [(&str, &str); 3] = [
+    ("parameters", "_parameters.json"),
+    ("files", "_files.json"),
+    ("sketches", "_sketches.bin"),
+];
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("https://") || source.starts_with("http://")
+}
+
+fn agent() -> ureq::Agent {
+    // large databases take a while, only connecting has a deadline
+    ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(30))
+        .build()
+}
+
+pub fn read_catalog(source: &str) -> Result<Vec<Table>, Box<dyn Error>> {
+    let text = if is_url(source) {
+        agent().get(source).call()?.into_string()?
+    } else {
+        fs::read_to_string(source)?
+    };
+    let table: Table = text.parse()?;
+    match table.get("database") {
+        Some(Value::Array(entries)) => entries
+            .iter()
+            .map(|e| {
+                e.as_table()
+                    .cloned()
+                    .ok_or_else(|| "[[database]] entries must be tables".into())
+            })
+            .collect(),
+        _ => Err(format!("{} has no [[database]] tables", source).into()),
+    }
+}
+
+fn field<'a>(entry: &'a Table, key: &str) -> Result<&'a str, String> {
+    entry
+        .get(key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("catalog entry {} has no `{}`", entry.get("name").and_then(Value::as_str).unwrap_or("?"), key))
+}
+
+pub fn list(catalog: &[Table]) -> Result<(), Box<dyn Error>> {
+    println!("Name\tVersion\tDescription");
+    for entry in catalog {
+        println!(
+            "{}\t{}\t{}",
+            field(entry, "name")?,
+            field(entry, "version")?,
+            entry.get("description").and_then(Value::as_str).unwrap_or("")
+        );
+    }
+    Ok(())
+}
+
+// reader that hashes everything read through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+// download `url` to `path`, returns the hex SHA-256 of what was written
+fn download(url: &str, path: &str) -> Result<String, Box<dyn Error>> {
+    let response = agent().get(url).call()?;
+    let mut reader = HashingReader {
+        inner: response.into_reader(),
+        hasher: Sha256::new(),
+    };
+    let mut writer = BufWriter::new(File::create(path)?);
+    let bytes = io::copy(&mut reader, &mut writer)?;
+    writer.flush()?;
+    info!("{}: {} bytes", url, bytes);
+    Ok(reader.hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// one file of a database, checked against its `<key>_sha256`
+fn fetch_part(entry: &Table, key: &str, url: &str, part: &str) -> Result<(), Box<dyn Error>> {
+    let expected = field(entry, &format!("{}_sha256", key))?.to_ascii_lowercase();
+    println!("Downloading {}", url);
+    let actual = download(url, part)?;
+    if actual != expected {
+        return Err(format!("checksum mismatch for {}: expected {}, got {}", url, expected, actual).into());
+    }
+    Ok(())
+}
+
+// fetch database `name` (version `version`, or the last one listed) as the sketch set `output`
+pub fn fetch(catalog: &[Table], name: &str, version: Option<&str>, output: &str) -> Result<(), Box<dyn Error>> {
+    let entry = catalog
+        .iter()
+        .rev()
+        .find(|e| {
+            e.get("name").and_then(Value::as_str) == Some(name)
+                && (version.is_none() || e.get("version").and_then(Value::as_str) == version)
+        })
+        .ok_or_else(|| match version {
+            Some(v) => format!("database {} version {} is not in the catalog", name, v),
+            None => format!("database {} is not in the catalog", name),
+        })?;
+    let url = field(entry, "url")?;
+    let db_version = field(entry, "version")?;
+
+    // everything goes to .part files first, nothing is replaced unless all checksums match
+    let mut parts = Vec::with_capacity(SET_FILES.len());
+    for (key, suffix) in SET_FILES {
+        let part = format!("{}{}.part", output, suffix);
+        parts.push((part.clone(), format!("{}{}", output, suffix)));
+        if let Err(e) = fetch_part(entry, key, &format!("{}{}", url, suffix), &part) {
+            for (part, _) in &parts {
+                let _ = fs::remove_file(part);
+            }
+            return Err(e);
+        }
+    }
+    for (part, target) in &parts {
+        fs::rename(part, target)?;
+    }
+
+    // refuse a layout this lash cannot read before anyone uses it
+    format_version(&read_params(&format!("{}_parameters.json", output))?)?;
+    sketchset::validate(&[output.to_string()])?;
+    println!("{} {} written to {} (sketch set prefix).", name, db_version, output);
+    Ok(())
+}
@@ -0,0 +1,676 @@
+// src/format.rs
+// On-disk layout of single sketches inside `_sketches.bin` (the zstd stream).
+// Every multi-byte integer and float is little-endian regardless of the host,
+// so sketch sets written on x86_64 load unchanged on ARM and big-endian hosts.
+//
+//   hmh: 2^14 registers, u16 each
+//   hll: alpha f64 | zero u64 | sum f64 | p u8 | m u64 | m registers, u8 each
+//        (alpha, zero and sum are recomputed from the registers on load)
+//   hll, sparse (version 2): 0 f64 | zero u64 | sum f64 | p u8 | n u64 |
+//        n nonzero registers as index u16, value u8; written when fewer than
+//        m / 3 registers are set (plasmids, viruses) and made dense on load
+//   ull: m u64 | m registers, u8 each
+//   ull, with martingale (version 3): m | 2^63 u64 | m registers, u8 each |
+//        martingale estimate f64; written for sketches built in one pass,
+//        merged sketches keep the plain layout
+//   fmh: scaled u64 | n u64 | n hashes, u64 each, ascending
+//   minhash: sketch_size u64 | n u64 | n hashes, u64 each, ascending
+//   theta: sketch_size u64 | n u64 | n hashes, u64 each, ascending | theta u64
+//   hyperbitbit: level u8 | sketch u64 | sketch2 u64
+//   ordermh: m u64 | distinct k-mers u64 | m tuple hashes, u64 each (0 for sequences too short)
+//   probminhash, dartminhash: m u64 | m k-mer hashes, u64 each | m register values, f64 each
+//   hmh+hll: the hmh sketch, then the hll sketch
+//   ehll: p u8 | 2^p registers, u8 each (rank << 1 | rank - 1 seen)
+//   superminhash: m u64 | m register values, f64 each (infinite for empty registers)
+//
+// Count-Min sketches of `--count-min` sets are kept in `_counts.bin`, same order:
+//   countmin: depth u64 | width u64 | depth * width counters, u32 each |
+//             n u64 | n spectrum bins, u64 each
+//
+// This is the layout the sketch libraries wrote through bincode before it was
+// pinned down here, so older sketch sets stay readable.
+//
+// The layout version is recorded as "format" in `_parameters.json`; sets without
+// it predate the key and are version 1. Version 2 adds the sparse HLL layout,
+// version 3 the martingale estimate of ULL sketches.
+// `lash convert` rewrites older versions.
+
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+
+use hashbrown::HashMap;
+
+use hyperminhash::Sketch;
+use streaming_algorithms::HyperLogLog;
+use ultraloglog::UltraLogLog;
+
+use crate::countmin::{CountMin, DEPTH};
+use crate::hyperbitbit::HyperBitBit;
+use crate::minhash::{FracMinHash, MinHash, Theta};
+use crate::ehll::ExtendedHll;
+use crate::ordermh::OrderSignature;
+use crate::weighted::Signature;
+
+const HLL_HEADER: usize = 8 + 8 + 8 + 1;
+
+pub const FORMAT_VERSION: u32 = 3;
+
+// layout version of a sketch set from its parameters
+pub fn format_version(params: &HashMap<String, String>) -> Result<u32, String> {
+    let version = match params.get("format") {
+        Some(v) => v.parse().map_err(|_| format!("invalid format version {}", v))?,
+        None => 1,
+    };
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(format!(
+            "sketch format version {} is not supported by lash {} (up to version {})",
+            version,
+            env!("CARGO_PKG_VERSION"),
+            FORMAT_VERSION
+        ));
+    }
+    Ok(version)
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    Ok(f64::from_bits(read_u64(reader)?))
+}
+
+pub fn write_hmh<W: Write>(writer: &mut W, sketch: &Sketch) -> io::Result<()> {
+    // hyperminhash already writes its registers as explicit little-endian u16
+    sketch.save(writer)
+}
+
+pub fn read_hmh<R: Read>(reader: &mut R) -> io::Result<Sketch> {
+    Sketch::load(reader)
+}
+
+// precision and registers of an HLL
+pub fn hll_registers(hll: &HyperLogLog<i64>) -> (u8, Vec<u8>) {
+    let mut bytes = Vec::new();
+    hll.save(&mut bytes).expect("in-memory serialization cannot fail");
+    let p = bytes[HLL_HEADER - 1];
+    (p, bytes[HLL_HEADER + 8..].to_vec())
+}
+
+// rebuild an HLL from its precision and registers
+pub fn hll_from_registers(p: u8, registers: &[u8]) -> io::Result<HyperLogLog<i64>> {
+    if !(4..=16).contains(&p) || registers.len() != 1usize << p {
+        return Err(invalid(format!(
+            "invalid HLL: p={} with {} registers",
+            p,
+            registers.len()
+        )));
+    }
+    let mut bytes = Vec::with_capacity(HLL_HEADER + 8 + registers.len());
+    bytes.extend_from_slice(&0f64.to_le_bytes());
+    bytes.extend_from_slice(&0u64.to_le_bytes());
+    bytes.extend_from_slice(&0f64.to_le_bytes());
+    bytes.push(p);
+    bytes.extend_from_slice(&(registers.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(registers);
+    HyperLogLog::load(bytes.as_slice())
+}
+
+pub fn write_hll<W: Write>(writer: &mut W, hll: &HyperLogLog<i64>) -> io::Result<()> {
+    let (p, registers) = hll_registers(hll);
+    let m = registers.len() as u64;
+    let zero = registers.iter().filter(|&&r| r == 0).count() as u64;
+    let sum: f64 = registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+    let set = m - zero;
+    if set * 3 < m {
+        // sparse, an alpha of 0 marks it
+        writer.write_all(&0f64.to_le_bytes())?;
+        writer.write_all(&zero.to_le_bytes())?;
+        writer.write_all(&sum.to_le_bytes())?;
+        writer.write_all(&[p])?;
+        writer.write_all(&set.to_le_bytes())?;
+        for (i, &r) in registers.iter().enumerate().filter(|(_, &r)| r != 0) {
+            writer.write_all(&(i as u16).to_le_bytes())?;
+            writer.write_all(&[r])?;
+        }
+        return Ok(());
+    }
+    let alpha = match p {
+        4 => 0.673,
+        5 => 0.697,
+        6 => 0.709,
+        _ => 0.7213 / (1.0 + 1.079 / m as f64),
+    };
+    writer.write_all(&f64::to_le_bytes(alpha))?;
+    writer.write_all(&zero.to_le_bytes())?;
+    writer.write_all(&sum.to_le_bytes())?;
+    writer.write_all(&[p])?;
+    writer.write_all(&m.to_le_bytes())?;
+    writer.write_all(&registers)
+}
+
+pub fn read_hll<R: Read>(reader: &mut R) -> io::Result<HyperLogLog<i64>> {
+    let mut header = [0u8; HLL_HEADER];
+    reader.read_exact(&mut header)?;
+    let p = header[HLL_HEADER - 1];
+    if header[..8] == 0f64.to_le_bytes() {
+        return read_sparse_hll(reader, p);
+    }
+    let m = read_u64(reader)?;
+    if m > 1 << 16 {
+        return Err(invalid(format!("invalid HLL register count {}", m)));
+    }
+    let mut registers = vec![0u8; m as usize];
+    reader.read_exact(&mut registers)?;
+    hll_from_registers(p, &registers)
+}
+
+fn read_sparse_hll<R: Read>(reader: &mut R, p: u8) -> io::Result<HyperLogLog<i64>> {
+    if !(4..=16).contains(&p) {
+        return Err(invalid(format!("invalid sparse HLL: p={}", p)));
+    }
+    let m = 1usize << p;
+    let n = read_u64(reader)?;
+    if n > m as u64 {
+        return Err(invalid(format!("sparse HLL with {} of {} registers set", n, m)));
+    }
+    let mut registers = vec![0u8; m];
+    let mut pair = [0u8; 3];
+    for _ in 0..n {
+        reader.read_exact(&mut pair)?;
+        let index = u16::from_le_bytes([pair[0], pair[1]]) as usize;
+        if index >= m {
+            return Err(invalid(format!("sparse HLL register {} out of {}", index, m)));
+        }
+        registers[index] = pair[2];
+    }
+    hll_from_registers(p, &registers)
+}
+
+// top bit of the ULL register count, set when a martingale estimate follows the registers
+const ULL_MARTINGALE: u64 = 1 << 63;
+
+pub fn write_ull<W: Write>(writer: &mut W, ull: &UltraLogLog, martingale: Option<f64>) -> io::Result<()> {
+    let state = ull.get_state();
+    let flag = if martingale.is_some() { ULL_MARTINGALE } else { 0 };
+    writer.write_all(&(state.len() as u64 | flag).to_le_bytes())?;
+    writer.write_all(state)?;
+    if let Some(estimate) = martingale {
+        writer.write_all(&estimate.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn read_ull<R: Read>(reader: &mut R) -> io::Result<(UltraLogLog, Option<f64>)> {
+    let header = read_u64(reader)?;
+    let m = header & !ULL_MARTINGALE;
+    if m > 1 << 26 {
+        return Err(invalid(format!("invalid ULL register count {}", m)));
+    }
+    let mut state = vec![0u8; m as usize];
+    reader.read_exact(&mut state)?;
+    let martingale = if header & ULL_MARTINGALE != 0 {
+        Some(read_f64(reader)?)
+    } else {
+        None
+    };
+    let ull = UltraLogLog::wrap(state).map_err(|e| invalid(e.to_string()))?;
+    Ok((ull, martingale))
+}
+
+// size parameter and ascending hash list, shared by fmh and minhash
+fn write_hash_list<W: Write>(writer: &mut W, size: u64, hashes: &BTreeSet<u64>) -> io::Result<()> {
+    writer.write_all(&size.to_le_bytes())?;
+    writer.write_all(&(hashes.len() as u64).to_le_bytes())?;
+    for hash in hashes {
+        writer.write_all(&hash.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_hash_list<R: Read>(reader: &mut R, what: &str) -> io::Result<(u64, BTreeSet<u64>)> {
+    let size = read_u64(reader)?;
+    if size == 0 {
+        return Err(invalid(format!("invalid {} size parameter 0", what)));
+    }
+    let n = read_u64(reader)?;
+    if n > 1 << 32 {
+        return Err(invalid(format!("invalid {} hash count {}", what, n)));
+    }
+    let mut hashes = BTreeSet::new();
+    let mut last = None;
+    for _ in 0..n {
+        let hash = read_u64(reader)?;
+        if last.is_some_and(|l| hash <= l) {
+            return Err(invalid(format!("{} hashes are not in ascending order", what)));
+        }
+        last = Some(hash);
+        hashes.insert(hash);
+    }
+    Ok((size, hashes))
+}
+
+pub fn write_fmh<W: Write>(writer: &mut W, fmh: &FracMinHash) -> io::Result<()> {
+    write_hash_list(writer, fmh.scaled, &fmh.hashes)
+}
+
+pub fn read_fmh<R: Read>(reader: &mut R) -> io::Result<FracMinHash> {
+    let (scaled, hashes) = read_hash_list(reader, "FracMinHash")?;
+    Ok(FracMinHash { scaled, hashes })
+}
+
+pub fn write_minhash<W: Write>(writer: &mut W, minhash: &MinHash) -> io::Result<()> {
+    write_hash_list(writer, minhash.sketch_size as u64, &minhash.hashes)
+}
+
+pub fn read_minhash<R: Read>(reader: &mut R) -> io::Result<MinHash> {
+    let (sketch_size, hashes) = read_hash_list(reader, "MinHash")?;
+    if hashes.len() as u64 > sketch_size {
+        return Err(invalid(format!("MinHash with {} hashes for sketch size {}", hashes.len(), sketch_size)));
+    }
+    Ok(MinHash { sketch_size: sketch_size as usize, hashes })
+}
+
+pub fn write_theta<W: Write>(writer: &mut W, theta: &Theta) -> io::Result<()> {
+    write_hash_list(writer, theta.sketch_size as u64, &theta.hashes)?;
+    writer.write_all(&theta.theta.to_le_bytes())
+}
+
+pub fn read_theta<R: Read>(reader: &mut R) -> io::Result<Theta> {
+    let (sketch_size, hashes) = read_hash_list(reader, "Theta")?;
+    let theta = read_u64(reader)?;
+    if hashes.len() as u64 > sketch_size || hashes.last().is_some_and(|&h| h >= theta) {
+        return Err(invalid(format!("Theta sketch with {} hashes inconsistent with its theta", hashes.len())));
+    }
+    Ok(Theta { sketch_size: sketch_size as usize, theta, hashes })
+}
+
+pub fn write_hyperbitbit<W: Write>(writer: &mut W, hbb: &HyperBitBit) -> io::Result<()> {
+    writer.write_all(&[hbb.level])?;
+    writer.write_all(&hbb.sketch.to_le_bytes())?;
+    writer.write_all(&hbb.sketch2.to_le_bytes())
+}
+
+pub fn read_hyperbitbit<R: Read>(reader: &mut R) -> io::Result<HyperBitBit> {
+    let mut level = [0u8; 1];
+    reader.read_exact(&mut level)?;
+    if !(5..=58).contains(&level[0]) {
+        return Err(invalid(format!("invalid HyperBitBit level {}", level[0])));
+    }
+    Ok(HyperBitBit { level: level[0], sketch: read_u64(reader)?, sketch2: read_u64(reader)? })
+}
+
+pub fn write_ordermh<W: Write>(writer: &mut W, signature: &OrderSignature) -> io::Result<()> {
+    writer.write_all(&(signature.values.len() as u64).to_le_bytes())?;
+    writer.write_all(&signature.kmers.to_le_bytes())?;
+    for value in &signature.values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn read_ordermh<R: Read>(reader: &mut R) -> io::Result<OrderSignature> {
+    let m = read_u64(reader)?;
+    if !(1..=1 << 20).contains(&m) {
+        return Err(invalid(format!("invalid OrderMinHash sketch size {}", m)));
+    }
+    let kmers = read_u64(reader)?;
+    let values = (0..m).map(|_| read_u64(reader)).collect::<io::Result<_>>()?;
+    Ok(OrderSignature { kmers, values })
+}
+
+pub fn write_weighted<W: Write>(writer: &mut W, signature: &Signature) -> io::Result<()> {
+    writer.write_all(&(signature.values.len() as u64).to_le_bytes())?;
+    for hash in &signature.hashes {
+        writer.write_all(&hash.to_le_bytes())?;
+    }
+    for value in &signature.values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn read_weighted<R: Read>(reader: &mut R) -> io::Result<Signature> {
+    let m = read_u64(reader)?;
+    if !(2..=1 << 24).contains(&m) {
+        return Err(invalid(format!("invalid weighted sketch size {}", m)));
+    }
+    let mut signature = Signature::empty(m as usize);
+    for hash in signature.hashes.iter_mut() {
+        *hash = read_u64(reader)?;
+    }
+    for value in signature.values.iter_mut() {
+        *value = read_f64(reader)?;
+    }
+    Ok(signature)
+}
+
+pub fn write_ehll<W: Write>(writer: &mut W, sketch: &ExtendedHll) -> io::Result<()> {
+    writer.write_all(&[sketch.p])?;
+    writer.write_all(&sketch.registers)
+}
+
+pub fn read_ehll<R: Read>(reader: &mut R) -> io::Result<ExtendedHll> {
+    let mut p = [0u8; 1];
+    reader.read_exact(&mut p)?;
+    if !(4..=16).contains(&p[0]) {
+        return Err(invalid(format!("invalid EHLL precision {}", p[0])));
+    }
+    let mut registers = vec![0u8; 1 << p[0]];
+    reader.read_exact(&mut registers)?;
+    Ok(ExtendedHll::from_registers(p[0], registers))
+}
+
+pub fn write_superminhash<W: Write>(writer: &mut W, values: &[f64]) -> io::Result<()> {
+    writer.write_all(&(values.len() as u64).to_le_bytes())?;
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn read_superminhash<R: Read>(reader: &mut R) -> io::Result<Vec<f64>> {
+    let m = read_u64(reader)?;
+    if !(2..=1 << 24).contains(&m) {
+        return Err(invalid(format!("invalid SuperMinHash sketch size {}", m)));
+    }
+    (0..m).map(|_| read_f64(reader)).collect()
+}
+
+pub fn write_countmin<W: Write>(writer: &mut W, sketch: &CountMin) -> io::Result<()> {
+    writer.write_all(&(DEPTH as u64).to_le_bytes())?;
+    writer.write_all(&(sketch.width as u64).to_le_bytes())?;
+    for counter in &sketch.table {
+        writer.write_all(&counter.to_le_bytes())?;
+    }
+    writer.write_all(&(sketch.spectrum.len() as u64).to_le_bytes())?;
+    for bin in &sketch.spectrum {
+        writer.write_all(&bin.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn read_countmin<R: Read>(reader: &mut R) -> io::Result<CountMin> {
+    let depth = read_u64(reader)?;
+    let width = read_u64(reader)?;
+    if depth != DEPTH as u64 || width == 0 || width > 1 << 32 {
+        return Err(invalid(format!("invalid Count-Min sketch: {} x {}", depth, width)));
+    }
+    let mut bytes = vec![0u8; DEPTH * width as usize * 4];
+    reader.read_exact(&mut bytes)?;
+    let table = bytes
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes(b.try_into().expect("4 bytes")))
+        .collect();
+    let n = read_u64(reader)?;
+    if n > crate::countmin::MAX_ABUNDANCE as u64 + 1 {
+        return Err(invalid(format!("invalid Count-Min spectrum of {} bins", n)));
+    }
+    let spectrum = (0..n).map(|_| read_u64(reader)).collect::<io::Result<Vec<u64>>>()?;
+    Ok(CountMin { width: width as usize, table, spectrum })
+}
+
+// The layout above is checked two ways: every sketch type written, read back and
+// written again gives the same bytes, and hand-built little-endian fixtures read
+// to the values they encode and are written back byte for byte. The fixtures are
+// byte literals, so the same checks hold on a big-endian host (the big-endian CI
+// job runs `cross test --target s390x-unknown-linux-gnu`), and a change in how the sketch
+// libraries serialize (bincode for HLL) fails here rather than in a sketch set.
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::dual::HmhHll;
+    use crate::ordermh::OrderMinHash;
+    use crate::superminhash::SuperMinHash;
+    use crate::ull::UllSketch;
+    use crate::utils::KmerSketch;
+    use crate::weighted::{DartMinHash, ProbMinHash};
+
+    const SEED: u64 = 42;
+
+    fn filled<S: KmerSketch>(precision: Option<u32>, kmers: u64) -> S {
+        let mut sketch = S::new(precision);
+        for kmer in 0..kmers {
+            sketch.add_kmer(kmer.wrapping_mul(0x9e37_79b9_7f4a_7c15), SEED);
+        }
+        sketch
+    }
+
+    // bytes of `sketch`, checked to be written again unchanged once read back
+    fn round_trip<S: KmerSketch>(sketch: &S) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        sketch.save(&mut bytes).unwrap();
+        let mut reader = bytes.as_slice();
+        let loaded = S::load(&mut reader).unwrap();
+        assert!(reader.is_empty(), "{} bytes left unread", reader.len());
+        let mut again = Vec::new();
+        loaded.save(&mut again).unwrap();
+        assert_eq!(bytes, again);
+        assert_eq!(sketch.registers(), loaded.registers());
+        bytes
+    }
+
+    #[test]
+    fn every_sketch_type_round_trips() {
+        round_trip(&filled::<Sketch>(None, 1000));
+        round_trip(&filled::<HyperLogLog<i64>>(Some(10), 5000));
+        round_trip(&filled::<HyperLogLog<i64>>(Some(10), 20));
+        round_trip(&filled::<UllSketch>(Some(10), 5000));
+        round_trip(&UllSketch::from_parts(filled::<UllSketch>(Some(10), 5000).sketch, None));
+        round_trip(&filled::<HmhHll>(Some(10), 1000));
+        round_trip(&filled::<ExtendedHll>(Some(10), 1000));
+        round_trip(&filled::<FracMinHash>(Some(10), 1000));
+        round_trip(&filled::<MinHash>(Some(100), 1000));
+        round_trip(&filled::<Theta>(Some(100), 1000));
+        round_trip(&filled::<HyperBitBit>(None, 1000));
+        round_trip(&filled::<SuperMinHash>(Some(64), 1000));
+        round_trip(&filled::<OrderMinHash>(Some(64), 1000));
+        round_trip(&filled::<ProbMinHash>(Some(64), 1000));
+        round_trip(&filled::<DartMinHash>(Some(64), 1000));
+    }
+
+    #[test]
+    fn countmin_round_trips() {
+        let mut counts = CountMin::new(64);
+        for kmer in 0..500 {
+            counts.add_kmer(kmer % 97, SEED);
+        }
+        let mut bytes = Vec::new();
+        write_countmin(&mut bytes, &counts).unwrap();
+        let loaded = read_countmin(&mut bytes.as_slice()).unwrap();
+        let mut again = Vec::new();
+        write_countmin(&mut again, &loaded).unwrap();
+        assert_eq!(bytes, again);
+    }
+
+    // reads `fixture` with `read`, checks the value, and writes it back unchanged
+    fn check_fixture<T>(
+        fixture: &[u8],
+        read: impl Fn(&mut Cursor<Vec<u8>>) -> io::Result<T>,
+        write: impl Fn(&mut Vec<u8>, &T) -> io::Result<()>,
+        check: impl Fn(&T),
+    ) {
+        let mut reader = Cursor::new(fixture.to_vec());
+        let value = read(&mut reader).unwrap();
+        assert_eq!(reader.position(), fixture.len() as u64, "bytes left unread");
+        check(&value);
+        let mut bytes = Vec::new();
+        write(&mut bytes, &value).unwrap();
+        assert_eq!(bytes, fixture);
+    }
+
+    #[test]
+    fn hll_dense_fixture() {
+        let registers: Vec<u8> = (0..16).map(|i| i % 4 + 1).collect();
+        let sum: f64 = registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let mut fixture = vec![];
+        fixture.extend_from_slice(&[0xf0, 0xa7, 0xc6, 0x4b, 0x37, 0x89, 0xe5, 0x3f]); // alpha 0.673
+        fixture.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]); // no zero registers
+        fixture.extend_from_slice(&sum.to_bits().to_le_bytes());
+        fixture.push(4); // p
+        fixture.extend_from_slice(&[16, 0, 0, 0, 0, 0, 0, 0]); // m
+        fixture.extend_from_slice(&registers);
+        check_fixture(&fixture, read_hll::<Cursor<Vec<u8>>>, write_hll::<Vec<u8>>, |hll| {
+            assert_eq!(hll_registers(hll), (4, registers.clone()));
+        });
+    }
+
+    #[test]
+    fn hll_sparse_fixture() {
+        let sum = 14.0 + 0.5 + 0.125;
+        let mut fixture = vec![0, 0, 0, 0, 0, 0, 0, 0]; // alpha 0: sparse
+        fixture.extend_from_slice(&[14, 0, 0, 0, 0, 0, 0, 0]); // zero registers
+        fixture.extend_from_slice(&f64::to_le_bytes(sum));
+        fixture.push(4); // p
+        fixture.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 0]); // registers set
+        fixture.extend_from_slice(&[3, 0, 1]); // register 3 = 1
+        fixture.extend_from_slice(&[0x0b, 0, 3]); // register 11 = 3
+        check_fixture(&fixture, read_hll::<Cursor<Vec<u8>>>, write_hll::<Vec<u8>>, |hll| {
+            let (p, registers) = hll_registers(hll);
+            assert_eq!(p, 4);
+            assert_eq!(registers.iter().enumerate().filter(|(_, &r)| r != 0).collect::<Vec<_>>(), [(3, &1), (11, &3)]);
+        });
+    }
+
+    // `hll_registers` takes the registers from the bincode serialization of the
+    // streaming_algorithms HLL at a fixed offset: alpha, zero, sum, p, then the
+    // register vector with its u64 length
+    #[test]
+    fn hll_bincode_offset() {
+        let registers: Vec<u8> = (0..64).map(|i| (i * 7 % 11) as u8).collect();
+        let hll = hll_from_registers(6, &registers).unwrap();
+        let mut bytes = Vec::new();
+        hll.save(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), HLL_HEADER + 8 + 64);
+        assert_eq!(bytes[HLL_HEADER - 1], 6);
+        assert_eq!(bytes[HLL_HEADER..HLL_HEADER + 8], [64, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bytes[HLL_HEADER + 8..], registers[..]);
+        assert_eq!(hll_registers(&hll), (6, registers));
+    }
+
+    #[test]
+    fn hmh_fixture() {
+        let mut fixture = vec![0u8; 2 << 14];
+        fixture[..2].copy_from_slice(&[0x02, 0x01]);
+        fixture[2 * 9..2 * 9 + 2].copy_from_slice(&[0x34, 0x12]);
+        check_fixture(&fixture, read_hmh::<Cursor<Vec<u8>>>, write_hmh::<Vec<u8>>, |hmh| {
+            let registers = hmh.registers();
+            assert_eq!((registers[0], registers[9], registers[1]), (0x0102, 0x1234, 0));
+        });
+    }
+
+    #[test]
+    fn ull_fixtures() {
+        let state: Vec<u8> = vec![0, 4, 8, 9, 0, 12, 0, 5];
+        let mut plain = vec![8, 0, 0, 0, 0, 0, 0, 0];
+        plain.extend_from_slice(&state);
+        check_fixture(&plain, read_ull::<Cursor<Vec<u8>>>, |w, (ull, m)| write_ull(w, ull, *m), |(ull, m)| {
+            assert_eq!(ull.get_state(), &state[..]);
+            assert_eq!(*m, None);
+        });
+        let mut with_martingale = vec![8, 0, 0, 0, 0, 0, 0, 0x80];
+        with_martingale.extend_from_slice(&state);
+        with_martingale.extend_from_slice(&[0, 0, 0, 0, 0, 0x40, 0x5f, 0x40]); // 125.0
+        check_fixture(&with_martingale, read_ull::<Cursor<Vec<u8>>>, |w, (ull, m)| write_ull(w, ull, *m), |(ull, m)| {
+            assert_eq!(ull.get_state(), &state[..]);
+            assert_eq!(*m, Some(125.0));
+        });
+    }
+
+    #[test]
+    fn hash_list_fixtures() {
+        let mut fixture = vec![0xe8, 0x03, 0, 0, 0, 0, 0, 0]; // 1000
+        fixture.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 0]);
+        fixture.extend_from_slice(&[0x01, 0, 0, 0, 0, 0, 0, 0]);
+        fixture.extend_from_slice(&[0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]);
+        let hashes = BTreeSet::from([1, 0x0102_0304_0506_0708]);
+        check_fixture(&fixture, read_fmh::<Cursor<Vec<u8>>>, write_fmh::<Vec<u8>>, |fmh| {
+            assert_eq!((fmh.scaled, &fmh.hashes), (1000, &hashes));
+        });
+        check_fixture(&fixture, read_minhash::<Cursor<Vec<u8>>>, write_minhash::<Vec<u8>>, |mh| {
+            assert_eq!((mh.sketch_size, &mh.hashes), (1000, &hashes));
+        });
+        let mut theta = fixture.clone();
+        theta.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0x02]);
+        check_fixture(&theta, read_theta::<Cursor<Vec<u8>>>, write_theta::<Vec<u8>>, |t| {
+            assert_eq!((t.sketch_size, t.theta, &t.hashes), (1000, 0x0200_0000_0000_0000, &hashes));
+        });
+    }
+
+    #[test]
+    fn hyperbitbit_fixture() {
+        let mut fixture = vec![7];
+        fixture.extend_from_slice(&[0xff, 0, 0, 0, 0, 0, 0, 0x80]);
+        fixture.extend_from_slice(&[0x01, 0x02, 0, 0, 0, 0, 0, 0]);
+        check_fixture(&fixture, read_hyperbitbit::<Cursor<Vec<u8>>>, write_hyperbitbit::<Vec<u8>>, |h| {
+            assert_eq!((h.level, h.sketch, h.sketch2), (7, 0x8000_0000_0000_00ff, 0x0201));
+        });
+    }
+
+    #[test]
+    fn ordermh_fixture() {
+        let mut fixture = vec![2, 0, 0, 0, 0, 0, 0, 0];
+        fixture.extend_from_slice(&[0x10, 0x27, 0, 0, 0, 0, 0, 0]); // 10000 k-mers
+        fixture.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        fixture.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        check_fixture(&fixture, read_ordermh::<Cursor<Vec<u8>>>, write_ordermh::<Vec<u8>>, |s| {
+            assert_eq!((s.kmers, &s.values[..]), (10000, &[1, 0][..]));
+        });
+    }
+
+    #[test]
+    fn weighted_fixture() {
+        let mut fixture = vec![2, 0, 0, 0, 0, 0, 0, 0];
+        fixture.extend_from_slice(&[0xaa, 0, 0, 0, 0, 0, 0, 0]);
+        fixture.extend_from_slice(&[0xbb, 0, 0, 0, 0, 0, 0, 0]);
+        fixture.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0xe0, 0x3f]); // 0.5
+        fixture.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0xf0, 0x7f]); // infinity
+        check_fixture(&fixture, read_weighted::<Cursor<Vec<u8>>>, write_weighted::<Vec<u8>>, |s| {
+            assert_eq!(s.hashes, [0xaa, 0xbb]);
+            assert_eq!(s.values, [0.5, f64::INFINITY]);
+        });
+    }
+
+    #[test]
+    fn ehll_fixture() {
+        let mut fixture = vec![4];
+        fixture.extend((0..16).map(|i| (i as u8) << 1 | (i as u8 & 1)));
+        check_fixture(&fixture, read_ehll::<Cursor<Vec<u8>>>, write_ehll::<Vec<u8>>, |e| {
+            assert_eq!((e.p, e.registers[5]), (4, 0x0b));
+        });
+    }
+
+    #[test]
+    fn superminhash_fixture() {
+        let mut fixture = vec![2, 0, 0, 0, 0, 0, 0, 0];
+        fixture.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0xf0, 0x3f]); // 1.0
+        fixture.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0x04, 0x40]); // 2.5
+        check_fixture(&fixture, read_superminhash::<Cursor<Vec<u8>>>, |w, v| write_superminhash(w, v), |v| {
+            assert_eq!(v[..], [1.0, 2.5]);
+        });
+    }
+
+    #[test]
+    fn countmin_fixture() {
+        let mut fixture = vec![4, 0, 0, 0, 0, 0, 0, 0]; // depth
+        fixture.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 0]); // width
+        for counter in 0u8..8 {
+            fixture.extend_from_slice(&[counter, 0, 0, 0x01]);
+        }
+        fixture.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        fixture.extend_from_slice(&[0x05, 0x01, 0, 0, 0, 0, 0, 0]);
+        check_fixture(&fixture, read_countmin::<Cursor<Vec<u8>>>, write_countmin::<Vec<u8>>, |c| {
+            assert_eq!(c.width, 2);
+            assert_eq!(c.table[3], 0x0100_0003);
+            assert_eq!(c.spectrum, [0x105]);
+        });
+    }
+}
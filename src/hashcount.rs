@@ -0,0 +1,163 @@
+// src/hashcount.rs
+// Exact k-mer counter for `--exact-counts` (built with `--features exact-counts`):
+// an open-addressing hash table with Robin Hood probing.
+// The top `q` bits of a k-mer hash pick its home slot and the next `r` bits are
+// stored there with a 32-bit count, 12 bytes a slot. Counts are exact up to
+// collisions of q + r = 48 hash bits, so `--min-count` thresholds hold for deep
+// metagenomes where a Bloom filter saturates. The table starts at 2^16 slots and
+// doubles when 90% full, one bit moving from the remainders to the quotient
+// without re-reading the k-mers, so it takes 13 to 27 bytes per distinct k-mer.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+// largest filter of the run in bytes, for the summary printed after sketching
+pub static PEAK_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+const INITIAL_QUOTIENT_BITS: u32 = 16;
+const MAX_LOAD: f64 = 0.9;
+
+#[derive(Clone, Copy, Default)]
+struct Slot {
+    remainder: u32,
+    // 0 for an empty slot
+    count: u32,
+    // distance from the home slot
+    shift: u16,
+}
+
+pub struct HashCounter {
+    q: u32,
+    // remainder bits still stored, 32 less the bits moved into the quotient
+    r: u32,
+    slots: Vec<Slot>,
+    len: usize,
+    seed: u64,
+}
+
+impl HashCounter {
+    // grown with the distinct k-mers counted, not sized from the input: reads of a
+    // deep sample hold far fewer distinct k-mers than bases
+    pub fn new(seed: u64) -> Self {
+        let counter = HashCounter {
+            q: INITIAL_QUOTIENT_BITS,
+            r: 32,
+            slots: vec![Slot::default(); 1 << INITIAL_QUOTIENT_BITS],
+            len: 0,
+            seed: seed ^ 0x6371_6600_0000_0000,
+        };
+        PEAK_BYTES.fetch_max(counter.memory(), Ordering::Relaxed);
+        counter
+    }
+
+    pub fn memory(&self) -> usize {
+        self.slots.len() * std::mem::size_of::<Slot>()
+    }
+
+    fn split(&self, hash: u64) -> (usize, u32) {
+        let home = (hash >> (64 - self.q)) as usize;
+        let remainder = ((hash << self.q) >> (64 - self.r)) as u32;
+        (home, remainder)
+    }
+
+    // count one more occurrence of a k-mer, returning its count
+    pub fn increment(&mut self, masked: u64) -> u32 {
+        let hash = xxh3_64_with_seed(&masked.to_le_bytes(), self.seed);
+        loop {
+            let (home, remainder) = self.split(hash);
+            match self.insert(home, remainder, 1) {
+                Some(count) => return count,
+                None => self.grow(),
+            }
+        }
+    }
+
+    // Robin Hood insertion: an entry passes entries closer to their own home; None
+    // when the table is too full
+    fn insert(&mut self, home: usize, remainder: u32, count: u32) -> Option<u32> {
+        if self.len as f64 >= MAX_LOAD * self.slots.len() as f64 {
+            return None;
+        }
+        let mask = self.slots.len() - 1;
+        let mut entry = Slot { remainder, count, shift: 0 };
+        let mut i = home;
+        let mut placed: Option<u32> = None;
+        loop {
+            let slot = self.slots[i];
+            if slot.count == 0 {
+                self.slots[i] = entry;
+                self.len += 1;
+                return Some(placed.unwrap_or(entry.count));
+            }
+            if placed.is_none() && slot.shift == entry.shift && slot.remainder == entry.remainder {
+                self.slots[i].count = slot.count.saturating_add(entry.count);
+                return Some(self.slots[i].count);
+            }
+            if slot.shift < entry.shift {
+                // the displaced entry moves on; only the first one is the k-mer counted
+                self.slots[i] = entry;
+                placed.get_or_insert(entry.count);
+                entry = slot;
+            }
+            entry.shift += 1;
+            i = (i + 1) & mask;
+        }
+    }
+
+    // one more quotient bit, taken from the top of the remainders
+    fn grow(&mut self) {
+        assert!(self.r > 8, "k-mer counter out of remainder bits");
+        let old = std::mem::take(&mut self.slots);
+        let mask = old.len() - 1;
+        self.q += 1;
+        self.r -= 1;
+        self.slots = vec![Slot::default(); 1 << self.q];
+        self.len = 0;
+        PEAK_BYTES.fetch_max(self.memory(), Ordering::Relaxed);
+        for (i, slot) in old.into_iter().enumerate().filter(|(_, s)| s.count > 0) {
+            let home = (i.wrapping_sub(slot.shift as usize)) & mask;
+            let new_home = (home << 1) | (slot.remainder >> self.r) as usize;
+            let remainder = slot.remainder & ((1u32 << self.r) - 1);
+            self.insert(new_home, remainder, slot.count).expect("a doubled table has room");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn increment_counts_each_kmer() {
+        let mut counter = HashCounter::new(42);
+        for round in 1..=3 {
+            for kmer in 0..1000u64 {
+                assert_eq!(counter.increment(kmer * 7919), round);
+            }
+        }
+        assert_eq!(counter.len, 1000);
+        assert_eq!(counter.increment(1), 1);
+    }
+
+    #[test]
+    fn grow_keeps_counts() {
+        let mut counter = HashCounter::new(7);
+        // k-mer i seen i % 4 + 1 times, 300000 of them doubling the table three times
+        let kmers = 300_000u64;
+        for kmer in 0..kmers {
+            for _ in 0..=kmer % 4 {
+                counter.increment(kmer);
+            }
+        }
+        assert_eq!(counter.q, INITIAL_QUOTIENT_BITS + 3);
+        assert_eq!(counter.q + counter.r, INITIAL_QUOTIENT_BITS + 32);
+        assert_eq!(counter.len as u64, kmers);
+        counter.grow();
+        assert_eq!(counter.len as u64, kmers);
+        for kmer in 0..kmers {
+            assert_eq!(counter.increment(kmer), kmer as u32 % 4 + 2, "k-mer {}", kmer);
+        }
+        assert_eq!(counter.memory(), 12 << (INITIAL_QUOTIENT_BITS + 4));
+    }
+}
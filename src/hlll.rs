@@ -0,0 +1,116 @@
+// src/hlll.rs
+// Compressed HLL registers for `dist --compact`, after HyperLogLogLog (Karppa &
+// Pagh 2022): registers of a filled sketch sit within a few ranks of each other, so
+// each is stored as a 3-bit offset from a per-sketch base and the few that do not
+// fit are kept as exceptions. Unlike HyperLogLogLog nothing is rounded, registers
+// come back exactly, in about 3 bits each instead of 8. Sketches stay compressed
+// while loaded and are expanded one comparison at a time.
+
+use std::io;
+
+use streaming_algorithms::HyperLogLog;
+
+use crate::format;
+
+// offsets 0..=6 are stored in place, 7 marks an exception
+const ESCAPE: u8 = 7;
+
+// 3-bit offsets per u64 word, none straddles two words
+const PER_WORD: usize = 21;
+
+#[derive(Clone)]
+pub struct CompactHll {
+    p: u8,
+    base: u8,
+    packed: Vec<u64>,
+    // (register index, value) of the registers outside base..base + 7, by index
+    exceptions: Vec<(u16, u8)>,
+}
+
+impl CompactHll {
+    pub fn compress(hll: &HyperLogLog<i64>) -> Self {
+        let (p, registers) = format::hll_registers(hll);
+        let mut histogram = [0usize; 66];
+        for &r in &registers {
+            histogram[(r as usize).min(65)] += 1;
+        }
+        // the base leaving the most registers in place
+        let base = (0..=65 - ESCAPE as usize)
+            .max_by_key(|&b| (histogram[b..b + ESCAPE as usize].iter().sum::<usize>(), std::cmp::Reverse(b)))
+            .unwrap_or(0) as u8;
+        let mut packed = vec![0u64; registers.len().div_ceil(PER_WORD)];
+        let mut exceptions = Vec::new();
+        for (i, &r) in registers.iter().enumerate() {
+            let offset = if r >= base && r - base < ESCAPE {
+                r - base
+            } else {
+                exceptions.push((i as u16, r));
+                ESCAPE
+            };
+            packed[i / PER_WORD] |= (offset as u64) << (3 * (i % PER_WORD));
+        }
+        CompactHll { p, base, packed, exceptions }
+    }
+
+    pub fn registers(&self) -> Vec<u8> {
+        let mut registers = vec![0u8; 1 << self.p];
+        self.max_into(&mut registers);
+        registers
+    }
+
+    // registers[i] = max(registers[i], register i of this sketch)
+    pub fn max_into(&self, registers: &mut [u8]) {
+        let mut exceptions = self.exceptions.iter().peekable();
+        for (i, target) in registers.iter_mut().enumerate() {
+            let offset = ((self.packed[i / PER_WORD] >> (3 * (i % PER_WORD))) & 0b111) as u8;
+            let r = if offset == ESCAPE {
+                let &(_, r) = exceptions.next().expect("one exception per escaped register");
+                r
+            } else {
+                self.base + offset
+            };
+            *target = (*target).max(r);
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> io::Result<HyperLogLog<i64>> {
+        let mut registers = self.registers();
+        other.max_into(&mut registers);
+        format::hll_from_registers(self.p, &registers)
+    }
+}
+
+// the registers of a loaded sketch, as read or compressed
+pub enum HllRegisters {
+    Plain(HyperLogLog<i64>),
+    Compact(CompactHll),
+}
+
+impl HllRegisters {
+    pub fn new(hll: HyperLogLog<i64>, compact: bool) -> Self {
+        if compact {
+            HllRegisters::Compact(CompactHll::compress(&hll))
+        } else {
+            HllRegisters::Plain(hll)
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> io::Result<HyperLogLog<i64>> {
+        match (self, other) {
+            (HllRegisters::Plain(a), HllRegisters::Plain(b)) => {
+                let mut union = a.clone();
+                union.union(b);
+                Ok(union)
+            }
+            (HllRegisters::Compact(a), HllRegisters::Compact(b)) => a.union(b),
+            (a, b) => HllRegisters::Plain(a.expand()?).union(&HllRegisters::Plain(b.expand()?)),
+        }
+    }
+
+    fn expand(&self) -> io::Result<HyperLogLog<i64>> {
+        match self {
+            HllRegisters::Plain(hll) => Ok(hll.clone()),
+            HllRegisters::Compact(c) => format::hll_from_registers(c.p, &c.registers()),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+// src/hyperbitbit.rs
+// HyperBitBit (Sedgewick): two 64-bit words and a shared level, 136 bits per
+// genome. A k-mer sets bit (hash mod 64) of the first word when its rank exceeds
+// the level and of the second when it exceeds level + 1; once half of the first
+// word is set, the level goes up and the second word takes its place.
+//
+// Meant for collections too large for even p=10 HLLs. Cardinalities can be off by
+// a third; Jaccard is estimated from the overlap of the two words at a shared
+// level, which is coarse too, see `ERROR_NOTE`.
+
+use std::error::Error;
+
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+use crate::format;
+use crate::utils::KmerSketch;
+
+// recorded as "expected_error" in `_parameters.json` of hyperbitbit sets
+pub const ERROR_NOTE: &str = "about as accurate as a p=6 HLL: cardinality off by up to ~40%, \
+distance off by ~0.02 at 5% divergence and ~0.05 at 10%, beyond 20% it only tells unrelated genomes apart";
+
+#[derive(Clone, Copy, Default)]
+pub struct HyperBitBit {
+    pub level: u8,
+    pub sketch: u64,
+    pub sketch2: u64,
+}
+
+impl HyperBitBit {
+    // level up while more than half of the first word is set
+    fn normalize(&mut self) {
+        while self.sketch.count_ones() > 31 && self.level < 58 {
+            self.sketch = self.sketch2;
+            self.sketch2 = 0;
+            self.level += 1;
+        }
+    }
+
+    // linear-counting sizes of self, other and their union from the first words at
+    // a shared level, in the same unit so the level scale cancels in ratios
+    fn overlap(&self, other: &Self) -> (f64, f64, f64) {
+        let level = self.level.max(other.level);
+        let (a, _) = self.at_level(level);
+        let (b, _) = other.at_level(level);
+        let count = |word: u64| -(1.0 - (word.count_ones() as f64).min(63.5) / 64.0).ln();
+        (count(a), count(b), count(a | b))
+    }
+
+    // (first, second) word of this sketch seen at `level`, bits below it are lost
+    fn at_level(&self, level: u8) -> (u64, u64) {
+        match level - self.level {
+            0 => (self.sketch, self.sketch2),
+            1 => (self.sketch2, 0),
+            _ => (0, 0),
+        }
+    }
+}
+
+impl KmerSketch for HyperBitBit {
+    fn new(_: Option<u32>) -> Self {
+        HyperBitBit { level: 5, sketch: 0, sketch2: 0 }
+    }
+
+    fn add_kmer(&mut self, masked: u64, seed: u64) {
+        self.add_hashed(masked, xxh3_64_with_seed(&masked.to_le_bytes(), seed), seed);
+    }
+
+    fn add_hashed(&mut self, _masked: u64, hash: u64, _seed: u64) {
+        let bit = 1u64 << (hash & 63);
+        let rank = (hash >> 6).leading_zeros().saturating_sub(6) as u8;
+        if rank > self.level {
+            self.sketch |= bit;
+            if rank > self.level + 1 {
+                self.sketch2 |= bit;
+            }
+            self.normalize();
+        }
+    }
+
+    fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        Ok(format::write_hyperbitbit(writer, self)?)
+    }
+
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        format::read_hyperbitbit(reader)
+    }
+
+    fn cardinality(&self) -> f64 {
+        if self.sketch == 0 && self.level == 5 {
+            return 0.0;
+        }
+        2f64.powf(self.level as f64 + 5.4 + self.sketch.count_ones() as f64 / 32.0)
+    }
+
+    fn merged(&self, other: &Self) -> Self {
+        let level = self.level.max(other.level);
+        let (a, a2) = self.at_level(level);
+        let (b, b2) = other.at_level(level);
+        let mut union = HyperBitBit { level, sketch: a | b, sketch2: a2 | b2 };
+        union.normalize();
+        union
+    }
+
+    fn jaccard(&self, other: &Self) -> f64 {
+        let (a, b, union) = self.overlap(other);
+        if union == 0.0 {
+            return 0.0;
+        }
+        ((a + b - union) / union).clamp(0.0, 1.0)
+    }
+
+    fn containment(&self, other: &Self) -> f64 {
+        let (a, b, union) = self.overlap(other);
+        if a == 0.0 {
+            return 0.0;
+        }
+        ((a + b - union) / a).clamp(0.0, 1.0)
+    }
+
+    fn registers(&self) -> Vec<u16> {
+        [self.sketch, self.sketch2]
+            .iter()
+            .flat_map(|word| (0..4).map(move |i| (word >> (16 * i)) as u16))
+            .collect()
+    }
+}
@@ -0,0 +1,424 @@
+// src/index.rs
+// `lash index`: banded LSH over the registers of a sketch set, so that a query is
+// only compared with the references sharing at least one band. Closely related
+// genomes have mostly equal registers; the chance that a whole band of `rows`
+// registers matches drops off quickly as they diverge.
+//
+// The index sits next to the sketches:
+//
+//   {prefix}_index.lsh   zstd stream, for every band: u64 bucket count, then per
+//                        bucket the u64 band hash, u32 member count, u32 members
+//   {prefix}_index.json  rows, bands, number of indexed sketches and the sketch
+//                        parameters the index was built from
+//
+// Bands whose registers are all zero (empty in every sketch type) are not indexed,
+// they would put every small genome in the same bucket.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use hashbrown::HashMap;
+use rayon::prelude::*;
+use serde_json::to_writer_pretty;
+use xxhash_rust::xxh3::xxh3_64;
+use zstd::stream::{Decoder, Encoder};
+
+use crate::pairwise::{sketch_distance, QueryHits, SketchSet};
+use crate::utils::{
+    check_same_params, find_files, load_sketches, metadata_header, read_names, read_params, with_sketch_type,
+    KmerSketch,
+};
+
+// layout version of `_index.lsh`
+pub const INDEX_VERSION: u32 = 1;
+
+// sketches decoded and hashed at a time while building
+const BUILD_CHUNK: usize = 4096;
+
+// default band width: HMH registers carry a b-bit MinHash and ULL registers two
+// extra bits next to the rank, plain HLL ranks match by chance more often
+pub fn default_rows(algorithm: &str) -> usize {
+    if algorithm == "hll" {
+        6
+    } else {
+        4
+    }
+}
+
+pub struct LshIndex {
+    // band -> band hash -> sketch indices
+    pub bands: Vec<HashMap<u64, Vec<u32>>>,
+}
+
+// hash of every band, None for an empty band; a trailing partial band is dropped
+pub fn band_keys(registers: &[u16], rows: usize) -> Vec<Option<u64>> {
+    registers
+        .chunks_exact(rows)
+        .map(|band| {
+            if band.iter().all(|&r| r == 0) {
+                return None;
+            }
+            let bytes: Vec<u8> = band.iter().flat_map(|r| r.to_le_bytes()).collect();
+            Some(xxh3_64(&bytes))
+        })
+        .collect()
+}
+
+fn build_with<S: KmerSketch + Sync>(
+    sketch_file: &str,
+    count: usize,
+    rows: usize,
+) -> Result<LshIndex, Box<dyn Error>> {
+    let mut decoder = Decoder::new(BufReader::new(File::open(sketch_file)?))?;
+    let mut index = LshIndex { bands: Vec::new() };
+    let mut next = 0u32;
+    while (next as usize) < count {
+        let chunk = BUILD_CHUNK.min(count - next as usize);
+        let mut sketches: Vec<S> = Vec::with_capacity(chunk);
+        for _ in 0..chunk {
+            sketches.push(S::load(&mut decoder)?);
+        }
+        let keys: Vec<Vec<Option<u64>>> = sketches
+            .par_iter()
+            .map(|s| band_keys(&s.registers(), rows))
+            .collect();
+        for sketch_keys in keys {
+            if index.bands.is_empty() {
+                index.bands = vec![HashMap::new(); sketch_keys.len()];
+            }
+            for (band, key) in index.bands.iter_mut().zip(sketch_keys) {
+                if let Some(key) = key {
+                    band.entry(key).or_default().push(next);
+                }
+            }
+            next += 1;
+        }
+    }
+    Ok(index)
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+impl LshIndex {
+    pub fn write(&self, file_name: &str, threads: u32) -> Result<(), Box<dyn Error>> {
+        let mut encoder = Encoder::new(BufWriter::new(File::create(file_name)?), 3)?;
+        encoder.multithread(threads)?;
+        for band in &self.bands {
+            encoder.write_all(&(band.len() as u64).to_le_bytes())?;
+            // sorted buckets, the same set always gives the same file
+            let mut buckets: Vec<(&u64, &Vec<u32>)> = band.iter().collect();
+            buckets.sort_unstable_by_key(|(key, _)| **key);
+            for (key, members) in buckets {
+                encoder.write_all(&key.to_le_bytes())?;
+                encoder.write_all(&(members.len() as u32).to_le_bytes())?;
+                for m in members {
+                    encoder.write_all(&m.to_le_bytes())?;
+                }
+            }
+        }
+        encoder.finish()?.flush()?;
+        Ok(())
+    }
+
+    pub fn read(file_name: &str, bands: usize, sketches: usize) -> Result<Self, Box<dyn Error>> {
+        let mut decoder = Decoder::new(BufReader::new(File::open(file_name)?))?;
+        let mut index = LshIndex { bands: Vec::with_capacity(bands) };
+        for _ in 0..bands {
+            let count = read_u64(&mut decoder)? as usize;
+            let mut band = HashMap::with_capacity(count);
+            for _ in 0..count {
+                let key = read_u64(&mut decoder)?;
+                let size = read_u32(&mut decoder)? as usize;
+                if size > sketches {
+                    return Err(format!("{}: bucket of {} members in an index of {} sketches", file_name, size, sketches).into());
+                }
+                let mut members = Vec::with_capacity(size);
+                for _ in 0..size {
+                    let m = read_u32(&mut decoder)?;
+                    if m as usize >= sketches {
+                        return Err(format!("{}: member {} out of range", file_name, m).into());
+                    }
+                    members.push(m);
+                }
+                band.insert(key, members);
+            }
+            index.bands.push(band);
+        }
+        Ok(index)
+    }
+
+    // indices sharing at least one band with `keys`, most shared bands first
+    pub fn candidates(&self, keys: &[Option<u64>]) -> Vec<u32> {
+        let mut shared: HashMap<u32, u32> = HashMap::new();
+        for (band, key) in self.bands.iter().zip(keys) {
+            if let Some(members) = key.and_then(|key| band.get(&key)) {
+                for &m in members {
+                    *shared.entry(m).or_default() += 1;
+                }
+            }
+        }
+        let mut candidates: Vec<(u32, u32)> = shared.into_iter().collect();
+        candidates.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        candidates.into_iter().map(|(m, _)| m).collect()
+    }
+}
+
+pub fn index(prefix: &str, rows: Option<usize>, threads: u32) -> Result<(), Box<dyn Error>> {
+    let files = find_files(prefix)?;
+    let params = read_params(&files["params"])?;
+    let names = read_names(&files["files"])?;
+    let algorithm = params["algorithm"].clone();
+    let rows = rows.unwrap_or_else(|| default_rows(&algorithm));
+    if rows == 0 {
+        return Err("--rows must be at least 1".into());
+    }
+
+    let index = with_sketch_type!(
+        algorithm.as_str(),
+        build_with(&files["sketches"], names.len(), rows)
+    )?;
+    if index.bands.is_empty() && !names.is_empty() {
+        return Err(format!("{} registers per band is more than a sketch has", rows).into());
+    }
+    index.write(&format!("{}_index.lsh", prefix), threads)?;
+
+    let mut metadata: serde_json::Map<String, serde_json::Value> = params
+        .iter()
+        .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+        .collect();
+    for (key, value) in [
+        ("index_format", INDEX_VERSION.to_string()),
+        ("rows", rows.to_string()),
+        ("bands", index.bands.len().to_string()),
+        ("sketches", names.len().to_string()),
+    ] {
+        metadata.insert(key.to_string(), serde_json::Value::String(value));
+    }
+    to_writer_pretty(&File::create(format!("{}_index.json", prefix))?, &metadata)?;
+
+    let buckets: usize = index.bands.iter().map(HashMap::len).sum();
+    println!(
+        "Indexed {} sketches of {} in {} bands of {} registers ({} buckets), written to {}_index.lsh.",
+        names.len(),
+        prefix,
+        index.bands.len(),
+        rows,
+        buckets,
+        prefix
+    );
+    Ok(())
+}
+
+// index of a sketch set and its metadata, checked against the set it was built from
+fn open_index(prefix: &str, set: &SketchSet) -> Result<(LshIndex, usize), Box<dyn Error>> {
+    let metadata_file = format!("{}_index.json", prefix);
+    if !std::path::Path::new(&metadata_file).is_file() {
+        return Err(format!("no index found for {}, run `lash index -i {}` first", prefix, prefix).into());
+    }
+    let metadata = read_params(&metadata_file)?;
+    let field = |key: &str| -> Result<usize, Box<dyn Error>> {
+        metadata
+            .get(key)
+            .ok_or_else(|| format!("{} has no {}", metadata_file, key))?
+            .parse::<usize>()
+            .map_err(|e| format!("{}: invalid {}: {}", metadata_file, key, e).into())
+    };
+    let version = field("index_format")?;
+    if version > INDEX_VERSION as usize {
+        return Err(format!(
+            "{} uses index format {}, this lash reads up to {}",
+            metadata_file, version, INDEX_VERSION
+        ).into());
+    }
+    check_same_params(&metadata_file, &metadata, prefix, &set.params)?;
+    if field("sketches")? != set.names.len() {
+        return Err(format!(
+            "index of {} covers {} sketches but the set has {}, run `lash index` again",
+            prefix,
+            field("sketches")?,
+            set.names.len()
+        ).into());
+    }
+    let index = LshIndex::read(&format!("{}_index.lsh", prefix), field("bands")?, set.names.len())?;
+    Ok((index, field("rows")?))
+}
+
+pub struct QueryOptions {
+    pub query: String,
+    pub reference: String,
+    pub output: String,
+    pub top: usize,
+    // at most this many candidates per query get an exact distance, 0 for all of them
+    pub max_candidates: usize,
+    pub equation: u8,
+    pub metadata: bool,
+}
+
+fn query_with<S: KmerSketch + Sync>(
+    opts: &QueryOptions,
+    queries: &SketchSet,
+    references: &SketchSet,
+    index: &LshIndex,
+    rows: usize,
+) -> Result<(Vec<QueryHits>, usize), Box<dyn Error>> {
+    let query_sketches: Vec<S> = load_sketches(&queries.sketch_file, queries.names.len())?;
+    let ref_sketches: Vec<S> = load_sketches(&references.sketch_file, references.names.len())?;
+    let results: Vec<(QueryHits, usize)> = query_sketches
+        .par_iter()
+        .zip(&queries.names)
+        .map(|(q, q_name)| {
+            let mut candidates = index.candidates(&band_keys(&q.registers(), rows));
+            candidates.retain(|&j| references.names[j as usize] != *q_name);
+            if opts.max_candidates > 0 {
+                candidates.truncate(opts.max_candidates);
+            }
+            let compared = candidates.len();
+            let mut hits: QueryHits = candidates
+                .into_iter()
+                .map(|j| {
+                    let r = &ref_sketches[j as usize];
+                    (j as usize, sketch_distance(r, q, references.kmer_length, opts.equation))
+                })
+                .collect();
+            let by_distance = |a: &(usize, f64), b: &(usize, f64)| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0));
+            if hits.len() > opts.top {
+                hits.select_nth_unstable_by(opts.top, by_distance);
+                hits.truncate(opts.top);
+            }
+            hits.sort_by(by_distance);
+            (hits, compared)
+        })
+        .collect();
+    let compared = results.iter().map(|r| r.1).sum();
+    Ok((results.into_iter().map(|r| r.0).collect(), compared))
+}
+
+// approximate `search`: only references sharing a band with the query are compared,
+// queries with no candidate get no line
+pub fn query(opts: &QueryOptions) -> Result<(), Box<dyn Error>> {
+    let queries = SketchSet::open(&opts.query)?;
+    let references = SketchSet::open(&opts.reference)?;
+    check_same_params(&opts.query, &queries.params, &opts.reference, &references.params)?;
+    let (index, rows) = open_index(&opts.reference, &references)?;
+
+    let (hits, compared) = with_sketch_type!(
+        references.params["algorithm"].as_str(),
+        query_with(opts, &queries, &references, &index, rows)
+    )?;
+
+    let mut out = BufWriter::new(File::create(&opts.output)?);
+    if opts.metadata {
+        writeln!(out, "{}", metadata_header(&references.params, opts.equation))?;
+    }
+    writeln!(out, "Reference\tQuery\tDistance")?;
+    for (q_name, q_hits) in queries.names.iter().zip(&hits) {
+        for (j, d) in q_hits {
+            writeln!(out, "{}\t{}\t{:.6}", references.names[*j], q_name, d)?;
+        }
+    }
+    out.flush()?;
+
+    println!(
+        "Best {} hits for {} queries written to {}, {:.1} of {} references compared per query.",
+        opts.top,
+        queries.names.len(),
+        opts.output,
+        compared as f64 / queries.names.len().max(1) as f64,
+        references.names.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperminhash::Sketch;
+
+    use super::*;
+    use crate::utils::{write_sketch_set, Entry, TempDir};
+
+    const SEED: u64 = 42;
+
+    // `count` random k-mers of genome `genome`, of which the first `mutated` are
+    // swapped for k-mers of no other genome
+    fn kmers(genome: u64, count: usize, mutated: usize) -> Vec<u64> {
+        let mut state = genome.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+        (0..count)
+            .map(|i| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                if i < mutated {
+                    (state >> 32) ^ 0xffff_ffff
+                } else {
+                    state >> 32
+                }
+            })
+            .collect()
+    }
+
+    fn write_set(prefix: &str, name: &str, genomes: u64, mutated: usize) -> SketchSet {
+        let sketches: Vec<Sketch> = (0..genomes)
+            .map(|genome| {
+                let mut sketch = Sketch::new(None);
+                kmers(genome, 5000, mutated).into_iter().for_each(|kmer| sketch.add_kmer(kmer, SEED));
+                sketch
+            })
+            .collect();
+        let entries: Vec<Entry> = (0..genomes).map(|genome| Entry::named(format!("{}{}", name, genome))).collect();
+        let params: HashMap<String, String> = [("k", "21"), ("algorithm", "hmh"), ("seed", "42"), ("molecule", "nucleotide")]
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        write_sketch_set(prefix, &entries, &sketches, &params, 1).unwrap();
+        SketchSet::open(prefix).unwrap()
+    }
+
+    #[test]
+    fn query_finds_mutated_genomes() {
+        let dir = TempDir::new("index_recall");
+        let (reference, query) = (dir.path("references"), dir.path("queries"));
+        let references = write_set(&reference, "r", 50, 0);
+        // each query shares 90% of its k-mers with the reference of the same number
+        let queries = write_set(&query, "q", 50, 500);
+        index(&reference, None, 1).unwrap();
+
+        let (index, rows) = open_index(&reference, &references).unwrap();
+        assert_eq!(rows, default_rows("hmh"));
+        let opts = QueryOptions {
+            query,
+            reference,
+            output: dir.path("hits.tsv"),
+            top: 1,
+            max_candidates: 0,
+            equation: 0,
+            metadata: false,
+        };
+        let (hits, compared) = query_with::<Sketch>(&opts, &queries, &references, &index, rows).unwrap();
+        for (i, q_hits) in hits.iter().enumerate() {
+            assert_eq!(q_hits.first().map(|hit| hit.0), Some(i), "query {}", i);
+        }
+        // unrelated references are mostly left out
+        assert!(compared < 50 * 50 / 4, "{} references compared", compared);
+    }
+
+    #[test]
+    fn stale_index_is_rejected() {
+        let dir = TempDir::new("index_stale");
+        let reference = dir.path("references");
+        write_set(&reference, "r", 10, 0);
+        index(&reference, None, 1).unwrap();
+        let grown = write_set(&reference, "r", 11, 0);
+        assert!(open_index(&reference, &grown).is_err());
+    }
+}
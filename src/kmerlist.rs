@@ -0,0 +1,76 @@
+// src/kmerlist.rs
+// K-mers of given sequences, looked up in the k-mer loop: left out of every
+// sketch with `sketch --blocklist <file>` (adapters, PhiX, host), the only ones
+// sketched with `sketch --include-fasta <file>` (marker genes, a core genome).
+// Sequences come as FASTA/FASTQ, or as k-mer values one per line (the u64 lash
+// hashes: 2-bit packed canonical k-mers up to k 32, their xxh3_64 fold above).
+// The k-mers go into a Bloom filter per k-mer length; its false positives, about
+// 0.3% of other k-mers and the same ones in every genome, are left out by a
+// blocklist and let through by an allowlist. The path is recorded in the
+// parameters, so sets are updated and queried with the same list.
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::sync::Arc;
+
+use crate::bloom::BloomFilter;
+use crate::utils::{filter_out_n, homopolymer_compress, open_fastx, SketchOptions};
+
+#[derive(Clone)]
+pub struct KmerList {
+    pub path: String,
+    // a filter per k-mer length
+    filters: Arc<Vec<(usize, BloomFilter)>>,
+}
+
+impl KmerList {
+    // k-mers of `path` for each k-mer length, read the way the genomes are
+    pub fn load(path: &str, kmer_lengths: &[usize], options: &SketchOptions, seed: u64) -> Result<Self, Box<dyn Error>> {
+        let mut first = [0u8; 1];
+        let peeked = File::open(path)?.read(&mut first)?;
+        let filters = if peeked == 1 && first[0].is_ascii_digit() {
+            let mut values = Vec::new();
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                values.push(line.parse::<u64>().map_err(|_| format!("invalid k-mer value {} in {}", line, path))?);
+            }
+            kmer_lengths
+                .iter()
+                .map(|&k| {
+                    let mut filter = BloomFilter::for_kmers(values.len() as u64, seed);
+                    values.iter().for_each(|&v| { filter.insert(v); });
+                    (k, filter)
+                })
+                .collect()
+        } else {
+            // one k-mer per base at most, compressed files hold about four bases per byte
+            let bytes = fs::metadata(path)?.len();
+            let bases = if matches!(first[0], b'>' | b'@') { bytes } else { 4 * bytes };
+            let mut filters: Vec<(usize, BloomFilter)> =
+                kmer_lengths.iter().map(|&k| (k, BloomFilter::for_kmers(bases, seed))).collect();
+            let mut reader = open_fastx(path)?;
+            while let Some(res) = reader.next() {
+                let seqrec = res?;
+                let mut seq = filter_out_n(&seqrec.seq().to_ascii_uppercase());
+                if options.hpc {
+                    seq = homopolymer_compress(&seq);
+                }
+                for (k, filter) in filters.iter_mut() {
+                    options.for_each_dna_kmer(&seq, *k, |masked| { filter.insert(masked); });
+                }
+            }
+            filters
+        };
+        Ok(KmerList { path: path.to_string(), filters: Arc::new(filters) })
+    }
+
+    // the filter of one k-mer length
+    pub fn filter(&self, kmer_length: usize) -> Option<&BloomFilter> {
+        self.filters.iter().find(|(k, _)| *k == kmer_length).map(|(_, filter)| filter)
+    }
+}
@@ -1,31 +1,219 @@
-use clap::{Arg, ArgAction, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 // use needletail::kmer::Kmers;
 // use needletail::sequence::canonical;
 use hashbrown::HashMap;
 use std::error::Error;
 //use xxhash_rust::xxh3::Xxh3Builder;
-use std::fs;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
-use std::path::Path;
-use streaming_algorithms::HyperLogLog;
-use hyperminhash::Sketch;
-use ultraloglog::UltraLogLog;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+mod ambiguous;
+mod archive;
+mod batch;
+mod bloom;
+mod checkpoint;
+mod classify;
+mod compression;
+mod countmin;
+#[cfg(feature = "exact-counts")]
+mod hashcount;
+mod dual;
+mod dust;
+mod ehll;
+mod bench;
+mod fetchdb;
+mod format;
 mod hasher;
+mod hlll;
+mod hyperbitbit;
+mod index;
+mod kmerlist;
+mod minhash;
+mod ncbi;
+mod notify;
+mod nthash;
+mod ordermh;
+mod pairwise;
+mod profile;
+mod regions;
+mod remote;
+mod results;
+mod screen;
+mod serve;
+mod setops;
+mod simd;
+mod simulate;
+mod sampling;
+mod sketchset;
+mod spaced;
+mod sra;
+mod strobemer;
+mod superminhash;
+mod translate;
+mod tree;
+mod ull;
 use serde_json::json;
 mod utils;
-use crate::utils::{hll_distance, hmh_distance, ull_distance, sketch_files};
+mod watch;
+mod weighted;
+use crate::utils::{
+    compute_distance, core_kmers, exact_similarity, GroupKmers, external_ani, find_files, hll_distance, hmh_distance, kmer_set, jaccard_distance, load_sketches,
+    entries_by_name, metadata_header, multiway_cardinalities, read_entries, read_file_list, read_names, read_params, sketch_files, ull_distance, KmerSketch,
+    Entry, Sample, STDIN,
+    with_sketch_type, MAX_MULTIWAY,
+};
 use num_traits::Float;
+use xxhash_rust::xxh3::xxh3_64_with_seed;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use crate::notify::{run_report, Notifier};
+use rayon::prelude::*;
 
 fn main() -> Result<(), Box<dyn Error>> {
     // Initialize logger
     println!("\n ************** initializing logger *****************\n");
     env_logger::Builder::from_default_env().init();
-    // Set up the command-line arguments
-    let matches = Command::new("Genome Sketching via HyperLogLog, HyperMinhash and UltraLogLog")
+    let matches = cli().get_matches();
+    let start = Instant::now();
+    let result = run_command(&matches);
+
+    // watch, serve and run report each processed file/request/job themselves, serve
+    // also here when it cannot start
+    if let Some((name, sub_matches)) = matches.subcommand() {
+        if name != "watch" && name != "run" {
+            let error = result.as_ref().err().map(|e| e.to_string());
+            Notifier::from_matches(sub_matches)
+                .notify(&run_report(name, error, start.elapsed().as_secs_f64()));
+        }
+    }
+    result
+}
+
+// global rayon pool, reused when it already exists (e.g. jobs under `lash run`)
+fn init_thread_pool(threads: usize) {
+    let built = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.max(1))
+        .build_global();
+    if built.is_err() {
+        log::info!(
+            "thread pool already initialized, keeping {} threads",
+            rayon::current_num_threads()
+        );
+    }
+}
+
+// size parameter of the algorithm, taken from --precision or --scaled
+fn size_parameter(alg: &str, matches: &ArgMatches) -> Option<u32> {
+    utils::size_key(alg).map(|key| *matches.get_one::<usize>(key).expect("has default") as u32)
+}
+
+// (reference, query, distance) rows kept for the top hit passes
+type Hits = Option<Arc<Mutex<Vec<(String, String, f64)>>>>;
+
+// where and how the rows of the distance functions are written
+struct DistWriter {
+    output: Arc<Mutex<File>>,
+    create_matrix: bool,
+    same_files: bool,
+    // column of each query, for triangular matrix printing
+    file_idx: Mutex<HashMap<String, usize>>,
+    kmer_length: usize,
+    equation: u64,
+    hits: Hits,
+}
+
+impl DistWriter {
+    // callback of the distance functions
+    fn print<T: Float + std::fmt::Display>(&self, distance_list: Vec<(&String, &String, T)>) {
+        // printing columns for matrix output using the query list
+        let mut file = self.output.lock().unwrap();
+        if self.create_matrix && !distance_list.is_empty() && distance_list[0].0.is_empty() {
+            for (i, col) in distance_list.iter().enumerate() {
+                write!(file, "\t{}", col.1).expect("Error writing columns for matrix output");
+                if self.same_files {
+                    let mut idx = self.file_idx.lock().unwrap();
+                    idx.insert(col.1.clone(), i);
+                }
+            }
+        }
+        else {
+            for (i, row) in distance_list.iter().enumerate() {
+                let r_name = row.0;
+                let q_name = row.1;
+                let d: T = if q_name == r_name {
+                    T::zero()
+                } else {
+                    compute_distance::<T>(row.2, self.kmer_length, self.equation as u8)
+                };
+                if let Some(hits) = &self.hits {
+                    hits.lock().unwrap().push((
+                        r_name.clone(),
+                        q_name.clone(),
+                        d.to_f64().unwrap(),
+                    ));
+                }
+
+                if !self.create_matrix {
+                    writeln!(file, "{}\t{}\t{:.6}", r_name, q_name, d)
+                        .expect("Error writing to file");
+                } else {
+                    if i == 0 {
+                        write!(file, "\n{}", r_name).expect("Error writing to file");
+                    }
+                    write!(file, "\t{:.6}", d).expect("Error writing to file");
+                }
+
+            }
+        }
+    }
+}
+
+// best N references per query, self hits excluded
+fn best_hits(hits: &[(String, String, f64)], top_n: usize) -> Vec<(String, String, f64)> {
+    let mut hits = hits.to_vec();
+    hits.sort_by(|a, b| {
+        a.1.cmp(&b.1)
+            .then(a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal))
+            .then(a.0.cmp(&b.0))
+    });
+
+    let mut pairs: Vec<(String, String, f64)> = Vec::new();
+    let mut current: Option<String> = None;
+    let mut taken = 0;
+    for (r_name, q_name, d) in hits {
+        if current.as_ref() != Some(&q_name) {
+            current = Some(q_name.clone());
+            taken = 0;
+        }
+        if r_name == q_name || taken >= top_n {
+            continue;
+        }
+        taken += 1;
+        pairs.push((r_name, q_name, d));
+    }
+    pairs
+}
+
+// Set up the command-line arguments
+fn cli() -> Command {
+    Command::new("Genome Sketching via HyperLogLog, HyperMinhash and UltraLogLog")
         .version("0.1.4")
         .about("Fast and Memory Efficient (Meta)genome Sketching via HyperLogLog, HyperMinhash and UltraLogLog")
+        .arg(
+            Arg::new("webhook")
+            .long("webhook")
+            .help("URL the JSON run report is POSTed to when a command finishes (per file for watch, per query for serve, per job with the keys of a job and per batch for run)")
+            .required(false)
+            .global(true)
+            .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("hook")
+            .long("hook")
+            .help("Shell command run when a command finishes, the JSON run report is passed on stdin")
+            .required(false)
+            .global(true)
+            .action(ArgAction::Set)
+        )
         .subcommand(
             Command::new("sketch")
             .about("Sketches genomes and serializes them, sketches are compressed")
@@ -33,8 +221,62 @@ fn main() -> Result<(), Box<dyn Error>> {
                 Arg::new("file")
                 .short('f')
                 .long("file")
-                .help("One file containing list of FASTA/FASTQ files (.gz/.bz2/.zstd supported), one per line. File must be UTF-8.")
-                .required(true)
+                .help("One file containing list of FASTA/FASTQ files (.gz/.bz2/.xz/.lz4/.zstd supported), one per line. File must be UTF-8. A line with - reads standard input. A line with R1 and R2 FASTQ files separated by a tab is one paired-end sample, named by R1. http(s):// and ftp:// URLs are streamed, with retries. NCBI assembly accessions (GCF_000005845.2) are fetched and cached, see --cache-dir. SRA run accessions (SRR/ERR/DRR) are streamed from ENA. Tar archives (.tar, .tar.gz, .tar.zst, .tar.xz, .tar.lz4) are read member by member, one sample per FASTA/FASTQ member (all members in one sample on a --manifest line). A directory or a quoted glob pattern (genomes/*.fna.gz, ** for any depth) is also accepted, and - reads the list from standard input (find ... | lash sketch -f -)")
+                .required_unless_present_any(["stdin", "manifest"])
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("manifest")
+                .long("manifest")
+                .help("Tab-separated file of samples instead of -f, one <sample name>\t<path> per line; several paths separated by tabs (R1 and R2, sequencing lanes) are unioned into one sketch. The names label the sketches and every dist output, _files.json keeps them with their paths")
+                .conflicts_with_all(["file", "stdin"])
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("recursive")
+                .short('r')
+                .long("recursive")
+                .help("With a directory for -f, also sketch the files in its subdirectories")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("extension")
+                .long("extension")
+                .visible_alias("extensions")
+                .help("File extensions taken from a directory or glob for -f, comma separated (fna,fa), compression suffixes aside. Default for a directory: fa, fasta, fna, ffn, faa, fq, fastq. The files found are listed in <output>_inputs.json")
+                .required(false)
+                .value_delimiter(',')
+                .action(ArgAction::Append)
+            )
+            .arg(
+                Arg::new("cache_dir")
+                .long("cache-dir")
+                .help("Where assemblies given by NCBI accession (GCF_/GCA_) in -f or --manifest are cached once fetched through the NCBI Datasets API [default: $XDG_CACHE_HOME/lash/ncbi or ~/.cache/lash/ncbi]")
+                .required(false)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("resume")
+                .long("resume")
+                .help("Continue an interrupted run with the same inputs and parameters: samples already sketched are read back from the <output>_checkpoint directory, where every run keeps its finished samples until the sets are written")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("stdin")
+                .long("stdin")
+                .help("Sketch one FASTA/FASTQ stream from standard input instead of a file list")
+                .conflicts_with("file")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("name")
+                .long("name")
+                .help("Sample name of the standard input stream")
+                .required(false)
+                .default_value("stdin")
                 .action(ArgAction::Set)
             )
             .arg(
@@ -50,10 +292,11 @@ fn main() -> Result<(), Box<dyn Error>> {
                 Arg::new("kmer_length")
                 .short('k')
                 .long("kmer")
-                .help("Length of the kmer")
+                .help("Length of the kmer, 1 to 64. Several, comma separated (16,21,31), sketch every genome at each in one pass and write one set per k, <output>_k16, <output>_k21, ...")
                 .required(false)
                 .default_value("16")
                 .value_parser(clap::value_parser!(usize))
+                .value_delimiter(',')
                 .action(ArgAction::Set)
             )
             .arg(
@@ -69,21 +312,49 @@ fn main() -> Result<(), Box<dyn Error>> {
                 Arg::new("algorithm")
                 .short('a')
                 .long("algorithm")
-                .help("Which algorithm to use: HyperMinHash (hmh), UltraLogLog (ull), or HyperLogLog (hll)")
+                .help("Which algorithm to use: HyperMinHash (hmh), HyperMinHash and HyperLogLog from one pass (hmh+hll), UltraLogLog (ull), HyperLogLog (hll), ExtendedHyperLogLog (ehll, between hll and ull), FracMinHash (fmh), bottom-k MinHash (minhash), SuperMinHash (superminhash, lower variance than minhash at the same size), Theta (theta), HyperBitBit (hyperbitbit, 136 bits, coarse), OrderMinHash (ordermh, order-aware, for sequences up to a few Mbp, keeps 8 bytes per base while sketching), or abundance-weighted ProbMinHash (probminhash) and DartMinHash (dartminhash, weighted Jaccard of k-mer counts)")
                 .required(false)
                 .default_value("hmh")
+                .value_parser(["hmh", "hmh+hll", "hll", "ehll", "ull", "fmh", "minhash", "superminhash", "theta", "hyperbitbit", "ordermh", "probminhash", "dartminhash"])
                 .action(ArgAction::Set)
             )
             .arg(
                 Arg::new("precision")
                 .short('p')
                 .long("precision")
-                .help("Specifiy precision, for ull and hll only.")
+                .help("Specifiy precision, for ull, ehll, hll and hmh+hll only. Several, comma separated (8,10,12), sketch once and write one set per precision, <output>_p8, <output>_p10, ... auto picks the lowest meeting --target-error, no higher than the largest input needs")
                 .required(false)
-                .value_parser(clap::value_parser!(usize))
+                .value_delimiter(',')
                 .default_value("10")
                 .action(ArgAction::Set)
             )
+            .arg(
+                Arg::new("target_error")
+                .long("target-error")
+                .help("Relative standard error of cardinalities aimed at by --precision auto")
+                .required(false)
+                .value_parser(clap::value_parser!(f64))
+                .default_value("0.02")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("scaled")
+                .long("scaled")
+                .help("Keep one k-mer hash in this many, for fmh; given for ull, hll, ehll, probminhash or dartminhash, only k-mers hashing below 2^64/scaled are sketched, bounding the work (and the k-mer counts held by the weighted sketches) on huge metagenomes (stats scales cardinalities back)")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1000")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("sketch_size")
+                .long("sketch-size")
+                .help("Number of k-mer hashes kept, for minhash, superminhash, theta, ordermh, probminhash and dartminhash only")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1000")
+                .action(ArgAction::Set)
+            )
             .arg(
                 Arg::new("seed")
                 .short('s')
@@ -94,14 +365,274 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .default_value("42")
                 .action(ArgAction::Set)
             )
-            // .arg(
-            //     Arg::new("aa")
-            //     .long("aa")
-            //     .help("Amino acid sketching")
-            //     .required(false)
-            //     .action(clap::ArgAction::SetTrue)
-            //     .num_args(0)
-            // )
+            .arg(
+                Arg::new("per_contig")
+                .short('i')
+                .long("per-contig")
+                .help("One sketch per FASTA/FASTQ record instead of per file, named <file>:<record id> from its header (as mash sketch -i); dist and --verify-top take these names")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("seed_mask")
+                .long("seed-mask")
+                .help("Spaced seed instead of contiguous k-mers, e.g. 110110111011: only bases at 1s are hashed, k is the mask length and distances use its number of 1s")
+                .required(false)
+                .conflicts_with("kmer_length")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("minimizer")
+                .long("minimizer")
+                .help("Sketch only the minimizer of every window of --window k-mers, about 2/(window+1) of them; cardinalities then count minimizers")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("window")
+                .short('w')
+                .long("window")
+                .help("Number of consecutive k-mers per minimizer window, with --minimizer")
+                .required(false)
+                .value_parser(clap::value_parser!(u32).range(2..))
+                .default_value("10")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("syncmer")
+                .long("syncmer")
+                .help("Sketch only syncmers, k-mers whose smallest s-mer is first or last (closed, --syncmer s) or at position t counted from 1 (open, --syncmer s,t); k up to 32")
+                .required(false)
+                .conflicts_with_all(["minimizer", "seed_mask"])
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("seed_type")
+                .long("seed-type")
+                .help("Unit sketched: k-mers, randstrobes (strobemer) or minstrobes (minstrobe) of two k-mer strobes, robust to small indels; strobemer distances use 2k")
+                .required(false)
+                .value_parser(["kmer", "strobemer", "minstrobe"])
+                .default_value("kmer")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("strobe_window")
+                .long("strobe-window")
+                .help("Positions after the first strobe where the second is picked, min,max, with --seed-type strobemer or minstrobe")
+                .required(false)
+                .default_value("25,50")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("min_count")
+                .long("min-count")
+                .help("Leave out k-mers of FASTQ files seen fewer times, 2 drops singletons (mostly sequencing errors) with a Bloom filter; FASTA inputs keep every k-mer")
+                .required(false)
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("hash")
+                .long("hash")
+                .help("K-mer hash: xxh3 of 2-bit packed k-mers, or nthash rolled in O(1) per base for any k (contiguous dna k-mers only); sets built with different hashes cannot be compared")
+                .required(false)
+                .value_parser(["xxh3", "nthash"])
+                .default_value("xxh3")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("hpc")
+                .long("hpc")
+                .help("Collapse homopolymer runs (AAAC to AC) before taking k-mers, so Nanopore and Illumina assemblies compare better; query sets need it too")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("circular")
+                .long("circular")
+                .help("Read every record as circular (plasmids, circular chromosomes) and also sketch the k-mers across its end/start junction")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("exclude_softmasked")
+                .long("exclude-softmasked")
+                .help("Leave out lowercase (soft-masked, e.g. repeat-masked) sequence under any --ambiguous policy, no k-mer spans it, so distances cover the non-repetitive part of eukaryotic genomes")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("filter_low_complexity")
+                .long("filter-low-complexity")
+                .help("Leave out low-complexity k-mers (poly-A, short tandem repeats) whose DUST triplet score is above 2, so repeat-rich genomes do not look alike for their simple repeats alone; contiguous dna k-mers, k up to 32")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("blocklist")
+                .long("blocklist")
+                .help("FASTA/FASTQ of sequences (adapters, PhiX, host) whose k-mers are left out of every sketch, or k-mer values one per line, looked up in a Bloom filter; recorded so queries and updates leave them out too")
+                .required(false)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("include_fasta")
+                .long("include-fasta")
+                .help("FASTA/FASTQ of target sequences (marker genes, core genome), or k-mer values one per line: only their k-mers are sketched, so distances are not moved by mobile elements; recorded like --blocklist")
+                .required(false)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("include_bed")
+                .long("include-bed")
+                .help("BED file of target regions: only these intervals of records whose id matches the chromosome column are sketched, other records are skipped; recorded like --blocklist")
+                .required(false)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("ambiguous")
+                .long("ambiguous")
+                .help("Bases other than ACGT: drop joins the bases around them, skip-kmer leaves out k-mers overlapping them, expand also sketches each resolution of k-mers with up to 16, random resolves IUPAC codes from the seed; counts per genome go to <output>_ambiguous.tsv")
+                .required(false)
+                .value_parser(["drop", "skip-kmer", "expand", "random"])
+                .default_value("drop")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("no_canonical")
+                .long("no-canonical")
+                .help("Keep k-mers as read instead of merging them with their reverse complement, for transcripts and strand-specific data; query sets need it too")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("min_copies")
+                .short('m')
+                .long("min-copies")
+                .help("Leave out k-mers seen fewer times in an input, FASTA or FASTQ, counted exactly in memory (as mash sketch -m)")
+                .required(false)
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .default_value("1")
+                .conflicts_with("min_count")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("min_qual")
+                .long("min-qual")
+                .help("Cut FASTQ reads at bases below this Phred score, so no k-mer spans one (low-quality Nanopore tails); 0 keeps every base")
+                .required(false)
+                .value_parser(clap::value_parser!(u8))
+                .default_value("0")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("min_mean_qual")
+                .long("min-mean-qual")
+                .help("Skip FASTQ reads with a mean Phred score below this; 0 keeps every read")
+                .required(false)
+                .value_parser(clap::value_parser!(u8))
+                .default_value("0")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("min_seq_len")
+                .long("min-seq-len")
+                .help("Skip records shorter than this (short junk contigs, short reads), counts per file are printed")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("max_seq_len")
+                .long("max-seq-len")
+                .help("Skip records longer than this (chimeric or misassembled records), counts per file are printed")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("subsample")
+                .long("subsample")
+                .help("Sketch only this fraction of FASTQ reads (0.1 for a tenth), drawn from the read names and --seed, for fast distance estimates of deep runs; FASTA records are all kept")
+                .required(false)
+                .value_parser(clap::value_parser!(f64))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("interleaved")
+                .long("interleaved")
+                .help("FASTQ files hold read pairs interleaved, R1 then R2 of each pair: the mates pass --min-mean-qual, --subsample and the length limits together or are both skipped")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("keep_mate")
+                .long("keep-mate")
+                .help("Mates of interleaved pairs that are sketched, 1 or 2 to drop the other one (a mate with lower quality, or overlapping mates counted once)")
+                .requires("interleaved")
+                .value_parser(["both", "1", "2"])
+                .default_value("both")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("trim_head")
+                .long("trim-head")
+                .help("Cut this many bases from the start of every FASTQ read before taking k-mers (barcodes, primers)")
+                .required(false)
+                .value_parser(clap::value_parser!(u32))
+                .default_value("0")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("trim_tail")
+                .long("trim-tail")
+                .help("Cut this many bases from the end of every FASTQ read before taking k-mers (adapters)")
+                .required(false)
+                .value_parser(clap::value_parser!(u32))
+                .default_value("0")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("crop")
+                .long("crop")
+                .help("Keep at most this many bases of every FASTQ read, after --trim-head and --trim-tail")
+                .required(false)
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("exact_counts")
+                .long("exact-counts")
+                .help("Count k-mers for --min-count exactly in a hash table, needed above 2 (lash built with --features exact-counts)")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("count_min")
+                .long("count-min")
+                .help("Also count k-mers in a Count-Min sketch with this many counters per row (4 rows), written to <output>_counts.bin for dist --abundance and stats spectra")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("molecule")
+                .long("molecule")
+                .help("Sequence type: dna, or protein for proteomes (amino-acid k-mers, k up to 12, 7 to 12 is typical; distances are then AAI-like)")
+                .required(false)
+                .value_parser(["dna", "protein"])
+                .default_value("dna")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("translate")
+                .long("translate")
+                .help("Translate nucleotide inputs in all six frames, cut at stop codons, and sketch amino-acid k-mers (k up to 12) for protein-level comparisons")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
         )
         .subcommand(
             Command::new("dist")
@@ -111,9 +642,17 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .short('q')
                 .long("query")
                 .help("Prefix to search for query genome files")
-                .required(true)
+                .required_unless_present("query_fasta")
                 .action(ArgAction::Set)
             )
+            .arg(
+                Arg::new("query_fasta")
+                .long("query-fasta")
+                .help("FASTA/FASTQ query files sketched on the fly with the reference's parameters, instead of --query")
+                .conflicts_with("query")
+                .num_args(1..)
+                .action(ArgAction::Append)
+            )
             .arg(
                 Arg::new("reference")
                 .short('r')
@@ -144,11 +683,17 @@ fn main() -> Result<(), Box<dyn Error>> {
                 Arg::new("estimator")
                 .short('e')
                 .long("estimator")
-                .help("Specify estimator (fgra or ml), for ull only")
+                .help("Specify estimator: fgra (default), ml or martingale (sketches built in one pass) for ull, classic (default) or improved (Ertl's improved raw estimator) for hll")
                 .required(false)
-                .default_value("fgra")
                 .action(ArgAction::Set)
             )
+            .arg(
+                Arg::new("compact")
+                .long("compact")
+                .help("Keep hll registers compressed in memory (about 3 bits each instead of 8, HyperLogLogLog-style) for very large sketch sets, at some cost in speed")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
             .arg(
                 Arg::new("model")
                 .short('m')
@@ -173,77 +718,1837 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .action(clap::ArgAction::SetTrue)
                 .num_args(0)
             )
+            .arg(
+                Arg::new("no_metadata")
+                .long("no-metadata")
+                .help("Do not start outputs with the commented parameter line (# lash=... k=... algorithm=...)")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("verify_top")
+                .long("verify-top")
+                .help("Re-read the FASTA files of the N best hits per query and compute exact k-mer Jaccard/containment, written to <output_file>_verified")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("set_ops")
+                .long("set-ops")
+                .help("Also estimate union, intersection and difference sizes of every pair, written to <output_file>_setops")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("abundance")
+                .long("abundance")
+                .help("Also compare k-mer abundance profiles (cosine) of sets sketched with --count-min, written to <output_file>_abundance")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("ani_tool")
+                .long("ani-tool")
+                .help("Confirm the best hits per query with an external ANI tool (skani or fastani), written to <output_file>_ani")
+                .required(false)
+                .value_parser(["skani", "fastani"])
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("assign")
+                .long("assign")
+                .help("Also write one row per query to <output_file>_assign: best reference, distance, ANI (1 - distance), margin to the second-best hit, and unassigned below this ANI (%)")
+                .required(false)
+                .value_parser(clap::value_parser!(f64))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("ani_top")
+                .long("ani-top")
+                .help("Number of best hits per query passed to --ani-tool")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("5")
+                .action(ArgAction::Set)
+            )
         )
-        .get_matches();
+        .subcommand(
+            Command::new("compare")
+            .about("Sketches two FASTA/FASTQ files in memory and prints their Jaccard index, distance and ANI")
+            .arg(
+                Arg::new("genome_a")
+                .short('a')
+                .help("First FASTA/FASTQ file (.gz/.bz2/.xz/.lz4/.zstd supported)")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("genome_b")
+                .short('b')
+                .help("Second FASTA/FASTQ file (.gz/.bz2/.xz/.lz4/.zstd supported)")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("kmer_length")
+                .short('k')
+                .long("kmer")
+                .help("Length of the kmer")
+                .required(false)
+                .default_value("16")
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("algorithm")
+                .long("algorithm")
+                .help("Which algorithm to use: HyperMinHash (hmh), HyperMinHash and HyperLogLog from one pass (hmh+hll), UltraLogLog (ull), HyperLogLog (hll), ExtendedHyperLogLog (ehll, between hll and ull), FracMinHash (fmh), bottom-k MinHash (minhash), SuperMinHash (superminhash, lower variance than minhash at the same size), Theta (theta), HyperBitBit (hyperbitbit, 136 bits, coarse), OrderMinHash (ordermh, order-aware, for sequences up to a few Mbp, keeps 8 bytes per base while sketching), or abundance-weighted ProbMinHash (probminhash) and DartMinHash (dartminhash, weighted Jaccard of k-mer counts)")
+                .required(false)
+                .default_value("hmh")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("precision")
+                .short('p')
+                .long("precision")
+                .help("Specifiy precision, for ull, ehll, hll and hmh+hll only.")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("scaled")
+                .long("scaled")
+                .help("Keep one k-mer hash in this many, for fmh only")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1000")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("sketch_size")
+                .long("sketch-size")
+                .help("Number of k-mer hashes kept, for minhash, superminhash, theta, ordermh, probminhash and dartminhash only")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1000")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("seed")
+                .short('s')
+                .long("seed")
+                .help("Random seed")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("42")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Equation used to calculate distance: 1 for poisson model or 0 for binomial model")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("ani")
+            .about("Computes ANI (100 * (1 - distance)) between sketches, Poisson and binomial models side by side")
+            .arg(
+                Arg::new("query")
+                .short('q')
+                .long("query")
+                .help("Prefix to search for query genome files")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("reference")
+                .short('r')
+                .long("reference")
+                .help("Prefix to search for reference genome files")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of output file to write results")
+                .required(false)
+                .default_value("ani.txt")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("no_metadata")
+                .long("no-metadata")
+                .help("Do not start the output with the commented parameter line")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+        )
+        .subcommand(
+            Command::new("triangle")
+            .about("Computes all-vs-all distances within one sketch set, each pair once, as a lower-triangular matrix")
+            .arg(
+                Arg::new("input")
+                .short('i')
+                .long("input")
+                .help("Prefix of the sketch set")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of output file to write results")
+                .required(false)
+                .default_value("triangle.txt")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Equation used to calculate distance: 1 for poisson model or 0 for binomial model")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("no_metadata")
+                .long("no-metadata")
+                .help("Do not start the output with the commented parameter line")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+        )
+        .subcommand(
+            Command::new("setops")
+            .about("Estimates union, intersection and difference sizes of the k-mer sets of every reference/query pair, natively for theta sketches")
+            .arg(
+                Arg::new("query")
+                .short('q')
+                .long("query")
+                .help("Prefix to search for query genome files")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("reference")
+                .short('r')
+                .long("reference")
+                .help("Prefix to search for reference genome files")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of output file to write results")
+                .required(false)
+                .default_value("setops.txt")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Equation used to calculate distance: 1 for poisson model or 0 for binomial model")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("no_metadata")
+                .long("no-metadata")
+                .help("Do not start the output with the commented parameter line")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+        )
+        .subcommand(
+            Command::new("search")
+            .about("Reports only the best reference hits of each query, sorted by distance")
+            .arg(
+                Arg::new("query")
+                .short('q')
+                .long("query")
+                .help("Prefix to search for query genome files")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("reference")
+                .short('r')
+                .long("reference")
+                .help("Prefix to search for reference genome files")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of output file to write results")
+                .required(false)
+                .default_value("search.txt")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("top")
+                .short('n')
+                .long("top")
+                .help("Number of hits kept per query")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Equation used to calculate distance: 1 for poisson model or 0 for binomial model")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("no_metadata")
+                .long("no-metadata")
+                .help("Do not start the output with the commented parameter line")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+        )
+        .subcommand(
+            Command::new("index")
+            .about("Builds an LSH index over a sketch set for approximate nearest-neighbor queries")
+            .arg(
+                Arg::new("input")
+                .short('i')
+                .long("input")
+                .help("Prefix of the sketch set, the index is written as <prefix>_index.lsh and <prefix>_index.json")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("rows")
+                .long("rows")
+                .help("Registers per band, more rows means fewer but closer candidates [default: 6 for hll, 4 otherwise]")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("query")
+            .about("Approximate best hits of each query from the LSH index of a reference set")
+            .arg(
+                Arg::new("query")
+                .short('q')
+                .long("query")
+                .help("Prefix to search for query genome files")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("reference")
+                .short('r')
+                .long("reference")
+                .help("Prefix of the reference sketch set, indexed with lash index")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of output file to write results")
+                .required(false)
+                .default_value("query.txt")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("top")
+                .short('n')
+                .long("top")
+                .help("Number of hits kept per query")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("candidates")
+                .long("candidates")
+                .help("Most candidates compared per query, ranked by shared bands; 0 compares all of them")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("0")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Equation used to calculate distance: 1 for poisson model or 0 for binomial model")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("no_metadata")
+                .long("no-metadata")
+                .help("Do not start the output with the commented parameter line")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+        )
+        .subcommand(
+            Command::new("serve")
+            .about("Loads a reference sketch set once and answers top-hit queries over HTTP with JSON")
+            .arg(
+                Arg::new("reference")
+                .short('r')
+                .long("reference")
+                .help("Prefix of the reference sketch set")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("bind")
+                .short('b')
+                .long("bind")
+                .help("Address and port to listen on")
+                .required(false)
+                .default_value("127.0.0.1:8080")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("top")
+                .short('n')
+                .long("top")
+                .help("Number of hits returned when a request does not set ?top=")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Equation used to calculate distance: 1 for poisson model or 0 for binomial model")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("workers")
+                .long("workers")
+                .help("Requests handled at the same time")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("4")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("classify")
+            .about("Assigns each query the lineage of its best reference hit, down to the lowest rank whose distance threshold it passes")
+            .arg(
+                Arg::new("query")
+                .short('q')
+                .long("query")
+                .help("Prefix to search for query genome files")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("reference")
+                .short('r')
+                .long("reference")
+                .help("Prefix to search for reference genome files")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("taxonomy")
+                .long("taxonomy")
+                .help("Tab-separated reference name and lineage (d__;p__;c__;o__;f__;g__;s__), one reference per line")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("thresholds")
+                .long("thresholds")
+                .help("Largest best-hit distance per rank, rank=distance separated by commas")
+                .required(false)
+                .default_value("species=0.05,genus=0.2")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of output file to write results")
+                .required(false)
+                .default_value("classify.txt")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Equation used to calculate distance: 1 for poisson model or 0 for binomial model")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("no_metadata")
+                .long("no-metadata")
+                .help("Do not start the output with the commented parameter line")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+        )
+        .subcommand(
+            Command::new("cluster")
+            .about("Single-linkage clustering of a sketch set at a distance threshold")
+            .arg(
+                Arg::new("input")
+                .short('i')
+                .long("input")
+                .help("Prefix of the sketch set")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of the cluster table (genome, cluster, representative)")
+                .required(false)
+                .default_value("clusters.txt")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threshold")
+                .short('d')
+                .long("threshold")
+                .help("Largest distance linking two genomes, 0.05 is about 95% ANI")
+                .required(false)
+                .value_parser(clap::value_parser!(f64))
+                .default_value("0.05")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Equation used to calculate distance: 1 for poisson model or 0 for binomial model")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("dereplicate")
+            .about("Sketches a genome collection and greedily keeps representatives that are no closer than a distance threshold")
+            .arg(
+                Arg::new("file")
+                .short('f')
+                .long("file")
+                .help("One file containing list of FASTA/FASTQ files (.gz/.bz2/.xz/.lz4/.zstd supported), one per line. File must be UTF-8.")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Prefix of the kept (<output>_kept.txt) and discarded (<output>_discarded.txt) lists")
+                .required(false)
+                .default_value("derep")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threshold")
+                .short('d')
+                .long("threshold")
+                .help("Genomes within this distance of a kept genome are discarded, 0.05 is about 95% ANI")
+                .required(false)
+                .value_parser(clap::value_parser!(f64))
+                .default_value("0.05")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("kmer_length")
+                .short('k')
+                .long("kmer")
+                .help("Length of the kmer")
+                .required(false)
+                .default_value("16")
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("algorithm")
+                .short('a')
+                .long("algorithm")
+                .help("Which algorithm to use: HyperMinHash (hmh), HyperMinHash and HyperLogLog from one pass (hmh+hll), UltraLogLog (ull), HyperLogLog (hll), ExtendedHyperLogLog (ehll, between hll and ull), FracMinHash (fmh), bottom-k MinHash (minhash), SuperMinHash (superminhash, lower variance than minhash at the same size), Theta (theta), HyperBitBit (hyperbitbit, 136 bits, coarse), OrderMinHash (ordermh, order-aware, for sequences up to a few Mbp, keeps 8 bytes per base while sketching), or abundance-weighted ProbMinHash (probminhash) and DartMinHash (dartminhash, weighted Jaccard of k-mer counts)")
+                .required(false)
+                .default_value("hmh")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("precision")
+                .short('p')
+                .long("precision")
+                .help("Specifiy precision, for ull, ehll, hll and hmh+hll only.")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("scaled")
+                .long("scaled")
+                .help("Keep one k-mer hash in this many, for fmh only")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1000")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("sketch_size")
+                .long("sketch-size")
+                .help("Number of k-mer hashes kept, for minhash, superminhash, theta, ordermh, probminhash and dartminhash only")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1000")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("seed")
+                .short('s')
+                .long("seed")
+                .help("Random seed")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("42")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Equation used to calculate distance: 1 for poisson model or 0 for binomial model")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("tree")
+            .about("Builds a neighbor-joining or UPGMA tree of a sketch set, written as Newick")
+            .arg(
+                Arg::new("input")
+                .short('i')
+                .long("input")
+                .help("Prefix of the sketch set")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of the Newick file")
+                .required(false)
+                .default_value("tree.nwk")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("method")
+                .long("method")
+                .help("Tree building method")
+                .required(false)
+                .value_parser(["nj", "upgma"])
+                .default_value("nj")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Equation used to calculate distance: 1 for poisson model or 0 for binomial model")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("multiway")
+            .about("Estimates union and intersection cardinalities shared by a group of sketches")
+            .arg(
+                Arg::new("input")
+                .short('i')
+                .long("input")
+                .help("Prefix of the sketch set")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("names")
+                .short('n')
+                .long("names")
+                .help("File listing the genomes of the group, one per line, default to the whole sketch set (at most 20 genomes)")
+                .required(false)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of output file to write results")
+                .required(false)
+                .default_value("multiway")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("exact")
+                .long("exact")
+                .help("Re-read the FASTA files of the group and count union/intersection exactly, no limit on group size")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("core")
+                .long("core")
+                .help("With --exact, write the hashes of the k-mers shared by all genomes to this file, one per line")
+                .required(false)
+                .requires("exact")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("watch")
+            .about("Watches a directory and compares newly appearing FASTA/FASTQ files against reference sketches")
+            .arg(
+                Arg::new("dir")
+                .short('d')
+                .long("dir")
+                .help("Directory to watch for new FASTA/FASTQ files (.gz/.bz2/.xz/.zstd supported)")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("reference")
+                .short('r')
+                .long("reference")
+                .help("Prefix to search for reference genome files")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of output file results are appended to")
+                .required(false)
+                .default_value("watch_dist")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("interval")
+                .short('i')
+                .long("interval")
+                .help("Seconds between two scans of the directory")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("10")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("top")
+                .long("top")
+                .help("Only append the N closest references per new file, default to all")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Equation used to calculate distance: 1 for poisson model or 0 for binomial model")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("no_metadata")
+                .long("no-metadata")
+                .help("Do not start a new output with the commented parameter line (# lash=... k=... algorithm=...)")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("once")
+                .long("once")
+                .help("Process the files currently in the directory and exit")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("screen")
+            .about("Reports which reference genomes are contained in a read set, with containment and containment ANI")
+            .arg(
+                Arg::new("reads")
+                .help("FASTA/FASTQ files of the sample (.gz/.bz2/.xz/.lz4/.zstd supported), hashed together")
+                .required(true)
+                .num_args(1..)
+                .action(ArgAction::Append)
+            )
+            .arg(
+                Arg::new("reference")
+                .short('r')
+                .long("reference")
+                .help("Prefix of the reference sketch set, its parameters are used to hash the reads")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of output file to write results")
+                .required(false)
+                .default_value("screen.txt")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("min_containment")
+                .long("min-containment")
+                .help("Only report references with at least this fraction of their k-mers in the reads")
+                .required(false)
+                .value_parser(clap::value_parser!(f64))
+                .default_value("0")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("no_metadata")
+                .long("no-metadata")
+                .help("Do not start the output with the commented parameter line")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+        )
+        .subcommand(
+            Command::new("profile")
+            .about("Sketches a file set and compares it all-vs-all, reporting the time spent in each stage")
+            .arg(
+                Arg::new("file")
+                .short('f')
+                .long("file")
+                .help("One file containing list of FASTA/FASTQ files (.gz/.bz2/.xz/.lz4/.zstd supported), one per line. File must be UTF-8.")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Name of the per-stage timing table")
+                .required(false)
+                .default_value("profile.tsv")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("json")
+                .long("json")
+                .help("Also write the timings as flame graph JSON (name/value/children) to this file")
+                .required(false)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("kmer_length")
+                .short('k')
+                .long("kmer")
+                .help("Length of the kmer")
+                .required(false)
+                .default_value("16")
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("algorithm")
+                .short('a')
+                .long("algorithm")
+                .help("Which algorithm to use: HyperMinHash (hmh), HyperMinHash and HyperLogLog from one pass (hmh+hll), UltraLogLog (ull), HyperLogLog (hll), ExtendedHyperLogLog (ehll, between hll and ull), FracMinHash (fmh), bottom-k MinHash (minhash), SuperMinHash (superminhash, lower variance than minhash at the same size), Theta (theta), HyperBitBit (hyperbitbit, 136 bits, coarse), OrderMinHash (ordermh, order-aware, for sequences up to a few Mbp, keeps 8 bytes per base while sketching), or abundance-weighted ProbMinHash (probminhash) and DartMinHash (dartminhash, weighted Jaccard of k-mer counts)")
+                .required(false)
+                .default_value("hmh")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("precision")
+                .short('p')
+                .long("precision")
+                .help("Specifiy precision, for ull, ehll, hll and hmh+hll only.")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("scaled")
+                .long("scaled")
+                .help("Keep one k-mer hash in this many, for fmh only")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1000")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("sketch_size")
+                .long("sketch-size")
+                .help("Number of k-mer hashes kept, for minhash, superminhash, theta, ordermh, probminhash and dartminhash only")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1000")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("seed")
+                .short('s')
+                .long("seed")
+                .help("Random seed")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("42")
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("split")
+            .about("Splits a sketch set into shards that can be used on their own")
+            .arg(
+                Arg::new("input")
+                .short('i')
+                .long("input")
+                .help("Prefix of the sketch set to split")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Prefix of the shards, written as <output>_1, <output>_2, ...")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("parts")
+                .long("parts")
+                .help("Number of shards, sketch counts differ by at most one")
+                .required_unless_present("max_size")
+                .conflicts_with("max_size")
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("max_size")
+                .long("max-size")
+                .help("Largest uncompressed size of the sketches in one shard, e.g. 500M or 2G")
+                .required(false)
+                .value_parser(sketchset::parse_size)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("paste")
+            .about("Combines sketch sets built with the same parameters into one")
+            .arg(
+                Arg::new("inputs")
+                .help("Prefixes of the sketch sets, k, algorithm, precision and seed must match")
+                .required(true)
+                .num_args(1..)
+                .action(ArgAction::Append)
+            )
+            .arg(
+                Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Prefix of the combined sketch set")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("fetchdb")
+            .about("Downloads a prebuilt reference sketch database listed in a catalog and verifies its checksums")
+            .arg(
+                Arg::new("catalog")
+                .short('c')
+                .long("catalog")
+                .help("TOML catalog of databases, local file or https:// URL")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("list")
+                .long("list")
+                .help("List the databases of the catalog and exit")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("name")
+                .short('n')
+                .long("name")
+                .help("Name of the database to download")
+                .required_unless_present("list")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("db_version")
+                .long("db-version")
+                .help("Version of the database, default to the last one listed")
+                .required(false)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Prefix the sketch set is written to, default to the database name")
+                .required(false)
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("update")
+            .about("Sketches new genomes with the parameters of an existing sketch set and appends them to it")
+            .arg(
+                Arg::new("input")
+                .short('i')
+                .long("input")
+                .help("Prefix of the sketch set to extend")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("file")
+                .short('f')
+                .long("file")
+                .help("One file containing list of FASTA/FASTQ files (.gz/.bz2/.xz/.lz4/.zstd supported), one per line. File must be UTF-8.")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("remove")
+            .about("Deletes genomes from an existing sketch set, parameters are left intact")
+            .arg(
+                Arg::new("input")
+                .short('i')
+                .long("input")
+                .help("Prefix of the sketch set to edit")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("names")
+                .short('n')
+                .long("names")
+                .help("File with the names of the genomes to remove, one per line, as listed in <prefix>_files.json")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("rename")
+            .about("Relabels genomes of an existing sketch set, the sketches are not touched")
+            .arg(
+                Arg::new("input")
+                .short('i')
+                .long("input")
+                .help("Prefix of the sketch set to edit")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("mapping")
+                .short('m')
+                .long("mapping")
+                .help("Tab-separated file of old name and new name, one genome per line")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("convert")
+            .about("Rewrites a sketch set in the current format version, optionally with another zstd level")
+            .arg(
+                Arg::new("input")
+                .short('i')
+                .long("input")
+                .help("Prefix of the sketch set to convert")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Prefix of the converted sketch set")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("level")
+                .short('l')
+                .long("level")
+                .help("zstd compression level of the sketches (1-22)")
+                .required(false)
+                .value_parser(clap::value_parser!(i32).range(1..=22))
+                .default_value("3")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("validate")
+            .about("Checks that sketch sets are complete and readable, reporting PASS/FAIL per file")
+            .arg(
+                Arg::new("inputs")
+                .help("Prefixes of the sketch sets to check")
+                .required(true)
+                .num_args(1..)
+                .action(ArgAction::Append)
+            )
+        )
+        .subcommand(
+            Command::new("stats")
+            .about("Reports the estimated number of distinct k-mers and genome size of every sketch in a set, and k-mer frequency spectra of read sets sketched with --count-min")
+            .arg(
+                Arg::new("input")
+                .short('i')
+                .long("input")
+                .help("Prefix of the sketch set")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of output file to write results")
+                .required(false)
+                .default_value("stats.txt")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("merge-results")
+            .about("Merges partial distance outputs into one table, checking that their parameters match")
+            .arg(
+                Arg::new("inputs")
+                .help("Distance outputs of dist runs on shards or resumed runs")
+                .required(true)
+                .num_args(1..)
+                .action(ArgAction::Append)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of the merged output")
+                .required(false)
+                .default_value("merged.txt")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("dm")
+                .long("dm")
+                .help("Write the merged distances as a matrix, references as rows and queries as columns")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+            .arg(
+                Arg::new("no_metadata")
+                .long("no-metadata")
+                .help("Do not start the output with the commented parameter line")
+                .action(clap::ArgAction::SetTrue)
+                .num_args(0)
+            )
+        )
+        .subcommand(
+            Command::new("bench")
+            .about("Sketches a file set with each algorithm and precision, reporting wall time, peak memory and sketch size")
+            .arg(
+                Arg::new("file")
+                .short('f')
+                .long("file")
+                .help("One file containing list of FASTA/FASTQ files (.gz/.bz2/.xz/.lz4/.zstd supported), one per line. File must be UTF-8.")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("kmer_length")
+                .short('k')
+                .long("kmer")
+                .help("length of the kmer")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("16")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("algorithms")
+                .short('a')
+                .long("algorithms")
+                .help("Comma-separated algorithms to benchmark")
+                .required(false)
+                .value_delimiter(',')
+                .value_parser(["hmh", "hmh+hll", "hll", "ehll", "ull", "fmh", "minhash", "superminhash", "theta", "hyperbitbit", "ordermh", "probminhash", "dartminhash"])
+                .default_value("hmh,hll,ull")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("precisions")
+                .short('p')
+                .long("precisions")
+                .help("Comma-separated precisions for hll, ehll and ull, the other algorithms run once")
+                .required(false)
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(u32))
+                .default_value("10,12,14")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of output file to write results")
+                .required(false)
+                .default_value("bench.tsv")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("simulate")
+            .about("Simulates genome pairs with known mutation rates and reports estimated vs true distance per algorithm")
+            .arg(
+                Arg::new("kmer_length")
+                .short('k')
+                .long("kmer")
+                .help("length of the kmer")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("16")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("algorithms")
+                .short('a')
+                .long("algorithms")
+                .help("Comma-separated algorithms to evaluate")
+                .required(false)
+                .value_delimiter(',')
+                .value_parser(["hmh", "hmh+hll", "hll", "ehll", "ull", "fmh", "minhash", "superminhash", "theta", "hyperbitbit", "ordermh", "probminhash", "dartminhash"])
+                .default_value("hmh,hll,ull")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("precisions")
+                .short('p')
+                .long("precisions")
+                .help("Comma-separated precisions for hll, ehll and ull, the other algorithms run once")
+                .required(false)
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(u32))
+                .default_value("10")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("rates")
+                .short('r')
+                .long("rates")
+                .help("Comma-separated substitution rates of the mutated copies")
+                .required(false)
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(f64))
+                .default_value("0.001,0.005,0.01,0.02,0.05,0.1")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("length")
+                .short('l')
+                .long("length")
+                .help("Length of the simulated genomes in bp")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1000000")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("replicates")
+                .long("replicates")
+                .help("Genome pairs simulated per mutation rate")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .default_value("3")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("random_seed")
+                .long("random-seed")
+                .help("Seed of the simulated sequences, the same seed gives the same genomes")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("seed")
+                .short('s')
+                .long("seed")
+                .help("Hash seed")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("42")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Equation used to calculate distance: 1 for poisson model or 0 for binomial model")
+                .required(false)
+                .value_parser(clap::value_parser!(u64))
+                .default_value("1")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("output_file")
+                .short('o')
+                .long("output_file")
+                .help("Name of output file to write results")
+                .required(false)
+                .default_value("simulate.tsv")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads to use, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+        .subcommand(
+            Command::new("run")
+            .about("Runs the sketch/dist jobs described in a TOML file on a shared thread pool")
+            .arg(
+                Arg::new("config")
+                .help("TOML file with one [[job]] table per job, keys are the long options of the job's subcommand")
+                .required(true)
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("report")
+                .short('o')
+                .long("report")
+                .help("Name of the consolidated report written after all jobs")
+                .required(false)
+                .default_value("run_report.tsv")
+                .action(ArgAction::Set)
+            )
+            .arg(
+                Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .help("Number of threads shared by all jobs, default to all logical cores")
+                .required(false)
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Set)
+            )
+        )
+}
 
+fn run_command(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     match matches.subcommand() {
         Some(("sketch", s_matches)) => {
-            // organize the inputs
-            let sketch_file_name = s_matches.get_one::<String>("file").expect("required");
-            let kmer_length: usize = *s_matches.get_one::<usize>("kmer_length").expect("required");
+            // organize the inputs, a spaced seed sets k to its span
+            let seed_mask = s_matches
+                .get_one::<String>("seed_mask")
+                .map(|m| spaced::SeedMask::parse(m))
+                .transpose()?;
+            let mut kmer_lengths: Vec<usize> = match &seed_mask {
+                Some(mask) => vec![mask.span()],
+                None => s_matches
+                    .get_many::<usize>("kmer_length")
+                    .expect("has default")
+                    .copied()
+                    .collect(),
+            };
+            kmer_lengths.sort_unstable();
+            kmer_lengths.dedup();
             let threads = s_matches
                 .get_one::<usize>("threads")
                 .copied()
                 .unwrap_or_else(num_cpus::get);
 
-            rayon::ThreadPoolBuilder::new()
-                .num_threads(threads.max(1))
-                .build_global()
-                .unwrap();
+            init_thread_pool(threads);
 
             let output_name = s_matches.get_one::<String>("output").expect("required");
             let alg = s_matches.get_one::<String>("algorithm").expect("required");
             let seed: u64 = *s_matches.get_one::<u64>("seed").expect("required");
 
-            let aa = false; //s_matches.get_flag("aa");
+            let translate = s_matches.get_flag("translate");
+            let protein = s_matches.get_one::<String>("molecule").expect("has default") == "protein";
+            if translate && protein {
+                return Err("--translate reads nucleotide inputs, not --molecule protein".into());
+            }
+            let aa = protein || translate;
+            if aa && kmer_lengths.iter().any(|&k| k > 12) {
+                return Err("protein k-mers are at most 12 residues long, give -k 7 to 12 with --molecule protein or --translate".into());
+            }
 
-            let files: Vec<String> = {
-                let f = File::open(sketch_file_name)?;
-                BufReader::new(f)
-                    .lines()
-                    .filter_map(|l| l.ok())
-                    .filter(|l| !l.trim().is_empty())
+            // a stream piped in with --stdin, or a list of files where "-" also stands for stdin
+            let stdin_name = s_matches.get_one::<String>("name").expect("required");
+            let mut files: Vec<Sample> = if s_matches.get_flag("stdin") {
+                vec![Sample { name: stdin_name.clone(), path: STDIN.to_string(), more: Vec::new(), source: None }]
+            } else if let Some(manifest) = s_matches.get_one::<String>("manifest") {
+                utils::read_manifest(manifest)?
+            } else {
+                let extensions: Vec<String> = s_matches.get_many::<String>("extension").map(|e| e.cloned().collect()).unwrap_or_default();
+                utils::read_inputs(s_matches.get_one::<String>("file").expect("required"), s_matches.get_flag("recursive"), &extensions)?
+                    .into_iter()
+                    .map(|path| match path.as_str() {
+                        STDIN => Sample { name: stdin_name.clone(), path, more: Vec::new(), source: None },
+                        _ => Sample::from_path(path),
+                    })
                     .collect()
             };
+            if files.iter().filter(|f| f.path == STDIN).count() > 1 {
+                return Err("standard input can only be sketched once".into());
+            }
+            if s_matches.get_one::<String>("file").is_some_and(|f| f == STDIN) && files.iter().any(|f| f.path == STDIN) {
+                return Err("standard input holds the file list with -f -, it cannot also be sketched".into());
+            }
+            let cache_dir = s_matches.get_one::<String>("cache_dir").map(std::path::PathBuf::from).unwrap_or_else(ncbi::default_cache_dir);
+            ncbi::resolve(&mut files, &cache_dir)?;
+            sra::resolve(&mut files)?;
+            // members of tar archives, extracted until the sets are written
+            let (files, _extracted) = archive::expand(files)?;
+            utils::check_inputs(&files)?;
+            // a scanned directory or glob finds other files once it changes
+            if s_matches.get_one::<String>("file").is_some_and(|f| utils::is_scanned(f)) {
+                let inputs = format!("{}_inputs.json", output_name);
+                utils::write_inputs(&inputs, &files)?;
+                println!("{} input files found, listed in {}.", files.len(), inputs);
+            }
 
-            let result: Result<(), Box<dyn Error>>;
-            if alg == "hmh" {
-                // create hypermash object and sketch
-                result = sketch_files::<Sketch> (
-                    None,
-                    files,
-                    kmer_length,
-                    output_name.clone(),
-                    threads as u32,
-                    seed,
-                    aa
-                );
-            } else if alg == "hll" {
-                let precision: u32 = *s_matches.get_one::<usize>("precision").unwrap_or(&10) as u32;
-                result = sketch_files::<HyperLogLog<i64>>(
-                    Some(precision),
-                    files,
-                    kmer_length,
-                    output_name.clone(),
-                    threads as u32,
-                    seed,
-                    aa
-                );
-            } else if alg == "ull" {
-                let precision: u32 = *s_matches.get_one::<usize>("precision").unwrap_or(&10) as u32;
-                result = sketch_files::<UltraLogLog>(
-                    Some(precision),
-                    files,
-                    kmer_length,
-                    output_name.clone(),
-                    threads as u32,
-                    seed,
-                    aa
-                );
+            let mut options = utils::SketchOptions {
+                count_min: s_matches.get_one::<usize>("count_min").copied(),
+                per_contig: s_matches.get_flag("per_contig"),
+                min_count: *s_matches.get_one::<u32>("min_count").expect("has default"),
+                exact_counts: s_matches.get_flag("exact_counts"),
+                min_copies: *s_matches.get_one::<u32>("min_copies").expect("has default"),
+                seed_mask,
+                minimizer_window: s_matches
+                    .get_flag("minimizer")
+                    .then(|| *s_matches.get_one::<u32>("window").expect("has default") as usize),
+                syncmer: s_matches
+                    .get_one::<String>("syncmer")
+                    .map(|s| sampling::SyncmerScheme::parse(s))
+                    .transpose()?,
+                strobemer: match s_matches.get_one::<String>("seed_type").expect("has default").as_str() {
+                    "kmer" => None,
+                    seed_type => Some(strobemer::StrobeScheme::new(
+                        seed_type,
+                        s_matches.get_one::<String>("strobe_window").expect("has default"),
+                    )?),
+                },
+                translate,
+                hpc: s_matches.get_flag("hpc"),
+                nthash: s_matches.get_one::<String>("hash").expect("has default") == "nthash",
+                circular: s_matches.get_flag("circular"),
+                exclude_softmasked: s_matches.get_flag("exclude_softmasked"),
+                filter_low_complexity: s_matches.get_flag("filter_low_complexity"),
+                blocklist: None,
+                include_fasta: None,
+                include_bed: None,
+                subsample: (["ull", "hll", "ehll", "probminhash", "dartminhash"].contains(&alg.as_str())
+                    && s_matches.value_source("scaled") == Some(clap::parser::ValueSource::CommandLine))
+                    .then(|| *s_matches.get_one::<usize>("scaled").expect("has default") as u64),
+                ambiguous: ambiguous::AmbiguityPolicy::parse(s_matches.get_one::<String>("ambiguous").expect("has default"))?,
+                ambiguous_bases: Default::default(),
+                rna_bases: Default::default(),
+                min_seq_len: *s_matches.get_one::<u64>("min_seq_len").expect("has default") as usize,
+                max_seq_len: s_matches.get_one::<u64>("max_seq_len").map(|&max| max as usize),
+                skipped_records: Default::default(),
+                stranded: s_matches.get_flag("no_canonical"),
+                min_qual: *s_matches.get_one::<u8>("min_qual").expect("has default"),
+                min_mean_qual: *s_matches.get_one::<u8>("min_mean_qual").expect("has default"),
+                trim_head: *s_matches.get_one::<u32>("trim_head").expect("has default") as usize,
+                trim_tail: *s_matches.get_one::<u32>("trim_tail").expect("has default") as usize,
+                crop: s_matches.get_one::<u32>("crop").map(|&c| c as usize),
+                read_fraction: s_matches.get_one::<f64>("subsample").copied(),
+                interleaved: s_matches
+                    .get_flag("interleaved")
+                    .then(|| utils::Mates::parse(s_matches.get_one::<String>("keep_mate").expect("has default")))
+                    .transpose()?,
+                input_bytes: None,
+                resume: s_matches.get_flag("resume"),
+            };
+            if options.strobemer.is_some()
+                && (options.seed_mask.is_some() || options.minimizer_window.is_some() || options.syncmer.is_some())
+            {
+                return Err("strobemers cannot be combined with --seed-mask, --minimizer or --syncmer".into());
+            }
+            if aa && (options.seed_mask.is_some() || options.syncmer.is_some() || options.strobemer.is_some()) {
+                return Err("--seed-mask, --syncmer and strobemers apply to dna k-mers only, not protein k-mers".into());
+            }
+            if aa && (options.hpc || options.stranded) {
+                return Err("--hpc and --no-canonical apply to dna k-mers only, not protein k-mers".into());
+            }
+            if options.ambiguous != ambiguous::AmbiguityPolicy::Drop && aa {
+                return Err("--ambiguous applies to dna k-mers only, not protein k-mers".into());
+            }
+            if options.ambiguous == ambiguous::AmbiguityPolicy::Expand && (options.hpc || options.strobemer.is_some()) {
+                return Err("--ambiguous expand sketches single k-mers, not with --hpc or strobemers".into());
+            }
+            if options.nthash
+                && (aa || options.seed_mask.is_some() || options.strobemer.is_some() || options.syncmer.is_some() || options.filter_low_complexity)
+            {
+                return Err("--hash nthash hashes contiguous dna k-mers, not with protein k-mers, --seed-mask, strobemers, --syncmer or --filter-low-complexity, which read packed k-mers".into());
+            }
+            if options.circular && (aa || options.strobemer.is_some()) {
+                return Err("--circular applies to dna k-mers and spaced seeds, not protein k-mers or strobemers".into());
+            }
+            if options.stranded && (options.seed_mask.is_some() || options.strobemer.is_some()) {
+                return Err("--no-canonical applies to contiguous k-mers, not spaced seeds or strobemers".into());
+            }
+            if options.filter_low_complexity
+                && (aa || options.seed_mask.is_some() || options.strobemer.is_some() || kmer_lengths.iter().any(|&k| k > 32))
+            {
+                return Err("--filter-low-complexity applies to contiguous dna k-mers with k up to 32".into());
+            }
+            let listed = ["blocklist", "include_fasta"].iter().any(|arg| s_matches.get_one::<String>(arg).is_some());
+            if listed && (aa || options.seed_mask.is_some() || options.strobemer.is_some()) {
+                return Err("--blocklist and --include-fasta apply to contiguous dna k-mers, not protein k-mers, spaced seeds or strobemers".into());
+            }
+            // recorded absolute, so sets are found again from anywhere
+            let absolute = |path: &String| std::fs::canonicalize(path).map(|p| p.to_string_lossy().into_owned());
+            if let Some(path) = s_matches.get_one::<String>("blocklist") {
+                options.blocklist = Some(kmerlist::KmerList::load(&absolute(path)?, &kmer_lengths, &options, seed)?);
+            }
+            if let Some(path) = s_matches.get_one::<String>("include_fasta") {
+                options.include_fasta = Some(kmerlist::KmerList::load(&absolute(path)?, &kmer_lengths, &options, seed)?);
+            }
+            if let Some(path) = s_matches.get_one::<String>("include_bed") {
+                options.include_bed = Some(regions::Regions::load(&absolute(path)?)?);
+            }
+            if let Some(scheme) = &options.syncmer {
+                for &k in &kmer_lengths {
+                    scheme.check(k)?;
+                }
+            }
+            if options.read_fraction.is_some_and(|f| !(f > 0.0 && f <= 1.0)) {
+                return Err("--subsample takes a fraction of reads above 0 and at most 1".into());
+            }
+            if options.max_seq_len.is_some_and(|max| max < options.min_seq_len) {
+                return Err("--max-seq-len must be at least --min-seq-len".into());
+            }
+            if options.exact_counts && !cfg!(feature = "exact-counts") {
+                return Err("--exact-counts needs lash built with --features exact-counts".into());
+            }
+            if options.exact_counts && options.min_count < 2 {
+                return Err("--exact-counts only applies with --min-count 2 or more".into());
+            }
+            if options.min_count > 2 && !options.exact_counts {
+                return Err("--min-count above 2 needs --exact-counts, the Bloom filter only drops singletons".into());
+            }
+
+            // several precisions: sketched once at the highest, the others derived from it
+            let precision_values: Vec<&String> = s_matches
+                .get_many::<String>("precision")
+                .expect("has default")
+                .collect();
+            let auto_precision = precision_values.iter().any(|p| p.as_str() == "auto");
+            let target_error = *s_matches.get_one::<f64>("target_error").expect("has default");
+            let mut precisions: Vec<usize> = if auto_precision {
+                if precision_values.len() > 1 {
+                    return Err("--precision auto cannot be combined with other precisions".into());
+                }
+                let precision = utils::auto_precision(alg, target_error, &files)?;
+                println!("Precision {} chosen for a target error of {}.", precision, target_error);
+                vec![precision]
             } else {
-                // input for alg is not hmh, ull, or hll
-                panic!("Algorithm must be either hmh, ull, or hll");
+                precision_values
+                    .iter()
+                    .map(|p| p.parse::<usize>().map_err(|_| format!("invalid precision {}, give a number or auto", p)))
+                    .collect::<Result<_, _>>()?
+            };
+            precisions.sort_unstable();
+            precisions.dedup();
+            let top_precision = *precisions.last().expect("has default");
+            if precisions.len() > 1 && alg != "hll" && alg != "ull" {
+                return Err("several precisions can only be given for hll and ull".into());
             }
+            // one set per k-mer length and precision: <output>_k21_p10, without the suffixes
+            // that have a single value
+            let bases: Vec<(usize, String)> = kmer_lengths
+                .iter()
+                .map(|&k| match kmer_lengths.len() {
+                    1 => (k, output_name.clone()),
+                    _ => (k, format!("{}_k{}", output_name, k)),
+                })
+                .collect();
+            let sets: Vec<(usize, String)> = bases
+                .iter()
+                .map(|(k, base)| match precisions.len() {
+                    1 => (*k, base.clone()),
+                    _ => (*k, format!("{}_p{}", base, top_precision)),
+                })
+                .collect();
+
+            // size parameter of the algorithm: the largest precision, --scaled or --sketch-size
+            let size_key = utils::size_key(alg);
+            let size: Option<usize> = size_key.map(|key| match key {
+                "precision" => top_precision,
+                _ => *s_matches.get_one::<usize>(key).expect("has default"),
+            });
+            let result = with_sketch_type!(
+                alg.as_str(),
+                sketch_files(size.map(|size| size as u32), files, &sets, threads as u32, seed, aa, &options)
+            );
 
             let molecule_param = if aa {
                 "amino_acid".to_string()
@@ -252,89 +2557,52 @@ fn main() -> Result<(), Box<dyn Error>> {
             };
 
             // parameter JSONs
-            let params;
-            if alg == "ull" || alg == "hll" {
-                let precision: u32 = *s_matches.get_one::<usize>("precision").unwrap_or(&10) as u32;
-                params = json!({
-                    "k": kmer_length.to_string(),
-                    "algorithm": alg,
-                    "precision": precision.to_string(),
-                    "seed": seed.to_string(),
-                    "molecule": molecule_param
-                });
-            } else {
-                params = json!({
-                    "k": kmer_length.to_string(),
-                    "algorithm": alg,
-                    "seed": seed.to_string(),
-                    "molecule": molecule_param
-                });
+            let mut params = json!({
+                "algorithm": alg,
+                "seed": seed.to_string(),
+                "molecule": molecule_param,
+                "format": format::FORMAT_VERSION.to_string()
+            });
+            if let (Some(key), Some(size)) = (size_key, size) {
+                params[key] = json!(size.to_string());
+            }
+            if alg == "hyperbitbit" {
+                params["expected_error"] = json!(hyperbitbit::ERROR_NOTE);
+            }
+            options.record(&mut params);
+            if auto_precision {
+                params["target_error"] = json!(target_error.to_string());
             }
 
             // writing out
-            File::create(format!("{}_parameters.json", output_name))?
-                .write_all(serde_json::to_string_pretty(&params)?.as_bytes())?;
+            for (k, set_name) in &sets {
+                params["k"] = json!(k.to_string());
+                File::create(format!("{}_parameters.json", set_name))?
+                    .write_all(serde_json::to_string_pretty(&params)?.as_bytes())?;
+            }
+            result?;
+            #[cfg(feature = "exact-counts")]
+            if options.exact_counts {
+                let peak = hashcount::PEAK_BYTES.load(std::sync::atomic::Ordering::Relaxed);
+                println!("Exact k-mer counts took up to {:.1} MiB per input and k.", peak as f64 / (1 << 20) as f64);
+            }
 
-            result
+            for ((_, base), (_, set_name)) in bases.iter().zip(&sets) {
+                for &precision in precisions.iter().rev().skip(1) {
+                    let target = format!("{}_p{}", base, precision);
+                    sketchset::downsize_set(set_name, &target, precision as u32, threads as u32)?;
+                }
+            }
+            if sets.len() > 1 || precisions.len() > 1 {
+                println!(
+                    "Sketch sets written for k {:?} and precisions {:?}, with prefix {}.",
+                    kmer_lengths, precisions, output_name
+                );
+            }
+            Ok(())
         }
         Some(("dist", s_matches)) => {
             let ref_prefix = s_matches.get_one::<String>("reference").expect("required");
-            let query_prefix = s_matches.get_one::<String>("query").expect("required");
-
-            fn find_files(prefix: &str) -> std::io::Result<HashMap<&'static str, String>> {
-                let mut files: Vec<String> = Vec::new();
-                let dir = "./"; // use curent directory
-
-                let norm_prefix = {
-                    let p = Path::new(prefix)
-                        .file_name()
-                        .and_then(|os_str| os_str.to_str())
-                        .unwrap_or(prefix);
-                    // .strip_prefix("./")
-                    // .unwrap_or(prefix)
-
-                    p.strip_prefix("./").unwrap_or(p)
-                };
-
-                for entry in fs::read_dir(dir)? {
-                    let entry = entry?;
-                    let path = entry.path();
-
-                    // if file starts with prefix, add it to vector of possible reference/ sketch files
-                    if path.is_file() {
-                        if let Some(filename) = path.file_name().and_then(|n| n.to_str()) {
-                            if filename.starts_with(norm_prefix) {
-                                files.push(filename.to_string());
-                            }
-                        }
-                    }
-                }
-
-                // if files.len() != 3 {
-                //     panic!("There should be 3 files starting with {} but {} were found instead",
-                //     prefix,
-                //     files.len());
-                // }
-                let mut file_map: HashMap<&str, String> = HashMap::new();
-                for file in files {
-                    if file.ends_with("parameters.json") {
-                        file_map.insert("params", file);
-                    } else if file.ends_with("files.json") {
-                        file_map.insert("files", file);
-                    } else if file.ends_with(".bin") {
-                        // .bin file for sketches
-                        file_map.insert("sketches", file);
-                    }
-                }
-                if file_map.keys().len() != 3 {
-                    panic!(
-                        "There should be 3 files starting with {} but {} were found instead",
-                        norm_prefix,
-                        file_map.keys().len()
-                    );
-                }
-                Ok(file_map)
-            }
 
             let output_file: &String = s_matches
                 .get_one::<String>("output_file")
@@ -344,10 +2612,21 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .copied()
                 .unwrap_or_else(num_cpus::get);
 
-            rayon::ThreadPoolBuilder::new()
-                .num_threads(threads.max(1))
-                .build_global()
-                .unwrap();
+            init_thread_pool(threads);
+
+            // raw query files are sketched into a temporary set, removed when dist returns
+            let query_set = match s_matches.get_many::<String>("query_fasta") {
+                Some(files) => {
+                    let files: Vec<String> = files.cloned().collect();
+                    let ref_params = read_params(&find_files(ref_prefix)?["params"])?;
+                    Some(sketchset::TempSketchSet::sketch(files, &ref_params, threads as u32)?)
+                }
+                None => None,
+            };
+            let query_prefix = match &query_set {
+                Some(set) => &set.prefix,
+                None => s_matches.get_one::<String>("query").expect("required"),
+            };
 
             // go through the files needed, find name file, sketch file, and param file
             let ref_files = find_files(ref_prefix)?;
@@ -359,36 +2638,46 @@ fn main() -> Result<(), Box<dyn Error>> {
             // println!("{}", query_param_file);
 
             // read in parameter json files into hashmaps
-            let contents_ref = fs::read_to_string(ref_param_file)?;
-            let contents_query = fs::read_to_string(query_param_file)?;
-            let ref_map: HashMap<String, String> = serde_json::from_str(&contents_ref)?;
-            let query_map: HashMap<String, String> = serde_json::from_str(&contents_query)?;
+            let ref_map = read_params(&ref_param_file)?;
+            let query_map = read_params(&query_param_file)?;
+
+            format::format_version(&ref_map).map_err(|e| format!("{}: {}", ref_prefix, e))?;
+            format::format_version(&query_map).map_err(|e| format!("{}: {}", query_prefix, e))?;
 
             // check that parameters match between ref and query genomes
-            if ref_map["k"] != query_map["k"] {
-                panic!("Genomes were not sketched with the same k");
-            }
-            if ref_map["algorithm"] != query_map["algorithm"] {
-                panic!("Algorithms do not match in query and sketch genomes")
-            }
-            if ref_map["algorithm"] == "ull" || ref_map["algorithm"] == "hll" {
-                if ref_map["precision"] != query_map["precision"] {
-                    panic!(
-                        "{} was not sketched with same precision btwn genomes",
-                        ref_map["algorithm"]
-                    );
+            utils::check_same_params(ref_prefix, &ref_map, query_prefix, &query_map)?;
+
+            // estimators of the algorithm, checked before any output is written
+            if let Some(estimator) = s_matches.get_one::<String>("estimator") {
+                let estimators: &[&str] = match ref_map["algorithm"].as_str() {
+                    "ull" => &["fgra", "ml", "martingale"],
+                    "hll" => &["classic", "improved"],
+                    _ => &[],
+                };
+                if estimators.is_empty() {
+                    return Err(format!("--estimator only applies to ull and hll sketches, not {}", ref_map["algorithm"]).into());
+                }
+                if !estimators.contains(&estimator.as_str()) {
+                    return Err(format!(
+                        "unknown estimator {} for {} sketches, choose from {}",
+                        estimator,
+                        ref_map["algorithm"],
+                        estimators.join(", ")
+                    ).into());
                 }
             }
-            // assign kmer length once k matches
-            let kmer_length: usize = ref_map["k"].parse()?;
-
-            // function to read in names of the genomes, outputs a vector of names
-            fn read_names(file_name: &str) -> std::io::Result<Vec<String>> {
-                let file = File::open(file_name)?;
-                let reader = BufReader::new(file);
-                let names: Vec<String> = serde_json::from_reader(reader)?;
-                Ok(names)
+            if s_matches.contains_id("ani_tool") && (ref_map.contains_key("per_contig") || query_map.contains_key("per_contig")) {
+                return Err("--ani-tool compares whole genome files, not --per-contig sketches".into());
             }
+            if s_matches.get_flag("compact") && ref_map["algorithm"] != "hll" {
+                return Err(format!("--compact only applies to hll sketches, not {}", ref_map["algorithm"]).into());
+            }
+            // assign kmer length once k matches, a spaced seed counts its 1s, a strobemer both strobes
+            let kmer_length: usize = match (ref_map.get("seed_mask"), ref_map.get("seed_type")) {
+                (Some(mask), _) => spaced::SeedMask::parse(mask)?.weight(),
+                (None, Some(_)) => strobemer::StrobeScheme::distance_k(ref_map["k"].parse()?),
+                (None, None) => ref_map["k"].parse()?,
+            };
 
             //create query sketch hashmap
             let query_namefile = query_files["files"].clone();
@@ -406,214 +2695,840 @@ fn main() -> Result<(), Box<dyn Error>> {
             let equation = *s_matches.get_one::<u64>("model").expect("required");
             let fp32 = s_matches.get_flag("fp32");
 
+            let metadata = (!s_matches.get_flag("no_metadata"))
+                .then(|| metadata_header(&ref_map, equation as u8));
+            if let Some(line) = &metadata {
+                let mut file = output.lock().unwrap();
+                writeln!(file, "{}", line)?;
+            }
             if !create_matrix {
                 let mut file = output.lock().unwrap();
                 writeln!(file, "Reference\tQuery\tDistance")?;
             }
             
-            // function to compute distance from fraction
-            fn compute_distance<F: Float>(frac: F, kmer_length: usize, equation: u8) -> F {
-                let k = F::from(kmer_length).unwrap();
-
-                match equation {
-                    1 => (-frac.ln() / k).min(F::one()),
-                    0 => F::one() - frac.powf(F::one() / k),
-                    _ => panic!("model needs to be 0 or 1"),
-                }
-            }
-
-            // for triangular matrix printing 
-            let file_idx = Arc::new(Mutex::new(HashMap::<String, usize>::new()));
+            // rows kept in memory for the exact second pass
+            let verify_top = s_matches.get_one::<usize>("verify_top").copied();
+            let ani_tool = s_matches.get_one::<String>("ani_tool").cloned();
+            let ani_top = *s_matches.get_one::<usize>("ani_top").expect("required");
+            let min_ani = s_matches.get_one::<f64>("assign").copied();
+            let hits = (verify_top.is_some() || ani_tool.is_some() || min_ani.is_some())
+                .then(|| Arc::new(Mutex::new(Vec::new())));
+            let writer = DistWriter {
+                output,
+                create_matrix,
+                same_files,
+                file_idx: Mutex::new(HashMap::new()),
+                kmer_length,
+                equation,
+                hits: hits.clone(),
+            };
+            let writer = &writer;
 
-            // callback and emit function
-            fn print_dist<T: Float + std::fmt::Display>(
-                distance_list: Vec<(&String, &String, T)>, 
-                output: &Arc<Mutex<File>>, 
-                create_matrix: bool, 
-                same_files: bool, 
-                file_idx: &Arc<Mutex<HashMap<String, usize>>>, 
-                kmer_length: usize, 
-                equation: u64) {
-                // printing columns for matrix output using the query list
-                let mut file = output.lock().unwrap();
-                if create_matrix && !distance_list.is_empty() && distance_list[0].0.is_empty() {
-                    for (i, col) in distance_list.iter().enumerate() {
-                        write!(file, "\t{}", col.1).expect("Error writing columns for matrix output");
-                        if same_files {
-                            let mut idx = file_idx.lock().unwrap();
-                            idx.insert(col.1.clone(), i);
-                        }
-                    }
-                }
-                else {
-                    for (i, row) in distance_list.iter().enumerate() {
-                        let r_name = row.0;
-                        let q_name = row.1;
-                        let d: T = if q_name == r_name {
-                            T::zero()
-                        } else {
-                            compute_distance::<T>(row.2, kmer_length, equation as u8)
-                        };
-                        
-                        if !create_matrix {
-                            writeln!(file, "{}\t{}\t{:.6}", r_name, q_name, d)
-                                .expect("Error writing to file");
-                        } else {
-                            if i == 0 { 
-                                write!(file, "\n{}", r_name).expect("Error writing to file"); 
-                            }
-                            write!(file, "\t{:.6}", d).expect("Error writing to file");            
-                        }
-                    
-                    }
-                }
-                //Ok(())
-            }
+            let dist_options = utils::DistOptions {
+                estimator: s_matches
+                    .get_one::<String>("estimator")
+                    .cloned()
+                    .unwrap_or_else(|| if ref_map["algorithm"] == "ull" { "fgra" } else { "classic" }.to_string()),
+                compact: s_matches.get_flag("compact"),
+                create_matrix,
+                same_files,
+            };
 
             // for each algorithm, use a different generic depending on if user wants F32 or F64
-            let result = if ref_map["algorithm"] == "hmh" {
+            if ref_map["algorithm"] == "hmh" {
                 if fp32 {
-                    let emit = move |rows: Vec<(&String, &String, f32)>| {
-                        print_dist(
-                            rows, 
-                            &output, 
-                            create_matrix, 
-                            same_files, 
-                            &file_idx, 
-                            kmer_length, 
-                            equation
-                        );
-                    };
+                    let emit = move |rows: Vec<(&String, &String, f32)>| writer.print(rows);
                     hmh_distance::<_, f32>(
                         reference_names,
                         ref_sketch_file_name,
                         query_names,
                         query_sketch_file_name,
-                        create_matrix,
-                        same_files,
+                        &dist_options,
                         emit,
                     )?
                 } else {
-                    let emit = move |rows: Vec<(&String, &String, f64)>| {
-                        print_dist(
-                            rows, 
-                            &output, 
-                            create_matrix, 
-                            same_files, 
-                            &file_idx, 
-                            kmer_length, 
-                            equation
-                        );
-                    };
-                    hmh_distance::<_, f64>(
+                    let emit = move |rows: Vec<(&String, &String, f64)>| writer.print(rows);
+                    hmh_distance::<_, f64>(
+                        reference_names,
+                        ref_sketch_file_name,
+                        query_names,
+                        query_sketch_file_name,
+                        &dist_options,
+                        emit
+                    )?
+                }
+            } else if ref_map["algorithm"] == "ull" {
+                if fp32 {
+                    let emit = move |rows: Vec<(&String, &String, f32)>| writer.print(rows);
+                    ull_distance::<_, f32>(
+                        reference_names,
+                        ref_sketch_file_name,
+                        query_names,
+                        query_sketch_file_name,
+                        &dist_options,
+                        emit
+                    )?
+                } else {
+                    let emit = move |rows: Vec<(&String, &String, f64)>| writer.print(rows);
+                    ull_distance::<_, f64>(
                         reference_names,
                         ref_sketch_file_name,
                         query_names,
                         query_sketch_file_name,
-                        create_matrix,
-                        same_files,
+                        &dist_options,
                         emit
                     )?
                 }
-            } else if ref_map["algorithm"] == "ull" {
-                let estimator = s_matches
-                    .get_one::<String>("estimator")
-                    .cloned()
-                    .unwrap_or_else(|| "fgra".to_string());
+            } else if ["hmh+hll", "ehll", "fmh", "minhash", "superminhash", "theta", "hyperbitbit", "ordermh", "probminhash", "dartminhash"].contains(&ref_map["algorithm"].as_str()) {
                 if fp32 {
-                    let emit = move |rows: Vec<(&String, &String, f32)>| {
-                        print_dist(
-                            rows, 
-                            &output, 
-                            create_matrix, 
-                            same_files, 
-                            &file_idx, 
-                            kmer_length, 
-                            equation
-                        );
-                    };
-                    ull_distance::<_, f32>(
+                    let emit = move |rows: Vec<(&String, &String, f32)>| writer.print(rows);
+                    jaccard_distance::<_, f32>(
+                        &ref_map["algorithm"],
                         reference_names,
                         ref_sketch_file_name,
                         query_names,
                         query_sketch_file_name,
-                        estimator, 
-                        create_matrix,
-                        same_files,
+                        &dist_options,
                         emit
                     )?
                 } else {
-                    let emit = move |rows: Vec<(&String, &String, f64)>| {
-                        print_dist(
-                            rows, 
-                            &output, 
-                            create_matrix, 
-                            same_files, 
-                            &file_idx, 
-                            kmer_length, 
-                            equation
-                        );
-                    };
-                    ull_distance::<_, f64>(
+                    let emit = move |rows: Vec<(&String, &String, f64)>| writer.print(rows);
+                    jaccard_distance::<_, f64>(
+                        &ref_map["algorithm"],
                         reference_names,
                         ref_sketch_file_name,
                         query_names,
                         query_sketch_file_name,
-                        estimator, 
-                        create_matrix,
-                        same_files,
+                        &dist_options,
                         emit
                     )?
                 }
             } else {
                 // HLL
                 if fp32 {
-                    let emit = move |rows: Vec<(&String, &String, f32)>| {
-                        print_dist(
-                            rows, 
-                            &output, 
-                            create_matrix, 
-                            same_files, 
-                            &file_idx, 
-                            kmer_length, 
-                            equation
-                        );
-                    };
+                    let emit = move |rows: Vec<(&String, &String, f32)>| writer.print(rows);
                     hll_distance::<_, f32>(
                         reference_names,
                         ref_sketch_file_name,
                         query_names,
                         query_sketch_file_name,
-                        create_matrix,
-                        same_files,
+                        &dist_options,
                         emit
                     )?
                 } else {
-                    let emit = move |rows: Vec<(&String, &String, f64)>| {
-                        print_dist(
-                            rows, 
-                            &output, 
-                            create_matrix, 
-                            same_files, 
-                            &file_idx, 
-                            kmer_length, 
-                            equation
-                        );
-                    };
+                    let emit = move |rows: Vec<(&String, &String, f64)>| writer.print(rows);
                     hll_distance::<_, f64>(
                         reference_names,
                         ref_sketch_file_name,
                         query_names,
                         query_sketch_file_name,
-                        create_matrix,
-                        same_files,
+                        &dist_options,
                         emit
                     )?
                 }
-            };
+            }
 
             println!("Distances computed.");
-            Ok(result)
+
+            let mut hits = hits
+                .map(|h| std::mem::take(&mut *h.lock().unwrap()))
+                .unwrap_or_default();
+            if same_files {
+                // only one triangle was computed, every genome is also a query of the other half
+                let mirrored: Vec<_> = hits
+                    .iter()
+                    .filter(|(r_name, q_name, _)| r_name != q_name)
+                    .map(|(r_name, q_name, d)| (q_name.clone(), r_name.clone(), *d))
+                    .collect();
+                hits.extend(mirrored);
+            }
+
+            if let Some(min_ani) = min_ani {
+                let assign_file = format!("{}_assign", output_file);
+                let mut file = File::create(&assign_file)?;
+                if let Some(line) = &metadata {
+                    writeln!(file, "{}", line)?;
+                }
+                writeln!(file, "Query\tReference\tDistance\tANI\tMargin\tStatus")?;
+                let pairs = best_hits(&hits, 2);
+                let mut assigned = 0;
+                let mut queries = 0;
+                for (i, (r_name, q_name, d)) in pairs.iter().enumerate() {
+                    if i > 0 && pairs[i - 1].1 == *q_name {
+                        continue;
+                    }
+                    // distance gap to the runner-up, NA with a single reference
+                    let margin = match pairs.get(i + 1) {
+                        Some(next) if next.1 == *q_name => format!("{:.6}", next.2 - d),
+                        _ => "NA".to_string(),
+                    };
+                    let ani = 100.0 * (1.0 - d);
+                    let status = if ani >= min_ani { "assigned" } else { "unassigned" };
+                    if ani >= min_ani {
+                        assigned += 1;
+                    }
+                    queries += 1;
+                    writeln!(
+                        file,
+                        "{}\t{}\t{:.6}\t{:.4}\t{}\t{}",
+                        q_name, r_name, d, ani, margin, status
+                    )?;
+                }
+                println!(
+                    "{} of {} queries assigned at {}% ANI, table written to {}.",
+                    assigned, queries, min_ani, assign_file
+                );
+            }
+
+            // the genomes of hits, by name, with the files they were read from
+            let (ref_entries, query_entries) = match verify_top.is_some() || ani_tool.is_some() {
+                true => (entries_by_name(&ref_namefile)?, entries_by_name(&query_namefile)?),
+                false => Default::default(),
+            };
+
+            if let Some(top_n) = verify_top {
+                // k-mers selected as the reference set was sketched
+                let params = utils::SketchParams::from_map(&ref_map)?;
+                let pairs = best_hits(&hits, top_n);
+
+                // exact k-mer sets of every genome involved, read once
+                let mut genomes: Vec<&Entry> = pairs.iter().flat_map(|p| [&ref_entries[&p.0], &query_entries[&p.1]]).collect();
+                genomes.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.path.cmp(&b.path)));
+                genomes.dedup();
+                let kmers: HashMap<&Entry, hashbrown::HashSet<u64>> = genomes
+                    .par_iter()
+                    .map(|entry| {
+                        let set = kmer_set(entry, &params).map_err(|e| format!("Error re-reading {}: {}", entry.name, e))?;
+                        Ok((*entry, set))
+                    })
+                    .collect::<Result<_, String>>()?;
+
+                let verified_file = format!("{}_verified", output_file);
+                let mut file = File::create(&verified_file)?;
+                if let Some(line) = &metadata {
+                    writeln!(file, "{}", line)?;
+                }
+                writeln!(file, "Reference\tQuery\tDistance\tExactJaccard\tExactContainment\tExactDistance")?;
+                for (r_name, q_name, d) in &pairs {
+                    let (jaccard, containment) = exact_similarity(&kmers[&query_entries[q_name]], &kmers[&ref_entries[r_name]]);
+                    let exact_d = compute_distance::<f64>(
+                        2.0 * jaccard / (1.0 + jaccard),
+                        kmer_length,
+                        equation as u8,
+                    );
+                    writeln!(
+                        file,
+                        "{}\t{}\t{:.6}\t{:.6}\t{:.6}\t{:.6}",
+                        r_name, q_name, d, jaccard, containment, exact_d
+                    )?;
+                }
+                println!("Top {} hits per query verified in {}.", top_n, verified_file);
+            }
+
+            if s_matches.get_flag("set_ops") {
+                setops::set_ops(
+                    ref_prefix,
+                    query_prefix,
+                    &format!("{}_setops", output_file),
+                    equation as u8,
+                    metadata.is_some(),
+                )?;
+            }
+
+            if s_matches.get_flag("abundance") {
+                countmin::abundance_similarity(
+                    ref_prefix,
+                    query_prefix,
+                    &format!("{}_abundance", output_file),
+                    equation as u8,
+                    metadata.is_some(),
+                )?;
+            }
+
+            if let Some(tool) = ani_tool {
+                let pairs = best_hits(&hits, ani_top);
+                // the aligners take one FASTA per genome
+                let mut genomes = pairs.iter().flat_map(|(r_name, q_name, _)| [&ref_entries[r_name], &query_entries[q_name]]);
+                if let Some(entry) = genomes.find(|entry| !entry.more.is_empty()) {
+                    return Err(format!("--ani-tool compares one file per genome, {} was sketched from {}", entry.name, entry.files().join(", ")).into());
+                }
+                let ani: Vec<f64> = pairs
+                    .par_iter()
+                    .map(|(r_name, q_name, _)| {
+                        external_ani(&tool, ref_entries[r_name].file(), query_entries[q_name].file())
+                            .map_err(|e| format!("{} failed on {} vs {}: {}", tool, r_name, q_name, e))
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                let ani_file = format!("{}_ani", output_file);
+                let mut file = File::create(&ani_file)?;
+                if let Some(line) = &metadata {
+                    writeln!(file, "{}", line)?;
+                }
+                writeln!(file, "Reference\tQuery\tDistance\tANI")?;
+                for ((r_name, q_name, d), ani) in pairs.iter().zip(ani) {
+                    writeln!(file, "{}\t{}\t{:.6}\t{:.4}", r_name, q_name, d, ani)?;
+                }
+                println!("Top {} hits per query confirmed with {} in {}.", ani_top, tool, ani_file);
+            }
+
+            Ok(())
+        }
+        Some(("compare", s_matches)) => {
+            let alg = s_matches.get_one::<String>("algorithm").expect("required").clone();
+            let precision = size_parameter(&alg, s_matches);
+            let params = utils::SketchParams {
+                kmer_length: *s_matches.get_one::<usize>("kmer_length").expect("required"),
+                algorithm: alg,
+                precision,
+                seed: *s_matches.get_one::<u64>("seed").expect("required"),
+                aa: false,
+                options: utils::SketchOptions::default(),
+            };
+            pairwise::compare(
+                s_matches.get_one::<String>("genome_a").expect("required"),
+                s_matches.get_one::<String>("genome_b").expect("required"),
+                &params,
+                *s_matches.get_one::<u64>("model").expect("required") as u8,
+            )
+        }
+        Some(("ani", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            pairwise::ani(
+                s_matches.get_one::<String>("query").expect("required"),
+                s_matches.get_one::<String>("reference").expect("required"),
+                s_matches.get_one::<String>("output_file").expect("required"),
+                !s_matches.get_flag("no_metadata"),
+            )
+        }
+        Some(("triangle", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            pairwise::triangle(
+                s_matches.get_one::<String>("input").expect("required"),
+                s_matches.get_one::<String>("output_file").expect("required"),
+                *s_matches.get_one::<u64>("model").expect("required") as u8,
+                !s_matches.get_flag("no_metadata"),
+            )
+        }
+        Some(("setops", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            setops::set_ops(
+                s_matches.get_one::<String>("reference").expect("required"),
+                s_matches.get_one::<String>("query").expect("required"),
+                s_matches.get_one::<String>("output_file").expect("required"),
+                *s_matches.get_one::<u64>("model").expect("required") as u8,
+                !s_matches.get_flag("no_metadata"),
+            )
+        }
+        Some(("search", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            pairwise::search(
+                s_matches.get_one::<String>("query").expect("required"),
+                s_matches.get_one::<String>("reference").expect("required"),
+                s_matches.get_one::<String>("output_file").expect("required"),
+                *s_matches.get_one::<usize>("top").expect("required"),
+                *s_matches.get_one::<u64>("model").expect("required") as u8,
+                !s_matches.get_flag("no_metadata"),
+            )
+        }
+        Some(("index", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            index::index(
+                s_matches.get_one::<String>("input").expect("required"),
+                s_matches.get_one::<usize>("rows").copied(),
+                threads as u32,
+            )
+        }
+        Some(("query", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            let opts = index::QueryOptions {
+                query: s_matches.get_one::<String>("query").expect("required").clone(),
+                reference: s_matches.get_one::<String>("reference").expect("required").clone(),
+                output: s_matches.get_one::<String>("output_file").expect("required").clone(),
+                top: *s_matches.get_one::<usize>("top").expect("required"),
+                max_candidates: *s_matches.get_one::<usize>("candidates").expect("required"),
+                equation: *s_matches.get_one::<u64>("model").expect("required") as u8,
+                metadata: !s_matches.get_flag("no_metadata"),
+            };
+            index::query(&opts)
+        }
+        Some(("serve", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            let opts = serve::ServeOptions {
+                reference: s_matches.get_one::<String>("reference").expect("required").clone(),
+                bind: s_matches.get_one::<String>("bind").expect("required").clone(),
+                top: *s_matches.get_one::<usize>("top").expect("required"),
+                equation: *s_matches.get_one::<u64>("model").expect("required") as u8,
+                workers: *s_matches.get_one::<usize>("workers").expect("required"),
+            };
+            serve::serve(&opts, &Notifier::from_matches(s_matches))
+        }
+        Some(("classify", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            let thresholds = classify::parse_thresholds(s_matches.get_one::<String>("thresholds").expect("required"))?;
+            classify::classify(
+                s_matches.get_one::<String>("query").expect("required"),
+                s_matches.get_one::<String>("reference").expect("required"),
+                s_matches.get_one::<String>("taxonomy").expect("required"),
+                &thresholds,
+                s_matches.get_one::<String>("output_file").expect("required"),
+                *s_matches.get_one::<u64>("model").expect("required") as u8,
+                !s_matches.get_flag("no_metadata"),
+            )
+        }
+        Some(("cluster", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            pairwise::cluster(
+                s_matches.get_one::<String>("input").expect("required"),
+                s_matches.get_one::<String>("output_file").expect("required"),
+                *s_matches.get_one::<f64>("threshold").expect("required"),
+                *s_matches.get_one::<u64>("model").expect("required") as u8,
+            )
+        }
+        Some(("dereplicate", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            let alg = s_matches.get_one::<String>("algorithm").expect("required").clone();
+            let precision = size_parameter(&alg, s_matches);
+            let params = utils::SketchParams {
+                kmer_length: *s_matches.get_one::<usize>("kmer_length").expect("required"),
+                algorithm: alg,
+                precision,
+                seed: *s_matches.get_one::<u64>("seed").expect("required"),
+                aa: false,
+                options: utils::SketchOptions::default(),
+            };
+            pairwise::dereplicate(
+                &read_file_list(s_matches.get_one::<String>("file").expect("required"))?,
+                &params,
+                s_matches.get_one::<String>("output").expect("required"),
+                *s_matches.get_one::<f64>("threshold").expect("required"),
+                *s_matches.get_one::<u64>("model").expect("required") as u8,
+            )
+        }
+        Some(("tree", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            tree::tree(
+                s_matches.get_one::<String>("input").expect("required"),
+                s_matches.get_one::<String>("output_file").expect("required"),
+                s_matches.get_one::<String>("method").expect("required"),
+                *s_matches.get_one::<u64>("model").expect("required") as u8,
+            )
+        }
+        Some(("multiway", s_matches)) => {
+            let prefix = s_matches.get_one::<String>("input").expect("required");
+            let output_file = s_matches.get_one::<String>("output_file").expect("required");
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            let files = find_files(prefix)?;
+            let params = read_params(&files["params"])?;
+            let entries = read_entries(&files["files"])?;
+            let names: Vec<&String> = entries.iter().map(|entry| &entry.name).collect();
+
+            // genomes of the group, in sketch file order
+            let selected: Vec<usize> = match s_matches.get_one::<String>("names") {
+                Some(list) => {
+                    let wanted: Vec<String> = BufReader::new(File::open(list)?)
+                        .lines()
+                        .map_while(Result::ok)
+                        .map(|l| l.trim().to_string())
+                        .filter(|l| !l.is_empty())
+                        .collect();
+                    wanted
+                        .iter()
+                        .map(|w| {
+                            names
+                                .iter()
+                                .position(|n| *n == w)
+                                .ok_or_else(|| format!("{} is not in sketch set {}", w, prefix))
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                None => (0..names.len()).collect(),
+            };
+            let exact = s_matches.get_flag("exact");
+            if selected.is_empty() || (!exact && selected.len() > MAX_MULTIWAY) {
+                return Err(format!(
+                    "multiway needs 1–{} genomes, {} were given",
+                    MAX_MULTIWAY,
+                    selected.len()
+                ).into());
+            }
+
+            fn estimate<S: KmerSketch + Sync>(
+                sketch_file: &str,
+                count: usize,
+                selected: &[usize],
+            ) -> Result<(Vec<f64>, f64, f64), Box<dyn Error>> {
+                let sketches: Vec<S> = load_sketches(sketch_file, count)?;
+                let group: Vec<&S> = selected.iter().map(|&i| &sketches[i]).collect();
+                let sizes = group.iter().map(|s| s.cardinality()).collect();
+                let (union, intersection) = multiway_cardinalities(&group);
+                Ok((sizes, union, intersection))
+            }
+
+            if !exact && params["algorithm"] == "ordermh" {
+                return Err("ordermh sketches keep only their signature, no union of several can be estimated; use --exact".into());
+            }
+            let (sizes, union, intersection) = if exact {
+                let group: Vec<&Entry> = selected.iter().map(|&i| &entries[i]).collect();
+                let GroupKmers { sizes, union, core } = core_kmers(&group, &utils::SketchParams::from_map(&params)?)?;
+
+                if let Some(core_file) = s_matches.get_one::<String>("core") {
+                    // hashed the same way HLL/ULL sketches hash k-mers
+                    let seed: u64 = params["seed"].parse()?;
+                    let mut hashes: Vec<u64> = core
+                        .iter()
+                        .map(|kmer| xxh3_64_with_seed(&kmer.to_le_bytes(), seed))
+                        .collect();
+                    hashes.sort_unstable();
+                    let mut out = BufWriter::new(File::create(core_file)?);
+                    for h in hashes {
+                        writeln!(out, "{}", h)?;
+                    }
+                    out.flush()?;
+                    println!("{} core k-mer hashes written to {}.", core.len(), core_file);
+                }
+                (
+                    sizes.into_iter().map(|n| n as f64).collect(),
+                    union as f64,
+                    core.len() as f64,
+                )
+            } else {
+                with_sketch_type!(
+                    params["algorithm"].as_str(),
+                    estimate(&files["sketches"], names.len(), &selected)
+                )?
+            };
+
+            let mut file = File::create(output_file)?;
+            writeln!(file, "Genome\tCardinality")?;
+            for (&i, size) in selected.iter().zip(&sizes) {
+                writeln!(file, "{}\t{:.0}", names[i], size)?;
+            }
+            writeln!(file, "Union\t{:.0}", union)?;
+            writeln!(file, "Intersection\t{:.0}", intersection)?;
+
+            println!(
+                "{} genomes: union {:.0}, intersection {:.0} k-mers.",
+                selected.len(),
+                union,
+                intersection
+            );
+            Ok(())
+        }
+        Some(("watch", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            let opts = watch::WatchOptions {
+                dir: s_matches.get_one::<String>("dir").expect("required").clone(),
+                reference: s_matches.get_one::<String>("reference").expect("required").clone(),
+                output: s_matches.get_one::<String>("output_file").expect("required").clone(),
+                interval: *s_matches.get_one::<u64>("interval").expect("required"),
+                top: s_matches.get_one::<usize>("top").copied(),
+                equation: *s_matches.get_one::<u64>("model").expect("required") as u8,
+                once: s_matches.get_flag("once"),
+                metadata: !s_matches.get_flag("no_metadata"),
+            };
+            watch::watch(&opts, &Notifier::from_matches(s_matches))
+        }
+        Some(("screen", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            let opts = screen::ScreenOptions {
+                reads: s_matches.get_many::<String>("reads").expect("required").cloned().collect(),
+                reference: s_matches.get_one::<String>("reference").expect("required").clone(),
+                output: s_matches.get_one::<String>("output_file").expect("required").clone(),
+                min_containment: *s_matches.get_one::<f64>("min_containment").expect("required"),
+                metadata: !s_matches.get_flag("no_metadata"),
+            };
+            screen::screen(&opts)
+        }
+        Some(("profile", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            let alg = s_matches.get_one::<String>("algorithm").expect("required").clone();
+            let precision = size_parameter(&alg, s_matches);
+            let opts = profile::ProfileOptions {
+                files: read_file_list(s_matches.get_one::<String>("file").expect("required"))?,
+                kmer_length: *s_matches.get_one::<usize>("kmer_length").expect("required"),
+                algorithm: alg,
+                precision,
+                seed: *s_matches.get_one::<u64>("seed").expect("required"),
+                aa: false,
+                threads: threads as u32,
+            };
+            profile::profile(
+                &opts,
+                s_matches.get_one::<String>("output").expect("required"),
+                s_matches.get_one::<String>("json"),
+            )
+        }
+        Some(("split", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            let shards = match s_matches.get_one::<usize>("parts") {
+                Some(&parts) => sketchset::Shards::Parts(parts),
+                None => sketchset::Shards::MaxSize(*s_matches.get_one::<u64>("max_size").expect("required")),
+            };
+            sketchset::split(
+                s_matches.get_one::<String>("input").expect("required"),
+                s_matches.get_one::<String>("output").expect("required"),
+                &shards,
+                threads as u32,
+            )
+        }
+        Some(("paste", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            let inputs: Vec<String> = s_matches
+                .get_many::<String>("inputs")
+                .expect("required")
+                .cloned()
+                .collect();
+            sketchset::paste(
+                &inputs,
+                s_matches.get_one::<String>("output").expect("required"),
+                threads as u32,
+            )
+        }
+        Some(("fetchdb", s_matches)) => {
+            let catalog = fetchdb::read_catalog(s_matches.get_one::<String>("catalog").expect("required"))?;
+            if s_matches.get_flag("list") {
+                return fetchdb::list(&catalog);
+            }
+            let name = s_matches.get_one::<String>("name").expect("required");
+            fetchdb::fetch(
+                &catalog,
+                name,
+                s_matches.get_one::<String>("db_version").map(String::as_str),
+                s_matches.get_one::<String>("output").unwrap_or(name),
+            )
+        }
+        Some(("update", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            let files = read_file_list(s_matches.get_one::<String>("file").expect("required"))?;
+            sketchset::update(
+                s_matches.get_one::<String>("input").expect("required"),
+                &files,
+                threads as u32,
+            )
+        }
+        Some(("remove", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            let names = read_file_list(s_matches.get_one::<String>("names").expect("required"))?;
+            sketchset::remove(
+                s_matches.get_one::<String>("input").expect("required"),
+                &names,
+                threads as u32,
+            )
+        }
+        Some(("rename", s_matches)) => {
+            let mapping = sketchset::read_rename_map(s_matches.get_one::<String>("mapping").expect("required"))?;
+            sketchset::rename(s_matches.get_one::<String>("input").expect("required"), &mapping)
+        }
+        Some(("convert", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            sketchset::convert(
+                s_matches.get_one::<String>("input").expect("required"),
+                s_matches.get_one::<String>("output").expect("required"),
+                *s_matches.get_one::<i32>("level").expect("required"),
+                threads as u32,
+            )
+        }
+        Some(("validate", s_matches)) => {
+            let inputs: Vec<String> = s_matches
+                .get_many::<String>("inputs")
+                .expect("required")
+                .cloned()
+                .collect();
+            sketchset::validate(&inputs)
+        }
+        Some(("stats", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            sketchset::stats(
+                s_matches.get_one::<String>("input").expect("required"),
+                s_matches.get_one::<String>("output_file").expect("required"),
+            )
+        }
+        Some(("merge-results", s_matches)) => {
+            let inputs: Vec<String> = s_matches
+                .get_many::<String>("inputs")
+                .expect("required")
+                .cloned()
+                .collect();
+            results::merge_results(
+                &inputs,
+                s_matches.get_one::<String>("output_file").expect("required"),
+                s_matches.get_flag("dm"),
+                !s_matches.get_flag("no_metadata"),
+            )
+        }
+        Some(("bench", s_matches)) => {
+            let opts = bench::BenchOptions {
+                file_list: s_matches.get_one::<String>("file").expect("required").clone(),
+                kmer_length: *s_matches.get_one::<usize>("kmer_length").expect("required"),
+                algorithms: s_matches.get_many::<String>("algorithms").expect("required").cloned().collect(),
+                precisions: s_matches.get_many::<u32>("precisions").expect("required").copied().collect(),
+                threads: s_matches
+                    .get_one::<usize>("threads")
+                    .copied()
+                    .unwrap_or_else(num_cpus::get),
+                output: s_matches.get_one::<String>("output_file").expect("required").clone(),
+            };
+            bench::bench(&opts)
+        }
+        Some(("simulate", s_matches)) => {
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+
+            let opts = simulate::SimulateOptions {
+                kmer_length: *s_matches.get_one::<usize>("kmer_length").expect("required"),
+                algorithms: s_matches.get_many::<String>("algorithms").expect("required").cloned().collect(),
+                precisions: s_matches.get_many::<u32>("precisions").expect("required").copied().collect(),
+                rates: s_matches.get_many::<f64>("rates").expect("required").copied().collect(),
+                length: *s_matches.get_one::<usize>("length").expect("required"),
+                replicates: *s_matches.get_one::<usize>("replicates").expect("required"),
+                random_seed: *s_matches.get_one::<u64>("random_seed").expect("required"),
+                seed: *s_matches.get_one::<u64>("seed").expect("required"),
+                equation: *s_matches.get_one::<u64>("model").expect("required") as u8,
+                output: s_matches.get_one::<String>("output_file").expect("required").clone(),
+            };
+            simulate::simulate(&opts)
+        }
+        Some(("run", s_matches)) => {
+            let config = s_matches.get_one::<String>("config").expect("required");
+            let report = s_matches.get_one::<String>("report").expect("required");
+            let threads = s_matches
+                .get_one::<usize>("threads")
+                .copied()
+                .unwrap_or_else(num_cpus::get);
+
+            init_thread_pool(threads);
+            batch::run_jobs(config, report, &Notifier::from_matches(s_matches))
         }
         _ => Ok(()),
     }
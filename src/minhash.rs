@@ -0,0 +1,349 @@
+// src/minhash.rs
+// Sketches that keep hash values instead of registers. FracMinHash (`fmh`) keeps
+// every k-mer hash below u64::MAX / scaled, so a sketch grows with the genome and
+// containment between sets of very different sizes stays unbiased, as in sourmash.
+// Bottom-k MinHash (`minhash`) keeps the `sketch_size` smallest hashes, the Mash
+// sketch, with its known error bounds.
+// Theta sketches (`theta`, as in Apache DataSketches) keep the hashes below a
+// threshold theta, lowered so that at most `sketch_size` remain; unions,
+// intersections and differences of two sketches are sketches again.
+
+use std::collections::BTreeSet;
+use std::error::Error;
+
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+use crate::format;
+use crate::utils::KmerSketch;
+
+// scale used when none is given, one hash in a thousand is kept
+pub const DEFAULT_SCALED: u64 = 1000;
+
+// bottom-k size used when none is given, as in Mash
+pub const DEFAULT_SKETCH_SIZE: usize = 1000;
+
+// number of one-permutation buckets `registers` reports for hash-list sketches
+const SIGNATURE_BUCKETS: usize = 1024;
+
+// one-permutation MinHash signature of a hash list, so hash-list sketches can be
+// banded by `lash index` like register sketches; 0 marks an empty bucket
+fn signature<I: Iterator<Item = u64>>(hashes: I) -> Vec<u16> {
+    let mut buckets = vec![0u16; SIGNATURE_BUCKETS];
+    let mut minimum = vec![u64::MAX; SIGNATURE_BUCKETS];
+    for hash in hashes {
+        // the kept hashes are all small, remix before taking the bucket from the top bits
+        let h = hash.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        let bucket = (h >> 54) as usize;
+        if h < minimum[bucket] {
+            minimum[bucket] = h;
+            buckets[bucket] = ((h >> 16) as u16).max(1);
+        }
+    }
+    buckets
+}
+
+#[derive(Clone)]
+pub struct FracMinHash {
+    pub scaled: u64,
+    pub hashes: BTreeSet<u64>,
+}
+
+impl FracMinHash {
+    pub fn max_hash(&self) -> u64 {
+        u64::MAX / self.scaled
+    }
+
+    fn shared(&self, other: &Self) -> usize {
+        self.hashes.intersection(&other.hashes).count()
+    }
+}
+
+impl KmerSketch for FracMinHash {
+    fn new(precision: Option<u32>) -> Self {
+        let scaled = precision.map_or(DEFAULT_SCALED, u64::from);
+        assert!(scaled > 0, "FracMinHash scale must be at least 1");
+        FracMinHash { scaled, hashes: BTreeSet::new() }
+    }
+
+    fn add_kmer(&mut self, masked: u64, seed: u64) {
+        self.add_hashed(masked, xxh3_64_with_seed(&masked.to_le_bytes(), seed), seed);
+    }
+
+    fn add_hashed(&mut self, _masked: u64, hash: u64, _seed: u64) {
+        if hash <= self.max_hash() {
+            self.hashes.insert(hash);
+        }
+    }
+
+    fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        Ok(format::write_fmh(writer, self)?)
+    }
+
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        format::read_fmh(reader)
+    }
+
+    fn cardinality(&self) -> f64 {
+        (self.hashes.len() as u64 * self.scaled) as f64
+    }
+
+    fn merged(&self, other: &Self) -> Self {
+        assert_eq!(self.scaled, other.scaled, "FracMinHash sketches with different scales");
+        FracMinHash {
+            scaled: self.scaled,
+            hashes: self.hashes.union(&other.hashes).copied().collect(),
+        }
+    }
+
+    // both estimates are exact on the kept hashes
+    fn jaccard(&self, other: &Self) -> f64 {
+        let shared = self.shared(other);
+        let union = self.hashes.len() + other.hashes.len() - shared;
+        if union == 0 {
+            return 0.0;
+        }
+        shared as f64 / union as f64
+    }
+
+    fn containment(&self, other: &Self) -> f64 {
+        if self.hashes.is_empty() {
+            return 0.0;
+        }
+        self.shared(other) as f64 / self.hashes.len() as f64
+    }
+
+    fn registers(&self) -> Vec<u16> {
+        signature(self.hashes.iter().copied())
+    }
+}
+
+#[derive(Clone)]
+pub struct MinHash {
+    pub sketch_size: usize,
+    pub hashes: BTreeSet<u64>,
+}
+
+impl MinHash {
+    // (shared, taken) over the `sketch_size` smallest hashes of the union
+    fn union_bottom(&self, other: &Self) -> (usize, usize) {
+        let size = self.sketch_size.min(other.sketch_size);
+        let (mut a, mut b) = (self.hashes.iter().peekable(), other.hashes.iter().peekable());
+        let (mut shared, mut taken) = (0, 0);
+        while taken < size {
+            match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) if x == y => {
+                    shared += 1;
+                    a.next();
+                    b.next();
+                }
+                (Some(x), Some(y)) if x < y => {
+                    a.next();
+                }
+                (Some(_), Some(_)) | (None, Some(_)) => {
+                    b.next();
+                }
+                (Some(_), None) => {
+                    a.next();
+                }
+                (None, None) => break,
+            }
+            taken += 1;
+        }
+        (shared, taken)
+    }
+}
+
+impl KmerSketch for MinHash {
+    fn new(precision: Option<u32>) -> Self {
+        let sketch_size = precision.map_or(DEFAULT_SKETCH_SIZE, |s| s as usize);
+        assert!(sketch_size > 0, "MinHash sketch size must be at least 1");
+        MinHash { sketch_size, hashes: BTreeSet::new() }
+    }
+
+    fn add_kmer(&mut self, masked: u64, seed: u64) {
+        self.add_hashed(masked, xxh3_64_with_seed(&masked.to_le_bytes(), seed), seed);
+    }
+
+    fn add_hashed(&mut self, _masked: u64, hash: u64, _seed: u64) {
+        if self.hashes.len() < self.sketch_size {
+            self.hashes.insert(hash);
+        } else if self.hashes.last().is_some_and(|&max| hash < max) && self.hashes.insert(hash) {
+            self.hashes.pop_last();
+        }
+    }
+
+    fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        Ok(format::write_minhash(writer, self)?)
+    }
+
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        format::read_minhash(reader)
+    }
+
+    // (k - 1) / normalized k-th smallest hash once the sketch is full, exact before
+    fn cardinality(&self) -> f64 {
+        match self.hashes.last() {
+            Some(&max) if self.hashes.len() >= self.sketch_size && self.sketch_size > 1 => {
+                (self.sketch_size - 1) as f64 / (max as f64 / u64::MAX as f64)
+            }
+            _ => self.hashes.len() as f64,
+        }
+    }
+
+    fn merged(&self, other: &Self) -> Self {
+        let sketch_size = self.sketch_size.min(other.sketch_size);
+        MinHash {
+            sketch_size,
+            hashes: self.hashes.union(&other.hashes).copied().take(sketch_size).collect(),
+        }
+    }
+
+    fn jaccard(&self, other: &Self) -> f64 {
+        let (shared, taken) = self.union_bottom(other);
+        if taken == 0 {
+            return 0.0;
+        }
+        shared as f64 / taken as f64
+    }
+
+    // share of this sketch's hashes found in the other, counting only hashes the
+    // other sketch could have kept (not above its k-th smallest once it is full)
+    fn containment(&self, other: &Self) -> f64 {
+        let bound = match other.hashes.last() {
+            Some(&max) if other.hashes.len() >= other.sketch_size => max,
+            _ => u64::MAX,
+        };
+        let kept: Vec<&u64> = self.hashes.range(..=bound).collect();
+        if kept.is_empty() {
+            return 0.0;
+        }
+        kept.iter().filter(|h| other.hashes.contains(h)).count() as f64 / kept.len() as f64
+    }
+
+    fn registers(&self) -> Vec<u16> {
+        signature(self.hashes.iter().copied())
+    }
+}
+
+#[derive(Clone)]
+pub struct Theta {
+    pub sketch_size: usize,
+    pub theta: u64,
+    pub hashes: BTreeSet<u64>,
+}
+
+impl Theta {
+    // drop the largest hashes until `sketch_size` remain, theta becomes the first dropped
+    fn trim(&mut self) {
+        while self.hashes.len() > self.sketch_size {
+            self.theta = self.hashes.pop_last().expect("not empty");
+        }
+    }
+
+    fn fraction(&self) -> f64 {
+        self.theta as f64 / u64::MAX as f64
+    }
+
+    // hashes of both sketches below the smaller theta
+    fn below<'a>(&'a self, other: &'a Self) -> (u64, impl Iterator<Item = &'a u64>) {
+        let theta = self.theta.min(other.theta);
+        (theta, self.hashes.range(..theta))
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        let (theta, hashes) = self.below(other);
+        Theta {
+            sketch_size: self.sketch_size.max(other.sketch_size),
+            theta,
+            hashes: hashes.filter(|h| other.hashes.contains(h)).copied().collect(),
+        }
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        let (theta, hashes) = self.below(other);
+        Theta {
+            sketch_size: self.sketch_size,
+            theta,
+            hashes: hashes.filter(|h| !other.hashes.contains(h)).copied().collect(),
+        }
+    }
+}
+
+impl KmerSketch for Theta {
+    fn new(precision: Option<u32>) -> Self {
+        let sketch_size = precision.map_or(DEFAULT_SKETCH_SIZE, |s| s as usize);
+        assert!(sketch_size > 0, "Theta sketch size must be at least 1");
+        Theta { sketch_size, theta: u64::MAX, hashes: BTreeSet::new() }
+    }
+
+    fn add_kmer(&mut self, masked: u64, seed: u64) {
+        self.add_hashed(masked, xxh3_64_with_seed(&masked.to_le_bytes(), seed), seed);
+    }
+
+    fn add_hashed(&mut self, _masked: u64, hash: u64, _seed: u64) {
+        if hash < self.theta && self.hashes.insert(hash) {
+            self.trim();
+        }
+    }
+
+    fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        Ok(format::write_theta(writer, self)?)
+    }
+
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        format::read_theta(reader)
+    }
+
+    fn cardinality(&self) -> f64 {
+        if self.theta == u64::MAX {
+            return self.hashes.len() as f64;
+        }
+        self.hashes.len() as f64 / self.fraction()
+    }
+
+    fn merged(&self, other: &Self) -> Self {
+        let theta = self.theta.min(other.theta);
+        let mut union = Theta {
+            sketch_size: self.sketch_size.min(other.sketch_size),
+            theta,
+            hashes: self.hashes.union(&other.hashes).filter(|&&h| h < theta).copied().collect(),
+        };
+        union.trim();
+        union
+    }
+
+    // shared hashes among those of the union sketch
+    fn jaccard(&self, other: &Self) -> f64 {
+        let union = self.merged(other);
+        if union.hashes.is_empty() {
+            return 0.0;
+        }
+        let shared = union
+            .hashes
+            .iter()
+            .filter(|h| self.hashes.contains(h) && other.hashes.contains(h))
+            .count();
+        shared as f64 / union.hashes.len() as f64
+    }
+
+    fn containment(&self, other: &Self) -> f64 {
+        let (_, mine) = self.below(other);
+        let mine: Vec<&u64> = mine.collect();
+        if mine.is_empty() {
+            return 0.0;
+        }
+        mine.iter().filter(|h| other.hashes.contains(h)).count() as f64 / mine.len() as f64
+    }
+
+    fn intersection_size(&self, other: &Self) -> f64 {
+        self.intersection(other).cardinality()
+    }
+
+    fn difference_size(&self, other: &Self) -> f64 {
+        self.difference(other).cardinality()
+    }
+
+    fn registers(&self) -> Vec<u16> {
+        signature(self.hashes.iter().copied())
+    }
+}
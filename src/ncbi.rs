@@ -0,0 +1,151 @@
+// src/ncbi.rs
+// NCBI assembly accessions (GCF_000005845.2, GCA_...) given in place of a path
+// in a file list or manifest: the genomic FASTA is fetched through the NCBI
+// Datasets API (a zip holding ncbi_dataset/data/<accession>/<...>_genomic.fna)
+// and cached gzip-compressed as <cache dir>/<accession>.fna.gz, so a second run
+// over the same accessions, or an update of the set, downloads nothing. The
+// samples keep the accession as their name.
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::info;
+
+use crate::remote;
+use crate::utils::Sample;
+
+const DATASETS_API: &str = "https://api.ncbi.nlm.nih.gov/datasets/v2/genome/accession";
+
+// GCF_ or GCA_, 9 digits, an optional version
+pub fn is_accession(path: &str) -> bool {
+    let Some(rest) = path.strip_prefix("GCF_").or_else(|| path.strip_prefix("GCA_")) else {
+        return false;
+    };
+    let (digits, version) = rest.split_once('.').unwrap_or((rest, "0"));
+    digits.len() == 9
+        && digits.bytes().all(|b| b.is_ascii_digit())
+        && !version.is_empty()
+        && version.bytes().all(|b| b.is_ascii_digit())
+}
+
+// $XDG_CACHE_HOME/lash/ncbi, or ~/.cache/lash/ncbi
+pub fn default_cache_dir() -> PathBuf {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("lash")
+        .join("ncbi")
+}
+
+// accessions among the sample paths replaced by their cached FASTA, fetched
+// first where missing; files on disk with an accession-like name are left alone
+pub fn resolve(samples: &mut [Sample], cache_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let wanted = |path: &str| is_accession(path) && !Path::new(path).exists();
+    if !samples.iter().any(|s| wanted(&s.path) || s.more.iter().any(|p| wanted(p))) {
+        return Ok(());
+    }
+    fs::create_dir_all(cache_dir)?;
+    let (mut fetched, mut cached) = (0, 0);
+    let mut local = |accession: &str| -> Result<String, Box<dyn Error>> {
+        let path = cache_dir.join(format!("{}.fna.gz", accession));
+        if path.exists() {
+            cached += 1;
+        } else {
+            fetch(accession, &path)?;
+            fetched += 1;
+        }
+        Ok(path.to_string_lossy().into_owned())
+    };
+    for sample in samples.iter_mut() {
+        if wanted(&sample.path) {
+            sample.path = local(&sample.path)?;
+        }
+        for path in sample.more.iter_mut().filter(|p| wanted(p)) {
+            *path = local(path)?;
+        }
+    }
+    println!("{} assemblies fetched from NCBI, {} found in {}.", fetched, cached, cache_dir.display());
+    Ok(())
+}
+
+// genomic FASTA of an assembly into `path`, written under a temporary name first
+// so an interrupted download never passes for a cached one
+fn fetch(accession: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    let url = format!("{}/{}/download?include_annotation_type=GENOME_FASTA", DATASETS_API, accession);
+    let mut archive = Vec::new();
+    remote::open(&url)?.read_to_end(&mut archive)?;
+    let partial = path.with_extension("gz.part");
+    let mut encoder = GzEncoder::new(File::create(&partial)?, Compression::fast());
+    let written = write_genomic_fasta(&archive, &mut encoder)
+        .map_err(|e| format!("{}: {}", accession, e))?;
+    if written == 0 {
+        fs::remove_file(&partial)?;
+        return Err(format!("{}: no genomic FASTA in the NCBI Datasets package (unknown or suppressed accession?)", accession).into());
+    }
+    encoder.finish()?;
+    fs::rename(&partial, path)?;
+    info!("{}: {} bytes of FASTA cached in {}", accession, written, path.display());
+    Ok(())
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> io::Result<usize> {
+    bytes
+        .get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "truncated zip archive"))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> io::Result<usize> {
+    bytes
+        .get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "truncated zip archive"))
+}
+
+// every *_genomic.fna of the zip archive (one per assembly, the CDS and RNA
+// *_from_genomic.fna files aside) written to `out`, returns the bytes written
+fn write_genomic_fasta<W: Write>(archive: &[u8], out: &mut W) -> io::Result<u64> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    // end of central directory record: signature, ..., entry count at 10,
+    // directory offset at 16, then a comment of at most 64 KiB
+    let end = (0..archive.len().saturating_sub(21))
+        .rev()
+        .take(22 + 65535)
+        .find(|&i| archive[i..].starts_with(&[0x50, 0x4b, 0x05, 0x06]))
+        .ok_or_else(|| invalid("not a zip archive"))?;
+    let entries = u16_at(archive, end + 10)?;
+    let mut at = u32_at(archive, end + 16)?;
+    let mut written = 0;
+    for _ in 0..entries {
+        if !archive.get(at..).is_some_and(|rest| rest.starts_with(&[0x50, 0x4b, 0x01, 0x02])) {
+            return Err(invalid("invalid zip central directory"));
+        }
+        let method = u16_at(archive, at + 10)?;
+        let compressed = u32_at(archive, at + 20)?;
+        let name_length = u16_at(archive, at + 28)?;
+        let next = at + 46 + name_length + u16_at(archive, at + 30)? + u16_at(archive, at + 32)?;
+        let local = u32_at(archive, at + 42)?;
+        let name = String::from_utf8_lossy(archive.get(at + 46..at + 46 + name_length).unwrap_or_default());
+        at = next;
+        if !name.ends_with("_genomic.fna") || name.ends_with("_from_genomic.fna") {
+            continue;
+        }
+        if compressed == u32::MAX as usize || local == u32::MAX as usize {
+            return Err(invalid("zip64 archives are not supported"));
+        }
+        let start = local + 30 + u16_at(archive, local + 26)? + u16_at(archive, local + 28)?;
+        let data = archive.get(start..start + compressed).ok_or_else(|| invalid("truncated zip archive"))?;
+        written += match method {
+            0 => io::copy(&mut &data[..], out)?,
+            8 => io::copy(&mut DeflateDecoder::new(data), out)?,
+            _ => return Err(invalid(&format!("zip compression method {} is not supported", method))),
+        };
+    }
+    Ok(written)
+}
@@ -0,0 +1,78 @@
+// src/notify.rs
+// completion notifications: POST the JSON run report to a webhook and/or pipe
+// it to a shell command, so LIMS systems do not need to poll for results
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use clap::ArgMatches;
+use log::{info, warn};
+use serde_json::{json, Value};
+
+pub struct Notifier {
+    webhook: Option<String>,
+    hook: Option<String>,
+}
+
+impl Notifier {
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        let get = |id: &str| matches.try_get_one::<String>(id).ok().flatten().cloned();
+        Notifier {
+            webhook: get("webhook"),
+            hook: get("hook"),
+        }
+    }
+
+    // a failed notification is logged, it never fails the run itself
+    pub fn notify(&self, report: &Value) {
+        let body = report.to_string();
+        if let Some(url) = &self.webhook {
+            let agent = ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build();
+            match agent
+                .post(url)
+                .set("Content-Type", "application/json")
+                .send_string(&body)
+            {
+                Ok(_) => info!("run report posted to {}", url),
+                Err(e) => warn!("webhook {} failed: {}", url, e),
+            }
+        }
+        if let Some(cmd) = &self.hook {
+            // the report is passed on stdin
+            let spawned = Command::new("sh")
+                .args(["-c", cmd])
+                .stdin(Stdio::piped())
+                .spawn();
+            match spawned {
+                Ok(mut child) => {
+                    if let Some(mut stdin) = child.stdin.take() {
+                        if let Err(e) = stdin.write_all(body.as_bytes()) {
+                            warn!("could not pass run report to hook: {}", e);
+                        }
+                    }
+                    match child.wait() {
+                        Ok(status) if !status.success() => warn!("hook `{}` exited with {}", cmd, status),
+                        Err(e) => warn!("hook `{}` failed: {}", cmd, e),
+                        _ => {}
+                    }
+                }
+                Err(e) => warn!("hook `{}` failed to start: {}", cmd, e),
+            }
+        }
+    }
+}
+
+// JSON run report of one finished subcommand
+pub fn run_report(command: &str, error: Option<String>, seconds: f64) -> Value {
+    json!({
+        "command": command,
+        "status": if error.is_none() { "ok" } else { "failed" },
+        "error": error,
+        "seconds": seconds,
+        "args": std::env::args().collect::<Vec<String>>(),
+        "version": env!("CARGO_PKG_VERSION"),
+    })
+}
@@ -0,0 +1,43 @@
+// src/nthash.rs
+// ntHash (Mohamadi et al. 2016) for `sketch --hash nthash`: the hash of each
+// k-mer is rolled from the previous one in O(1) with two rotations and XORs per
+// strand, so no 2-bit k-mer is packed and every k costs the same. The canonical
+// hash is the smaller of the two strands' hashes, as ntHash's NTC64. The values
+// stand in for packed k-mers in the rest of the k-mer loop, so sets built with
+// either hash never share k-mers and dist refuses to compare them.
+
+// seeds of A, C, G and T
+const SEEDS: [u64; 4] = [0x3c8b_fbb3_95c6_0474, 0x3193_c185_62a0_2b4c, 0x2032_3ed0_8257_2324, 0x2955_49f5_4be2_4456];
+
+fn code(base: u8) -> Option<usize> {
+    match base {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    }
+}
+
+// hashes of the k-mers of `seq`, canonical or as read on the given strand
+pub fn for_each_hash<F: FnMut(u64)>(seq: &[u8], kmer_length: usize, canonical: bool, mut f: F) {
+    let k = kmer_length as u32;
+    for stretch in seq.split(|&c| code(c).is_none()) {
+        if stretch.len() < kmer_length || kmer_length == 0 {
+            continue;
+        }
+        let codes = |i: usize| code(stretch[i]).expect("ACGT stretch");
+        let (mut forward, mut reverse) = (0u64, 0u64);
+        for (i, c) in (0..kmer_length).map(codes).enumerate() {
+            forward ^= SEEDS[c].rotate_left(k - 1 - i as u32);
+            reverse ^= SEEDS[3 - c].rotate_left(i as u32);
+        }
+        f(if canonical { forward.min(reverse) } else { forward });
+        for i in kmer_length..stretch.len() {
+            let (out, into) = (codes(i - kmer_length), codes(i));
+            forward = forward.rotate_left(1) ^ SEEDS[out].rotate_left(k) ^ SEEDS[into];
+            reverse = reverse.rotate_right(1) ^ SEEDS[3 - out].rotate_right(1) ^ SEEDS[3 - into].rotate_left(k - 1);
+            f(if canonical { forward.min(reverse) } else { forward });
+        }
+    }
+}
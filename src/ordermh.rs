@@ -0,0 +1,201 @@
+// src/ordermh.rs
+// OrderMinHash (`ordermh`, Marçais et al. 2019) for plasmids, genes and viral
+// genomes: each of `sketch_size` hash functions picks the `ORDER_LENGTH` k-mers
+// with the smallest hashes and records them in the order they occur in the
+// sequence. Rearrangements and indels change that order, so the share of equal
+// values follows edit distance more closely than set similarity does.
+//
+// The k-mers of a file are kept in sequence order while it is sketched and the
+// signature is computed the first time it is needed; sketches loaded from disk
+// only hold the signature, so they cannot be merged (set operations go through
+// the Jaccard estimate instead, multiway needs --exact). Sketching keeps the hash
+// of every k-mer occurrence, 8 bytes per base: about 40 MB for a 5 Mbp genome and
+// the size of all reads for a read set, and the signature takes sketch_size
+// passes over them, so ordermh is meant for sequences up to a few megabases.
+
+use std::error::Error;
+use std::sync::OnceLock;
+
+use hashbrown::HashMap;
+use xxhash_rust::xxh3::{xxh3_64, xxh3_64_with_seed};
+
+use crate::format;
+use crate::utils::KmerSketch;
+
+// k-mers per ordered tuple
+pub const ORDER_LENGTH: usize = 3;
+
+// hash functions used when none is given, sketching costs sketch_size hashes per k-mer
+pub const DEFAULT_SKETCH_SIZE: usize = 128;
+
+#[derive(Clone)]
+pub struct OrderSignature {
+    pub kmers: u64,
+    pub values: Vec<u64>,
+}
+
+#[derive(Clone)]
+pub struct OrderMinHash {
+    pub sketch_size: usize,
+    // k-mer hashes in sequence order, empty for loaded sketches
+    kmers: Vec<u64>,
+    signature: OnceLock<OrderSignature>,
+}
+
+impl OrderMinHash {
+    pub fn from_signature(signature: OrderSignature) -> Self {
+        OrderMinHash {
+            sketch_size: signature.values.len(),
+            kmers: Vec::new(),
+            signature: OnceLock::from(signature),
+        }
+    }
+
+    pub fn signature(&self) -> &OrderSignature {
+        self.signature.get_or_init(|| {
+            // repeats of a k-mer are told apart by their occurrence number
+            let mut occurrences: HashMap<u64, u64> = HashMap::new();
+            let keyed: Vec<u64> = self
+                .kmers
+                .iter()
+                .map(|&kmer| {
+                    let occurrence = occurrences.entry(kmer).or_insert(0);
+                    *occurrence += 1;
+                    xxh3_64_with_seed(&kmer.to_le_bytes(), *occurrence)
+                })
+                .collect();
+            let values = if self.kmers.len() < ORDER_LENGTH {
+                vec![0; self.sketch_size]
+            } else {
+                (0..self.sketch_size as u64).map(|i| self.order_value(&keyed, i)).collect()
+            };
+            OrderSignature { kmers: occurrences.len() as u64, values }
+        })
+    }
+
+    // value of hash function `i`: the ORDER_LENGTH k-mers of smallest hash, hashed
+    // in sequence order
+    fn order_value(&self, keyed: &[u64], i: u64) -> u64 {
+        let mut ranked: Vec<(u64, usize)> = keyed
+            .iter()
+            .enumerate()
+            .map(|(position, key)| (xxh3_64_with_seed(&key.to_le_bytes(), i), position))
+            .collect();
+        ranked.select_nth_unstable(ORDER_LENGTH - 1);
+        let mut chosen: Vec<usize> = ranked[..ORDER_LENGTH].iter().map(|&(_, p)| p).collect();
+        chosen.sort_unstable();
+        let bytes: Vec<u8> = chosen.iter().flat_map(|&p| self.kmers[p].to_le_bytes()).collect();
+        xxh3_64(&bytes).max(1)
+    }
+
+    // share of hash functions with the same ordered tuple
+    pub fn order_similarity(&self, other: &Self) -> f64 {
+        let (a, b) = (self.signature(), other.signature());
+        if a.kmers == 0 || b.kmers == 0 {
+            return 0.0;
+        }
+        let same = a.values.iter().zip(&b.values).filter(|(x, y)| x == y).count();
+        same as f64 / a.values.len() as f64
+    }
+}
+
+impl KmerSketch for OrderMinHash {
+    fn new(precision: Option<u32>) -> Self {
+        let sketch_size = precision.map_or(DEFAULT_SKETCH_SIZE, |s| s as usize);
+        assert!(sketch_size > 0, "OrderMinHash sketch size must be at least 1");
+        OrderMinHash { sketch_size, kmers: Vec::new(), signature: OnceLock::new() }
+    }
+
+    fn add_kmer(&mut self, masked: u64, seed: u64) {
+        self.add_hashed(masked, xxh3_64_with_seed(&masked.to_le_bytes(), seed), seed);
+    }
+
+    fn add_hashed(&mut self, _masked: u64, hash: u64, _seed: u64) {
+        self.kmers.push(hash);
+        self.signature.take();
+    }
+
+    fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        Ok(format::write_ordermh(writer, self.signature())?)
+    }
+
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        Ok(OrderMinHash::from_signature(format::read_ordermh(reader)?))
+    }
+
+    fn cardinality(&self) -> f64 {
+        self.signature().kmers as f64
+    }
+
+    // the second sequence appended to the first
+    fn merged(&self, other: &Self) -> Self {
+        assert!(
+            self.signature.get().is_none() || !self.kmers.is_empty(),
+            "OrderMinHash sketches loaded from disk cannot be merged, their k-mer order is not stored"
+        );
+        let mut merged = self.clone();
+        merged.kmers.extend_from_slice(&other.kmers);
+        merged.signature = OnceLock::new();
+        merged
+    }
+
+    // per-k-mer Jaccard: a tuple is shared when all ORDER_LENGTH of its k-mers are
+    fn jaccard(&self, other: &Self) -> f64 {
+        self.order_similarity(other).powf(1.0 / ORDER_LENGTH as f64)
+    }
+
+    // the set operations go through the Jaccard estimate and the k-mer counts, not a union
+    fn union_size(&self, other: &Self) -> f64 {
+        self.cardinality() + other.cardinality() - self.intersection_size(other)
+    }
+
+    fn containment(&self, other: &Self) -> f64 {
+        let a = self.cardinality();
+        if a == 0.0 {
+            return 0.0;
+        }
+        (self.intersection_size(other) / a).clamp(0.0, 1.0)
+    }
+
+    fn intersection_size(&self, other: &Self) -> f64 {
+        let jaccard = self.jaccard(other);
+        jaccard * (self.cardinality() + other.cardinality()) / (1.0 + jaccard)
+    }
+
+    fn difference_size(&self, other: &Self) -> f64 {
+        (self.cardinality() - self.intersection_size(other)).max(0.0)
+    }
+
+    fn registers(&self) -> Vec<u16> {
+        let values = &self.signature().values;
+        values.iter().map(|&v| if v == 0 { 0 } else { ((v >> 48) as u16).max(1) }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sketch(kmers: impl Iterator<Item = u64>) -> OrderMinHash {
+        let mut sketch = OrderMinHash::new(Some(64));
+        kmers.for_each(|kmer| sketch.add_kmer(kmer, 42));
+        sketch
+    }
+
+    // sketches read back from a set hold only the signature; set operations on
+    // them must not need a union of k-mers
+    #[test]
+    fn loaded_sketches_answer_set_operations() {
+        let load = |sketch: &OrderMinHash| {
+            let mut bytes = Vec::new();
+            sketch.save(&mut bytes).unwrap();
+            OrderMinHash::load(&mut bytes.as_slice()).unwrap()
+        };
+        let (a, b) = (load(&sketch(0..1000)), load(&sketch(500..1500)));
+        let intersection = a.intersection_size(&b);
+        assert!(intersection > 0.0 && intersection < 1000.0);
+        assert_eq!(a.union_size(&b), 2000.0 - intersection);
+        assert_eq!(a.difference_size(&b), 1000.0 - intersection);
+        assert_eq!(a.union_size(&a), 1000.0);
+    }
+}
@@ -0,0 +1,431 @@
+// src/pairwise.rs
+// Comparisons that reduce the distance matrix as it is computed: all-vs-all within
+// one sketch set with each unordered pair computed once, top hits per query,
+// clustering on top of the all-vs-all distances, greedy dereplication, ANI tables,
+// and one-off comparisons of two FASTA files
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use hashbrown::HashMap;
+use rayon::prelude::*;
+
+use crate::utils::{
+    check_same_params, compute_distance, find_files, load_sketches, metadata_header, read_names, read_params, sketch_file,
+    with_sketch_type, KmerSketch, SketchParams,
+};
+
+// a sketch set loaded once, with its names and parameters
+pub struct SketchSet {
+    pub names: Vec<String>,
+    pub params: HashMap<String, String>,
+    pub kmer_length: usize,
+    pub sketch_file: String,
+}
+
+impl SketchSet {
+    pub fn open(prefix: &str) -> Result<Self, Box<dyn Error>> {
+        let files = find_files(prefix)?;
+        let params = read_params(&files["params"])?;
+        Ok(SketchSet {
+            names: read_names(&files["files"])?,
+            kmer_length: params["k"].parse()?,
+            params,
+            sketch_file: files["sketches"].clone(),
+        })
+    }
+}
+
+// distance between two sketches, same estimators as `dist`
+pub fn sketch_distance<S: KmerSketch>(a: &S, b: &S, kmer_length: usize, equation: u8) -> f64 {
+    let jaccard = a.jaccard(b);
+    compute_distance(2.0 * jaccard / (1.0 + jaccard), kmer_length, equation)
+}
+
+fn triangle_with<S: KmerSketch + Sync>(set: &SketchSet, equation: u8) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    let sketches: Vec<S> = load_sketches(&set.sketch_file, set.names.len())?;
+    Ok(sketches
+        .par_iter()
+        .enumerate()
+        .map(|(i, a)| {
+            sketches[..i]
+                .iter()
+                .map(|b| sketch_distance(a, b, set.kmer_length, equation))
+                .collect()
+        })
+        .collect())
+}
+
+// row i holds the distances of genome i to genomes 0..i
+pub fn lower_triangle(set: &SketchSet, equation: u8) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    with_sketch_type!(set.params["algorithm"].as_str(), triangle_with(set, equation))
+}
+
+// lower-triangular matrix without the diagonal, laid out like `dist --dm`
+pub fn triangle(prefix: &str, output: &str, equation: u8, metadata: bool) -> Result<(), Box<dyn Error>> {
+    let set = SketchSet::open(prefix)?;
+    let rows = lower_triangle(&set, equation)?;
+
+    let mut out = BufWriter::new(File::create(output)?);
+    if metadata {
+        writeln!(out, "{}", metadata_header(&set.params, equation))?;
+    }
+    for name in &set.names {
+        write!(out, "\t{}", name)?;
+    }
+    writeln!(out)?;
+    for (name, row) in set.names.iter().zip(&rows) {
+        write!(out, "{}", name)?;
+        for d in row {
+            write!(out, "\t{:.6}", d)?;
+        }
+        writeln!(out)?;
+    }
+    out.flush()?;
+
+    let n = set.names.len();
+    println!("{} pairs of {} genomes written to {}.", n * n.saturating_sub(1) / 2, n, output);
+    Ok(())
+}
+
+// (reference index, distance), best first
+pub type QueryHits = Vec<(usize, f64)>;
+
+fn search_with<S: KmerSketch + Sync>(
+    queries: &SketchSet,
+    references: &SketchSet,
+    top: usize,
+    equation: u8,
+) -> Result<Vec<QueryHits>, Box<dyn Error>> {
+    let query_sketches: Vec<S> = load_sketches(&queries.sketch_file, queries.names.len())?;
+    let ref_sketches: Vec<S> = load_sketches(&references.sketch_file, references.names.len())?;
+    Ok(query_sketches
+        .par_iter()
+        .zip(&queries.names)
+        .map(|(q, q_name)| {
+            let mut hits: QueryHits = ref_sketches
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| references.names[*j] != *q_name)
+                .map(|(j, r)| (j, sketch_distance(r, q, references.kmer_length, equation)))
+                .collect();
+            // only the best `top` are sorted
+            let by_distance = |a: &(usize, f64), b: &(usize, f64)| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0));
+            if hits.len() > top {
+                hits.select_nth_unstable_by(top, by_distance);
+                hits.truncate(top);
+            }
+            hits.sort_by(by_distance);
+            hits
+        })
+        .collect())
+}
+
+// best `top` references of every query, self hits excluded
+pub fn search(
+    query: &str,
+    reference: &str,
+    output: &str,
+    top: usize,
+    equation: u8,
+    metadata: bool,
+) -> Result<(), Box<dyn Error>> {
+    let queries = SketchSet::open(query)?;
+    let references = SketchSet::open(reference)?;
+    check_same_params(query, &queries.params, reference, &references.params)?;
+
+    let hits = with_sketch_type!(
+        references.params["algorithm"].as_str(),
+        search_with(&queries, &references, top, equation)
+    )?;
+
+    let mut out = BufWriter::new(File::create(output)?);
+    if metadata {
+        writeln!(out, "{}", metadata_header(&references.params, equation))?;
+    }
+    writeln!(out, "Reference\tQuery\tDistance")?;
+    for (q_name, q_hits) in queries.names.iter().zip(&hits) {
+        for (j, d) in q_hits {
+            writeln!(out, "{}\t{}\t{:.6}", references.names[*j], q_name, d)?;
+        }
+    }
+    out.flush()?;
+
+    println!(
+        "Best {} of {} references for {} queries written to {}.",
+        top,
+        references.names.len(),
+        queries.names.len(),
+        output
+    );
+    Ok(())
+}
+
+fn find_root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+// single-linkage clusters of the lower triangle `rows`: genomes closer than
+// `threshold` end up together, transitively; clusters are numbered by their first
+// genome, each with the member of smallest summed distance to the rest as representative
+fn single_linkage(rows: &[Vec<f64>], threshold: f64) -> Vec<(Vec<usize>, usize)> {
+    let n = rows.len();
+    let distance = |i: usize, j: usize| match i.cmp(&j) {
+        std::cmp::Ordering::Greater => rows[i][j],
+        std::cmp::Ordering::Less => rows[j][i],
+        std::cmp::Ordering::Equal => 0.0,
+    };
+
+    let mut parent: Vec<usize> = (0..n).collect();
+    for (i, row) in rows.iter().enumerate() {
+        for (j, &d) in row.iter().enumerate() {
+            if d <= threshold {
+                let (a, b) = (find_root(&mut parent, i), find_root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+    let mut cluster_of_root: HashMap<usize, usize> = HashMap::new();
+    for i in 0..n {
+        let root = find_root(&mut parent, i);
+        let c = *cluster_of_root.entry(root).or_insert_with(|| {
+            clusters.push(Vec::new());
+            clusters.len() - 1
+        });
+        clusters[c].push(i);
+    }
+
+    clusters
+        .into_iter()
+        .map(|members| {
+            let representative = *members
+                .iter()
+                .min_by(|&&a, &&b| {
+                    let sum = |x: usize| members.iter().map(|&m| distance(x, m)).sum::<f64>();
+                    sum(a).total_cmp(&sum(b))
+                })
+                .expect("clusters are never empty");
+            (members, representative)
+        })
+        .collect()
+}
+
+// single-linkage clusters of a sketch set at distance `threshold`
+pub fn cluster(prefix: &str, output: &str, threshold: f64, equation: u8) -> Result<(), Box<dyn Error>> {
+    let set = SketchSet::open(prefix)?;
+    let clusters = single_linkage(&lower_triangle(&set, equation)?, threshold);
+
+    let mut out = BufWriter::new(File::create(output)?);
+    writeln!(out, "Genome\tCluster\tRepresentative")?;
+    for (c, (members, representative)) in clusters.iter().enumerate() {
+        for &m in members {
+            writeln!(out, "{}\t{}\t{}", set.names[m], c + 1, set.names[*representative])?;
+        }
+    }
+    out.flush()?;
+
+    println!(
+        "{} genomes in {} clusters at distance {}, written to {}.",
+        set.names.len(),
+        clusters.len(),
+        threshold,
+        output
+    );
+    Ok(())
+}
+
+// representative (index into kept) and distance of every discarded genome
+type Discarded = Vec<(usize, usize, f64)>;
+
+fn dereplicate_with<S: KmerSketch + Sync>(
+    files: &[String],
+    params: &SketchParams,
+    threshold: f64,
+    equation: u8,
+) -> Result<(Vec<usize>, Discarded), Box<dyn Error>> {
+    let sketches: Vec<S> = files
+        .par_iter()
+        .map(|file_name| sketch_file(file_name, params).map_err(|e| format!("{}: {}", file_name, e)))
+        .collect::<Result<_, _>>()?;
+
+    // largest genomes first, they are the most complete candidates
+    let sizes: Vec<f64> = sketches.par_iter().map(|s| s.cardinality()).collect();
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by(|&a, &b| sizes[b].total_cmp(&sizes[a]).then(a.cmp(&b)));
+
+    let mut kept: Vec<usize> = Vec::new();
+    let mut discarded = Vec::new();
+    for i in order {
+        let closest = kept
+            .par_iter()
+            .map(|&r| (r, sketch_distance(&sketches[r], &sketches[i], params.kmer_length, equation)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        match closest {
+            Some((r, d)) if d <= threshold => discarded.push((i, r, d)),
+            _ => kept.push(i),
+        }
+    }
+    Ok((kept, discarded))
+}
+
+// greedy dereplication: a genome is kept unless a kept genome is within `threshold`,
+// writes `{output}_kept.txt` (a file list for `sketch -f`) and `{output}_discarded.txt`
+pub fn dereplicate(
+    files: &[String],
+    params: &SketchParams,
+    output: &str,
+    threshold: f64,
+    equation: u8,
+) -> Result<(), Box<dyn Error>> {
+    let (kept, discarded) = with_sketch_type!(
+        params.algorithm.as_str(),
+        dereplicate_with(files, params, threshold, equation)
+    )?;
+
+    let kept_file = format!("{}_kept.txt", output);
+    let mut out = BufWriter::new(File::create(&kept_file)?);
+    for &i in &kept {
+        writeln!(out, "{}", files[i])?;
+    }
+    out.flush()?;
+
+    let discarded_file = format!("{}_discarded.txt", output);
+    let mut out = BufWriter::new(File::create(&discarded_file)?);
+    writeln!(out, "Genome\tRepresentative\tDistance")?;
+    for &(i, r, d) in &discarded {
+        writeln!(out, "{}\t{}\t{:.6}", files[i], files[r], d)?;
+    }
+    out.flush()?;
+
+    println!(
+        "{} of {} genomes kept at distance {}: {}, {}.",
+        kept.len(),
+        files.len(),
+        threshold,
+        kept_file,
+        discarded_file
+    );
+    Ok(())
+}
+
+fn ani_with<S: KmerSketch + Sync>(
+    queries: &SketchSet,
+    references: &SketchSet,
+    same_set: bool,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let ref_sketches: Vec<S> = load_sketches(&references.sketch_file, references.names.len())?;
+    let query_sketches: Vec<S> = if same_set {
+        Vec::new()
+    } else {
+        load_sketches(&queries.sketch_file, queries.names.len())?
+    };
+    let query_sketches = if same_set { &ref_sketches } else { &query_sketches };
+    let k = references.kmer_length;
+
+    // one block of lines per reference, in sketch set order
+    Ok(ref_sketches
+        .par_iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let mut lines = String::new();
+            // within one set every unordered pair once, as `dist` does
+            let n = if same_set { i + 1 } else { query_sketches.len() };
+            for (j, q) in query_sketches[..n].iter().enumerate() {
+                let jaccard = r.jaccard(q);
+                let frac = 2.0 * jaccard / (1.0 + jaccard);
+                let (poisson, binomial) = if same_set && i == j {
+                    (0.0, 0.0)
+                } else {
+                    (compute_distance(frac, k, 1), compute_distance(frac, k, 0))
+                };
+                lines.push_str(&format!(
+                    "{}\t{}\t{:.4}\t{:.4}\n",
+                    references.names[i],
+                    queries.names[j],
+                    100.0 * (1.0 - poisson),
+                    100.0 * (1.0 - binomial)
+                ));
+            }
+            lines
+        })
+        .collect())
+}
+
+// query vs reference ANI = 100 * (1 - distance), Poisson and binomial models side by side
+pub fn ani(query: &str, reference: &str, output: &str, metadata: bool) -> Result<(), Box<dyn Error>> {
+    let queries = SketchSet::open(query)?;
+    let references = SketchSet::open(reference)?;
+    check_same_params(query, &queries.params, reference, &references.params)?;
+    let same_set = queries.sketch_file == references.sketch_file;
+
+    let blocks = with_sketch_type!(
+        references.params["algorithm"].as_str(),
+        ani_with(&queries, &references, same_set)
+    )?;
+
+    let mut out = BufWriter::new(File::create(output)?);
+    if metadata {
+        let line = metadata_header(&references.params, 1).replace("model=poisson", "model=poisson,binomial");
+        writeln!(out, "{}", line)?;
+    }
+    writeln!(out, "Reference\tQuery\tANI_Poisson\tANI_Binomial")?;
+    for block in blocks {
+        out.write_all(block.as_bytes())?;
+    }
+    out.flush()?;
+    println!("ANI written to {}.", output);
+    Ok(())
+}
+
+fn compare_with<S: KmerSketch + Sync>(a: &str, b: &str, params: &SketchParams) -> Result<f64, Box<dyn Error>> {
+    let (sa, sb) = rayon::join(
+        || sketch_file::<S>(a, params).map_err(|e| format!("{}: {}", a, e)),
+        || sketch_file::<S>(b, params).map_err(|e| format!("{}: {}", b, e)),
+    );
+    Ok(sa?.jaccard(&sb?))
+}
+
+// sketch two files in memory and print their Jaccard index, distance and ANI
+pub fn compare(a: &str, b: &str, params: &SketchParams, equation: u8) -> Result<(), Box<dyn Error>> {
+    let jaccard = with_sketch_type!(params.algorithm.as_str(), compare_with(a, b, params))?;
+    let distance = compute_distance(2.0 * jaccard / (1.0 + jaccard), params.kmer_length, equation);
+    println!("GenomeA\tGenomeB\tJaccard\tDistance\tANI");
+    println!(
+        "{}\t{}\t{:.6}\t{:.6}\t{:.4}",
+        a,
+        b,
+        jaccard,
+        distance,
+        100.0 * (1.0 - distance)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_linkage_chains_close_genomes() {
+        // 0-1 and 1-2 are close, 0-2 is not; 3 and 4 are close to each other only
+        let rows = vec![
+            vec![],
+            vec![0.01],
+            vec![0.08, 0.02],
+            vec![0.30, 0.31, 0.29],
+            vec![0.32, 0.30, 0.31, 0.04],
+            vec![0.40, 0.41, 0.42, 0.35, 0.36],
+        ];
+        let clusters = single_linkage(&rows, 0.05);
+        assert_eq!(clusters, vec![(vec![0, 1, 2], 1), (vec![3, 4], 3), (vec![5], 5)]);
+        assert_eq!(single_linkage(&rows, 0.0).len(), 6);
+        assert_eq!(single_linkage(&rows, 1.0), vec![(vec![0, 1, 2, 3, 4, 5], 1)]);
+    }
+}
@@ -0,0 +1,191 @@
+// src/profile.rs
+// `lash profile`: sketch + all-vs-all dist on a file set with every stage timed,
+// so users can tell whether IO or CPU limits their runs
+
+use std::error::Error;
+use std::fs::File;
+use std::hint::black_box;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use needletail::errors::{ParseError, ParseErrorKind};
+use rayon::prelude::*;
+use serde_json::json;
+use xxhash_rust::xxh3::{xxh3_128_with_seed, xxh3_64_with_seed};
+use zstd::stream::{Decoder, Encoder};
+
+use crate::utils::{filter_out_n, for_each_aa_kmer, for_each_kmer, open_fastx, with_sketch_type, KmerSketch};
+
+// nanoseconds summed over all threads, per stage
+#[derive(Default)]
+struct StageTimes {
+    parsing: AtomicU64,
+    kmerization: AtomicU64,
+    hashing: AtomicU64,
+    insertion: AtomicU64,
+    compression: AtomicU64,
+    loading: AtomicU64,
+    pairwise: AtomicU64,
+}
+
+fn add_elapsed(counter: &AtomicU64, start: Instant) -> u64 {
+    let ns = start.elapsed().as_nanos() as u64;
+    counter.fetch_add(ns, Ordering::Relaxed);
+    ns
+}
+
+pub struct ProfileOptions {
+    pub files: Vec<String>,
+    pub kmer_length: usize,
+    pub algorithm: String,
+    pub precision: Option<u32>,
+    pub seed: u64,
+    pub aa: bool,
+    pub threads: u32,
+}
+
+// the sketching loop of `sketch_files`, split into separately timed stages;
+// hashing is timed on its own pass, insertion is what add_kmer costs on top of it
+fn profile_sketch<S: KmerSketch>(opts: &ProfileOptions, file_name: &str, times: &StageTimes) -> Result<S, ParseError> {
+    let mut reader = open_fastx(file_name)?;
+    let mut sketch = S::new(opts.precision);
+    let mut kmers: Vec<u64> = Vec::new();
+
+    loop {
+        let start = Instant::now();
+        let record = reader.next();
+        add_elapsed(&times.parsing, start);
+        let seqrec = match record {
+            Some(Ok(seqrec)) => seqrec,
+            Some(Err(e)) if e.kind == ParseErrorKind::Io => return Err(e),
+            Some(Err(_)) => continue,
+            None => break,
+        };
+
+        let start = Instant::now();
+        kmers.clear();
+        if opts.aa {
+            let seq = seqrec.seq().to_ascii_uppercase();
+            for_each_aa_kmer(&seq, opts.kmer_length, |masked| kmers.push(masked));
+        } else {
+            let seq = filter_out_n(seqrec.seq().as_ref());
+            for_each_kmer(&seq, opts.kmer_length, |masked| kmers.push(masked));
+        }
+        add_elapsed(&times.kmerization, start);
+
+        let start = Instant::now();
+        for &masked in &kmers {
+            if opts.algorithm == "hmh" {
+                black_box(xxh3_128_with_seed(&(masked as u32).to_le_bytes(), opts.seed));
+            } else {
+                black_box(xxh3_64_with_seed(&masked.to_le_bytes(), opts.seed));
+            }
+        }
+        let hashing = add_elapsed(&times.hashing, start);
+
+        let start = Instant::now();
+        for &masked in &kmers {
+            sketch.add_kmer(masked, opts.seed);
+        }
+        let adding = start.elapsed().as_nanos() as u64;
+        times.insertion.fetch_add(adding.saturating_sub(hashing), Ordering::Relaxed);
+    }
+    Ok(sketch)
+}
+
+fn profile_with<S: KmerSketch + Sync>(opts: &ProfileOptions, times: &StageTimes) -> Result<usize, Box<dyn Error>> {
+    let sketches: Vec<S> = opts
+        .files
+        .par_iter()
+        .map(|file_name| profile_sketch(opts, file_name, times).map_err(|e| format!("{}: {}", file_name, e)))
+        .collect::<Result<_, _>>()?;
+
+    let start = Instant::now();
+    let mut encoder = Encoder::new(Vec::new(), 3)?;
+    encoder.multithread(opts.threads)?;
+    for sketch in &sketches {
+        sketch.save(&mut encoder)?;
+    }
+    let compressed = encoder.finish()?;
+    add_elapsed(&times.compression, start);
+
+    let start = Instant::now();
+    let mut decoder = Decoder::new(compressed.as_slice())?;
+    let loaded: Vec<S> = (0..sketches.len())
+        .map(|_| S::load(&mut decoder))
+        .collect::<std::io::Result<_>>()?;
+    add_elapsed(&times.loading, start);
+
+    let start = Instant::now();
+    let pairs: usize = loaded
+        .par_iter()
+        .enumerate()
+        .map(|(i, a)| {
+            for b in &loaded[..=i] {
+                black_box(a.jaccard(b));
+            }
+            i + 1
+        })
+        .sum();
+    add_elapsed(&times.pairwise, start);
+
+    Ok(pairs)
+}
+
+pub fn profile(opts: &ProfileOptions, output: &str, json_file: Option<&String>) -> Result<(), Box<dyn Error>> {
+    let times = StageTimes::default();
+    let start = Instant::now();
+    let pairs = with_sketch_type!(opts.algorithm.as_str(), profile_with(opts, &times))?;
+    let wall = start.elapsed().as_secs_f64();
+
+    let stages: Vec<(&str, &str, f64)> = [
+        ("sketch", "parsing", &times.parsing),
+        ("sketch", "kmerization", &times.kmerization),
+        ("sketch", "hashing", &times.hashing),
+        ("sketch", "insertion", &times.insertion),
+        ("sketch", "compression", &times.compression),
+        ("dist", "loading", &times.loading),
+        ("dist", "pairwise", &times.pairwise),
+    ]
+    .iter()
+    .map(|(group, stage, ns)| (*group, *stage, ns.load(Ordering::Relaxed) as f64 / 1e9))
+    .collect();
+    let total: f64 = stages.iter().map(|s| s.2).sum();
+
+    let mut file = File::create(output)?;
+    writeln!(file, "Stage\tSeconds\tShare")?;
+    println!("Stage\tSeconds\tShare");
+    for (_, stage, secs) in &stages {
+        let share = if total > 0.0 { 100.0 * secs / total } else { 0.0 };
+        writeln!(file, "{}\t{:.4}\t{:.1}%", stage, secs, share)?;
+        println!("{}\t{:.4}\t{:.1}%", stage, secs, share);
+    }
+    println!(
+        "{} files, {} pairs in {:.3}s wall time on {} threads (stage times are summed over threads).",
+        opts.files.len(),
+        pairs,
+        wall,
+        rayon::current_num_threads()
+    );
+
+    // flame graph JSON (name/value/children, as read by d3-flame-graph), values in microseconds
+    if let Some(json_file) = json_file {
+        let group = |name: &str| {
+            let children: Vec<_> = stages
+                .iter()
+                .filter(|s| s.0 == name)
+                .map(|s| json!({"name": s.1, "value": (s.2 * 1e6) as u64}))
+                .collect();
+            let value: f64 = stages.iter().filter(|s| s.0 == name).map(|s| s.2).sum();
+            json!({"name": name, "value": (value * 1e6) as u64, "children": children})
+        };
+        let flame = json!({
+            "name": "lash",
+            "value": (total * 1e6) as u64,
+            "children": [group("sketch"), group("dist")],
+        });
+        File::create(json_file)?.write_all(serde_json::to_string_pretty(&flame)?.as_bytes())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+// src/regions.rs
+// Target regions for `sketch --include-bed <file>`: only the BED intervals of a
+// record (0-based, end exclusive, chromosome column matched against the record
+// id) are sketched, records without one are skipped. With one BED for all inputs
+// the genomes are compared over the same marker genes or core regions, so mobile
+// elements outside them do not move distances. The path is recorded in the
+// parameters, so sets are updated and queried over the same regions.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct Regions {
+    pub path: String,
+    // sorted (start, end) intervals per record id
+    by_record: Arc<HashMap<String, Vec<(usize, usize)>>>,
+}
+
+impl Regions {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let mut by_record: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let invalid = || format!("invalid BED line in {}: {}", path, line);
+            if fields.len() < 3 {
+                return Err(invalid().into());
+            }
+            let start: usize = fields[1].trim().parse().map_err(|_| invalid())?;
+            let end: usize = fields[2].trim().parse().map_err(|_| invalid())?;
+            if end > start {
+                by_record.entry(fields[0].to_string()).or_default().push((start, end));
+            }
+        }
+        // overlapping intervals merged, so no k-mer is sketched twice
+        for intervals in by_record.values_mut() {
+            intervals.sort_unstable();
+            let mut merged: Vec<(usize, usize)> = Vec::with_capacity(intervals.len());
+            for &(start, end) in intervals.iter() {
+                match merged.last_mut() {
+                    Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                    _ => merged.push((start, end)),
+                }
+            }
+            *intervals = merged;
+        }
+        Ok(Regions { path: path.to_string(), by_record: Arc::new(by_record) })
+    }
+
+    // the intervals of a record, clipped to its length; empty when it has none
+    pub fn of_record(&self, id: &str, length: usize) -> Vec<(usize, usize)> {
+        self.by_record
+            .get(id)
+            .map(|intervals| {
+                intervals
+                    .iter()
+                    .map(|&(start, end)| (start.min(length), end.min(length)))
+                    .filter(|(start, end)| end > start)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,291 @@
+// src/remote.rs
+// Inputs read over the network: http://, https:// and ftp:// paths in a file
+// list are streamed straight into the FASTA/FASTQ parser, which decompresses
+// them like local files, so nothing is written to disk. Connecting is retried
+// with exponential backoff (1, 2, 4, 8 s) on network errors, HTTP 5xx/408/429
+// and FTP 4xx replies, and a transfer cut off midway is resumed at the byte it
+// stopped (HTTP Range, FTP REST), so one dropped connection does not lose a
+// large read set. FTP is anonymous unless the URL has user:password@.
+
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use log::warn;
+
+const ATTEMPTS: u32 = 5;
+// a stalled transfer fails after this long and is resumed
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub fn is_remote(path: &str) -> bool {
+    ["http://", "https://", "ftp://"].iter().any(|scheme| path.starts_with(scheme))
+}
+
+// stream of the file at `url`, reconnecting where it stopped when cut off
+pub struct RemoteReader {
+    url: String,
+    offset: u64,
+    inner: Box<dyn Read + Send>,
+}
+
+pub fn open(url: &str) -> io::Result<RemoteReader> {
+    Ok(RemoteReader { url: url.to_string(), offset: 0, inner: connect_with_retry(url, 0)? })
+}
+
+impl Read for RemoteReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.inner.read(buf) {
+                Ok(n) => {
+                    self.offset += n as u64;
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    warn!("{}: {} after {} bytes, resuming", self.url, e, self.offset);
+                    self.inner = connect_with_retry(&self.url, self.offset)?;
+                }
+            }
+        }
+    }
+}
+
+// refused, missing or malformed: trying again will not help
+fn permanent(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::NotFound | ErrorKind::PermissionDenied | ErrorKind::InvalidInput)
+}
+
+fn connect_with_retry(url: &str, offset: u64) -> io::Result<Box<dyn Read + Send>> {
+    let mut attempt = 0;
+    loop {
+        let connected = match url.starts_with("ftp://") {
+            true => ftp_retrieve(url, offset),
+            false => http_get(url, offset),
+        };
+        match connected {
+            Err(e) if !permanent(&e) && attempt + 1 < ATTEMPTS => {
+                let wait = Duration::from_secs(1 << attempt);
+                warn!("{}: {}, retrying in {} s", url, e, wait.as_secs());
+                thread::sleep(wait);
+                attempt += 1;
+            }
+            connected => return connected.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", url, e))),
+        }
+    }
+}
+
+fn http_get(url: &str, offset: u64) -> io::Result<Box<dyn Read + Send>> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(30))
+        .timeout_read(READ_TIMEOUT)
+        .build();
+    let mut request = agent.get(url);
+    if offset > 0 {
+        request = request.set("Range", &format!("bytes={}-", offset));
+    }
+    let response = match request.call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, _)) => {
+            let kind = match code {
+                408 | 429 | 500.. => ErrorKind::Other,
+                401 | 403 => ErrorKind::PermissionDenied,
+                404 | 410 => ErrorKind::NotFound,
+                _ => ErrorKind::InvalidInput,
+            };
+            return Err(io::Error::new(kind, format!("HTTP status {}", code)));
+        }
+        Err(ureq::Error::Transport(e)) => {
+            return Err(io::Error::other(format!("{}: {}", e.kind(), e.message().unwrap_or_default())));
+        }
+    };
+    let resumed = response.status() == 206;
+    let mut reader = response.into_reader();
+    // a server without range requests sends the whole file again
+    if offset > 0 && !resumed {
+        io::copy(&mut (&mut reader).take(offset), &mut io::sink())?;
+    }
+    Ok(Box::new(reader))
+}
+
+// the data connection of an FTP transfer; its end is only the end of the file
+// once the control connection confirms the transfer completed
+struct FtpTransfer {
+    data: TcpStream,
+    control: BufReader<TcpStream>,
+}
+
+impl Read for FtpTransfer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.data.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            ftp_reply(&mut self.control, &[226, 250])?;
+        }
+        Ok(n)
+    }
+}
+
+// code of the next (possibly multi-line) reply, an error unless it is one of `expected`
+fn ftp_reply(control: &mut BufReader<TcpStream>, expected: &[u32]) -> io::Result<(u32, String)> {
+    let mut line = String::new();
+    let read_line = |control: &mut BufReader<TcpStream>, line: &mut String| -> io::Result<()> {
+        line.clear();
+        match control.read_line(line)? {
+            0 => Err(io::Error::new(ErrorKind::UnexpectedEof, "FTP server closed the connection")),
+            _ => Ok(()),
+        }
+    };
+    read_line(control, &mut line)?;
+    let code: u32 = line
+        .get(..3)
+        .and_then(|c| c.parse().ok())
+        .ok_or_else(|| io::Error::other(format!("invalid FTP reply: {}", line.trim_end())))?;
+    // a multi-line reply ends with a line starting with the code and a space
+    if line.as_bytes().get(3) == Some(&b'-') {
+        let end = format!("{} ", code);
+        let mut last = String::new();
+        while !last.starts_with(&end) {
+            read_line(control, &mut last)?;
+        }
+    }
+    if expected.contains(&code) {
+        return Ok((code, line));
+    }
+    let kind = match code {
+        530 | 532 => ErrorKind::PermissionDenied,
+        550 => ErrorKind::NotFound,
+        500..=599 => ErrorKind::InvalidInput,
+        _ => ErrorKind::Other,
+    };
+    Err(io::Error::new(kind, format!("FTP reply {}", line.trim_end())))
+}
+
+fn ftp_retrieve(url: &str, offset: u64) -> io::Result<Box<dyn Read + Send>> {
+    let invalid = || io::Error::new(ErrorKind::InvalidInput, "expected ftp://[user[:password]@]host[:port]/path");
+    let (authority, path) = url.strip_prefix("ftp://").and_then(|rest| rest.split_once('/')).ok_or_else(invalid)?;
+    let (user, password, host) = match authority.rsplit_once('@') {
+        Some((credentials, host)) => match credentials.split_once(':') {
+            Some((user, password)) => (user, password, host),
+            None => (credentials, "", host),
+        },
+        None => ("anonymous", "lash@", authority),
+    };
+    let address = match host.contains(':') {
+        true => host.to_string(),
+        false => format!("{}:21", host),
+    };
+
+    let stream = TcpStream::connect(&address)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut control = BufReader::new(stream);
+    let mut command = |control: &mut BufReader<TcpStream>, line: &str, expected: &[u32]| {
+        writer.write_all(format!("{}\r\n", line).as_bytes())?;
+        ftp_reply(control, expected)
+    };
+    ftp_reply(&mut control, &[220])?;
+    if command(&mut control, &format!("USER {}", user), &[230, 331])?.0 == 331 {
+        command(&mut control, &format!("PASS {}", password), &[230])?;
+    }
+    command(&mut control, "TYPE I", &[200])?;
+    if offset > 0 {
+        command(&mut control, &format!("REST {}", offset), &[350])?;
+    }
+    // passive mode, the data port is the last two numbers of (h1,h2,h3,h4,p1,p2);
+    // connected on the control connection's address, some servers announce a private one
+    let (_, reply) = command(&mut control, "PASV", &[227])?;
+    let numbers: Vec<u16> = reply
+        .rsplit_once('(')
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .map(|(inside, _)| inside.split(',').filter_map(|n| n.trim().parse().ok()).collect())
+        .unwrap_or_default();
+    let [.., high, low] = numbers[..] else {
+        return Err(io::Error::other(format!("invalid PASV reply: {}", reply.trim_end())));
+    };
+    let data = TcpStream::connect((control.get_ref().peer_addr()?.ip(), high * 256 + low))?;
+    data.set_read_timeout(Some(READ_TIMEOUT))?;
+    command(&mut control, &format!("RETR /{}", path), &[125, 150])?;
+    Ok(Box::new(FtpTransfer { data, control }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn body() -> Vec<u8> {
+        (0..100_000u64).map(|i| b"ACGT"[((i * i + i / 3) % 4) as usize]).collect()
+    }
+
+    // HTTP server on a local port answering `connections` requests for `body()`: the
+    // first is cut off halfway, later ones start at the Range offset if `ranges`;
+    // returns the URL and the offsets asked for
+    fn server(connections: usize, ranges: bool) -> (String, thread::JoinHandle<Vec<u64>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/genome.fa", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let body = body();
+            let mut offsets = Vec::new();
+            for i in 0..connections {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut offset = 0;
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    if let Some(range) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        offset = range.trim().trim_end_matches('-').parse().unwrap();
+                    }
+                    line.clear();
+                }
+                offsets.push(offset);
+                let mut stream = stream;
+                let (status, start) = match ranges && offset > 0 {
+                    true => ("206 Partial Content", offset as usize),
+                    false => ("200 OK", 0),
+                };
+                let header = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len() - start);
+                stream.write_all(header.as_bytes()).unwrap();
+                let end = if i == 0 { body.len() / 2 } else { body.len() };
+                stream.write_all(&body[start..end]).unwrap();
+            }
+            offsets
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn cut_transfer_resumes_at_offset() {
+        for ranges in [true, false] {
+            let (url, server) = server(2, ranges);
+            let mut read = Vec::new();
+            open(&url).unwrap().read_to_end(&mut read).unwrap();
+            assert!(read == body(), "ranges {}", ranges);
+            let offsets = server.join().unwrap();
+            assert_eq!(offsets[0], 0);
+            assert!(offsets[1] > 0 && offsets[1] <= body().len() as u64 / 2);
+        }
+    }
+
+    #[test]
+    fn missing_file_fails_at_once() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/missing.fa", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+        });
+        let error = open(&url).err().expect("404 is an error");
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn remote_paths() {
+        assert!(is_remote("https://ftp.ncbi.nlm.nih.gov/genomes/a.fna.gz"));
+        assert!(is_remote("ftp://ftp.ebi.ac.uk/pub/a.fastq.gz"));
+        assert!(!is_remote("data/http/a.fa"));
+    }
+}
@@ -0,0 +1,209 @@
+// src/results.rs
+// Tools working on distance outputs rather than on sketches
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+use hashbrown::{HashMap, HashSet};
+use log::warn;
+
+// one distance output, long form or --dm matrix
+pub struct DistTable {
+    // the `# lash=...` line, if the file has one
+    pub metadata: Option<String>,
+    // column names of the long form, the matrix is read as Reference/Query/Distance
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+// key=value pairs of a metadata line, without the version that wrote it
+fn metadata_params(line: &str) -> Vec<(String, String)> {
+    line.trim_start_matches('#')
+        .split_whitespace()
+        .filter_map(|kv| kv.split_once('='))
+        .filter(|(k, _)| *k != "lash")
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+pub fn read_dist_table(file_name: &str) -> Result<DistTable, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(file_name)?);
+    let mut metadata = None;
+    let mut header: Option<Vec<String>> = None;
+    let mut matrix_columns: Option<Vec<String>> = None;
+    // `dist --dm` on one sketch set writes the lower triangle only, its first row has one distance
+    let mut triangle = None;
+    let mut rows = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with('#') {
+            if header.is_none() && matrix_columns.is_none() {
+                metadata = Some(line);
+            }
+            continue;
+        }
+        let fields: Vec<String> = line.split('\t').map(str::to_string).collect();
+        if header.is_none() && matrix_columns.is_none() {
+            // the matrix header starts with an empty cell
+            if fields[0].is_empty() {
+                matrix_columns = Some(fields[1..].to_vec());
+                header = Some(vec!["Reference".into(), "Query".into(), "Distance".into()]);
+            } else {
+                header = Some(fields);
+            }
+            continue;
+        }
+        match &matrix_columns {
+            Some(columns) => {
+                if fields.len() > columns.len() + 1 {
+                    return Err(format!("{}: matrix row {} has {} columns", file_name, fields[0], fields.len()).into());
+                }
+                let triangle = *triangle.get_or_insert(fields.len() <= columns.len());
+                for (query, d) in columns.iter().zip(&fields[1..]) {
+                    rows.push(vec![fields[0].clone(), query.clone(), d.clone()]);
+                    if triangle && *query != fields[0] {
+                        rows.push(vec![query.clone(), fields[0].clone(), d.clone()]);
+                    }
+                }
+            }
+            None => {
+                let width = header.as_ref().map_or(0, Vec::len);
+                if fields.len() != width {
+                    return Err(format!("{}: expected {} columns, found {} in: {}", file_name, width, fields.len(), line).into());
+                }
+                rows.push(fields);
+            }
+        }
+    }
+    let header = header.ok_or_else(|| format!("{} has no header line", file_name))?;
+    if header.len() < 3 {
+        return Err(format!("{} is not a lash distance output", file_name).into());
+    }
+    Ok(DistTable { metadata, header, rows })
+}
+
+// combine partial dist outputs (shards, resumed runs) into one table, overlapping
+// Reference/Query pairs are kept once
+pub fn merge_results(
+    inputs: &[String],
+    output: &str,
+    matrix: bool,
+    write_metadata: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut tables = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        tables.push((input, read_dist_table(input)?));
+    }
+    let (first_name, first) = &tables[0];
+
+    // same columns and same parameters everywhere
+    let expected = first.metadata.as_deref().map(metadata_params);
+    for (name, table) in &tables[1..] {
+        if table.header != first.header {
+            return Err(format!(
+                "{} has columns {} but {} has {}",
+                name,
+                table.header.join(","),
+                first_name,
+                first.header.join(",")
+            ).into());
+        }
+        match (&expected, table.metadata.as_deref().map(metadata_params)) {
+            (Some(a), Some(b)) if *a != b => {
+                return Err(format!(
+                    "{} and {} were computed with different parameters:\n{}\n{}",
+                    first_name,
+                    name,
+                    first.metadata.as_deref().unwrap_or(""),
+                    table.metadata.as_deref().unwrap_or("")
+                ).into());
+            }
+            (Some(_), Some(_)) => {}
+            _ => warn!("{} or {} has no parameter line, parameters were not checked", first_name, name),
+        }
+    }
+    if matrix && first.header.len() != 3 {
+        return Err(format!("--dm needs Reference/Query/Distance tables, {} has {} columns", first_name, first.header.len()).into());
+    }
+
+    // first occurrence of a pair wins
+    let mut seen: HashMap<(String, String), usize> = HashMap::new();
+    let mut rows: Vec<&Vec<String>> = Vec::new();
+    let mut duplicates = 0;
+    let mut conflicts = 0;
+    for (_, table) in &tables {
+        for row in &table.rows {
+            let key = (row[0].clone(), row[1].clone());
+            match seen.get(&key) {
+                Some(&i) => {
+                    duplicates += 1;
+                    if rows[i] != row {
+                        conflicts += 1;
+                    }
+                }
+                None => {
+                    seen.insert(key, rows.len());
+                    rows.push(row);
+                }
+            }
+        }
+    }
+    if conflicts > 0 {
+        warn!("{} overlapping pairs have different values, the first occurrence was kept", conflicts);
+    }
+
+    let mut out = BufWriter::new(File::create(output)?);
+    if write_metadata {
+        if let Some(metadata) = &first.metadata {
+            writeln!(out, "{}", metadata)?;
+        }
+    }
+    if matrix {
+        // references as rows, queries as columns, like `dist --dm`
+        let mut references: Vec<&String> = Vec::new();
+        let mut queries: Vec<&String> = Vec::new();
+        let mut seen_ref = HashSet::new();
+        let mut seen_query = HashSet::new();
+        for row in &rows {
+            if seen_ref.insert(&row[0]) {
+                references.push(&row[0]);
+            }
+            if seen_query.insert(&row[1]) {
+                queries.push(&row[1]);
+            }
+        }
+        for query in &queries {
+            write!(out, "\t{}", query)?;
+        }
+        for reference in &references {
+            write!(out, "\n{}", reference)?;
+            for query in &queries {
+                match seen.get(&((*reference).clone(), (*query).clone())) {
+                    Some(&i) => write!(out, "\t{}", rows[i][2])?,
+                    None => write!(out, "\tNA")?,
+                }
+            }
+        }
+        writeln!(out)?;
+    } else {
+        writeln!(out, "{}", first.header.join("\t"))?;
+        for row in &rows {
+            writeln!(out, "{}", row.join("\t"))?;
+        }
+    }
+    out.flush()?;
+
+    println!(
+        "{} files merged into {}: {} pairs, {} duplicates dropped.",
+        inputs.len(),
+        output,
+        rows.len(),
+        duplicates
+    );
+    Ok(())
+}
@@ -0,0 +1,145 @@
+// src/sampling.rs
+// K-mer subsampling ahead of the sketch. With `--minimizer -w <window>` only the
+// smallest k-mer (by hash) of every `window` consecutive k-mers of a record is
+// sketched, about 2/(window + 1) of them, so sketching long reads and large
+// metagenomes inserts far fewer k-mers. Genomes sharing a region pick the same
+// minimizers there, which keeps distances usable; cardinalities count minimizers.
+//
+// Syncmers (`--syncmer s[,t]`, Edgar 2021) are chosen from the k-mer alone: a
+// closed syncmer has its smallest s-mer first or last, an open one at position t.
+// Selection does not depend on the neighbours, so a mutation changes fewer
+// selected k-mers and they spread more evenly along the genome than minimizers.
+
+use std::collections::VecDeque;
+
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+pub struct Minimizers {
+    window: usize,
+    seed: u64,
+    // (order, position, k-mer), orders increasing from the front
+    queue: VecDeque<(u64, usize, u64)>,
+    position: usize,
+    // position of the last minimizer handed on
+    last: Option<usize>,
+}
+
+impl Minimizers {
+    pub fn new(window: usize, seed: u64) -> Self {
+        Minimizers {
+            window,
+            seed: seed ^ 0x6D69_6E69_6D00_0000,
+            queue: VecDeque::with_capacity(window),
+            position: 0,
+            last: None,
+        }
+    }
+
+    // start a new record
+    pub fn reset(&mut self) {
+        self.queue.clear();
+        self.position = 0;
+        self.last = None;
+    }
+
+    // the next k-mer of the record, `f` gets every new minimizer once
+    pub fn push<F: FnMut(u64)>(&mut self, masked: u64, f: &mut F) {
+        let order = xxh3_64_with_seed(&masked.to_le_bytes(), self.seed);
+        while self.queue.back().is_some_and(|&(o, _, _)| o > order) {
+            self.queue.pop_back();
+        }
+        self.queue.push_back((order, self.position, masked));
+        while self.queue.front().is_some_and(|&(_, p, _)| p + self.window <= self.position) {
+            self.queue.pop_front();
+        }
+        self.position += 1;
+        if self.position >= self.window {
+            self.emit(f);
+        }
+    }
+
+    // end of the record: one shorter than a window still gives its minimizer
+    pub fn finish<F: FnMut(u64)>(&mut self, f: &mut F) {
+        if self.position < self.window {
+            self.emit(f);
+        }
+    }
+
+    fn emit<F: FnMut(u64)>(&mut self, f: &mut F) {
+        if let Some(&(_, position, masked)) = self.queue.front() {
+            if self.last != Some(position) {
+                self.last = Some(position);
+                f(masked);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyncmerScheme {
+    pub s: usize,
+    // 1-based position of the smallest s-mer of an open syncmer, None for closed
+    pub t: Option<usize>,
+}
+
+impl SyncmerScheme {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid syncmer {}, give s for closed or s,t for open syncmers", text);
+        let mut fields = text.split(',').map(|f| f.trim().parse::<usize>().map_err(|_| invalid()));
+        let s = fields.next().ok_or_else(invalid)??;
+        let t = fields.next().transpose()?;
+        if fields.next().is_some() || s == 0 || t == Some(0) {
+            return Err(invalid());
+        }
+        Ok(SyncmerScheme { s, t })
+    }
+
+    // s below k, t within the k - s + 1 s-mer positions of a k-mer
+    pub fn check(&self, kmer_length: usize) -> Result<(), String> {
+        if kmer_length > 32 || self.s >= kmer_length {
+            return Err(format!("--syncmer {} needs s below k and k up to 32, k is {}", self, kmer_length));
+        }
+        if self.t.is_some_and(|t| t > kmer_length - self.s + 1) {
+            return Err(format!("--syncmer {}: t must be at most k - s + 1 = {}", self, kmer_length - self.s + 1));
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for SyncmerScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.t {
+            Some(t) => write!(f, "{},{}", self.s, t),
+            None => write!(f, "{}", self.s),
+        }
+    }
+}
+
+pub struct Syncmers {
+    scheme: SyncmerScheme,
+    kmer_length: usize,
+    seed: u64,
+}
+
+impl Syncmers {
+    pub fn new(scheme: SyncmerScheme, kmer_length: usize, seed: u64) -> Self {
+        Syncmers { scheme, kmer_length, seed: seed ^ 0x7379_6E63_6D00_0000 }
+    }
+
+    // whether a canonical k-mer, packed 2 bits per base, is a syncmer
+    pub fn select(&self, masked: u64) -> bool {
+        let s = self.scheme.s;
+        let positions = self.kmer_length - s + 1;
+        let smer_mask = u64::MAX >> (64 - 2 * s);
+        let smallest = (0..positions)
+            .min_by_key(|&i| {
+                let smer = (masked >> (2 * (positions - 1 - i))) & smer_mask;
+                xxh3_64_with_seed(&smer.to_le_bytes(), self.seed)
+            })
+            .expect("at least one s-mer");
+        match self.scheme.t {
+            Some(t) => smallest == t - 1,
+            None => smallest == 0 || smallest == positions - 1,
+        }
+    }
+}
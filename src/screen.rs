@@ -0,0 +1,87 @@
+// src/screen.rs
+// `lash screen`: which references are contained in a read set, mash screen style.
+// The reads are hashed once into a single sketch, each reference is then scored by
+// the fraction of its k-mers found in that sketch
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use hashbrown::HashMap;
+use rayon::prelude::*;
+
+use crate::utils::{
+    containment_ani, find_files, load_sketches, metadata_header, read_names, read_params, sketch_file,
+    with_sketch_type, KmerSketch, SketchParams,
+};
+
+pub struct ScreenOptions {
+    pub reads: Vec<String>,
+    pub reference: String,
+    pub output: String,
+    pub min_containment: f64,
+    pub metadata: bool,
+}
+
+fn screen_with<S: KmerSketch + Sync>(
+    opts: &ScreenOptions,
+    params: &SketchParams,
+    sketch_file_name: &str,
+    names: &[String],
+) -> Result<Vec<(usize, f64)>, Box<dyn Error>> {
+    // every read file goes into the same sketch
+    let reads = opts
+        .reads
+        .par_iter()
+        .map(|file_name| sketch_file::<S>(file_name, params).map_err(|e| format!("{}: {}", file_name, e)))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .reduce(|a, b| a.merged(&b))
+        .ok_or("no read files given")?;
+
+    let references: Vec<S> = load_sketches(sketch_file_name, names.len())?;
+    Ok(references
+        .par_iter()
+        .map(|reference| reference.containment(&reads))
+        .enumerate()
+        .collect())
+}
+
+pub fn screen(opts: &ScreenOptions) -> Result<(), Box<dyn Error>> {
+    let files = find_files(&opts.reference)?;
+    let param_map: HashMap<String, String> = read_params(&files["params"])?;
+    let params = SketchParams::from_map(&param_map)?;
+    let names = read_names(&files["files"])?;
+
+    let mut hits = with_sketch_type!(
+        params.algorithm.as_str(),
+        screen_with(opts, &params, &files["sketches"], &names)
+    )?;
+    hits.retain(|h| h.1 >= opts.min_containment);
+    hits.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut out = BufWriter::new(File::create(&opts.output)?);
+    if opts.metadata {
+        writeln!(out, "{}", metadata_header(&param_map, 1))?;
+    }
+    writeln!(out, "Reference\tContainment\tContainmentANI")?;
+    for (i, containment) in &hits {
+        writeln!(
+            out,
+            "{}\t{:.6}\t{:.4}",
+            names[*i],
+            containment,
+            100.0 * containment_ani(*containment, params.kmer_length)
+        )?;
+    }
+    out.flush()?;
+
+    println!(
+        "{} of {} references with containment >= {}, written to {}.",
+        hits.len(),
+        names.len(),
+        opts.min_containment,
+        opts.output
+    );
+    Ok(())
+}
@@ -0,0 +1,248 @@
+// src/serve.rs
+// `lash serve`: keep a reference sketch set in memory and answer queries over HTTP,
+// so services do not pay the load/decompression cost of `dist` per request.
+//
+//   GET  /health                      sketch count and parameters of the set
+//   POST /query?top=N&name=ID         body: FASTA/FASTQ (plain or gzipped), sketched
+//                                     with the parameters of the set
+//   POST /query_sketch?top=N&name=ID  body: one sketch in the `_sketches.bin` layout
+//                                     (decompressed), made with the same parameters
+//
+// Both queries answer {"query": ID, "hits": [{"reference", "distance"}, ...]},
+// best hits first. With --webhook or --hook every query is reported once answered,
+// by the worker that answered it.
+
+use std::error::Error;
+use std::io::Cursor;
+use std::thread;
+use std::time::Instant;
+
+use log::{info, warn};
+use needletail::parse_fastx_reader;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::notify::{run_report, Notifier};
+use crate::pairwise::{sketch_distance, QueryHits, SketchSet};
+use crate::utils::{load_sketches, sketch_reader, with_sketch_type, KmerSketch, SketchParams};
+
+pub struct ServeOptions {
+    pub reference: String,
+    pub bind: String,
+    // hits returned when the request does not ask for a number
+    pub top: usize,
+    pub equation: u8,
+    // requests handled at the same time, each one uses the whole thread pool
+    pub workers: usize,
+}
+
+// value of `key` in the query string of `url`
+fn query_param<'a>(url: &'a str, key: &str) -> Option<&'a str> {
+    url.split_once('?')?
+        .1
+        .split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+struct Database<S> {
+    set: SketchSet,
+    params: SketchParams,
+    sketches: Vec<S>,
+}
+
+impl<S: KmerSketch + Sync> Database<S> {
+    fn best_hits(&self, query: &S, top: usize, equation: u8) -> QueryHits {
+        let mut hits: QueryHits = self
+            .sketches
+            .par_iter()
+            .map(|r| sketch_distance(r, query, self.set.kmer_length, equation))
+            .enumerate()
+            .collect();
+        let by_distance = |a: &(usize, f64), b: &(usize, f64)| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0));
+        if hits.len() > top {
+            hits.select_nth_unstable_by(top, by_distance);
+            hits.truncate(top);
+        }
+        hits.sort_by(by_distance);
+        hits
+    }
+
+    // status code and JSON body of one request
+    fn handle(&self, request: &mut Request, opts: &ServeOptions) -> (u16, Value) {
+        let url = request.url().to_string();
+        let path = url.split('?').next().unwrap_or("");
+        match (request.method(), path) {
+            (Method::Get, "/health") => (
+                200,
+                json!({ "status": "ok", "sketches": self.sketches.len(), "parameters": self.set.params }),
+            ),
+            (Method::Post, "/query") | (Method::Post, "/query_sketch") => {
+                let top = match query_param(&url, "top").map(str::parse::<usize>) {
+                    None => opts.top,
+                    Some(Ok(top)) => top,
+                    Some(Err(_)) => return (400, json!({ "error": "top must be a number" })),
+                };
+                let name = query_param(&url, "name").unwrap_or("query").to_string();
+                let mut body = Vec::new();
+                if let Err(e) = request.as_reader().read_to_end(&mut body) {
+                    return (400, json!({ "error": format!("could not read request body: {}", e) }));
+                }
+                let query: S = if path == "/query" {
+                    match parse_fastx_reader(Cursor::new(body)).and_then(|reader| sketch_reader(reader, &self.params)) {
+                        Ok(sketch) => sketch,
+                        Err(e) => return (400, json!({ "error": format!("not FASTA/FASTQ: {}", e) })),
+                    }
+                } else {
+                    match S::load(&mut body.as_slice()) {
+                        Ok(sketch) => sketch,
+                        Err(e) => return (400, json!({ "error": format!("invalid sketch: {}", e) })),
+                    }
+                };
+                let hits: Vec<Value> = self
+                    .best_hits(&query, top, opts.equation)
+                    .into_iter()
+                    .map(|(j, d)| json!({ "reference": self.set.names[j], "distance": d }))
+                    .collect();
+                (200, json!({ "query": name, "hits": hits }))
+            }
+            _ => (404, json!({ "error": format!("no endpoint {} {}", request.method(), path) })),
+        }
+    }
+}
+
+fn serve_with<S: KmerSketch + Sync>(opts: &ServeOptions, notifier: &Notifier, set: SketchSet) -> Result<(), Box<dyn Error>> {
+    let params = SketchParams::from_map(&set.params)?;
+    let sketches: Vec<S> = load_sketches(&set.sketch_file, set.names.len())?;
+    let db = Database { set, params, sketches };
+
+    let server = Server::http(&opts.bind).map_err(|e| format!("cannot listen on {}: {}", opts.bind, e))?;
+    println!(
+        "Serving {} sketches of {} on http://{}",
+        db.sketches.len(),
+        opts.reference,
+        opts.bind
+    );
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).expect("valid header");
+    thread::scope(|scope| {
+        for _ in 0..opts.workers.max(1) {
+            scope.spawn(|| {
+                for mut request in server.incoming_requests() {
+                    let start = Instant::now();
+                    let (status, body) = db.handle(&mut request, opts);
+                    let (method, url) = (request.method().to_string(), request.url().to_string());
+                    info!("{} {} -> {}", method, url, status);
+                    let response = Response::from_string(body.to_string())
+                        .with_status_code(status)
+                        .with_header(content_type.clone());
+                    if let Err(e) = request.respond(response) {
+                        warn!("could not answer request: {}", e);
+                    }
+                    if method == "POST" {
+                        let error = body["error"].as_str().map(str::to_string);
+                        let mut report = run_report("serve", error, start.elapsed().as_secs_f64());
+                        report["request"] = json!(url);
+                        report["status_code"] = json!(status);
+                        report["query"] = body["query"].clone();
+                        notifier.notify(&report);
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+// runs until the process is stopped
+pub fn serve(opts: &ServeOptions, notifier: &Notifier) -> Result<(), Box<dyn Error>> {
+    let set = SketchSet::open(&opts.reference)?;
+    let algorithm = set.params["algorithm"].clone();
+    with_sketch_type!(algorithm.as_str(), serve_with(opts, notifier, set))
+}
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashMap;
+    use tiny_http::TestRequest;
+
+    use super::*;
+    use crate::minhash::MinHash;
+
+    // FASTA of a random genome, leaked to stand as a request body
+    fn genome(seed: u64) -> &'static str {
+        let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+        let seq: String = (0..3000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                b"ACGT"[(state >> 32) as usize % 4] as char
+            })
+            .collect();
+        Box::leak(format!(">g{}\n{}\n", seed, seq).into_boxed_str())
+    }
+
+    fn database() -> Database<MinHash> {
+        let param_map: HashMap<String, String> =
+            [("k", "16"), ("algorithm", "minhash"), ("sketch_size", "64"), ("seed", "42"), ("molecule", "nucleotide")]
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+        let params = SketchParams::from_map(&param_map).unwrap();
+        let sketches = (0..3)
+            .map(|seed| sketch_reader(parse_fastx_reader(Cursor::new(genome(seed))).unwrap(), &params).unwrap())
+            .collect();
+        let set = SketchSet {
+            names: (0..3).map(|seed| format!("g{}", seed)).collect(),
+            params: param_map,
+            kmer_length: 16,
+            sketch_file: String::new(),
+        };
+        Database { set, params, sketches }
+    }
+
+    fn opts() -> ServeOptions {
+        ServeOptions { reference: "set".to_string(), bind: String::new(), top: 2, equation: 0, workers: 1 }
+    }
+
+    fn handle(db: &Database<MinHash>, request: TestRequest) -> (u16, Value) {
+        db.handle(&mut request.into(), &opts())
+    }
+
+    #[test]
+    fn query_returns_best_hits() {
+        let db = database();
+        let request = TestRequest::new().with_method(Method::Post).with_path("/query?name=q&top=1").with_body(genome(1));
+        let (status, body) = handle(&db, request);
+        assert_eq!(status, 200);
+        assert_eq!(body["query"], "q");
+        assert_eq!(body["hits"], json!([{ "reference": "g1", "distance": 0.0 }]));
+
+        // --top when the request does not ask
+        let (_, body) = handle(&db, TestRequest::new().with_method(Method::Post).with_path("/query").with_body(genome(2)));
+        assert_eq!(body["query"], "query");
+        assert_eq!(body["hits"].as_array().unwrap().len(), 2);
+        assert_eq!(body["hits"][0]["reference"], "g2");
+    }
+
+    #[test]
+    fn bad_requests_are_answered() {
+        let db = database();
+        let (status, body) = handle(&db, TestRequest::new().with_path("/health"));
+        assert_eq!((status, &body["sketches"]), (200, &json!(3)));
+
+        let post = |path: &str, body: &'static str| TestRequest::new().with_method(Method::Post).with_path(path).with_body(body);
+        for (request, status) in [
+            (post("/query?top=many", genome(0)), 400),
+            (post("/query", "not a sequence"), 400),
+            (post("/query_sketch", "too short"), 400),
+            (TestRequest::new().with_path("/query"), 404),
+        ] {
+            let (got, body) = handle(&db, request);
+            assert_eq!(got, status);
+            assert!(body["error"].is_string());
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// src/setops.rs
+// `lash setops`: estimated sizes of the union, intersection and both differences of
+// the k-mer sets of every reference/query pair. Theta sketches answer these from
+// the sketches themselves; the other algorithms fall back to inclusion-exclusion
+// on cardinalities, which gets noisy when one set is much smaller.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use rayon::prelude::*;
+
+use crate::pairwise::{sketch_distance, SketchSet};
+use crate::utils::{check_same_params, load_sketches, metadata_header, with_sketch_type, KmerSketch};
+
+struct SetOps {
+    reference: usize,
+    query: usize,
+    union: f64,
+    intersection: f64,
+    reference_only: f64,
+    query_only: f64,
+    jaccard: f64,
+    distance: f64,
+}
+
+fn set_ops_with<S: KmerSketch + Sync>(
+    references: &SketchSet,
+    queries: &SketchSet,
+    equation: u8,
+) -> Result<Vec<SetOps>, Box<dyn Error>> {
+    let ref_sketches: Vec<S> = load_sketches(&references.sketch_file, references.names.len())?;
+    let query_sketches: Vec<S> = load_sketches(&queries.sketch_file, queries.names.len())?;
+    Ok(ref_sketches
+        .par_iter()
+        .enumerate()
+        .flat_map_iter(|(i, r)| {
+            query_sketches.iter().enumerate().map(move |(j, q)| SetOps {
+                reference: i,
+                query: j,
+                union: r.union_size(q),
+                intersection: r.intersection_size(q),
+                reference_only: r.difference_size(q),
+                query_only: q.difference_size(r),
+                jaccard: r.jaccard(q),
+                distance: sketch_distance(r, q, references.kmer_length, equation),
+            })
+        })
+        .collect())
+}
+
+pub fn set_ops(
+    reference: &str,
+    query: &str,
+    output: &str,
+    equation: u8,
+    metadata: bool,
+) -> Result<(), Box<dyn Error>> {
+    let references = SketchSet::open(reference)?;
+    let queries = SketchSet::open(query)?;
+    check_same_params(reference, &references.params, query, &queries.params)?;
+
+    let rows = with_sketch_type!(
+        references.params["algorithm"].as_str(),
+        set_ops_with(&references, &queries, equation)
+    )?;
+
+    let mut out = BufWriter::new(File::create(output)?);
+    if metadata {
+        writeln!(out, "{}", metadata_header(&references.params, equation))?;
+    }
+    writeln!(out, "Reference\tQuery\tUnion\tIntersection\tReferenceOnly\tQueryOnly\tJaccard\tDistance")?;
+    for row in &rows {
+        writeln!(
+            out,
+            "{}\t{}\t{:.0}\t{:.0}\t{:.0}\t{:.0}\t{:.6}\t{:.6}",
+            references.names[row.reference],
+            queries.names[row.query],
+            row.union,
+            row.intersection,
+            row.reference_only,
+            row.query_only,
+            row.jaccard,
+            row.distance
+        )?;
+    }
+    out.flush()?;
+
+    println!("Set operations of {} pairs written to {}.", rows.len(), output);
+    Ok(())
+}
@@ -0,0 +1,159 @@
+// src/simd.rs
+// Vectorized pieces of the k-mer loop, taken when the CPU has AVX2 (checked at
+// run time, so one binary runs everywhere) and done one at a time otherwise:
+// - `encode`: bases to 2-bit codes, 32 at a time, 4 for anything but ACGT/acgt,
+// - `HashBatch`: k-mers kept for a sketch hashed 4 to a register, 8 per batch,
+//   bit for bit as xxh3_64_with_seed of their 8 little-endian bytes, so the
+//   sketches match those built without AVX2.
+
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+// the default xxh3 secret's words at bytes 8 and 16, which 4- to 8-byte inputs use
+const SECRET_8: u64 = 0x1cad_21f7_2c81_017c;
+const SECRET_16: u64 = 0xdb97_9083_e96d_d4de;
+const PRIME_MX2: u64 = 0x9fb2_1c65_1e98_df25;
+
+pub const BATCH: usize = 8;
+
+fn has_avx2() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        std::arch::is_x86_feature_detected!("avx2")
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+fn base_code(c: u8) -> u8 {
+    match c {
+        b'A' | b'a' => 0,
+        b'C' | b'c' => 1,
+        b'G' | b'g' => 2,
+        b'T' | b't' => 3,
+        _ => 4,
+    }
+}
+
+// 2-bit codes of `seq` into `codes`, which is at least as long
+pub fn encode(seq: &[u8], codes: &mut [u8]) {
+    let mut done = 0;
+    #[cfg(target_arch = "x86_64")]
+    if has_avx2() {
+        // SAFETY: AVX2 is available
+        done = unsafe { encode_avx2(seq, codes) };
+    }
+    for (code, &c) in codes[done..].iter_mut().zip(&seq[done..]) {
+        *code = base_code(c);
+    }
+}
+
+// the leading multiple of 32 bases; A 0x41, C 0x43, G 0x47, T 0x54 (and their
+// lowercase) give their code as bits 1-2 XOR bit 2
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn encode_avx2(seq: &[u8], codes: &mut [u8]) -> usize {
+    use std::arch::x86_64::*;
+    let blocks = seq.len() / 32;
+    let case = _mm256_set1_epi8(0xDFu8 as i8);
+    let (a, c, g, t) = (_mm256_set1_epi8(b'A' as i8), _mm256_set1_epi8(b'C' as i8), _mm256_set1_epi8(b'G' as i8), _mm256_set1_epi8(b'T' as i8));
+    let (three, one, invalid) = (_mm256_set1_epi8(3), _mm256_set1_epi8(1), _mm256_set1_epi8(4));
+    for block in 0..blocks {
+        let bytes = _mm256_loadu_si256(seq.as_ptr().add(32 * block) as *const __m256i);
+        let upper = _mm256_and_si256(bytes, case);
+        let valid = _mm256_or_si256(
+            _mm256_or_si256(_mm256_cmpeq_epi8(upper, a), _mm256_cmpeq_epi8(upper, c)),
+            _mm256_or_si256(_mm256_cmpeq_epi8(upper, g), _mm256_cmpeq_epi8(upper, t)),
+        );
+        let code = _mm256_xor_si256(
+            _mm256_and_si256(_mm256_srli_epi16(bytes, 1), three),
+            _mm256_and_si256(_mm256_srli_epi16(bytes, 2), one),
+        );
+        let code = _mm256_blendv_epi8(invalid, code, valid);
+        _mm256_storeu_si256(codes.as_mut_ptr().add(32 * block) as *mut __m256i, code);
+    }
+    32 * blocks
+}
+
+// k-mers waiting to be hashed and handed to a sketch
+pub struct HashBatch {
+    masked: [u64; BATCH],
+    len: usize,
+    avx2: bool,
+}
+
+impl HashBatch {
+    pub fn new() -> Self {
+        HashBatch { masked: [0; BATCH], len: 0, avx2: has_avx2() }
+    }
+
+    // queue a k-mer, `f` gets every k-mer with its hash once the batch is full
+    pub fn push<F: FnMut(u64, u64)>(&mut self, masked: u64, seed: u64, f: F) {
+        self.masked[self.len] = masked;
+        self.len += 1;
+        if self.len == BATCH {
+            self.flush(seed, f);
+        }
+    }
+
+    // hand on the k-mers still queued
+    pub fn flush<F: FnMut(u64, u64)>(&mut self, seed: u64, mut f: F) {
+        let mut hashes = [0u64; BATCH];
+        #[cfg(target_arch = "x86_64")]
+        if self.avx2 && self.len == BATCH {
+            // SAFETY: AVX2 is available
+            unsafe { xxh3_batch_avx2(&self.masked, seed, &mut hashes) };
+            for (&masked, &hash) in self.masked.iter().zip(&hashes) {
+                f(masked, hash);
+            }
+            self.len = 0;
+            return;
+        }
+        for (hash, masked) in hashes.iter_mut().zip(&self.masked[..self.len]) {
+            *hash = xxh3_64_with_seed(&masked.to_le_bytes(), seed);
+        }
+        for (&masked, &hash) in self.masked[..self.len].iter().zip(&hashes) {
+            f(masked, hash);
+        }
+        self.len = 0;
+    }
+}
+
+impl Default for HashBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// xxh3_64_with_seed of 8 bytes (its 4-to-8-byte path) for BATCH k-mers
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn xxh3_batch_avx2(masked: &[u64; BATCH], seed: u64, hashes: &mut [u64; BATCH]) {
+    use std::arch::x86_64::*;
+    let seed = seed ^ (((seed as u32).swap_bytes() as u64) << 32);
+    let flip = _mm256_set1_epi64x((SECRET_8 ^ SECRET_16).wrapping_sub(seed) as i64);
+    let prime = _mm256_set1_epi64x(PRIME_MX2 as i64);
+    let prime_high = _mm256_set1_epi64x((PRIME_MX2 >> 32) as i64);
+    let len = _mm256_set1_epi64x(8);
+    // low 64 bits of v * PRIME_MX2 from 32-bit products
+    let mul = |v: __m256i| {
+        let low = _mm256_mul_epu32(v, prime);
+        let cross = _mm256_add_epi64(_mm256_mul_epu32(_mm256_srli_epi64(v, 32), prime), _mm256_mul_epu32(v, prime_high));
+        _mm256_add_epi64(low, _mm256_slli_epi64(cross, 32))
+    };
+    for group in 0..BATCH / 4 {
+        let v = _mm256_loadu_si256(masked.as_ptr().add(4 * group) as *const __m256i);
+        // the two 4-byte reads swap the halves
+        let v = _mm256_or_si256(_mm256_slli_epi64(v, 32), _mm256_srli_epi64(v, 32));
+        let mut v = _mm256_xor_si256(v, flip);
+        let rotl49 = _mm256_or_si256(_mm256_slli_epi64(v, 49), _mm256_srli_epi64(v, 15));
+        let rotl24 = _mm256_or_si256(_mm256_slli_epi64(v, 24), _mm256_srli_epi64(v, 40));
+        v = _mm256_xor_si256(v, _mm256_xor_si256(rotl49, rotl24));
+        v = mul(v);
+        v = _mm256_xor_si256(v, _mm256_add_epi64(_mm256_srli_epi64(v, 35), len));
+        v = mul(v);
+        v = _mm256_xor_si256(v, _mm256_srli_epi64(v, 28));
+        _mm256_storeu_si256(hashes.as_mut_ptr().add(4 * group) as *mut __m256i, v);
+    }
+}
@@ -0,0 +1,729 @@
+// src/sketchset.rs
+// Subcommands that rewrite sketch sets without re-reading the genomes
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+
+use hashbrown::{HashMap, HashSet};
+use log::warn;
+use rayon::prelude::*;
+use streaming_algorithms::HyperLogLog;
+use zstd::stream::{Decoder, Encoder};
+
+use crate::countmin::{self, CountMin};
+use crate::format::{format_version, FORMAT_VERSION};
+use crate::ull::UllSketch;
+use crate::utils::{
+    check_inputs, check_same_params, downsize_hll, find_files, is_fastq, load_sketches, open_fastx, read_entries, read_names,
+    read_params, sketch_files, sketch_sequences, with_sketch_type, write_entries, write_set_metadata, write_sketch_set, Entry, KmerSketch,
+    Sample, SketchOptions, SketchParams, Sketched,
+};
+
+// how `split` cuts a sketch set
+pub enum Shards {
+    Parts(usize),
+    // uncompressed bytes of sketches per shard
+    MaxSize(u64),
+}
+
+// "500M", "2G", "64k" or plain bytes
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let (number, unit) = match size.char_indices().find(|(_, c)| c.is_ascii_alphabetic()) {
+        Some((i, _)) => size.split_at(i),
+        None => (size, ""),
+    };
+    let multiplier: u64 = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("unknown size unit in {}", size)),
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid size {}", size))?;
+    if number <= 0.0 {
+        return Err(format!("size must be positive, got {}", size));
+    }
+    Ok((number * multiplier as f64) as u64)
+}
+
+fn sketch_bytes<S: KmerSketch>(sketch: &S) -> Result<u64, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    sketch.save(&mut bytes)?;
+    Ok(bytes.len() as u64)
+}
+
+fn split_with<S: KmerSketch>(
+    sketch_file: &str,
+    names: &[Entry],
+    params: &HashMap<String, String>,
+    output: &str,
+    shards: &Shards,
+    threads: u32,
+) -> Result<Vec<usize>, Box<dyn Error>> {
+    let mut decoder = Decoder::new(BufReader::new(File::open(sketch_file)?))?;
+    let mut sizes = Vec::new();
+    let mut start = 0;
+    let write_shard = |sketches: &[S], sizes: &mut Vec<usize>, start: &mut usize| {
+        let end = *start + sketches.len();
+        let name = format!("{}_{}", output, sizes.len() + 1);
+        sizes.push(sketches.len());
+        let result = write_sketch_set(&name, &names[*start..end], sketches, params, threads);
+        *start = end;
+        result
+    };
+
+    match *shards {
+        Shards::Parts(parts) => {
+            // contiguous shards whose sizes differ by at most one sketch
+            let parts = parts.min(names.len());
+            for i in 0..parts {
+                let count = names.len() / parts + usize::from(i < names.len() % parts);
+                let sketches = (0..count)
+                    .map(|_| S::load(&mut decoder))
+                    .collect::<std::io::Result<Vec<S>>>()?;
+                write_shard(&sketches, &mut sizes, &mut start)?;
+            }
+        }
+        Shards::MaxSize(max_size) => {
+            let mut shard: Vec<S> = Vec::new();
+            let mut shard_bytes = 0;
+            for _ in 0..names.len() {
+                let sketch = S::load(&mut decoder)?;
+                let bytes = sketch_bytes(&sketch)?;
+                if !shard.is_empty() && shard_bytes + bytes > max_size {
+                    write_shard(&shard, &mut sizes, &mut start)?;
+                    shard.clear();
+                    shard_bytes = 0;
+                }
+                shard_bytes += bytes;
+                shard.push(sketch);
+            }
+            if !shard.is_empty() {
+                write_shard(&shard, &mut sizes, &mut start)?;
+            }
+        }
+    }
+    Ok(sizes)
+}
+
+// cut the sketch set `input` into shards `{output}_1`, `{output}_2`, ... in input order,
+// every shard carries the parameters of the input so it can be used with `dist` on its own
+pub fn split(input: &str, output: &str, shards: &Shards, threads: u32) -> Result<(), Box<dyn Error>> {
+    let files = find_files(input)?;
+    let params = read_params(&files["params"])?;
+    let names = read_entries(&files["files"])?;
+    if names.is_empty() {
+        return Err(format!("sketch set {} is empty", input).into());
+    }
+    if let Shards::Parts(0) = shards {
+        return Err("--parts must be at least 1".into());
+    }
+
+    let sizes = with_sketch_type!(
+        params["algorithm"].as_str(),
+        split_with(&files["sketches"], &names, &params, output, shards, threads)
+    )?;
+    for (i, size) in sizes.iter().enumerate() {
+        println!("{}_{}\t{} sketches", output, i + 1, size);
+    }
+    println!("{} sketches split into {} shards.", names.len(), sizes.len());
+    Ok(())
+}
+
+// concatenate sketch sets built with the same parameters, in the order given
+pub fn paste(inputs: &[String], output: &str, threads: u32) -> Result<(), Box<dyn Error>> {
+    let sets = inputs
+        .iter()
+        .map(|input| {
+            let files = find_files(input)?;
+            let params = read_params(&files["params"])?;
+            let entries = read_entries(&files["files"])?;
+            Ok((input, files, params, entries))
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+    let (first_input, _, first_params, _) = &sets[0];
+    let mut names: Vec<Entry> = Vec::new();
+    let mut seen = HashSet::new();
+    for (input, _, params, entries) in &sets {
+        check_same_params(first_input, first_params, input, params)?;
+        for entry in entries {
+            if !seen.insert(&entry.name) {
+                return Err(format!("{} is in more than one sketch set, last seen in {}", entry.name, input).into());
+            }
+        }
+        names.extend(entries.iter().cloned());
+    }
+
+    // the sketch streams are plain concatenations of sketches, no need to decode them
+    let writer = BufWriter::new(File::create(format!("{}_sketches.bin", output))?);
+    let mut encoder = Encoder::new(writer, 3)?;
+    encoder.multithread(threads)?;
+    for (_, files, _, _) in &sets {
+        let mut decoder = Decoder::new(BufReader::new(File::open(&files["sketches"])?))?;
+        io::copy(&mut decoder, &mut encoder)?;
+    }
+    encoder.finish()?;
+    write_set_metadata(output, &names, first_params)?;
+
+    println!("{} sketch sets with {} sketches pasted into {}.", sets.len(), names.len(), output);
+    Ok(())
+}
+
+fn update_with<S: KmerSketch>(
+    sketch_file_name: &str,
+    new_files: &[String],
+    params: &SketchParams,
+    threads: u32,
+) -> Result<(Vec<Entry>, Vec<CountMin>), Box<dyn Error>> {
+    let sketched: Vec<Vec<(String, Sketched<S>)>> = new_files
+        .par_iter()
+        .map(|file_name| {
+            let (k, options) = ([params.kmer_length], params.options.for_input(file_name));
+            open_fastx(file_name)
+                .and_then(|reader| sketch_sequences(vec![reader], params.precision, &k, params.seed, params.aa, &options))
+                .map_err(|e| format!("{}: {}", file_name, e))
+        })
+        .collect::<Result<_, _>>()?;
+    let mut names = Vec::new();
+    let (mut sketches, mut counts): (Vec<S>, Vec<Option<CountMin>>) = (Vec::new(), Vec::new());
+    for (file_name, entries) in new_files.iter().zip(sketched) {
+        for (id, mut per_k) in entries {
+            names.push(Entry::named(match params.options.per_contig {
+                true => format!("{}:{}", file_name, id),
+                false => file_name.clone(),
+            }));
+            let (sketch, count) = per_k.pop().expect("one k-mer length");
+            sketches.push(sketch);
+            counts.push(count);
+        }
+    }
+
+    // old sketches first, then the new ones, swapped in once complete
+    let tmp = format!("{}.tmp", sketch_file_name);
+    let writer = BufWriter::new(File::create(&tmp)?);
+    let mut encoder = Encoder::new(writer, 3)?;
+    encoder.multithread(threads)?;
+    let mut decoder = Decoder::new(BufReader::new(File::open(sketch_file_name)?))?;
+    io::copy(&mut decoder, &mut encoder)?;
+    for sketch in &sketches {
+        sketch.save(&mut encoder)?;
+    }
+    encoder.finish()?;
+    fs::rename(&tmp, sketch_file_name)?;
+    Ok((names, counts.into_iter().flatten().collect()))
+}
+
+// the sketch set `source` at a lower `precision`, written to `target` without
+// re-reading the genomes; for sketches built at several precisions in one pass
+pub fn downsize_set(source: &str, target: &str, precision: u32, threads: u32) -> Result<(), Box<dyn Error>> {
+    let files = find_files(source)?;
+    let mut params = read_params(&files["params"])?;
+    let names = read_entries(&files["files"])?;
+    match params["algorithm"].as_str() {
+        "hll" => {
+            let sketches: Vec<HyperLogLog<i64>> = load_sketches(&files["sketches"], names.len())?;
+            let downsized = sketches
+                .par_iter()
+                .map(|hll| downsize_hll(hll, precision as u8))
+                .collect::<io::Result<Vec<_>>>()?;
+            params.insert("precision".to_string(), precision.to_string());
+            write_sketch_set(target, &names, &downsized, &params, threads)?;
+        }
+        "ull" => {
+            let sketches: Vec<UllSketch> = load_sketches(&files["sketches"], names.len())?;
+            let downsized = sketches
+                .par_iter()
+                .map(|ull| ull.downsized(precision))
+                .collect::<Result<Vec<_>, _>>()?;
+            params.insert("precision".to_string(), precision.to_string());
+            write_sketch_set(target, &names, &downsized, &params, threads)?;
+        }
+        other => return Err(format!("{} sketches have no precision to lower", other).into()),
+    }
+    // abundances do not depend on the precision
+    let counts_file = format!("{}_counts.bin", source);
+    if std::path::Path::new(&counts_file).is_file() {
+        fs::copy(&counts_file, format!("{}_counts.bin", target))?;
+    }
+    Ok(())
+}
+
+// sketch the files of `file_list` with the parameters of the sketch set `input` and append them,
+// files already in the set are skipped
+pub fn update(input: &str, file_list: &[String], threads: u32) -> Result<(), Box<dyn Error>> {
+    let files = find_files(input)?;
+    let param_map = read_params(&files["params"])?;
+    let params = SketchParams::from_map(&param_map)?;
+    let mut names = read_entries(&files["files"])?;
+
+    // sets sketched with --per-contig name their sketches <file>:<record id>
+    let mut known: HashSet<String> = match params.options.per_contig {
+        true => names.iter().map(|e| e.name.rsplit_once(':').map_or(e.name.clone(), |(f, _)| f.to_string())).collect(),
+        false => names.iter().map(|e| e.name.clone()).collect(),
+    };
+    let mut new_files: Vec<String> = Vec::new();
+    for file in file_list {
+        if !known.insert(file.clone()) {
+            warn!("{} is already in {}, skipped", file, input);
+        } else {
+            new_files.push(file.clone());
+        }
+    }
+    if new_files.is_empty() {
+        println!("No new files, {} is unchanged.", input);
+        return Ok(());
+    }
+    check_inputs(&new_files.iter().cloned().map(Sample::from_path).collect::<Vec<_>>())?;
+
+    // sets sketched with --count-min get the Count-Min sketches of the new files too
+    let (new_names, new_counts) = with_sketch_type!(
+        params.algorithm.as_str(),
+        update_with(&files["sketches"], &new_files, &params, threads)
+    )?;
+    if let Some(mut counts) = countmin::load_counts(input, names.len())? {
+        counts.extend(new_counts);
+        countmin::write_counts(input, &counts, threads)?;
+    }
+    names.extend(new_names.iter().cloned());
+    write_entries(&files["files"], &names)?;
+
+    println!("{} sketches added to {}, {} in total.", new_names.len(), input, names.len());
+    Ok(())
+}
+
+fn remove_with<S: KmerSketch>(
+    sketch_file_name: &str,
+    keep: &[bool],
+    threads: u32,
+) -> Result<(), Box<dyn Error>> {
+    let tmp = format!("{}.tmp", sketch_file_name);
+    let writer = BufWriter::new(File::create(&tmp)?);
+    let mut encoder = Encoder::new(writer, 3)?;
+    encoder.multithread(threads)?;
+    let mut decoder = Decoder::new(BufReader::new(File::open(sketch_file_name)?))?;
+    for &kept in keep {
+        let sketch = S::load(&mut decoder)?;
+        if kept {
+            sketch.save(&mut encoder)?;
+        }
+    }
+    encoder.finish()?;
+    fs::rename(&tmp, sketch_file_name)?;
+    Ok(())
+}
+
+// drop the genomes named in `remove` from the sketch set `input`, parameters are left as they are
+pub fn remove(input: &str, remove: &[String], threads: u32) -> Result<(), Box<dyn Error>> {
+    let files = find_files(input)?;
+    let params = read_params(&files["params"])?;
+    let entries = read_entries(&files["files"])?;
+
+    let remove: HashSet<&String> = remove.iter().collect();
+    let present: HashSet<&String> = entries.iter().map(|e| &e.name).collect();
+    for name in &remove {
+        if !present.contains(name) {
+            warn!("{} is not in {}, nothing to remove", name, input);
+        }
+    }
+    let keep: Vec<bool> = entries.iter().map(|e| !remove.contains(&e.name)).collect();
+    let removed = keep.iter().filter(|k| !**k).count();
+    if removed == 0 {
+        println!("No genome to remove, {} is unchanged.", input);
+        return Ok(());
+    }
+
+    with_sketch_type!(
+        params["algorithm"].as_str(),
+        remove_with(&files["sketches"], &keep, threads)
+    )?;
+    if let Some(counts) = countmin::load_counts(input, keep.len())? {
+        let counts: Vec<CountMin> = counts.into_iter().zip(&keep).filter(|(_, k)| **k).map(|(c, _)| c).collect();
+        countmin::write_counts(input, &counts, threads)?;
+    }
+    let entries: Vec<Entry> = entries.iter().zip(&keep).filter(|(_, k)| **k).map(|(e, _)| e.clone()).collect();
+    write_entries(&files["files"], &entries)?;
+    if std::path::Path::new(&format!("{}_index.json", input)).is_file() {
+        warn!("{} has an LSH index, run `lash index -i {}` again", input, input);
+    }
+
+    println!("{} sketches removed from {}, {} left.", removed, input, entries.len());
+    Ok(())
+}
+
+// old name -> new name, tab separated, one pair per line
+pub fn read_rename_map(file_name: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(file_name)?);
+    let mut map = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (old, new) = line
+            .split_once('\t')
+            .ok_or_else(|| format!("{} line {}: expected <old name>\t<new name>", file_name, i + 1))?;
+        if map.insert(old.to_string(), new.trim_end().to_string()).is_some() {
+            return Err(format!("{} line {}: {} is renamed twice", file_name, i + 1, old).into());
+        }
+    }
+    Ok(map)
+}
+
+// relabel genomes of the sketch set `input`, only `_files.json` is rewritten
+pub fn rename(input: &str, mapping: &HashMap<String, String>) -> Result<(), Box<dyn Error>> {
+    let files = find_files(input)?;
+    let mut entries = read_entries(&files["files"])?;
+
+    let mut renamed = 0;
+    for entry in entries.iter_mut() {
+        if let Some(new) = mapping.get(&entry.name) {
+            // a genome named by its file keeps the file as its path
+            if entry.path.is_none() {
+                entry.path = Some(entry.name.clone());
+            }
+            entry.name = new.clone();
+            renamed += 1;
+        }
+    }
+    if renamed < mapping.len() {
+        warn!("{} names of the mapping are not in {}", mapping.len() - renamed, input);
+    }
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = entries.iter().find(|e| !seen.insert(&e.name)) {
+        return Err(format!("renaming would give two genomes the name {}, {} is unchanged", duplicate.name, input).into());
+    }
+    write_entries(&files["files"], &entries)?;
+
+    println!("{} of {} genomes renamed in {}.", renamed, entries.len(), input);
+    Ok(())
+}
+
+fn convert_with<S: KmerSketch>(
+    sketch_file_name: &str,
+    count: usize,
+    output: &str,
+    level: i32,
+    threads: u32,
+) -> Result<(), Box<dyn Error>> {
+    let mut decoder = Decoder::new(BufReader::new(File::open(sketch_file_name)?))?;
+    let writer = BufWriter::new(File::create(format!("{}_sketches.bin", output))?);
+    let mut encoder = Encoder::new(writer, level)?;
+    encoder.multithread(threads)?;
+    // one sketch at a time, every sketch is decoded and written in the current layout
+    for i in 0..count {
+        let sketch = S::load(&mut decoder)
+            .map_err(|e| format!("sketch {} of {} could not be read: {}", i + 1, count, e))?;
+        sketch.save(&mut encoder)?;
+    }
+    encoder.finish()?;
+    Ok(())
+}
+
+// rewrite a sketch set in the current format version, optionally with another zstd level
+pub fn convert(input: &str, output: &str, level: i32, threads: u32) -> Result<(), Box<dyn Error>> {
+    let files = find_files(input)?;
+    let mut params = read_params(&files["params"])?;
+    let names = read_entries(&files["files"])?;
+    let version = format_version(&params)?;
+
+    with_sketch_type!(
+        params["algorithm"].as_str(),
+        convert_with(&files["sketches"], names.len(), output, level, threads)
+    )?;
+    params.insert("format".to_string(), FORMAT_VERSION.to_string());
+    write_set_metadata(output, &names, &params)?;
+
+    println!(
+        "{} sketches converted from format version {} to {} (zstd level {}), written to {}.",
+        names.len(),
+        version,
+        FORMAT_VERSION,
+        level,
+        output
+    );
+    Ok(())
+}
+
+fn validate_sketches<S: KmerSketch>(sketch_file_name: &str, count: usize) -> Result<(), Box<dyn Error>> {
+    let mut decoder = Decoder::new(BufReader::new(File::open(sketch_file_name)?))?;
+    for i in 0..count {
+        S::load(&mut decoder).map_err(|e| {
+            format!("sketch {} of {} could not be read: {}", i + 1, count, e)
+        })?;
+    }
+    let mut rest = [0u8; 1];
+    if decoder.read(&mut rest)? > 0 {
+        return Err(format!("more data than the {} sketches listed in the names file", count).into());
+    }
+    Ok(())
+}
+
+// check the three files of one sketch set, printing PASS/FAIL per file; returns whether all passed
+fn validate_set(prefix: &str) -> bool {
+    let params_file = format!("{}_parameters.json", prefix);
+    let names_file = format!("{}_files.json", prefix);
+    let sketch_file_name = format!("{}_sketches.bin", prefix);
+    let report = |file: &str, result: &Result<(), Box<dyn Error>>| match result {
+        Ok(()) => println!("{}\tPASS", file),
+        Err(e) => println!("{}\tFAIL\t{}", file, e),
+    };
+
+    let mut params = None;
+    let params_result = read_params(&params_file).and_then(|map| {
+        SketchParams::from_map(&map)?;
+        format_version(&map)?;
+        params = Some(map);
+        Ok(())
+    });
+    report(&params_file, &params_result);
+
+    let mut names = None;
+    let names_result = read_names(&names_file).map(|n| names = Some(n)).map_err(|e| e.into());
+    report(&names_file, &names_result);
+
+    let sketch_result = match (&params, &names) {
+        (Some(params), Some(names)) => with_sketch_type!(
+            params["algorithm"].as_str(),
+            validate_sketches(&sketch_file_name, names.len())
+        ),
+        _ => Err("not checked, the parameter or names file is invalid".into()),
+    };
+    report(&sketch_file_name, &sketch_result);
+
+    params_result.is_ok() && names_result.is_ok() && sketch_result.is_ok()
+}
+
+// integrity check of sketch sets, fails if any file of any set fails
+pub fn validate(prefixes: &[String]) -> Result<(), Box<dyn Error>> {
+    println!("File\tStatus\tMessage");
+    let failed: Vec<&String> = prefixes.iter().filter(|p| !validate_set(p)).collect();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} of {} sketch sets failed validation", failed.len(), prefixes.len()).into())
+    }
+}
+
+fn cardinalities<S: KmerSketch + Sync>(sketch_file_name: &str, count: usize) -> Result<Vec<f64>, Box<dyn Error>> {
+    let sketches: Vec<S> = load_sketches(sketch_file_name, count)?;
+    Ok(sketches.par_iter().map(|s| s.cardinality()).collect())
+}
+
+// distinct k-mer estimate of every sketch and the genome size it stands for: for
+// assemblies the two agree, for read sets sketched with --count-min the size leaves
+// out the error k-mers of the spectrum, reads without counts have no size
+pub fn stats(input: &str, output: &str) -> Result<(), Box<dyn Error>> {
+    let files = find_files(input)?;
+    let params = read_params(&files["params"])?;
+    let entries = read_entries(&files["files"])?;
+    let names: Vec<String> = entries.iter().map(|entry| entry.name.clone()).collect();
+    let mut sizes = with_sketch_type!(
+        params["algorithm"].as_str(),
+        cardinalities(&files["sketches"], names.len())
+    )?;
+    // sets subsampled with --scaled hold one k-mer in `scaled`
+    if let Some(scaled) = SketchOptions::from_map(&params)?.subsample {
+        sizes.iter_mut().for_each(|size| *size *= scaled as f64);
+    }
+    let counts = countmin::load_counts(input, names.len())?;
+
+    let mut out = BufWriter::new(File::create(output)?);
+    writeln!(out, "Genome\tType\tCardinality\tGenomeSize")?;
+    for (i, (entry, size)) in entries.iter().zip(&sizes).enumerate() {
+        let reads = is_fastq(entry.file());
+        // a genome of length L has about L distinct k-mers
+        let genome_size = match (reads, &counts) {
+            (false, _) => format!("{:.0}", size),
+            (true, Some(counts)) => counts[i].solid_kmers().to_string(),
+            (true, None) => "NA".to_string(),
+        };
+        let kind = if reads { "reads" } else { "assembly" };
+        writeln!(out, "{}\t{}\t{:.0}\t{}", entry.name, kind, size, genome_size)?;
+    }
+    out.flush()?;
+    println!("Statistics of {} sketches written to {}.", names.len(), output);
+
+    // k-mer frequency spectra of the read sets, if the set was sketched with --count-min
+    if let Some(counts) = counts {
+        let spectrum_file = format!("{}_spectrum", output);
+        countmin::write_spectra(&names, &counts, &spectrum_file)?;
+        println!("K-mer frequency spectra of the read sets written to {}.", spectrum_file);
+    }
+    Ok(())
+}
+
+// sketch set in the temporary directory, deleted when dropped
+pub struct TempSketchSet {
+    pub prefix: String,
+}
+
+impl TempSketchSet {
+    // sketch `files` with the parameters of another set so the two can be compared
+    pub fn sketch(files: Vec<String>, param_map: &HashMap<String, String>, threads: u32) -> Result<Self, Box<dyn Error>> {
+        let params = SketchParams::from_map(param_map)?;
+        let prefix = std::env::temp_dir()
+            .join(format!("lash_query_{}", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let set = TempSketchSet { prefix };
+        let samples: Vec<Sample> = files.into_iter().map(Sample::from_path).collect();
+        let names: Vec<Entry> = samples.iter().map(|s| Entry::named(s.name.clone())).collect();
+        check_inputs(&samples)?;
+        with_sketch_type!(
+            params.algorithm.as_str(),
+            sketch_files(
+                params.precision,
+                samples,
+                &[(params.kmer_length, set.prefix.clone())],
+                threads,
+                params.seed,
+                params.aa,
+                &SketchOptions { count_min: None, per_contig: false, ..params.options.clone() }
+            )
+        )?;
+        write_set_metadata(&set.prefix, &names, param_map)?;
+        Ok(set)
+    }
+}
+
+impl Drop for TempSketchSet {
+    fn drop(&mut self) {
+        for suffix in ["_parameters.json", "_files.json", "_sketches.bin"] {
+            let _ = fs::remove_file(format!("{}{}", self.prefix, suffix));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::minhash::MinHash;
+    use crate::utils::TempDir;
+
+    // FASTA files of unrelated random genomes, g0.fa, g1.fa, ...
+    fn genomes(dir: &TempDir, count: usize) -> Vec<String> {
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        (0..count)
+            .map(|i| {
+                let seq: Vec<u8> = (0..3000)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        b"ACGT"[(state >> 32) as usize % 4]
+                    })
+                    .collect();
+                let path = dir.path(&format!("g{}.fa", i));
+                fs::write(&path, [b">g\n", &seq[..], b"\n"].concat()).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    fn params() -> HashMap<String, String> {
+        [("k", "16"), ("algorithm", "minhash"), ("sketch_size", "64"), ("seed", "42"), ("molecule", "nucleotide")]
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn sketch_set(prefix: &str, files: &[String]) {
+        let samples: Vec<Sample> = files.iter().cloned().map(Sample::from_path).collect();
+        let entries: Vec<Entry> = files.iter().cloned().map(Entry::named).collect();
+        sketch_files::<MinHash>(Some(64), samples, &[(16, prefix.to_string())], 1, 42, false, &SketchOptions::default()).unwrap();
+        write_set_metadata(prefix, &entries, &params()).unwrap();
+    }
+
+    fn entries(prefix: &str) -> Vec<Entry> {
+        read_entries(&format!("{}_files.json", prefix)).unwrap()
+    }
+
+    fn sketches(prefix: &str) -> Vec<u8> {
+        zstd::decode_all(File::open(format!("{}_sketches.bin", prefix)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn split_and_paste_round_trip() {
+        let dir = TempDir::new("sketchset_split");
+        let files = genomes(&dir, 5);
+        let (set, shards, pasted) = (dir.path("set"), dir.path("shard"), dir.path("pasted"));
+        sketch_set(&set, &files);
+        rename(&set, &[(files[1].clone(), "renamed".to_string())].into_iter().collect()).unwrap();
+
+        split(&set, &shards, &Shards::Parts(2), 1).unwrap();
+        let (first, second) = (format!("{}_1", shards), format!("{}_2", shards));
+        assert_eq!(entries(&first).len(), 3);
+        assert_eq!(entries(&second).len(), 2);
+        assert_eq!(read_params(&format!("{}_parameters.json", second)).unwrap(), params());
+
+        paste(&[first, second], &pasted, 1).unwrap();
+        assert_eq!(entries(&pasted), entries(&set));
+        assert_eq!(entries(&pasted)[1], Entry { name: "renamed".to_string(), path: Some(files[1].clone()), more: Vec::new() });
+        assert_eq!(sketches(&pasted), sketches(&set));
+        assert_eq!(read_params(&format!("{}_parameters.json", pasted)).unwrap(), params());
+    }
+
+    #[test]
+    fn paste_rejects_duplicates() {
+        let dir = TempDir::new("sketchset_paste");
+        let files = genomes(&dir, 2);
+        let set = dir.path("set");
+        sketch_set(&set, &files);
+        assert!(paste(&[set.clone(), set], &dir.path("pasted"), 1).is_err());
+    }
+
+    #[test]
+    fn update_appends_new_files() {
+        let dir = TempDir::new("sketchset_update");
+        let files = genomes(&dir, 3);
+        let (set, whole) = (dir.path("set"), dir.path("whole"));
+        sketch_set(&set, &files[..2]);
+        sketch_set(&whole, &files);
+
+        // a file already in the set is skipped
+        update(&set, &files[1..], 1).unwrap();
+        assert_eq!(entries(&set), entries(&whole));
+        assert_eq!(sketches(&set), sketches(&whole));
+        update(&set, &files, 1).unwrap();
+        assert_eq!(entries(&set), entries(&whole));
+    }
+
+    #[test]
+    fn remove_drops_sketches() {
+        let dir = TempDir::new("sketchset_remove");
+        let files = genomes(&dir, 3);
+        let (set, rest) = (dir.path("set"), dir.path("rest"));
+        sketch_set(&set, &files);
+        sketch_set(&rest, &[files[0].clone(), files[2].clone()]);
+
+        remove(&set, &[files[1].clone(), "absent".to_string()], 1).unwrap();
+        assert_eq!(entries(&set), entries(&rest));
+        assert_eq!(sketches(&set), sketches(&rest));
+    }
+
+    #[test]
+    fn rename_keeps_paths() {
+        let dir = TempDir::new("sketchset_rename");
+        let files = genomes(&dir, 2);
+        let set = dir.path("set");
+        sketch_set(&set, &files);
+        let before = sketches(&set);
+
+        rename(&set, &[(files[0].clone(), "first".to_string())].into_iter().collect()).unwrap();
+        let renamed = entries(&set);
+        assert_eq!(renamed[0], Entry { name: "first".to_string(), path: Some(files[0].clone()), more: Vec::new() });
+        assert_eq!((renamed[1].name.as_str(), renamed[1].file()), (files[1].as_str(), files[1].as_str()));
+        assert_eq!(sketches(&set), before);
+
+        // two genomes of one name leave the set unchanged
+        assert!(rename(&set, &[(files[1].clone(), "first".to_string())].into_iter().collect()).is_err());
+        assert_eq!(entries(&set), renamed);
+    }
+}
@@ -0,0 +1,80 @@
+// src/spaced.rs
+// Spaced seeds for `sketch --seed-mask 110110111...`: every window of the mask's
+// length contributes the bases at its 1 positions only, so a substitution at a 0
+// position leaves the seed intact. Seeds survive more often between moderately
+// diverged genomes than contiguous k-mers of the same weight. Both strands are
+// masked and the smaller seed kept, so seeds are canonical as k-mers are.
+
+use xxhash_rust::xxh3::xxh3_64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeedMask {
+    text: String,
+    // bit offsets in the 2-bit encoded window of the bases kept, first base first
+    shifts: Vec<u32>,
+}
+
+impl SeedMask {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        if text.len() > 64 || !text.bytes().all(|c| c == b'0' || c == b'1') {
+            return Err(format!("invalid seed mask {}, give up to 64 0s and 1s", text));
+        }
+        if !text.starts_with('1') || !text.ends_with('1') {
+            return Err(format!("seed mask {} must start and end with 1", text));
+        }
+        let span = text.len() as u32;
+        let shifts = text
+            .bytes()
+            .enumerate()
+            .filter(|&(_, c)| c == b'1')
+            .map(|(i, _)| 2 * (span - 1 - i as u32))
+            .collect();
+        Ok(SeedMask { text: text.to_string(), shifts })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    // length of the window, the k recorded for the set
+    pub fn span(&self) -> usize {
+        self.text.len()
+    }
+
+    // bases kept per window, the k of distance formulas
+    pub fn weight(&self) -> usize {
+        self.shifts.len()
+    }
+
+    fn extract(&self, window: u128) -> u128 {
+        self.shifts.iter().fold(0, |seed, &s| (seed << 2) | ((window >> s) & 3))
+    }
+
+    // spaced counterpart of utils::for_each_kmer, `seq` holds ACGT only; seeds of
+    // up to 32 bases are packed in a u64, heavier ones folded by hashing
+    pub fn for_each_kmer<F: FnMut(u64)>(&self, seq: &[u8], mut f: F) {
+        let span = self.span();
+        let mask = u128::MAX >> (128 - 2 * span);
+        let shift = 2 * (span - 1);
+        let (mut forward, mut reverse, mut filled) = (0u128, 0u128, 0usize);
+        for &c in seq {
+            let code: u128 = match c {
+                b'A' => 0,
+                b'C' => 1,
+                b'G' => 2,
+                b'T' => 3,
+                _ => {
+                    filled = 0;
+                    continue;
+                }
+            };
+            forward = ((forward << 2) | code) & mask;
+            reverse = (reverse >> 2) | ((3 - code) << shift);
+            filled += 1;
+            if filled >= span {
+                let seed = self.extract(forward).min(self.extract(reverse));
+                f(if self.weight() <= 32 { seed as u64 } else { xxh3_64(&seed.to_le_bytes()) });
+            }
+        }
+    }
+}
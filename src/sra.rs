@@ -0,0 +1,73 @@
+// src/sra.rs
+// SRA/ENA/DDBJ run accessions (SRR, ERR, DRR) given in place of a path in a file
+// list or manifest: the run's FASTQ files are looked up in the ENA portal API
+// and streamed over HTTPS while sketching, nothing is downloaded first. Paired
+// runs (<run>_1 and <run>_2, plus <run>.fastq.gz of unpaired reads when there is
+// one) become one sample, named by the accession.
+
+use std::error::Error;
+use std::io::Read;
+use std::path::Path;
+
+use crate::remote;
+use crate::utils::Sample;
+
+const FILE_REPORT: &str = "https://www.ebi.ac.uk/ena/portal/api/filereport";
+
+// SRR, ERR or DRR and at least 6 digits
+pub fn is_run_accession(path: &str) -> bool {
+    ["SRR", "ERR", "DRR"].iter().any(|prefix| {
+        path.strip_prefix(prefix)
+            .is_some_and(|digits| digits.len() >= 6 && digits.bytes().all(|b| b.is_ascii_digit()))
+    })
+}
+
+// HTTPS URLs of the FASTQ files of a run
+fn fastq_urls(run: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let url = format!("{}?accession={}&result=read_run&fields=fastq_ftp&format=tsv", FILE_REPORT, run);
+    let mut report = String::new();
+    remote::open(&url)?.read_to_string(&mut report)?;
+    // a header, then run_accession<TAB>fastq_ftp with the files separated by ';'
+    let mut urls: Vec<String> = report
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split('\t').nth(1))
+        .flat_map(|files| files.split(';'))
+        .filter(|file| !file.trim().is_empty())
+        .map(|file| format!("https://{}", file.trim()))
+        .collect();
+    if urls.is_empty() {
+        return Err(format!("{}: no FASTQ files at ENA (unknown run, or not public yet?)", run).into());
+    }
+    // R1 and R2 ahead of unpaired reads
+    urls.sort_by_key(|url| !(url.ends_with("_1.fastq.gz") || url.ends_with("_2.fastq.gz")));
+    Ok(urls)
+}
+
+// run accessions among the sample paths replaced by the URLs of their FASTQ files
+pub fn resolve(samples: &mut [Sample]) -> Result<(), Box<dyn Error>> {
+    let wanted = |path: &str| is_run_accession(path) && !Path::new(path).exists();
+    let mut runs = 0;
+    for sample in samples.iter_mut() {
+        if !wanted(&sample.path) && !sample.more.iter().any(|p| wanted(p)) {
+            continue;
+        }
+        let paths = std::iter::once(&sample.path).chain(&sample.more);
+        let mut resolved = Vec::new();
+        for path in paths {
+            match wanted(path) {
+                true => {
+                    resolved.extend(fastq_urls(path)?);
+                    runs += 1;
+                }
+                false => resolved.push(path.clone()),
+            }
+        }
+        sample.path = resolved.remove(0);
+        sample.more = resolved;
+    }
+    if runs > 0 {
+        println!("{} sequencing runs streamed from ENA.", runs);
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+// src/strobemer.rs
+// Strobemers for `sketch --seed-type strobemer|minstrobe` (Sahlin 2021): two
+// k-mers, the first at every position and the second picked from a window
+// further along, are sketched as one unit. An indel between the strobes only
+// moves the second one within its window, so strobemers of indel-rich genomes
+// (long-read assemblies) still match where contiguous 2k-mers would not.
+// Randstrobes pick the second strobe jointly with the first, minstrobes by its
+// own hash. Both strobes are canonical k-mers and every strobemer is also linked
+// backwards, so a genome and its reverse complement give the same strobemers.
+
+use xxhash_rust::xxh3::{xxh3_64, xxh3_64_with_seed};
+
+use crate::utils::for_each_kmer;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StrobeScheme {
+    // randstrobes, else minstrobes
+    pub random: bool,
+    // window of the second strobe, in k-mer positions after the first
+    pub window: (usize, usize),
+}
+
+impl StrobeScheme {
+    pub fn new(seed_type: &str, window: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid strobe window {}, give min,max with 0 < min <= max", window);
+        let bounds: Vec<usize> = window
+            .split(',')
+            .map(|w| w.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        match bounds[..] {
+            [min, max] if min > 0 && min <= max => Ok(StrobeScheme {
+                random: seed_type != "minstrobe",
+                window: (min, max),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+
+    pub fn seed_type(&self) -> &'static str {
+        if self.random { "strobemer" } else { "minstrobe" }
+    }
+
+    // the k of distance formulas: a strobemer survives when both strobes do
+    pub fn distance_k(kmer_length: usize) -> usize {
+        2 * kmer_length
+    }
+
+    // strobemers of a record, `seq` holds ACGT only, each handed to `f` as a u64
+    pub fn for_each_strobemer<F: FnMut(u64)>(&self, seq: &[u8], kmer_length: usize, seed: u64, mut f: F) {
+        let mut kmers: Vec<u64> = Vec::new();
+        for_each_kmer(seq, kmer_length, |masked| kmers.push(masked));
+        let seed = seed ^ 0x7374_726F_6200_0000;
+        let hashes: Vec<u64> = kmers.iter().map(|m| xxh3_64_with_seed(&m.to_le_bytes(), seed)).collect();
+        let n = kmers.len();
+        let (min, max) = self.window;
+        let score = |i: usize, j: usize| if self.random { hashes[i] ^ hashes[j] } else { hashes[j] };
+        let mut emit = |i: usize, j: usize| {
+            let (a, b) = (kmers[i].min(kmers[j]), kmers[i].max(kmers[j]));
+            let mut pair = [0u8; 16];
+            pair[..8].copy_from_slice(&a.to_le_bytes());
+            pair[8..].copy_from_slice(&b.to_le_bytes());
+            f(xxh3_64(&pair));
+        };
+        for i in 0..n {
+            // forwards, nearest candidate first on ties
+            if i + min < n {
+                let j = (i + min..=(i + max).min(n - 1)).min_by_key(|&j| score(i, j)).expect("window");
+                emit(i, j);
+            }
+            // backwards, the same strobemer read from the other strand
+            if i >= min {
+                let j = (i.saturating_sub(max)..=i - min).rev().min_by_key(|&j| score(i, j)).expect("window");
+                emit(i, j);
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+// src/superminhash.rs
+// SuperMinHash (`superminhash`, Ertl 2017): each k-mer draws a random permutation
+// of the `sketch_size` registers and offers register p(j) the value j + r_j, with
+// r_j uniform in [0, 1). Values are correlated across registers instead of
+// independent, which lowers the variance of the Jaccard estimate below that of
+// classic MinHash with as many registers, most of all for similar genomes.
+// A k-mer stops once j reaches the largest register value's integer part, so
+// sketching costs O(1) per k-mer after the first few thousand.
+
+use std::error::Error;
+
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+use crate::format;
+use crate::utils::KmerSketch;
+use crate::weighted::KmerRng;
+
+// register count used when none is given
+pub const DEFAULT_SKETCH_SIZE: usize = 1000;
+
+#[derive(Clone)]
+pub struct SuperMinHash {
+    pub values: Vec<f64>,
+    // permutation scratch, entry j is valid only when stamps[j] is the current k-mer
+    permutation: Vec<u32>,
+    stamps: Vec<u64>,
+    kmers: u64,
+    // histogram[j]: registers whose value has integer part j (capped at m - 1),
+    // `bound` is the largest non-empty bin
+    histogram: Vec<u32>,
+    bound: usize,
+}
+
+impl SuperMinHash {
+    pub fn from_values(values: Vec<f64>) -> Self {
+        let m = values.len();
+        let mut histogram = vec![0; m];
+        for &value in &values {
+            histogram[Self::bin(value, m)] += 1;
+        }
+        let bound = histogram.iter().rposition(|&n| n > 0).unwrap_or(0);
+        SuperMinHash { permutation: vec![0; m], stamps: vec![u64::MAX; m], kmers: 0, histogram, bound, values }
+    }
+
+    fn bin(value: f64, m: usize) -> usize {
+        if value.is_finite() { (value as usize).min(m - 1) } else { m - 1 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.values.iter().all(|v| v.is_infinite())
+    }
+
+    // entry j of the current k-mer's permutation, the identity until swapped
+    fn slot(&mut self, j: usize) -> u32 {
+        if self.stamps[j] != self.kmers {
+            self.stamps[j] = self.kmers;
+            self.permutation[j] = j as u32;
+        }
+        self.permutation[j]
+    }
+
+    fn add_hash(&mut self, hash: u64) {
+        let m = self.values.len();
+        let mut rng = KmerRng(hash);
+        let mut j = 0;
+        while j <= self.bound {
+            let r = rng.next_unit();
+            let k = j + (rng.next_u64() % (m - j) as u64) as usize;
+            let (pj, pk) = (self.slot(j), self.slot(k));
+            self.permutation[j] = pk;
+            self.permutation[k] = pj;
+            let register = pk as usize;
+            let value = j as f64 + r;
+            if value < self.values[register] {
+                let old = Self::bin(self.values[register], m);
+                self.values[register] = value;
+                if j < old {
+                    self.histogram[old] -= 1;
+                    self.histogram[j] += 1;
+                    while self.bound > 0 && self.histogram[self.bound] == 0 {
+                        self.bound -= 1;
+                    }
+                }
+            }
+            j += 1;
+        }
+        self.kmers += 1;
+    }
+}
+
+impl KmerSketch for SuperMinHash {
+    fn new(precision: Option<u32>) -> Self {
+        let sketch_size = precision.map_or(DEFAULT_SKETCH_SIZE, |s| s as usize);
+        assert!(sketch_size > 1, "SuperMinHash sketch size must be at least 2");
+        SuperMinHash::from_values(vec![f64::INFINITY; sketch_size])
+    }
+
+    fn add_kmer(&mut self, masked: u64, seed: u64) {
+        self.add_hash(xxh3_64_with_seed(&masked.to_le_bytes(), seed));
+    }
+
+    fn add_hashed(&mut self, _masked: u64, hash: u64, _seed: u64) {
+        self.add_hash(hash);
+    }
+
+    fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        Ok(format::write_superminhash(writer, &self.values)?)
+    }
+
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        Ok(SuperMinHash::from_values(format::read_superminhash(reader)?))
+    }
+
+    // the j = 0 values fall on one random register per k-mer: linear counting while
+    // some registers have none, then the registers are minima of uniforms and
+    // -ln(1 - value) is exponential with rate distinct k-mers / m
+    fn cardinality(&self) -> f64 {
+        let m = self.values.len() as f64;
+        let hit = self.values.iter().filter(|&&v| v < 1.0).count() as f64;
+        if hit < m {
+            return m * (m / (m - hit)).ln();
+        }
+        let sum: f64 = self.values.iter().map(|&v| -(1.0 - v).ln()).sum();
+        m * (m - 1.0) / sum
+    }
+
+    fn merged(&self, other: &Self) -> Self {
+        assert_eq!(self.values.len(), other.values.len(), "SuperMinHash sketches with different sizes");
+        let values = self.values.iter().zip(&other.values).map(|(a, b)| a.min(*b)).collect();
+        SuperMinHash::from_values(values)
+    }
+
+    // share of registers holding the same value, the same k-mer won both
+    fn jaccard(&self, other: &Self) -> f64 {
+        if self.is_empty() || other.is_empty() {
+            return 0.0;
+        }
+        let same = self.values.iter().zip(&other.values).filter(|(a, b)| a == b).count();
+        same as f64 / self.values.len() as f64
+    }
+
+    fn registers(&self) -> Vec<u16> {
+        self.values
+            .iter()
+            .map(|v| {
+                if v.is_finite() {
+                    ((v.to_bits().wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 48) as u16).max(1)
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+}
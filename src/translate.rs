@@ -0,0 +1,57 @@
+// src/translate.rs
+// Six-frame translation for `sketch --translate`: nucleotide records are read in
+// the three frames of both strands with the standard genetic code and cut at stop
+// codons and ambiguous codons, so the amino-acid k-mers sketched never span a
+// stop. Unannotated genomes are then compared at the protein level without a gene
+// caller; frames that code for nothing add mostly short fragments.
+
+// standard code, codons in TCAG order of first, second and third base
+const CODE: &[u8; 64] = b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+fn base_index(base: u8) -> Option<usize> {
+    match base {
+        b'T' => Some(0),
+        b'C' => Some(1),
+        b'A' => Some(2),
+        b'G' => Some(3),
+        _ => None,
+    }
+}
+
+fn complement(base: u8) -> u8 {
+    match base {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        other => other,
+    }
+}
+
+// protein fragments between stops of the six frames, `seq` uppercase
+pub fn six_frames(seq: &[u8]) -> Vec<Vec<u8>> {
+    let reverse: Vec<u8> = seq.iter().rev().map(|&b| complement(b)).collect();
+    let mut fragments = Vec::new();
+    for strand in [seq, &reverse[..]] {
+        for frame in 0..3 {
+            let mut fragment = Vec::new();
+            for codon in strand.get(frame..).unwrap_or_default().chunks_exact(3) {
+                let residue = match (base_index(codon[0]), base_index(codon[1]), base_index(codon[2])) {
+                    (Some(a), Some(b), Some(c)) => CODE[16 * a + 4 * b + c],
+                    _ => b'*',
+                };
+                if residue == b'*' {
+                    if !fragment.is_empty() {
+                        fragments.push(std::mem::take(&mut fragment));
+                    }
+                } else {
+                    fragment.push(residue);
+                }
+            }
+            if !fragment.is_empty() {
+                fragments.push(fragment);
+            }
+        }
+    }
+    fragments
+}
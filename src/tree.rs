@@ -0,0 +1,188 @@
+// src/tree.rs
+// `lash tree`: neighbor-joining or UPGMA tree of a sketch set, written as Newick
+
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+use crate::pairwise::{lower_triangle, SketchSet};
+
+// tip label, quoted when it contains characters that mean something in Newick
+fn newick_label(name: &str) -> String {
+    if name.chars().any(|c| "()[]':;, \t".contains(c)) {
+        format!("'{}'", name.replace('\'', "''"))
+    } else {
+        name.to_string()
+    }
+}
+
+// full symmetric matrix from the lower triangle
+fn square(rows: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = rows.len();
+    let mut d = vec![vec![0.0; n]; n];
+    for (i, row) in rows.iter().enumerate() {
+        for (j, &v) in row.iter().enumerate() {
+            d[i][j] = v;
+            d[j][i] = v;
+        }
+    }
+    d
+}
+
+// remove rows/columns i and j (i < j) and append the new node's distances
+fn replace_pair(d: &mut Vec<Vec<f64>>, nodes: &mut Vec<String>, i: usize, j: usize, node: String, to_node: Vec<f64>) {
+    for row in d.iter_mut() {
+        row.remove(j);
+        row.remove(i);
+    }
+    d.remove(j);
+    d.remove(i);
+    nodes.remove(j);
+    nodes.remove(i);
+    for (row, &v) in d.iter_mut().zip(&to_node) {
+        row.push(v);
+    }
+    let mut last = to_node;
+    last.push(0.0);
+    d.push(last);
+    nodes.push(node);
+}
+
+pub fn neighbor_joining(mut d: Vec<Vec<f64>>, mut nodes: Vec<String>) -> String {
+    while nodes.len() > 3 {
+        let n = nodes.len();
+        let r: Vec<f64> = d.iter().map(|row| row.iter().sum()).collect();
+        let (mut bi, mut bj, mut best) = (0, 1, f64::INFINITY);
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let q = (n as f64 - 2.0) * d[i][j] - r[i] - r[j];
+                if q < best {
+                    (bi, bj, best) = (i, j, q);
+                }
+            }
+        }
+        let dij = d[bi][bj];
+        let li = (dij / 2.0 + (r[bi] - r[bj]) / (2.0 * (n as f64 - 2.0))).max(0.0);
+        let lj = (dij - li).max(0.0);
+        let node = format!("({}:{:.6},{}:{:.6})", nodes[bi], li, nodes[bj], lj);
+        let to_node: Vec<f64> = (0..n)
+            .filter(|&k| k != bi && k != bj)
+            .map(|k| ((d[bi][k] + d[bj][k] - dij) / 2.0).max(0.0))
+            .collect();
+        replace_pair(&mut d, &mut nodes, bi, bj, node, to_node);
+    }
+    match nodes.len() {
+        // unrooted: the last three nodes hang off one trifurcation
+        3 => {
+            let l0 = ((d[0][1] + d[0][2] - d[1][2]) / 2.0).max(0.0);
+            let l1 = ((d[0][1] + d[1][2] - d[0][2]) / 2.0).max(0.0);
+            let l2 = ((d[0][2] + d[1][2] - d[0][1]) / 2.0).max(0.0);
+            format!("({}:{:.6},{}:{:.6},{}:{:.6});", nodes[0], l0, nodes[1], l1, nodes[2], l2)
+        }
+        2 => format!("({}:{:.6},{}:{:.6});", nodes[0], d[0][1] / 2.0, nodes[1], d[0][1] / 2.0),
+        _ => format!("{};", nodes[0]),
+    }
+}
+
+pub fn upgma(mut d: Vec<Vec<f64>>, mut nodes: Vec<String>) -> String {
+    // cluster sizes and heights, in the order of `nodes`
+    let mut sizes = vec![1usize; nodes.len()];
+    let mut heights = vec![0.0f64; nodes.len()];
+    while nodes.len() > 1 {
+        let n = nodes.len();
+        let (mut bi, mut bj, mut best) = (0, 1, f64::INFINITY);
+        for (i, row) in d.iter().enumerate() {
+            for (j, &v) in row.iter().enumerate().skip(i + 1) {
+                if v < best {
+                    (bi, bj, best) = (i, j, v);
+                }
+            }
+        }
+        let height = best / 2.0;
+        let node = format!(
+            "({}:{:.6},{}:{:.6})",
+            nodes[bi],
+            (height - heights[bi]).max(0.0),
+            nodes[bj],
+            (height - heights[bj]).max(0.0)
+        );
+        let (si, sj) = (sizes[bi] as f64, sizes[bj] as f64);
+        let to_node: Vec<f64> = (0..n)
+            .filter(|&k| k != bi && k != bj)
+            .map(|k| (si * d[bi][k] + sj * d[bj][k]) / (si + sj))
+            .collect();
+        let size = sizes[bi] + sizes[bj];
+        sizes.remove(bj);
+        sizes.remove(bi);
+        sizes.push(size);
+        heights.remove(bj);
+        heights.remove(bi);
+        heights.push(height);
+        replace_pair(&mut d, &mut nodes, bi, bj, node, to_node);
+    }
+    format!("{};", nodes[0])
+}
+
+pub fn tree(prefix: &str, output: &str, method: &str, equation: u8) -> Result<(), Box<dyn Error>> {
+    let set = SketchSet::open(prefix)?;
+    if set.names.is_empty() {
+        return Err(format!("sketch set {} is empty", prefix).into());
+    }
+    let d = square(&lower_triangle(&set, equation)?);
+    let tips: Vec<String> = set.names.iter().map(|n| newick_label(n)).collect();
+    let newick = match method {
+        "nj" => neighbor_joining(d, tips),
+        "upgma" => upgma(d, tips),
+        other => return Err(format!("unknown tree method {}", other).into()),
+    };
+    writeln!(File::create(output)?, "{}", newick)?;
+    println!("{} tree of {} genomes written to {}.", method.to_uppercase(), set.names.len(), output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tips(names: &str) -> Vec<String> {
+        names.chars().map(String::from).collect()
+    }
+
+    #[test]
+    fn neighbor_joining_recovers_additive_tree() {
+        // distances along the tree ((a:2,b:3):3,c:4,(d:2,e:1):2)
+        let d = vec![
+            vec![0.0, 5.0, 9.0, 9.0, 8.0],
+            vec![5.0, 0.0, 10.0, 10.0, 9.0],
+            vec![9.0, 10.0, 0.0, 8.0, 7.0],
+            vec![9.0, 10.0, 8.0, 0.0, 3.0],
+            vec![8.0, 9.0, 7.0, 3.0, 0.0],
+        ];
+        assert_eq!(
+            neighbor_joining(d, tips("abcde")),
+            "(d:2.000000,e:1.000000,(c:4.000000,(a:2.000000,b:3.000000):3.000000):2.000000);"
+        );
+    }
+
+    #[test]
+    fn upgma_averages_clusters() {
+        let d = vec![
+            vec![0.0, 17.0, 21.0, 31.0, 23.0],
+            vec![17.0, 0.0, 30.0, 34.0, 21.0],
+            vec![21.0, 30.0, 0.0, 28.0, 39.0],
+            vec![31.0, 34.0, 28.0, 0.0, 43.0],
+            vec![23.0, 21.0, 39.0, 43.0, 0.0],
+        ];
+        assert_eq!(
+            upgma(d, tips("abcde")),
+            "((e:11.000000,(a:8.500000,b:8.500000):2.500000):5.500000,(c:14.000000,d:14.000000):2.500000);"
+        );
+    }
+
+    #[test]
+    fn labels_are_quoted() {
+        assert_eq!(newick_label("GCF_000005845.2"), "GCF_000005845.2");
+        assert_eq!(newick_label("E. coli K-12"), "'E. coli K-12'");
+        assert_eq!(newick_label("it's"), "'it''s'");
+    }
+}
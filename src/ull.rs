@@ -0,0 +1,90 @@
+// src/ull.rs
+// UltraLogLog sketches that also keep the martingale estimate (Ertl 2024): while
+// k-mers stream into a fresh sketch, every register change adds the inverse of the
+// probability that the sketch state could still change. This is the optimal
+// estimator for a sketch built in one pass, but it is lost on merging, so merged
+// sketches and unions only have the register estimators (fgra, ml).
+
+use std::error::Error;
+
+use ultraloglog::{StateChangeObserver, UltraLogLog};
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+use crate::format;
+use crate::utils::KmerSketch;
+
+#[derive(Clone, Copy)]
+pub struct Martingale {
+    pub estimate: f64,
+    // probability that the next new k-mer changes the sketch
+    probability: f64,
+}
+
+impl StateChangeObserver for Martingale {
+    fn state_changed(&mut self, probability_decrement: f64) {
+        self.estimate += 1.0 / self.probability;
+        self.probability -= probability_decrement;
+    }
+}
+
+pub struct UllSketch {
+    pub sketch: UltraLogLog,
+    // None for merged or downsized sketches and sketches written before format version 3
+    pub martingale: Option<Martingale>,
+}
+
+impl UllSketch {
+    pub fn from_parts(sketch: UltraLogLog, martingale: Option<f64>) -> Self {
+        // the probability is only needed while sketching, loaded sketches are complete
+        let martingale = martingale.map(|estimate| Martingale { estimate, probability: 0.0 });
+        UllSketch { sketch, martingale }
+    }
+
+    pub fn martingale_estimate(&self) -> Option<f64> {
+        self.martingale.map(|m| m.estimate)
+    }
+
+    // the same k-mers at a lower precision, without a martingale estimate
+    pub fn downsized(&self, p: u32) -> Result<Self, &'static str> {
+        Ok(UllSketch { sketch: self.sketch.downsize(p)?, martingale: None })
+    }
+}
+
+// sketching for UltraLogLog
+impl KmerSketch for UllSketch {
+    fn new(precision: Option<u32>) -> Self {
+        let sketch = UltraLogLog::new(precision.expect("ULL needs precision"))
+            .expect("failed to create ULL");
+        UllSketch { sketch, martingale: Some(Martingale { estimate: 0.0, probability: 1.0 }) }
+    }
+
+    fn add_kmer(&mut self, masked: u64, seed: u64) {
+        self.add_hashed(masked, xxh3_64_with_seed(&masked.to_le_bytes(), seed), seed);
+    }
+
+    fn add_hashed(&mut self, _masked: u64, hash: u64, _seed: u64) {
+        self.sketch.add_with_observer(hash, self.martingale.as_mut());
+    }
+
+    fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        Ok(format::write_ull(writer, &self.sketch, self.martingale_estimate())?)
+    }
+
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let (sketch, martingale) = format::read_ull(reader)?;
+        Ok(UllSketch::from_parts(sketch, martingale))
+    }
+
+    fn cardinality(&self) -> f64 {
+        self.sketch.get_distinct_count_estimate()
+    }
+
+    fn merged(&self, other: &Self) -> Self {
+        let sketch = UltraLogLog::merge(&self.sketch, &other.sketch).expect("failed to merge sketches");
+        UllSketch { sketch, martingale: None }
+    }
+
+    fn registers(&self) -> Vec<u16> {
+        self.sketch.get_state().iter().map(|&r| u16::from(r)).collect()
+    }
+}
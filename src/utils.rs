@@ -1,32 +1,49 @@
 // src/utils.rs
 
-use hashbrown::HashMap;
-use needletail::parse_fastx_file;
+use hashbrown::{HashMap, HashSet};
+use needletail::{parse_fastx_reader, FastxReader};
+use needletail::errors::{ParseError, ParseErrorKind};
 use rayon::prelude::*;
 use std::error::Error;
+use crate::ambiguous::{self, AmbiguityPolicy};
+use crate::archive;
+use crate::bloom::BloomFilter;
+use crate::compression;
+use crate::checkpoint::Checkpoint;
+use crate::countmin::{self, CountMin};
+use crate::dust::low_complexity;
+use crate::format;
 use crate::hasher::Xxh3Builder;
+use crate::hlll::HllRegisters;
+use crate::kmerlist::KmerList;
+use crate::nthash;
+use crate::regions::Regions;
+use crate::remote;
+use crate::sampling::{Minimizers, SyncmerScheme, Syncmers};
+use crate::simd::{self, HashBatch};
+use crate::spaced::SeedMask;
+use crate::strobemer::StrobeScheme;
+use crate::translate::six_frames;
 use num_traits::{Float};
-use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::fs::{self, File};
+use std::path::Path;
+use std::borrow::Cow;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::process::Command;
 
 use xxhash_rust::xxh3::xxh3_64_with_seed;
-// use xxhash_rust::xxh3::xxh3_64;
+use xxhash_rust::xxh3::xxh3_64;
 
 use zstd::stream::{Decoder, Encoder};
 use std::str::FromStr;
 use hyperminhash::Sketch;
-use kmerutils::base::KmerT;
-use kmerutils::base::{
-    kmergenerator::{KmerSeqIterator, KmerSeqIteratorT},
-    sequence::Sequence as KSeq,
-    CompressedKmerT, Kmer16b32bit, Kmer32bit, Kmer64bit,
-};
+use kmerutils::base::CompressedKmerT;
 
 use kmerutils::aautils::kmeraa::{KmerAA32bit, KmerAA64bit, 
     KmerSeqIterator as AAKmerSeqIterator, SequenceAA, KmerSeqIteratorT as aaIteratorT};
 use ultraloglog::{Estimator, MaximumLikelihoodEstimator, UltraLogLog};
 
-use log::info;
+use log::{info, warn};
 use serde_json::to_writer_pretty;
 use streaming_algorithms::HyperLogLog;
 
@@ -40,6 +57,25 @@ pub fn filter_out_n(seq: &[u8]) -> Vec<u8> {
     out
 }
 
+// read the U of RNA records as T, keeping the case; returns the bases converted
+pub fn rna_to_dna(seq: &mut [u8]) -> u64 {
+    let mut converted = 0;
+    for c in seq.iter_mut() {
+        if *c == b'U' || *c == b'u' {
+            *c -= b'U' - b'T';
+            converted += 1;
+        }
+    }
+    converted
+}
+
+// collapse runs of one base to a single base, for --hpc
+pub fn homopolymer_compress(seq: &[u8]) -> Vec<u8> {
+    let mut out = seq.to_vec();
+    out.dedup();
+    out
+}
+
 pub fn filter_out_a(seq: &[u8]) -> Vec<u8> {
     seq.iter()
     .copied()
@@ -54,15 +90,6 @@ pub fn filter_out_a(seq: &[u8]) -> Vec<u8> {
     .collect()
 }
 
-pub fn mask_bits(v: u64, k: usize) -> u64 {
-    let b = 2 * k as u32;
-    if b == 64 {
-        v
-    } else {
-        v & ((1u64 << b) - 1)
-    }
-}
-
 pub fn mask_aa_bits(v: u64, k: usize) -> u64 {
     let b = 5 * k as u32;
     if b == 0 {
@@ -80,32 +107,327 @@ fn bytes_to_sequence_aa(seq: &[u8]) -> SequenceAA {
     SequenceAA::from_str(s).expect("invalidAA sequence for SequenceAA")
 }
 
+// k-mer enumeration shared by sketching and exact verification: canonical k-mers,
+// 2 bits per base, handed to `f`
+pub fn for_each_kmer<F: FnMut(u64)>(seq: &[u8], kmer_length: usize, f: F) {
+    if kmer_length == 0 || kmer_length > 64 {
+        panic!("k-mer length must be 1–64");
+    }
+    roll_kmers(seq, kmer_length, true, f);
+}
+
+// k-mers as they read on the given strand, without canonicalization (--no-canonical)
+pub fn for_each_stranded_kmer<F: FnMut(u64)>(seq: &[u8], kmer_length: usize, f: F) {
+    if kmer_length == 0 || kmer_length > 64 {
+        panic!("k-mer length must be 1–64");
+    }
+    roll_kmers(seq, kmer_length, false, f);
+}
+
+// every k from 1 to 64 in one pass, rolling both strands 2 bits per base (A 0,
+// C 1, G 2, T 3, the packing kmerutils used, so sketches stay comparable) and
+// canonicalized unless stranded. Up to 32 bases roll in a u64 and are handed on
+// as packed, longer k-mers roll in a u128 and are folded to 64 bits by hashing.
+// Bases are encoded a block at a time, vectorized where the CPU allows
+fn roll_kmers<F: FnMut(u64)>(seq: &[u8], kmer_length: usize, canonical: bool, mut f: F) {
+    if seq.len() < kmer_length {
+        return;
+    }
+    let mut codes = [0u8; 256];
+    if kmer_length <= 32 {
+        let mask = u64::MAX >> (64 - 2 * kmer_length);
+        let shift = 2 * (kmer_length - 1);
+        let (mut forward, mut reverse, mut filled) = (0u64, 0u64, 0usize);
+        for block in seq.chunks(codes.len()) {
+            simd::encode(block, &mut codes);
+            for &code in &codes[..block.len()] {
+                if code > 3 {
+                    filled = 0;
+                    continue;
+                }
+                let code = code as u64;
+                forward = ((forward << 2) | code) & mask;
+                reverse = (reverse >> 2) | ((3 ^ code) << shift);
+                filled += 1;
+                if filled >= kmer_length {
+                    f(if canonical { forward.min(reverse) } else { forward });
+                }
+            }
+        }
+    } else {
+        let mask = u128::MAX >> (128 - 2 * kmer_length);
+        let shift = 2 * (kmer_length - 1);
+        let (mut forward, mut reverse, mut filled) = (0u128, 0u128, 0usize);
+        for block in seq.chunks(codes.len()) {
+            simd::encode(block, &mut codes);
+            for &code in &codes[..block.len()] {
+                if code > 3 {
+                    filled = 0;
+                    continue;
+                }
+                let code = code as u128;
+                forward = ((forward << 2) | code) & mask;
+                reverse = (reverse >> 2) | ((3 ^ code) << shift);
+                filled += 1;
+                if filled >= kmer_length {
+                    let kmer = if canonical { forward.min(reverse) } else { forward };
+                    f(xxh3_64(&kmer.to_le_bytes()));
+                }
+            }
+        }
+    }
+}
+
+// amino acid counterpart of for_each_kmer, `seq` must be uppercase
+pub fn for_each_aa_kmer<F: FnMut(u64)>(seq: &[u8], kmer_length: usize, mut f: F) {
+    if seq.len() < kmer_length {
+        return;
+    }
+
+    let seqaa = bytes_to_sequence_aa(&filter_out_a(seq));
+    if kmer_length <= 6 {
+        let mut it =
+            AAKmerSeqIterator::<KmerAA32bit>::new(kmer_length, &seqaa);
+        while let Some(km) = it.next() {
+            // no reverse complement for aa
+            f(mask_aa_bits(km.get_compressed_value() as u64, kmer_length));
+        }
+    }
+    else if kmer_length <= 12 {
+        let mut it =
+            AAKmerSeqIterator::<KmerAA64bit>::new(kmer_length, &seqaa);
+        while let Some(km) = it.next() {
+            f(mask_aa_bits(km.get_compressed_value(), kmer_length));
+        }
+    }
+    else {
+        panic!("k-mer length for amino acid must be 1–12");
+    }
+}
+
+// a sketch name of --per-contig, <file>:<record id>, as the file and the record;
+// names of existing files (even with a colon) are whole files
+pub fn split_record_name(name: &str) -> (&str, Option<&str>) {
+    if Path::new(name).exists() {
+        return (name, None);
+    }
+    match name.rsplit_once(':') {
+        Some((path, id)) if Path::new(path).exists() => (path, Some(id)),
+        _ => (name, None),
+    }
+}
+
+// the record id of a FASTA/FASTQ header, up to the first space
+pub fn record_id(header: &[u8]) -> String {
+    let header = String::from_utf8_lossy(header);
+    header.split_whitespace().next().unwrap_or_default().to_string()
+}
+
+// exact set of k-mers of a genome of a set, or of one record of its file for a
+// --per-contig name, used to verify top hits. The k-mers are selected by
+// sketch_sequences as for the sketches of the set (translation, HPC, strand, seeds
+// and filters alike), only collected whole instead of sketched
+pub fn kmer_set(entry: &Entry, params: &SketchParams) -> Result<HashSet<u64>, Box<dyn Error>> {
+    let (path, record) = match &entry.path {
+        // named by its file, <file>:<record id> with --per-contig
+        None => split_record_name(&entry.name),
+        // from a manifest, <sample name>:<record id> with --per-contig
+        Some(path) => (path.as_str(), entry.name.rsplit_once(':').filter(|_| params.options.per_contig).map(|(_, id)| id)),
+    };
+    // all files of the sample, as they were sketched together
+    let readers = std::iter::once(path)
+        .chain(entry.more.iter().map(String::as_str))
+        .map(open_fastx)
+        .collect::<Result<Vec<_>, _>>()?;
+    let options = SketchOptions {
+        count_min: None,
+        per_contig: record.is_some(),
+        ..params.options.for_input(path)
+    };
+    let entries = sketch_sequences::<ExactKmers>(readers, None, &[params.kmer_length], params.seed, params.aa, &options)?;
+    let mut kmers = HashSet::new();
+    for (name, sketched) in entries {
+        if record.is_none_or(|id| name == id) {
+            for (exact, _) in sketched {
+                kmers.extend(exact.0);
+            }
+        }
+    }
+    Ok(kmers)
+}
+
+// every k-mer added, kept whole: the "sketch" kmer_set collects with
+#[derive(Default)]
+struct ExactKmers(HashSet<u64>);
+
+impl KmerSketch for ExactKmers {
+    fn new(_precision: Option<u32>) -> Self {
+        ExactKmers::default()
+    }
+
+    fn add_kmer(&mut self, masked: u64, _seed: u64) {
+        self.0.insert(masked);
+    }
+
+    // the number of k-mers, then the k-mers in increasing order
+    fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        let mut kmers: Vec<u64> = self.0.iter().copied().collect();
+        kmers.sort_unstable();
+        writer.write_all(&(kmers.len() as u64).to_le_bytes())?;
+        for kmer in kmers {
+            writer.write_all(&kmer.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let mut word = [0u8; 8];
+        reader.read_exact(&mut word)?;
+        let mut kmers = HashSet::new();
+        for _ in 0..u64::from_le_bytes(word) {
+            reader.read_exact(&mut word)?;
+            kmers.insert(u64::from_le_bytes(word));
+        }
+        Ok(ExactKmers(kmers))
+    }
+
+    fn cardinality(&self) -> f64 {
+        self.0.len() as f64
+    }
+
+    fn merged(&self, other: &Self) -> Self {
+        ExactKmers(self.0.union(&other.0).copied().collect())
+    }
+
+    fn jaccard(&self, other: &Self) -> f64 {
+        exact_similarity(&self.0, &other.0).0
+    }
+
+    fn containment(&self, other: &Self) -> f64 {
+        exact_similarity(&self.0, &other.0).1
+    }
+
+    fn registers(&self) -> Vec<u16> {
+        Vec::new()
+    }
+}
+
+// exact Jaccard index and containment of query in reference
+pub fn exact_similarity(query: &HashSet<u64>, reference: &HashSet<u64>) -> (f64, f64) {
+    let (small, large) = if query.len() <= reference.len() {
+        (query, reference)
+    } else {
+        (reference, query)
+    };
+    let shared = small.iter().filter(|h| large.contains(*h)).count() as f64;
+    let union = query.len() as f64 + reference.len() as f64 - shared;
+    let jaccard = if union > 0.0 { shared / union } else { 0.0 };
+    let containment = if query.is_empty() { 0.0 } else { shared / query.len() as f64 };
+    (jaccard, containment)
+}
+
+// ANI of one pair computed by an external aligner (skani or fastANI),
+// NaN when the tool reports no alignment, e.g. for distant genomes
+pub fn external_ani(tool: &str, reference: &str, query: &str) -> Result<f64, Box<dyn Error>> {
+    let output = match tool {
+        "skani" => Command::new("skani")
+            .args(["dist", "-t", "1", "-r", reference, "-q", query])
+            .output()?,
+        "fastani" => Command::new("fastANI")
+            .args(["-t", "1", "-r", reference, "-q", query, "-o", "/dev/stdout"])
+            .output()?,
+        _ => return Err(format!("unknown ANI tool {}", tool).into()),
+    };
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned().into());
+    }
+
+    // skani prints a header line, ANI is column 3 for both tools
+    let stdout = String::from_utf8(output.stdout)?;
+    let line = stdout
+        .lines()
+        .filter(|l| !l.starts_with("Ref_file"))
+        .find(|l| !l.trim().is_empty());
+    match line {
+        Some(l) => Ok(l
+            .split('\t')
+            .nth(2)
+            .ok_or("unexpected ANI tool output")?
+            .trim()
+            .parse()?),
+        None => Ok(f64::NAN),
+    }
+}
+
+// commented first line of distance outputs, keeps result files self-describing
+pub fn metadata_header(params: &HashMap<String, String>, equation: u8) -> String {
+    let mut header = format!("# lash={}", env!("CARGO_PKG_VERSION"));
+    for key in ["k", "algorithm", "precision", "scaled", "sketch_size", "seed", "molecule"] {
+        if let Some(value) = params.get(key) {
+            header.push_str(&format!(" {}={}", key, value));
+        }
+    }
+    let model = if equation == 0 { "binomial" } else { "poisson" };
+    header.push_str(&format!(" model={}", model));
+    header
+}
+
+// identity implied by a containment fraction (as in mash screen): a k-mer survives
+// with probability ani^k
+pub fn containment_ani(containment: f64, kmer_length: usize) -> f64 {
+    containment.max(0.0).powf(1.0 / kmer_length as f64)
+}
+
+// function to compute distance from fraction
+pub fn compute_distance<F: Float>(frac: F, kmer_length: usize, equation: u8) -> F {
+    let k = F::from(kmer_length).unwrap();
+
+    match equation {
+        1 => (-frac.ln() / k).min(F::one()),
+        0 => F::one() - frac.powf(F::one() / k),
+        _ => panic!("model needs to be 0 or 1"),
+    }
+}
+
+// how `dist` estimates and reports the distances between two sketch sets
+#[derive(Clone, Default)]
+pub struct DistOptions {
+    // cardinality estimator: classic or improved for hll, fgra, ml or martingale for ull
+    pub estimator: String,
+    // hll registers kept compressed while loaded
+    pub compact: bool,
+    // column names sent first, for a matrix
+    pub create_matrix: bool,
+    // queries and references are the same set, each pair is computed once
+    pub same_files: bool,
+}
+
 // distances
 pub fn hmh_distance<F, T: Float>(
     reference_names: Vec<String>,
     ref_sketch_file: String,
     query_names: Vec<String>,
     query_sketch_file: String,
-    create_matrix: bool,
-    same_files: bool,
+    options: &DistOptions,
     emit: F,
 ) -> std::io::Result<()>
 where F: Fn(Vec<(&String, &String, T)>) + Send + Sync
 {
     fn read_sketches(file_name: &str, names: &Vec<String>) -> std::io::Result<Vec<Sketch>> {
-        let file = File::open(file_name).expect(&format!("Error opening {}", file_name));
+        let file = File::open(file_name)
+            .map_err(|e| std::io::Error::new(e.kind(), format!("Error opening {}: {}", file_name, e)))?;
         let reader = BufReader::new(file);
         let mut decoder = Decoder::new(reader).expect("failed to create decompress");
         let mut sketches = Vec::with_capacity(names.len());
         for _name in names {
-            let sketch = Sketch::load(&mut decoder)?;
+            let sketch = format::read_hmh(&mut decoder)?;
             sketches.push(sketch);
         }
         Ok(sketches)
     }
 
     let q_sketch_vec: Vec<Sketch> = read_sketches(&query_sketch_file, &query_names)
-        .expect(&format!("Error with reading from {}", query_sketch_file));
+        .map_err(|e| std::io::Error::new(e.kind(), format!("Error with reading from {}: {}", query_sketch_file, e)))?;
     let mut index = 0;
 
     // stable hasher to keep key order deterministic
@@ -117,7 +439,7 @@ where F: Fn(Vec<(&String, &String, T)>) + Send + Sync
     }
 
     let r_sketch_vec = read_sketches(&ref_sketch_file, &reference_names)
-        .expect(&format!("Error with reading from {}", ref_sketch_file));
+        .map_err(|e| std::io::Error::new(e.kind(), format!("Error with reading from {}: {}", ref_sketch_file, e)))?;
 
     let mut reference_sketches = HashMap::with_hasher(hasher.clone());
     index = 0;
@@ -129,19 +451,19 @@ where F: Fn(Vec<(&String, &String, T)>) + Send + Sync
     // send column names if printing matrix
     let mut file_idx: HashMap<&String, usize> = HashMap::new();
     
-    if same_files || create_matrix {
+    if options.same_files || options.create_matrix {
         let mut columns: Vec<(&String, &String, T)> = Vec::new();
         let blank_str = &"".to_string();
         for (i, q_name) in query_sketches.keys().enumerate() {
             // empty r_name string signals printing columns
-            if create_matrix {
+            if options.create_matrix {
                 columns.push((blank_str, q_name, T::one()));
             }
-            if same_files {
+            if options.same_files {
                 file_idx.insert(q_name, i);
             }
         }
-        if create_matrix {
+        if options.create_matrix {
             emit(columns);
         }
     }
@@ -155,7 +477,7 @@ where F: Fn(Vec<(&String, &String, T)>) + Send + Sync
         for q_name in query_sketches.keys() {
 
             // for triangular matrix purposes
-            if same_files && file_idx[q_name] > file_idx[ref_name] {
+            if options.same_files && file_idx[q_name] > file_idx[ref_name] {
                 continue;
             }
             let q_sketch = query_sketches[q_name];
@@ -188,9 +510,7 @@ pub fn ull_distance <F, T: Float>(
     ref_sketch_file: String,
     query_names: Vec<String>,
     query_sketch_file: String,
-    estimator: String,
-    create_matrix: bool,
-    same_files: bool,
+    options: &DistOptions,
     emit: F,
 )-> std::io::Result<()>
 where 
@@ -199,48 +519,59 @@ F: Fn(Vec<(&String, &String, T)>) + Send + Sync {
     let ref_sketch_file = File::open(ref_sketch_file).expect("Failed to open file");
     let query_sketch_file = File::open(query_sketch_file).expect("Failed to open file");
 
+    // sketch, its count with the chosen estimator and its fgra count, by name
+    type UllMap = HashMap<String, (UltraLogLog, f64, f64), Xxh3Builder>;
+
     fn create_ull_map(
         sketch_file: File,
         names: &Vec<String>,
-        estimator: &String,
-    ) -> Result<HashMap<String, (UltraLogLog, f64), Xxh3Builder>, std::io::Error>
+        estimator: &str,
+    ) -> Result<UllMap, std::io::Error>
     {
         let hasher = Xxh3Builder { seed: 93 };
         let mut sketches = HashMap::with_hasher(hasher);
         let reader = BufReader::new(sketch_file);
         let mut decoder = Decoder::new(reader).expect("failed to create decompressor");
         for file in names {
-            let ull = UltraLogLog::load(&mut decoder)?;
-            let c: f64 = match estimator.as_str() {
+            let (ull, martingale) = format::read_ull(&mut decoder)?;
+            let c: f64 = match estimator {
                 "fgra" => ull.get_distinct_count_estimate(),
                 "ml" => MaximumLikelihoodEstimator.estimate(&ull),
-                _ => panic!("estimator needs to be either fgra or ml"),
+                "martingale" => martingale.ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!(
+                            "{} has no martingale estimate (merged, downsized, or sketched before format version 3), use fgra or ml",
+                            file
+                        ),
+                    )
+                })?,
+                _ => panic!("estimator needs to be fgra, ml or martingale"),
             };
-            sketches.insert(file.clone(), (ull, c));
+            let fgra = ull.get_distinct_count_estimate();
+            sketches.insert(file.clone(), (ull, c, fgra));
         }
         Ok(sketches)
     }
 
-    let ref_map =
-        create_ull_map(ref_sketch_file, &reference_names, &estimator).unwrap();
-    let query_map =
-        create_ull_map(query_sketch_file, &query_names, &estimator).unwrap();
+    let ref_map = create_ull_map(ref_sketch_file, &reference_names, &options.estimator)?;
+    let query_map = create_ull_map(query_sketch_file, &query_names, &options.estimator)?;
 
     let mut file_idx: HashMap<&String, usize> = HashMap::new();
-    if same_files || create_matrix {
+    if options.same_files || options.create_matrix {
         let mut columns: Vec<(&String, &String, T)> = Vec::new();
         let blank = "".to_string();
         for (i, q_name) in query_map.keys().enumerate() {
             // empty r_name string signals printing columns
-            if create_matrix {
+            if options.create_matrix {
                 columns.push((&blank, q_name, T::one()));
             }
             // used for redundant distances
-            if same_files {
+            if options.same_files {
                 file_idx.insert(q_name, i);
             }
         }
-        if create_matrix {
+        if options.create_matrix {
             emit(columns);
         }
     }
@@ -253,7 +584,7 @@ F: Fn(Vec<(&String, &String, T)>) + Send + Sync {
         // loop through query sketches (j)
         for qry_name in query_map.keys() {
             // for redundant distances
-            if same_files && file_idx[qry_name] > file_idx[ref_name] {
+            if options.same_files && file_idx[qry_name] > file_idx[ref_name] {
                 continue;
             }
             let b: f64 = query_map[qry_name].1;
@@ -261,11 +592,15 @@ F: Fn(Vec<(&String, &String, T)>) + Send + Sync {
                 UltraLogLog::merge(&ref_map[ref_name].0, &query_map[qry_name].0)
                     .expect("failed to merge sketches");
 
-            //let union_count = union_ull.get_distinct_count_estimate();
-            let union_count: f64 = match estimator.as_str() {
-                "fgra" => union_ull.get_distinct_count_estimate(),
+            // a merged sketch has no martingale estimate: the fgra union is put on the
+            // martingale scale, so its errors still cancel against those of a and b
+            let union_count: f64 = match options.estimator.as_str() {
                 "ml" => MaximumLikelihoodEstimator.estimate(&union_ull),
-                _ => panic!("estimator needs to be either fgra or ml"),
+                "martingale" => {
+                    union_ull.get_distinct_count_estimate() * (a + b)
+                        / (ref_map[ref_name].2 + query_map[qry_name].2)
+                }
+                _ => union_ull.get_distinct_count_estimate(),
             };
 
             info!("Union: {}, a: {}, b: {}", union_count, a, b);
@@ -287,13 +622,110 @@ F: Fn(Vec<(&String, &String, T)>) + Send + Sync {
     Ok(())
 }
 
+// linear-counting thresholds of HLL++ (Heule et al. 2013), by precision from 4
+const HLLPP_THRESHOLDS: [f64; 15] = [
+    10.0, 20.0, 40.0, 80.0, 220.0, 400.0, 900.0, 1800.0, 3100.0, 6500.0, 11500.0, 20000.0,
+    50000.0, 120000.0, 350000.0,
+];
+
+// cardinality of an HLL: "classic" is the streaming_algorithms estimate, "improved"
+// is Ertl's improved raw estimator (2017), with linear counting below the HLL++
+// thresholds while registers are sparsely filled; not HLL++ itself, whose empirical
+// bias correction it makes unnecessary
+pub fn hll_estimate(hll: &HyperLogLog<i64>, estimator: &str) -> f64 {
+    match estimator {
+        "classic" => hll.len(),
+        "improved" => {
+            let (p, registers) = format::hll_registers(hll);
+            let m = registers.len() as f64;
+            let q = 64 - p as usize;
+            let mut histogram = vec![0f64; q + 2];
+            for &r in &registers {
+                histogram[(r as usize).min(q + 1)] += 1.0;
+            }
+            let zeros = histogram[0];
+            if zeros > 0.0 {
+                let linear = m * (m / zeros).ln();
+                if linear <= HLLPP_THRESHOLDS[(p as usize).saturating_sub(4).min(14)] {
+                    return linear;
+                }
+            }
+            // tau and sigma corrections for the saturated and empty registers
+            let tau = |x: f64| {
+                if x == 0.0 || x == 1.0 {
+                    return 0.0;
+                }
+                let (mut y, mut z, mut x) = (1.0, 1.0 - x, x);
+                loop {
+                    x = x.sqrt();
+                    let previous = z;
+                    y *= 0.5;
+                    z -= (1.0 - x).powi(2) * y;
+                    if previous == z {
+                        return z / 3.0;
+                    }
+                }
+            };
+            let sigma = |x: f64| {
+                if x == 1.0 {
+                    return f64::INFINITY;
+                }
+                let (mut y, mut z, mut x) = (1.0, x, x);
+                loop {
+                    x *= x;
+                    let previous = z;
+                    z += x * y;
+                    y += y;
+                    if previous == z {
+                        return z;
+                    }
+                }
+            };
+            let mut z = m * tau(1.0 - histogram[q + 1] / m);
+            for k in (1..=q).rev() {
+                z = 0.5 * (z + histogram[k]);
+            }
+            z += m * sigma(zeros / m);
+            m * m / (2.0 * std::f64::consts::LN_2 * z)
+        }
+        _ => panic!("estimator needs to be either classic or improved"),
+    }
+}
+
+// the HLL of the same k-mers at a lower precision `p`. Registers are indexed by the
+// low hash bits, so a register of the smaller sketch is the largest of those sharing
+// its low `p` bits; only a register whose hash bits above the index were all zero
+// takes its rank from the index bits that become part of the hash
+pub fn downsize_hll(hll: &HyperLogLog<i64>, p: u8) -> std::io::Result<HyperLogLog<i64>> {
+    let (old_p, registers) = format::hll_registers(hll);
+    if p > old_p {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("cannot raise HLL precision from {} to {}", old_p, p),
+        ));
+    }
+    let saturated = 65 - old_p;
+    let mut downsized = vec![0u8; 1 << p];
+    for (j, &r) in registers.iter().enumerate() {
+        let rank = if r == saturated {
+            let high = (j >> p) as u64;
+            let bits = 64 - high.leading_zeros() as u8;
+            64 - p - bits + 1
+        } else {
+            r
+        };
+        let target = &mut downsized[j & ((1 << p) - 1)];
+        *target = (*target).max(rank);
+    }
+    format::hll_from_registers(p, &downsized)
+}
+
 pub fn hll_distance<F, T: Float>(
     reference_names: Vec<String>,
     ref_sketch_file: String,
     query_names: Vec<String>,
     query_sketch_file: String,
-    create_matrix: bool,
-    same_files: bool,
+    options: &DistOptions,
     emit: F,
 ) -> std::io::Result<()>
 where F: Fn(Vec<(&String, &String, T)>) + Send + Sync {
@@ -303,7 +735,9 @@ where F: Fn(Vec<(&String, &String, T)>) + Send + Sync {
     fn create_ull_map(
         sketch_file: File,
         names: &Vec<String>,
-    ) -> Result<HashMap<String, (HyperLogLog<i64>, f64), Xxh3Builder>, std::io::Error> {
+        estimator: &str,
+        compact: bool,
+    ) -> Result<HashMap<String, (HllRegisters, f64), Xxh3Builder>, std::io::Error> {
         let hasher = Xxh3Builder { seed: 93 };
         let mut sketches = HashMap::with_hasher(hasher);
         let reader = BufReader::new(sketch_file);
@@ -311,30 +745,30 @@ where F: Fn(Vec<(&String, &String, T)>) + Send + Sync {
         // decompress sketches
         let mut decoder = Decoder::new(reader).expect("failed to create decompressor");
         for file in names {
-            let hll = HyperLogLog::load(&mut decoder)?;
-            let count = hll.len();
-            sketches.insert(file.clone(), (hll, count));
+            let hll = format::read_hll(&mut decoder)?;
+            let count = hll_estimate(&hll, estimator);
+            sketches.insert(file.clone(), (HllRegisters::new(hll, compact), count));
         }
         Ok(sketches)
     }
 
-    let ref_map = create_ull_map(ref_sketch_file, &reference_names).unwrap();
-    let query_map = create_ull_map(query_sketch_file, &query_names).unwrap();
+    let ref_map = create_ull_map(ref_sketch_file, &reference_names, &options.estimator, options.compact)?;
+    let query_map = create_ull_map(query_sketch_file, &query_names, &options.estimator, options.compact)?;
 
     let mut file_idx: HashMap<&String, usize> = HashMap::new();
-    if same_files || create_matrix {
+    if options.same_files || options.create_matrix {
         let mut columns: Vec<(&String, &String, T)> = Vec::new();
         let blank = &"".to_string();
         for (i, q_name) in query_map.keys().enumerate() {
             // empty r_name string signals printing columns
-            if create_matrix {
+            if options.create_matrix {
                 columns.push((blank, q_name, T::one()));
             }
-            if same_files {
+            if options.same_files {
                 file_idx.insert(q_name, i);
             }
         }
-        if create_matrix {
+        if options.create_matrix {
             emit(columns);
         }
     }
@@ -347,15 +781,13 @@ where F: Fn(Vec<(&String, &String, T)>) + Send + Sync {
         for qry_name in query_map.keys() {
 
             // for triangular matrix
-            if same_files && file_idx[qry_name] > file_idx[ref_name] {
+            if options.same_files && file_idx[qry_name] > file_idx[ref_name] {
                 continue;
             }
              // reference cardinality
             let b: f64 = query_map[qry_name].1; // query cardinality
-            let mut ref_hll = ref_map[ref_name].0.clone();
-            let q_hll = &query_map[qry_name].0;
-            ref_hll.union(q_hll);
-            let union_count = ref_hll.len();
+            let union = ref_map[ref_name].0.union(&query_map[qry_name].0).expect("invalid HLL registers");
+            let union_count = hll_estimate(&union, &options.estimator);
 
             info!("Union: {}, a: {}, b: {}", union_count, a, b);
 
@@ -373,16 +805,184 @@ where F: Fn(Vec<(&String, &String, T)>) + Send + Sync {
 }
 
 
-// sketch trait shared by HMH, ULL, and HLL
+// algorithms without a dedicated path above, through their Jaccard estimate
+pub fn jaccard_distance<F, T: Float>(
+    algorithm: &str,
+    reference_names: Vec<String>,
+    ref_sketch_file: String,
+    query_names: Vec<String>,
+    query_sketch_file: String,
+    options: &DistOptions,
+    emit: F,
+) -> std::io::Result<()>
+where F: Fn(Vec<(&String, &String, T)>) + Send + Sync
+{
+    match algorithm {
+        "hmh+hll" => kmer_sketch_distance::<crate::dual::HmhHll, F, T>(
+            reference_names, ref_sketch_file, query_names, query_sketch_file, options, emit,
+        ),
+        "ehll" => kmer_sketch_distance::<crate::ehll::ExtendedHll, F, T>(
+            reference_names, ref_sketch_file, query_names, query_sketch_file, options, emit,
+        ),
+        "fmh" => kmer_sketch_distance::<crate::minhash::FracMinHash, F, T>(
+            reference_names, ref_sketch_file, query_names, query_sketch_file, options, emit,
+        ),
+        "minhash" => kmer_sketch_distance::<crate::minhash::MinHash, F, T>(
+            reference_names, ref_sketch_file, query_names, query_sketch_file, options, emit,
+        ),
+        "superminhash" => kmer_sketch_distance::<crate::superminhash::SuperMinHash, F, T>(
+            reference_names, ref_sketch_file, query_names, query_sketch_file, options, emit,
+        ),
+        "theta" => kmer_sketch_distance::<crate::minhash::Theta, F, T>(
+            reference_names, ref_sketch_file, query_names, query_sketch_file, options, emit,
+        ),
+        "hyperbitbit" => kmer_sketch_distance::<crate::hyperbitbit::HyperBitBit, F, T>(
+            reference_names, ref_sketch_file, query_names, query_sketch_file, options, emit,
+        ),
+        "ordermh" => kmer_sketch_distance::<crate::ordermh::OrderMinHash, F, T>(
+            reference_names, ref_sketch_file, query_names, query_sketch_file, options, emit,
+        ),
+        "probminhash" => kmer_sketch_distance::<crate::weighted::ProbMinHash, F, T>(
+            reference_names, ref_sketch_file, query_names, query_sketch_file, options, emit,
+        ),
+        "dartminhash" => kmer_sketch_distance::<crate::weighted::DartMinHash, F, T>(
+            reference_names, ref_sketch_file, query_names, query_sketch_file, options, emit,
+        ),
+        other => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} sketches have a dedicated distance path", other),
+        )),
+    }
+}
+
+// any KmerSketch, through its Jaccard estimate
+fn kmer_sketch_distance<S, F, T: Float>(
+    reference_names: Vec<String>,
+    ref_sketch_file: String,
+    query_names: Vec<String>,
+    query_sketch_file: String,
+    options: &DistOptions,
+    emit: F,
+) -> std::io::Result<()>
+where
+    S: KmerSketch + Sync,
+    F: Fn(Vec<(&String, &String, T)>) + Send + Sync,
+{
+    let q_sketch_vec: Vec<S> = load_sketches(&query_sketch_file, query_names.len())?;
+    let r_sketch_vec: Vec<S> = load_sketches(&ref_sketch_file, reference_names.len())?;
+
+    // stable hasher to keep key order deterministic
+    let hasher = Xxh3Builder { seed: 93 };
+    let mut query_sketches = HashMap::with_hasher(hasher.clone());
+    for (name, sketch) in query_names.iter().zip(&q_sketch_vec) {
+        query_sketches.insert(name, sketch);
+    }
+    let mut reference_sketches = HashMap::with_hasher(hasher);
+    for (name, sketch) in reference_names.iter().zip(&r_sketch_vec) {
+        reference_sketches.insert(name, sketch);
+    }
+
+    // send column names if printing matrix
+    let mut file_idx: HashMap<&String, usize> = HashMap::new();
+    if options.same_files || options.create_matrix {
+        let mut columns: Vec<(&String, &String, T)> = Vec::new();
+        let blank_str = &"".to_string();
+        for (i, q_name) in query_sketches.keys().enumerate() {
+            // empty r_name string signals printing columns
+            if options.create_matrix {
+                columns.push((blank_str, q_name, T::one()));
+            }
+            if options.same_files {
+                file_idx.insert(q_name, i);
+            }
+        }
+        if options.create_matrix {
+            emit(columns);
+        }
+    }
+
+    reference_sketches.par_iter().for_each(|(ref_name, ref_sketch)| {
+        let mut ref_row: Vec<(&String, &String, T)> = Vec::new();
+        for (q_name, q_sketch) in query_sketches.iter() {
+            // for triangular matrix purposes
+            if options.same_files && file_idx[q_name] > file_idx[ref_name] {
+                continue;
+            }
+            let similarity = q_sketch.jaccard(ref_sketch).max(0.0);
+            let fraction = 2.0 * similarity / (1.0 + similarity);
+            let frac_t: T = T::from(fraction).expect("failed to convert to f64 or f32");
+            ref_row.push((ref_name, q_name, frac_t));
+        }
+        emit(ref_row);
+    });
+
+    Ok(())
+}
+
+// sketch trait shared by HMH, ULL, HLL, EHLL, their HMH+HLL pair, HyperBitBit, OrderMinHash, SuperMinHash, and the hash-list sketches of minhash.rs and weighted.rs
 pub trait KmerSketch: Send {
-    /// Create a new sketch
+    /// Create a new sketch, `precision` is the size parameter of the algorithm
+    /// (HLL/ULL precision, FracMinHash scale, sketch size of the other MinHash variants)
     fn new(precision: Option<u32>) -> Self;
 
     /// Add a masked k-mer
     fn add_kmer(&mut self, masked: u64, seed: u64);
 
+    /// Add a masked k-mer hashed ahead (in batches): `hash` is xxh3_64_with_seed of
+    /// its little-endian bytes, taken as is by sketches that hash k-mers that way
+    fn add_hashed(&mut self, masked: u64, _hash: u64, seed: u64) {
+        self.add_kmer(masked, seed);
+    }
+
     /// Serialize
     fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>>;
+
+    /// Deserialize one sketch from a (decompressed) stream
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> where Self: Sized;
+
+    /// Estimated number of distinct k-mers
+    fn cardinality(&self) -> f64;
+
+    /// Union of two sketches built with the same parameters
+    fn merged(&self, other: &Self) -> Self where Self: Sized;
+
+    /// Jaccard index estimate between two sketches, same estimators as `dist`
+    fn jaccard(&self, other: &Self) -> f64 where Self: Sized {
+        let a = self.cardinality();
+        let b = other.cardinality();
+        let union_count = self.merged(other).cardinality();
+        ((a + b - union_count) / union_count).max(0.0)
+    }
+
+    /// Fraction of the k-mers of `self` also found in `other`, not symmetric
+    fn containment(&self, other: &Self) -> f64 where Self: Sized {
+        let a = self.cardinality();
+        let b = other.cardinality();
+        let union_count = self.merged(other).cardinality();
+        if a <= 0.0 {
+            return 0.0;
+        }
+        ((a + b - union_count) / a).clamp(0.0, 1.0)
+    }
+
+    /// Estimated number of k-mers in either sketch
+    fn union_size(&self, other: &Self) -> f64 where Self: Sized {
+        self.merged(other).cardinality()
+    }
+
+    /// Estimated number of k-mers in both sketches
+    fn intersection_size(&self, other: &Self) -> f64 where Self: Sized {
+        let union_count = self.merged(other).cardinality();
+        (self.cardinality() + other.cardinality() - union_count).max(0.0)
+    }
+
+    /// Estimated number of k-mers of `self` not in `other`
+    fn difference_size(&self, other: &Self) -> f64 where Self: Sized {
+        (self.merged(other).cardinality() - other.cardinality()).max(0.0)
+    }
+
+    /// Register values in storage order, equal registers hint at shared k-mers
+    fn registers(&self) -> Vec<u16>;
 }
 
 
@@ -398,7 +998,39 @@ impl KmerSketch for Sketch {
     }
 
     fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
-        Ok(self.save(writer)?)
+        Ok(format::write_hmh(writer, self)?)
+    }
+
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        format::read_hmh(reader)
+    }
+
+    fn cardinality(&self) -> f64 {
+        Sketch::cardinality(self)
+    }
+
+    fn merged(&self, other: &Self) -> Self {
+        let mut union = self.clone();
+        union.union(other);
+        union
+    }
+
+    fn jaccard(&self, other: &Self) -> f64 {
+        self.similarity(other).max(0.0)
+    }
+
+    fn containment(&self, other: &Self) -> f64 {
+        let a = Sketch::cardinality(self);
+        if a <= 0.0 {
+            return 0.0;
+        }
+        (self.intersection(other) / a).clamp(0.0, 1.0)
+    }
+
+    fn registers(&self) -> Vec<u16> {
+        let mut bytes = Vec::new();
+        format::write_hmh(&mut bytes, self).expect("in-memory serialization cannot fail");
+        bytes.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect()
     }
 }
 
@@ -412,172 +1044,1671 @@ impl KmerSketch for HyperLogLog<i64> {
         self.push_hash64(xxh3_64_with_seed(&masked.to_le_bytes(), seed));
     }
 
+    fn add_hashed(&mut self, _masked: u64, hash: u64, _seed: u64) {
+        self.push_hash64(hash);
+    }
+
     fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
-        Ok(self.save(writer)?)
+        Ok(format::write_hll(writer, self)?)
     }
-}
 
-// sketching for UltraLogLog
-impl KmerSketch for UltraLogLog {
-    fn new(precision: Option<u32>) -> Self {
-        UltraLogLog::new(precision.expect("ULL needs precision"))
-            .expect("failed to create ULL")
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        format::read_hll(reader)
     }
 
-    fn add_kmer(&mut self, masked: u64, seed: u64) {
-        self.add(xxh3_64_with_seed(&masked.to_le_bytes(), seed));
+    fn cardinality(&self) -> f64 {
+        self.len()
     }
 
-    fn save<W: std::io::Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
-        Ok(self.save(writer)?)
+    fn merged(&self, other: &Self) -> Self {
+        let mut union = self.clone();
+        union.union(other);
+        union
     }
-}
 
-// general sketching function
-// Each file is processed to completion in its own task (no inner parallelism / no channels).
-// This is simple, avoids stack overflows, and matches the “parallel by sample” request.
-pub fn sketch_files <S: KmerSketch> (
-    precision: Option<u32>,
-    files: Vec<String>,
-    kmer_length: usize,
-    output_name: String,
-    threads: u32,
-    seed: u64,
-    aa: bool
-) -> Result<(), Box<dyn Error>> {
+    fn registers(&self) -> Vec<u16> {
+        format::hll_registers(self).1.into_iter().map(u16::from).collect()
+    }
+}
 
-    let sketches: Vec<S> = if ! aa { // genome sketching
-        files
-        .par_iter()
-        .map(|file_name| {
-            let mut reader = parse_fastx_file(file_name).expect("Invalid input file");
-            let mut sketch = S::new(precision);
-
-            // looping through each sequence in file
-            while let Some(res) = reader.next() {
-                if let Ok(seqrec) = res {
-                    let seq = filter_out_n(seqrec.seq().as_ref());
-                    if seq.len() < kmer_length {
-                        continue;
-                    }
+// input path standing for standard input
+pub const STDIN: &str = "-";
 
-                    let kseq = KSeq::new(&seq, 2);
-                    // kmers go through an iterator, canonicalized, masked, and added to the data structure
-                    if kmer_length <= 14 {
-                        let mut it =
-                            KmerSeqIterator::<Kmer32bit>::new(kmer_length as u8, &kseq);
-                        while let Some(km) = it.next() {
-                            let canon = km.min(km.reverse_complement());
-                            let masked = mask_bits(
-                                canon.get_compressed_value() as u64,
-                                kmer_length,
-                            );
-                            sketch.add_kmer(masked, seed);
-                        }
-                    }
-                    else if kmer_length == 16 {
-                        let mut it =
-                            KmerSeqIterator::<Kmer16b32bit>::new(16, &kseq);
-                        while let Some(km) = it.next() {
-                            let canon = km.min(km.reverse_complement());
-                            let masked = mask_bits(
-                                canon.get_compressed_value() as u64,
-                                kmer_length,
-                            );
-                            sketch.add_kmer(masked, seed);
-                        }
-                    }
-                    else if kmer_length <= 32 {
-                        let mut it =
-                            KmerSeqIterator::<Kmer64bit>::new(kmer_length as u8, &kseq);
-                        while let Some(km) = it.next() {
-                            let canon = km.min(km.reverse_complement());
-                            let masked =
-                                mask_bits(canon.get_compressed_value(), kmer_length);
-                            sketch.add_kmer(masked, seed);
-                        }
-                    }
-                    else {
-                        panic!("k-mer length must be 1–32");
-                    } 
-                    }
-                
-            }
+// FASTA/FASTQ parser for a file, for standard input when the path is "-", or
+// streamed from an http(s):// or ftp:// URL
+pub fn open_fastx(file_name: &str) -> Result<Box<dyn FastxReader>, ParseError> {
+    if file_name == STDIN {
+        parse_fastx_reader(compression::decoded(std::io::stdin())?)
+    } else if remote::is_remote(file_name) {
+        parse_fastx_reader(compression::decoded(remote::open(file_name)?)?)
+    } else {
+        parse_fastx_reader(compression::decoded(File::open(file_name)?)?)
+    }
+}
 
-            sketch
+// every local input opened and its format recognized before any is sketched, so
+// a bad path fails the run up front with all problems listed instead of panicking
+// a worker midway; URLs and standard input are read as they come
+pub fn check_inputs(samples: &[Sample]) -> Result<(), Box<dyn Error>> {
+    let paths: Vec<&String> = samples.iter().flat_map(|s| std::iter::once(&s.path).chain(&s.more)).collect();
+    let problems: Vec<String> = paths
+        .par_iter()
+        .filter(|path| path.as_str() != STDIN && !remote::is_remote(path))
+        .filter_map(|path| {
+            let problem = match fs::metadata(path.as_str()) {
+                Err(e) => e.to_string(),
+                Ok(meta) if meta.is_dir() => "is a directory".to_string(),
+                Ok(meta) if meta.len() == 0 => "is empty".to_string(),
+                Ok(_) => match open_fastx(path) {
+                    Err(e) => e.msg,
+                    Ok(_) => return None,
+                },
+            };
+            Some(format!("{}\t{}", path, problem))
         })
-        .collect()
+        .collect();
+    if problems.is_empty() {
+        return Ok(());
     }
-    else { // amino acid sketching
-        files
-        .par_iter()
-        .map(|file_name| {
-            let mut reader = parse_fastx_file(file_name).expect("Invalid input file");
-            let mut sketch: S = S::new(precision);
+    eprintln!("File\tProblem");
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    Err(format!("{} of {} inputs cannot be sketched, nothing was written", problems.len(), paths.len()).into())
+}
 
-            // looping through each sequence in the file
-            while let Some(res) = reader.next() {
-                if let Ok(seqrec) = res {
-                    let seq = seqrec.seq().to_ascii_uppercase();
+// one genome/sample to sketch: the name written to `_files.json` and where to read it
+pub struct Sample {
+    pub name: String,
+    pub path: String,
+    // more files of the sample (R2 of paired-end reads, other lanes), sketched
+    // with `path` into one sketch
+    pub more: Vec<String>,
+    // what `_files.json` records as read when `path` is a temporary copy
+    pub source: Option<String>,
+}
 
-                    if seq.len() < kmer_length {
-                        continue;
-                    }
+impl Sample {
+    // samples of a file list are named by their path; a line with R1 and R2
+    // separated by a tab is one paired-end sample, named by R1
+    pub fn from_path(path: String) -> Self {
+        match path.split_once('\t') {
+            Some((r1, r2)) if !r2.trim().is_empty() => Sample {
+                name: r1.trim().to_string(),
+                path: r1.trim().to_string(),
+                more: vec![r2.trim().to_string()],
+                source: None,
+            },
+            _ => Sample { name: path.clone(), path, more: Vec::new(), source: None },
+        }
+    }
 
-                    let seqaa = bytes_to_sequence_aa(&filter_out_a(&seq));
-                    
-                    if kmer_length <= 6 {
-                        let mut it =
-                            AAKmerSeqIterator::<KmerAA32bit>::new(kmer_length, &seqaa);
-                        while let Some(km) = it.next() {
-                            let masked = mask_aa_bits( // no reverse complement for aa
-                                km.get_compressed_value() as u64,
-                                kmer_length,
-                            );
-                            sketch.add_kmer(masked, seed);
-                        }
-                    }
-                    else if kmer_length <= 12 {
-                        let mut it =
-                            AAKmerSeqIterator::<KmerAA64bit>::new(kmer_length, &seqaa);
-
-                        while let Some(km) = it.next() {
-                            let packed = km.get_compressed_value();
-                            let masked = mask_aa_bits(
-                                packed as u64,
-                                kmer_length,
-                            );
-                            sketch.add_kmer(masked, seed);
-                        }
-                    }
-                    else {
-                        panic!("k-mer length for amino acid must be 1–12");
-                    };
-                }
-                
+    // a manifest line: name and one or more paths (R1 and R2, lanes), separated
+    // by tabs; all the files of a line are unioned into the sample's sketch
+    pub fn from_manifest(line: &str) -> Result<Self, Box<dyn Error>> {
+        let mut fields = line.split('\t').map(str::trim).filter(|f| !f.is_empty());
+        match (fields.next(), fields.next()) {
+            (Some(name), Some(path)) => {
+                let more = fields.map(String::from).collect();
+                Ok(Sample { name: name.to_string(), path: path.to_string(), more, source: None })
             }
+            _ => Err(format!("expected <sample name>\t<path>[\t<path>...], got: {}", line).into()),
+        }
+    }
+}
 
-            sketch
-        })
-        .collect()
-    };
-    
+// samples of a manifest, one per line; blank lines and lines starting with # ignored
+pub fn read_manifest(file_name: &str) -> Result<Vec<Sample>, Box<dyn Error>> {
+    let mut samples = Vec::new();
+    let mut names = HashSet::new();
+    for (i, line) in BufReader::new(File::open(file_name)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let sample = Sample::from_manifest(&line).map_err(|e| format!("{} line {}: {}", file_name, i + 1, e))?;
+        if !names.insert(sample.name.clone()) {
+            return Err(format!("{} line {}: sample name {} is given twice", file_name, i + 1, sample.name).into());
+        }
+        samples.push(sample);
+    }
+    Ok(samples)
+}
 
-    // write sketches
-    let writer = BufWriter::new(File::create(format!("{}_sketches.bin", output_name))?);
+// FASTA/FASTQ names, optionally compressed
+const EXTENSIONS: [&str; 7] = ["fa", "fasta", "fna", "ffn", "faa", "fq", "fastq"];
+const COMPRESSIONS: [&str; 6] = ["gz", "bz2", "xz", "lz4", "zst", "zstd"];
+
+// lowercase extension of a file name, compression suffix removed
+fn file_extension(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    let mut parts: Vec<&str> = name.rsplit('.').collect();
+    if parts.len() > 1 && COMPRESSIONS.contains(&parts[0]) {
+        parts.remove(0);
+    }
+    (parts.len() > 1).then(|| parts[0].to_string())
+}
+
+fn sequence_extension(path: &Path) -> Option<String> {
+    file_extension(path).filter(|ext| EXTENSIONS.contains(&ext.as_str()))
+}
+
+pub fn is_sequence_file(path: &Path) -> bool {
+    sequence_extension(path).is_some()
+}
+
+pub fn is_fastq(file_name: &str) -> bool {
+    sequence_extension(Path::new(file_name)).is_some_and(|e| e == "fq" || e == "fastq")
+}
+
+// relative standard error of a cardinality times sqrt(registers)
+fn error_constant(algorithm: &str) -> Option<f64> {
+    match algorithm {
+        "hll" | "hmh+hll" => Some(1.04),
+        "ehll" => Some(0.90),
+        "ull" => Some(0.78),
+        _ => None,
+    }
+}
+
+// `--precision auto`: the lowest precision whose relative standard error is within
+// `target_error`, capped at what the largest input needs, since registers beyond
+// its number of k-mers stay empty. File sizes bound the k-mer counts from above,
+// compressed files as holding four times their size in sequence; stdin is not capped
+pub fn auto_precision(algorithm: &str, target_error: f64, samples: &[Sample]) -> Result<usize, String> {
+    let constant = error_constant(algorithm)
+        .ok_or_else(|| format!("--precision auto only applies to hll, hmh+hll, ehll and ull, not {}", algorithm))?;
+    if !(target_error > 0.0 && target_error < 1.0) {
+        return Err(format!("--target-error must be between 0 and 1, got {}", target_error));
+    }
+    let registers = (constant / target_error).powi(2);
+    let mut precision = (registers.log2().ceil() as usize).clamp(4, 16);
+
+    let mut largest = 0u64;
+    for sample in samples {
+        if sample.path == STDIN {
+            return Ok(precision);
+        }
+        let size = std::fs::metadata(&sample.path).map_err(|e| format!("{}: {}", sample.path, e))?.len();
+        let compressed = Path::new(&sample.path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| COMPRESSIONS.contains(&e.to_ascii_lowercase().as_str()));
+        largest = largest.max(if compressed { size.saturating_mul(4) } else { size });
+    }
+    if largest > 0 {
+        precision = precision.min((largest as f64).log2().ceil().max(4.0) as usize);
+    }
+    Ok(precision)
+}
+
+// locate the parameter, name and sketch files written for a prefix
+pub fn find_files(prefix: &str) -> std::io::Result<HashMap<&'static str, String>> {
+    // exact names first, prefixes may share a stem (shard_1 and shard_10)
+    let exact = [
+        ("params", format!("{}_parameters.json", prefix)),
+        ("files", format!("{}_files.json", prefix)),
+        ("sketches", format!("{}_sketches.bin", prefix)),
+    ];
+    if exact.iter().all(|(_, f)| Path::new(f).is_file()) {
+        return Ok(exact.into_iter().collect());
+    }
+
+    let mut files: Vec<String> = Vec::new();
+    let dir = "./"; // use curent directory
+
+    let norm_prefix = {
+        let p = Path::new(prefix)
+            .file_name()
+            .and_then(|os_str| os_str.to_str())
+            .unwrap_or(prefix);
+
+        p.strip_prefix("./").unwrap_or(p)
+    };
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        // if file starts with prefix, add it to vector of possible reference/ sketch files
+        if path.is_file() {
+            if let Some(filename) = path.file_name().and_then(|n| n.to_str()) {
+                if filename.starts_with(norm_prefix) {
+                    files.push(filename.to_string());
+                }
+            }
+        }
+    }
+
+    let mut file_map: HashMap<&str, String> = HashMap::new();
+    for file in files {
+        if file.ends_with("parameters.json") {
+            file_map.insert("params", file);
+        } else if file.ends_with("files.json") {
+            file_map.insert("files", file);
+        } else if file.ends_with(".bin") {
+            // .bin file for sketches
+            file_map.insert("sketches", file);
+        }
+    }
+    if file_map.keys().len() != 3 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("There should be 3 files starting with {} but {} were found instead", norm_prefix, file_map.keys().len()),
+        ));
+    }
+    Ok(file_map)
+}
+
+// list of input files, one per line, blank lines ignored
+pub fn read_file_list(file_name: &str) -> std::io::Result<Vec<String>> {
+    let f = File::open(file_name)?;
+    Ok(BufReader::new(f)
+        .lines()
+        .map_while(Result::ok)
+        .filter(|l| !l.trim().is_empty())
+        .collect())
+}
+
+// `sketch -f`: a directory (its sequence files, in subdirectories too when
+// recursive), a glob pattern (* ? and ** for any depth), a tar archive, or a list
+// file as above, read from standard input for "-";
+// the files found are sorted so runs list them in the same order
+fn is_glob(input: &str) -> bool {
+    input.contains(['*', '?']) && !Path::new(input).exists()
+}
+
+// a directory or glob for -f, whose files are only known once scanned
+pub fn is_scanned(input: &str) -> bool {
+    Path::new(input).is_dir() || is_glob(input)
+}
+
+pub fn read_inputs(input: &str, recursive: bool, extensions: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+    let kept = |path: &Path| {
+        match extensions.is_empty() {
+            true => is_sequence_file(path),
+            false => file_extension(path).is_some_and(|ext| extensions.iter().any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&ext))),
+        }
+    };
+    let mut files = Vec::new();
+    if input == STDIN {
+        // paths piped in, from find or ls
+        return Ok(std::io::stdin()
+            .lock()
+            .lines()
+            .map_while(Result::ok)
+            .filter(|l| !l.trim().is_empty())
+            .collect());
+    } else if Path::new(input).is_dir() {
+        list_directory(Path::new(input), recursive, &mut |path| {
+            if kept(path) {
+                files.push(path.to_string_lossy().into_owned());
+            }
+        })?;
+        if files.is_empty() {
+            return Err(format!("no sequence files in directory {}", input).into());
+        }
+    } else if is_glob(input) {
+        let (root, pattern) = match input.starts_with('/') {
+            true => (Path::new("/"), &input[1..]),
+            false => (Path::new(""), input),
+        };
+        let parts: Vec<&str> = pattern.split('/').filter(|p| !p.is_empty()).collect();
+        expand_glob(root, &parts, &mut files)?;
+        if !extensions.is_empty() {
+            files.retain(|f| kept(Path::new(f)));
+        }
+        if files.is_empty() {
+            return Err(format!("no files match {}", input).into());
+        }
+    } else if archive::is_archive(input) {
+        return Ok(vec![input.to_string()]);
+    } else {
+        return Ok(read_file_list(input)?);
+    }
+    files.sort_unstable();
+    files.dedup();
+    Ok(files)
+}
+
+fn list_directory(dir: &Path, recursive: bool, f: &mut dyn FnMut(&Path)) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive {
+                list_directory(&path, recursive, f)?;
+            }
+        } else {
+            f(&path);
+        }
+    }
+    Ok(())
+}
+
+// files under `dir` matching the remaining pattern components
+fn expand_glob(dir: &Path, parts: &[&str], files: &mut Vec<String>) -> std::io::Result<()> {
+    let Some((&part, rest)) = parts.split_first() else {
+        return Ok(());
+    };
+    let listed = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    if part == "**" {
+        // no directory at all, or any subdirectory
+        expand_glob(dir, rest, files)?;
+        for entry in fs::read_dir(listed)? {
+            let path = dir.join(entry?.file_name());
+            if path.is_dir() {
+                expand_glob(&path, parts, files)?;
+            }
+        }
+        return Ok(());
+    }
+    if !part.contains(['*', '?']) {
+        let path = dir.join(part);
+        match rest.is_empty() {
+            true if path.is_file() => files.push(path.to_string_lossy().into_owned()),
+            false if path.is_dir() => expand_glob(&path, rest, files)?,
+            _ => {}
+        }
+        return Ok(());
+    }
+    for entry in fs::read_dir(listed)? {
+        let name = entry?.file_name();
+        let Some(name) = name.to_str() else { continue };
+        if name.starts_with('.') && !part.starts_with('.') || !wildcard_match(part.as_bytes(), name.as_bytes()) {
+            continue;
+        }
+        let path = dir.join(name);
+        match rest.is_empty() {
+            true if path.is_file() => files.push(path.to_string_lossy().into_owned()),
+            false if path.is_dir() => expand_glob(&path, rest, files)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// `*` any run of characters, `?` any one
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((sp, sn)) = star {
+            p = sp + 1;
+            n = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+// read in names of the genomes, outputs a vector of names
+pub fn read_names(file_name: &str) -> std::io::Result<Vec<String>> {
+    Ok(read_entries(file_name)?.into_iter().map(|entry| entry.name).collect())
+}
+
+// a genome of a sketch set: its name and, when the name is not the path (a
+// manifest), the file it was read from, with the other files of the sample
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Entry {
+    pub name: String,
+    pub path: Option<String>,
+    // R2 of paired-end reads, other lanes
+    pub more: Vec<String>,
+}
+
+impl Entry {
+    // a genome named by its file
+    pub fn named(name: String) -> Self {
+        Entry { name, path: None, more: Vec::new() }
+    }
+
+    // the (first) file the genome was read from
+    pub fn file(&self) -> &str {
+        self.path.as_deref().unwrap_or(&self.name)
+    }
+
+    // every file the genome was read from
+    pub fn files(&self) -> Vec<&str> {
+        std::iter::once(self.file()).chain(self.more.iter().map(String::as_str)).collect()
+    }
+}
+
+// names of the genomes with the files they were read from, where recorded: a
+// `_files.json` holds names, or {"name", "path"} objects for sets sketched from
+// a manifest or from read pairs, with "more" listing the other files of a sample
+pub fn read_entries(file_name: &str) -> std::io::Result<Vec<Entry>> {
+    let reader = BufReader::new(File::open(file_name)?);
+    let entries: Vec<serde_json::Value> = serde_json::from_reader(reader)?;
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, format!("invalid entry in {}", file_name));
+    entries
+        .into_iter()
+        .map(|entry| match entry {
+            serde_json::Value::String(name) => Ok(Entry::named(name)),
+            serde_json::Value::Object(mut pair) => match (pair.remove("name"), pair.remove("path"), pair.remove("more")) {
+                (Some(serde_json::Value::String(name)), Some(serde_json::Value::String(path)), more) => {
+                    let more = match more {
+                        None => Vec::new(),
+                        Some(more) => serde_json::from_value(more).map_err(|_| invalid())?,
+                    };
+                    Ok(Entry { name, path: Some(path), more })
+                }
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        })
+        .collect()
+}
+
+// the entries of a `_files.json` by genome name
+pub fn entries_by_name(file_name: &str) -> std::io::Result<HashMap<String, Entry>> {
+    Ok(read_entries(file_name)?.into_iter().map(|entry| (entry.name.clone(), entry)).collect())
+}
+
+// `_files.json` of a set, as name/path pairs when any genome has a path
+pub fn write_entries(file_name: &str, entries: &[Entry]) -> std::io::Result<()> {
+    let file = File::create(file_name)?;
+    if entries.iter().all(|entry| entry.path.is_none() && entry.more.is_empty()) {
+        let names: Vec<&String> = entries.iter().map(|entry| &entry.name).collect();
+        return Ok(to_writer_pretty(file, &names)?);
+    }
+    let pairs: Vec<serde_json::Value> = entries
+        .iter()
+        .map(|entry| {
+            let mut pair = serde_json::json!({ "name": entry.name, "path": entry.file() });
+            if !entry.more.is_empty() {
+                pair["more"] = serde_json::json!(entry.more);
+            }
+            pair
+        })
+        .collect();
+    Ok(to_writer_pretty(file, &pairs)?)
+}
+
+// the samples as sketched, name and path(s) each, so a directory scan or glob
+// can be repeated with exactly the same files
+pub fn write_inputs(file_name: &str, samples: &[Sample]) -> std::io::Result<()> {
+    let entries: Vec<serde_json::Value> = samples
+        .iter()
+        .map(|s| {
+            let mut entry = serde_json::json!({ "name": s.name, "path": s.source.as_ref().unwrap_or(&s.path) });
+            if !s.more.is_empty() {
+                entry["more"] = serde_json::json!(s.more);
+            }
+            entry
+        })
+        .collect();
+    Ok(to_writer_pretty(File::create(file_name)?, &entries)?)
+}
+
+// read in a parameter json file into a hashmap
+pub fn read_params(file_name: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let contents = fs::read_to_string(file_name)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+// parameter file key of the size parameter of an algorithm, passed to `KmerSketch::new`
+pub fn size_key(algorithm: &str) -> Option<&'static str> {
+    match algorithm {
+        "hll" | "hmh+hll" | "ehll" | "ull" => Some("precision"),
+        "fmh" => Some("scaled"),
+        "minhash" | "superminhash" | "theta" | "ordermh" | "probminhash" | "dartminhash" => Some("sketch_size"),
+        _ => None,
+    }
+}
+
+// sketching parameters recorded in _parameters.json
+pub struct SketchParams {
+    pub kmer_length: usize,
+    pub algorithm: String,
+    pub precision: Option<u32>,
+    pub seed: u64,
+    pub aa: bool,
+    pub options: SketchOptions,
+}
+
+// sketching options beyond k, algorithm, size, seed and molecule; recorded in
+// _parameters.json so sets are updated and queried the way they were built
+#[derive(Clone, Default)]
+pub struct SketchOptions {
+    // width of the Count-Min sketch kept next to each sketch
+    pub count_min: Option<usize>,
+    // one sketch per record, named <file>:<record id>
+    pub per_contig: bool,
+    // k-mers of FASTQ records seen fewer times are left out, 0 and 1 keep all
+    pub min_count: u32,
+    // count exactly in a hash table instead of a Bloom filter (feature exact-counts)
+    pub exact_counts: bool,
+    // k-mers of any input seen fewer times are left out, counted exactly (mash -m)
+    pub min_copies: u32,
+    // spaced seeds instead of contiguous k-mers, k is the mask's span
+    pub seed_mask: Option<SeedMask>,
+    // sketch only the minimizers of windows of this many k-mers
+    pub minimizer_window: Option<usize>,
+    // sketch only open or closed syncmers
+    pub syncmer: Option<SyncmerScheme>,
+    // strobemers instead of k-mers, each strobe a k-mer
+    pub strobemer: Option<StrobeScheme>,
+    // nucleotide records translated in six frames, sketched as amino-acid k-mers
+    pub translate: bool,
+    // homopolymer runs collapsed before k-mers are taken
+    pub hpc: bool,
+    // k-mers hashed with ntHash instead of packed 2 bits per base
+    pub nthash: bool,
+    // records read as circular, k-mers across the end/start junction included
+    pub circular: bool,
+    // lowercase (soft-masked) stretches left out, records cut around them
+    pub exclude_softmasked: bool,
+    // low-complexity k-mers (DUST score above 2) left out
+    pub filter_low_complexity: bool,
+    // k-mers of these sequences left out; the files below are loaded by
+    // SketchParams::from_map, which knows k and the seed
+    pub blocklist: Option<KmerList>,
+    // only k-mers of these sequences sketched
+    pub include_fasta: Option<KmerList>,
+    // only these regions of the records sketched
+    pub include_bed: Option<Regions>,
+    // only k-mers hashing below 2^64 / scaled reach hll, ull and ehll sketches
+    pub subsample: Option<u64>,
+    // what becomes of bases other than ACGT
+    pub ambiguous: AmbiguityPolicy,
+    // such bases met in this input under a policy other than drop, not recorded
+    pub ambiguous_bases: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    // U bases of RNA records read as T in this input, not recorded
+    pub rna_bases: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    // records shorter or longer than these are skipped
+    pub min_seq_len: usize,
+    pub max_seq_len: Option<usize>,
+    // records of this input skipped for their length, not recorded
+    pub skipped_records: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    // k-mers kept as read instead of canonical, for strand-specific data
+    pub stranded: bool,
+    // FASTQ bases below this Phred score cut records into separate stretches
+    pub min_qual: u8,
+    // FASTQ records below this mean Phred score are skipped
+    pub min_mean_qual: u8,
+    // bases cut from the start and the end of FASTQ records, then the length they are cropped to
+    pub trim_head: usize,
+    pub trim_tail: usize,
+    pub crop: Option<usize>,
+    // fraction of FASTQ reads sketched, drawn from the read names and the seed
+    pub read_fraction: Option<f64>,
+    // records read in pairs of mates, filtered together, and the mates sketched
+    pub interleaved: Option<Mates>,
+    // size of the file being sketched, not recorded
+    pub input_bytes: Option<u64>,
+    // read back the samples checkpointed by an interrupted run, not recorded
+    pub resume: bool,
+}
+
+// mates of interleaved read pairs that are sketched
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mates {
+    Both,
+    First,
+    Second,
+}
+
+impl Mates {
+    pub fn parse(mates: &str) -> Result<Self, Box<dyn Error>> {
+        match mates {
+            "both" => Ok(Mates::Both),
+            "1" => Ok(Mates::First),
+            "2" => Ok(Mates::Second),
+            _ => Err(format!("unknown mates {}, expected both, 1 or 2", mates).into()),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mates::Both => "both",
+            Mates::First => "1",
+            Mates::Second => "2",
+        }
+    }
+}
+
+impl SketchOptions {
+    pub fn from_map(map: &HashMap<String, String>) -> Result<Self, Box<dyn Error>> {
+        Ok(SketchOptions {
+            count_min: map.get("count_min").map(|w| w.parse()).transpose()?,
+            per_contig: map.get("per_contig").is_some_and(|v| v == "true"),
+            min_count: map.get("min_count").map(|c| c.parse()).transpose()?.unwrap_or(1),
+            exact_counts: map.get("exact_counts").is_some_and(|v| v == "true"),
+            min_copies: map.get("min_copies").map(|c| c.parse()).transpose()?.unwrap_or(1),
+            seed_mask: map.get("seed_mask").map(|m| SeedMask::parse(m)).transpose()?,
+            minimizer_window: map.get("minimizer_window").map(|w| w.parse()).transpose()?,
+            syncmer: map.get("syncmer").map(|s| SyncmerScheme::parse(s)).transpose()?,
+            strobemer: match (map.get("seed_type"), map.get("strobe_window")) {
+                (Some(seed_type), Some(window)) => Some(StrobeScheme::new(seed_type, window)?),
+                _ => None,
+            },
+            translate: map.get("translate").is_some_and(|v| v == "true"),
+            hpc: map.get("hpc").is_some_and(|v| v == "true"),
+            nthash: map.get("hash").is_some_and(|h| h == "nthash"),
+            circular: map.get("circular").is_some_and(|v| v == "true"),
+            exclude_softmasked: map.get("exclude_softmasked").is_some_and(|v| v == "true"),
+            filter_low_complexity: map.get("filter_low_complexity").is_some_and(|v| v == "true"),
+            blocklist: None,
+            include_fasta: None,
+            include_bed: None,
+            // scaled is the size parameter of fmh, a subsampling rate for the others
+            subsample: match map.get("algorithm").map(String::as_str) {
+                Some("fmh") => None,
+                _ => map.get("scaled").map(|s| s.parse()).transpose()?,
+            },
+            ambiguous: map.get("ambiguous").map(|p| AmbiguityPolicy::parse(p)).transpose()?.unwrap_or_default(),
+            ambiguous_bases: Default::default(),
+            rna_bases: Default::default(),
+            min_seq_len: map.get("min_seq_len").map(|n| n.parse()).transpose()?.unwrap_or(0),
+            max_seq_len: map.get("max_seq_len").map(|n| n.parse()).transpose()?,
+            skipped_records: Default::default(),
+            stranded: map.get("canonical").is_some_and(|v| v == "false"),
+            min_qual: map.get("min_qual").map(|q| q.parse()).transpose()?.unwrap_or(0),
+            min_mean_qual: map.get("min_mean_qual").map(|q| q.parse()).transpose()?.unwrap_or(0),
+            trim_head: map.get("trim_head").map(|n| n.parse()).transpose()?.unwrap_or(0),
+            trim_tail: map.get("trim_tail").map(|n| n.parse()).transpose()?.unwrap_or(0),
+            crop: map.get("crop").map(|n| n.parse()).transpose()?,
+            read_fraction: map.get("subsample").map(|f| f.parse()).transpose()?,
+            interleaved: map.get("interleaved").map(|m| Mates::parse(m)).transpose()?,
+            input_bytes: None,
+            resume: false,
+        })
+    }
+
+    // the options that differ from the defaults, as parameter file keys
+    pub fn record(&self, params: &mut serde_json::Value) {
+        if let Some(width) = self.count_min {
+            params["count_min"] = serde_json::json!(width.to_string());
+        }
+        if self.per_contig {
+            params["per_contig"] = serde_json::json!("true");
+        }
+        if self.min_count > 1 {
+            params["min_count"] = serde_json::json!(self.min_count.to_string());
+        }
+        if self.exact_counts {
+            params["exact_counts"] = serde_json::json!("true");
+        }
+        if self.min_copies > 1 {
+            params["min_copies"] = serde_json::json!(self.min_copies.to_string());
+        }
+        if let Some(mask) = &self.seed_mask {
+            params["seed_mask"] = serde_json::json!(mask.as_str());
+        }
+        if let Some(window) = self.minimizer_window {
+            params["minimizer_window"] = serde_json::json!(window.to_string());
+        }
+        if let Some(scheme) = &self.syncmer {
+            params["syncmer"] = serde_json::json!(scheme.to_string());
+        }
+        if let Some(scheme) = &self.strobemer {
+            params["seed_type"] = serde_json::json!(scheme.seed_type());
+            params["strobe_window"] = serde_json::json!(format!("{},{}", scheme.window.0, scheme.window.1));
+        }
+        if self.translate {
+            params["translate"] = serde_json::json!("true");
+        }
+        if self.hpc {
+            params["hpc"] = serde_json::json!("true");
+        }
+        if self.circular {
+            params["circular"] = serde_json::json!("true");
+        }
+        if self.nthash {
+            params["hash"] = serde_json::json!("nthash");
+        }
+        if self.exclude_softmasked {
+            params["exclude_softmasked"] = serde_json::json!("true");
+        }
+        if self.filter_low_complexity {
+            params["filter_low_complexity"] = serde_json::json!("true");
+        }
+        if let Some(blocklist) = &self.blocklist {
+            params["blocklist"] = serde_json::json!(blocklist.path);
+        }
+        if let Some(include) = &self.include_fasta {
+            params["include_fasta"] = serde_json::json!(include.path);
+        }
+        if let Some(regions) = &self.include_bed {
+            params["include_bed"] = serde_json::json!(regions.path);
+        }
+        if let Some(scaled) = self.subsample {
+            params["scaled"] = serde_json::json!(scaled.to_string());
+        }
+        if self.ambiguous != AmbiguityPolicy::Drop {
+            params["ambiguous"] = serde_json::json!(self.ambiguous.as_str());
+        }
+        if self.stranded {
+            params["canonical"] = serde_json::json!("false");
+        }
+        if self.min_qual > 0 {
+            params["min_qual"] = serde_json::json!(self.min_qual.to_string());
+        }
+        if self.min_mean_qual > 0 {
+            params["min_mean_qual"] = serde_json::json!(self.min_mean_qual.to_string());
+        }
+        if self.trim_head > 0 {
+            params["trim_head"] = serde_json::json!(self.trim_head.to_string());
+        }
+        if self.trim_tail > 0 {
+            params["trim_tail"] = serde_json::json!(self.trim_tail.to_string());
+        }
+        if let Some(crop) = self.crop {
+            params["crop"] = serde_json::json!(crop.to_string());
+        }
+        if let Some(fraction) = self.read_fraction {
+            params["subsample"] = serde_json::json!(fraction.to_string());
+        }
+        if let Some(mates) = self.interleaved {
+            params["interleaved"] = serde_json::json!(mates.as_str());
+        }
+        if self.min_seq_len > 0 {
+            params["min_seq_len"] = serde_json::json!(self.min_seq_len.to_string());
+        }
+        if let Some(max) = self.max_seq_len {
+            params["max_seq_len"] = serde_json::json!(max.to_string());
+        }
+    }
+
+    // contiguous dna k-mers as the options take them: ntHash values or packed
+    // k-mers, canonical unless stranded
+    pub fn for_each_dna_kmer<F: FnMut(u64)>(&self, seq: &[u8], kmer_length: usize, f: F) {
+        if self.nthash {
+            nthash::for_each_hash(seq, kmer_length, !self.stranded, f);
+        } else if self.stranded {
+            for_each_stranded_kmer(seq, kmer_length, f);
+        } else {
+            for_each_kmer(seq, kmer_length, f);
+        }
+    }
+
+    // the bases of a FASTQ record of `length` left after trimming, start and end
+    pub fn trimmed(&self, length: usize) -> (usize, usize) {
+        let first = self.trim_head.min(length);
+        let last = length.saturating_sub(self.trim_tail).max(first);
+        (first, self.crop.map_or(last, |crop| last.min(first + crop)))
+    }
+
+    // the same options for one input file
+    pub fn for_input(&self, path: &str) -> Self {
+        SketchOptions {
+            input_bytes: fs::metadata(path).ok().map(|m| m.len()),
+            ambiguous_bases: Default::default(),
+            rna_bases: Default::default(),
+            skipped_records: Default::default(),
+            ..self.clone()
+        }
+    }
+}
+
+impl SketchParams {
+    pub fn from_map(map: &HashMap<String, String>) -> Result<Self, Box<dyn Error>> {
+        let get = |key: &str| {
+            map.get(key)
+                .ok_or_else(|| format!("parameter {} missing from parameter file", key))
+        };
+        let (kmer_length, seed) = (get("k")?.parse()?, get("seed")?.parse()?);
+        let mut options = SketchOptions::from_map(map)?;
+        if let Some(path) = map.get("blocklist") {
+            options.blocklist = Some(KmerList::load(path, &[kmer_length], &options, seed)?);
+        }
+        if let Some(path) = map.get("include_fasta") {
+            options.include_fasta = Some(KmerList::load(path, &[kmer_length], &options, seed)?);
+        }
+        if let Some(path) = map.get("include_bed") {
+            options.include_bed = Some(Regions::load(path)?);
+        }
+        Ok(SketchParams {
+            kmer_length,
+            algorithm: get("algorithm")?.clone(),
+            precision: size_key(get("algorithm")?)
+                .and_then(|key| map.get(key))
+                .map(|p| p.parse())
+                .transpose()?,
+            seed,
+            aa: map.get("molecule").is_some_and(|m| m == "amino_acid"),
+            options,
+        })
+    }
+}
+
+// write the three files of a sketch set
+pub fn write_sketch_set<S: KmerSketch>(
+    output_name: &str,
+    entries: &[Entry],
+    sketches: &[S],
+    params: &HashMap<String, String>,
+    threads: u32,
+) -> Result<(), Box<dyn Error>> {
+    let writer = BufWriter::new(File::create(format!("{}_sketches.bin", output_name))?);
     let mut encoder = Encoder::new(writer, 3)?;
     encoder.multithread(threads)?;
-
     for sketch in sketches {
         sketch.save(&mut encoder)?;
     }
     encoder.finish()?;
+    write_set_metadata(output_name, entries, params)
+}
+
+// names and parameters of a sketch set, the sketches are written by the caller
+pub fn write_set_metadata(
+    output_name: &str,
+    entries: &[Entry],
+    params: &HashMap<String, String>,
+) -> Result<(), Box<dyn Error>> {
+    write_entries(&format!("{}_files.json", output_name), entries)?;
+
+    // sorted keys, same as the parameter files written by `sketch`
+    let params: serde_json::Map<String, serde_json::Value> = params
+        .iter()
+        .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+        .collect();
+    to_writer_pretty(&File::create(format!("{}_parameters.json", output_name))?, &params)?;
+    Ok(())
+}
+
+// parameters two sets have to share to be combined or compared: the sketch itself
+// and every option choosing which k-mers reach it
+pub const MATCHING_PARAMS: [&str; 22] = [
+    "k",
+    "algorithm",
+    "precision",
+    "sketch_size",
+    "seed",
+    "molecule",
+    "scaled",
+    "seed_mask",
+    "minimizer_window",
+    "syncmer",
+    "seed_type",
+    "strobe_window",
+    "hash",
+    "hpc",
+    "canonical",
+    "circular",
+    "ambiguous",
+    "exclude_softmasked",
+    "filter_low_complexity",
+    "blocklist",
+    "include_fasta",
+    "include_bed",
+];
+
+// sketches of two sets can only be combined or compared when `MATCHING_PARAMS` match,
+// a missing molecule means nucleotide (sets written before it was recorded)
+pub fn check_same_params(
+    a_name: &str,
+    a: &HashMap<String, String>,
+    b_name: &str,
+    b: &HashMap<String, String>,
+) -> Result<(), String> {
+    for key in MATCHING_PARAMS {
+        let default = (key == "molecule").then(|| "nucleotide".to_string());
+        let (va, vb) = (a.get(key).or(default.as_ref()), b.get(key).or(default.as_ref()));
+        if va != vb {
+            return Err(format!(
+                "{} has {}={} but {} has {}={}",
+                a_name,
+                key,
+                va.map_or("none", String::as_str),
+                b_name,
+                key,
+                vb.map_or("none", String::as_str)
+            ));
+        }
+    }
+    Ok(())
+}
+
+// call a function generic over the sketch type stored for `algorithm`
+macro_rules! with_sketch_type {
+    ($algorithm:expr, $func:ident($($arg:expr),* $(,)?)) => {
+        match $algorithm {
+            "hmh" => $func::<hyperminhash::Sketch>($($arg),*),
+            "hmh+hll" => $func::<crate::dual::HmhHll>($($arg),*),
+            "hll" => $func::<streaming_algorithms::HyperLogLog<i64>>($($arg),*),
+            "ull" => $func::<crate::ull::UllSketch>($($arg),*),
+            "ehll" => $func::<crate::ehll::ExtendedHll>($($arg),*),
+            "fmh" => $func::<crate::minhash::FracMinHash>($($arg),*),
+            "minhash" => $func::<crate::minhash::MinHash>($($arg),*),
+            "superminhash" => $func::<crate::superminhash::SuperMinHash>($($arg),*),
+            "theta" => $func::<crate::minhash::Theta>($($arg),*),
+            "hyperbitbit" => $func::<crate::hyperbitbit::HyperBitBit>($($arg),*),
+            "ordermh" => $func::<crate::ordermh::OrderMinHash>($($arg),*),
+            "probminhash" => $func::<crate::weighted::ProbMinHash>($($arg),*),
+            "dartminhash" => $func::<crate::weighted::DartMinHash>($($arg),*),
+            other => Err(format!("unsupported algorithm {}", other).into()),
+        }
+    };
+}
+pub(crate) use with_sketch_type;
+
+// decompress all sketches of a sketch file
+pub fn load_sketches<S: KmerSketch>(file_name: &str, count: usize) -> std::io::Result<Vec<S>> {
+    let reader = BufReader::new(File::open(file_name)?);
+    let mut decoder = Decoder::new(reader)?;
+    let mut sketches = Vec::with_capacity(count);
+    for _ in 0..count {
+        sketches.push(S::load(&mut decoder)?);
+    }
+    Ok(sketches)
+}
+
+// largest group for multiway estimates, inclusion–exclusion needs 2^n - 1 unions
+pub const MAX_MULTIWAY: usize = 20;
+
+// union and intersection cardinalities across a group of sketches,
+// the intersection follows from inclusion–exclusion over all subset unions
+pub fn multiway_cardinalities<S: KmerSketch + Sync>(sketches: &[&S]) -> (f64, f64) {
+    let n = sketches.len();
+    assert!(n > 0 && n <= MAX_MULTIWAY, "multiway needs 1–{} sketches", MAX_MULTIWAY);
+    let full: u64 = (1u64 << n) - 1;
+
+    let (union, intersection) = (1u64..=full)
+        .into_par_iter()
+        .map(|mask| {
+            let mut members = (0..n).filter(|i| mask >> i & 1 == 1);
+            let first = members.next().unwrap();
+            let count = match members.next() {
+                None => sketches[first].cardinality(),
+                Some(second) => {
+                    let mut union = sketches[first].merged(sketches[second]);
+                    for i in members {
+                        union = union.merged(sketches[i]);
+                    }
+                    union.cardinality()
+                }
+            };
+            let sign = if mask.count_ones() % 2 == 1 { 1.0 } else { -1.0 };
+            (if mask == full { count } else { 0.0 }, sign * count)
+        })
+        .reduce(|| (0.0, 0.0), |a, b| (a.0 + b.0, a.1 + b.1));
+
+    (union, intersection.max(0.0))
+}
+
+// exact k-mer content of a group of genomes
+pub struct GroupKmers {
+    /// distinct k-mers per genome
+    pub sizes: Vec<usize>,
+    /// distinct k-mers over the whole group
+    pub union: usize,
+    /// k-mers shared by every genome
+    pub core: HashSet<u64>,
+}
+
+pub fn core_kmers(
+    genomes: &[&Entry],
+    params: &SketchParams,
+) -> Result<GroupKmers, Box<dyn Error>> {
+    let sets: Vec<HashSet<u64>> = genomes
+        .par_iter()
+        .map(|entry| kmer_set(entry, params).map_err(|e| format!("{}: {}", entry.name, e)))
+        .collect::<Result<_, _>>()?;
+
+    let sizes = sets.iter().map(|set| set.len()).collect();
+    let mut union: HashSet<u64> = HashSet::new();
+    for set in &sets {
+        union.extend(set.iter().copied());
+    }
+    let mut sets = sets.into_iter();
+    let mut core = sets.next().unwrap_or_default();
+    for set in sets {
+        core.retain(|kmer| set.contains(kmer));
+    }
+    Ok(GroupKmers { sizes, union: union.len(), core })
+}
+
+// sketch one FASTA/FASTQ file in memory, with the parameters of a set
+pub fn sketch_file<S: KmerSketch>(file_name: &str, params: &SketchParams) -> Result<S, ParseError> {
+    let reader = open_fastx(file_name)?;
+    let options = SketchOptions { count_min: None, ..params.options.for_input(file_name) };
+    let mut sketched = sketch_reader_counted(reader, params.precision, &[params.kmer_length], params.seed, params.aa, &options)?;
+    Ok(sketched.remove(0).0)
+}
+
+// sketch every record of an open FASTA/FASTQ parser
+pub fn sketch_reader<S: KmerSketch>(reader: Box<dyn FastxReader>, params: &SketchParams) -> Result<S, ParseError> {
+    let options = SketchOptions { count_min: None, ..params.options.clone() };
+    let mut sketched = sketch_reader_counted(reader, params.precision, &[params.kmer_length], params.seed, params.aa, &options)?;
+    Ok(sketched.remove(0).0)
+}
+
+// sketches of one genome (or record), one per k-mer length, with their Count-Min sketch
+pub type Sketched<S> = Vec<(S, Option<CountMin>)>;
+
+// sketch every record once per k-mer length, in one pass over the sequences, also
+// counting k-mers in a Count-Min sketch with `options.count_min`
+pub fn sketch_reader_counted<S: KmerSketch>(
+    reader: Box<dyn FastxReader>,
+    precision: Option<u32>,
+    kmer_lengths: &[usize],
+    seed: u64,
+    aa: bool,
+    options: &SketchOptions,
+) -> Result<Sketched<S>, ParseError> {
+    let options = SketchOptions { per_contig: false, ..options.clone() };
+    let mut sketched = sketch_sequences(vec![reader], precision, kmer_lengths, seed, aa, &options)?;
+    Ok(sketched.pop().expect("one entry per file").1)
+}
+
+// stretches of a record between bases below `min_qual` (Phred+33), so no k-mer
+// spans a low-quality base; FASTA records and a threshold of 0 keep the record whole
+fn quality_runs<'a>(seq: &'a [u8], qual: Option<&[u8]>, min_qual: u8) -> Vec<&'a [u8]> {
+    let qual = match qual {
+        Some(qual) if min_qual > 0 => qual,
+        _ => return vec![seq],
+    };
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for (i, &q) in qual.iter().enumerate().take(seq.len()) {
+        if q.saturating_sub(33) < min_qual {
+            if i > start {
+                runs.push(&seq[start..i]);
+            }
+            start = i + 1;
+        }
+    }
+    if start < seq.len() {
+        runs.push(&seq[start..]);
+    }
+    runs
+}
+
+fn mean_quality(qual: &[u8]) -> f64 {
+    qual.iter().map(|&q| q.saturating_sub(33) as f64).sum::<f64>() / qual.len().max(1) as f64
+}
+
+// read name without a /1 or /2 suffix, the same for both mates of a pair
+fn mate_name(header: &[u8]) -> String {
+    let id = record_id(header);
+    id.strip_suffix("/1").or_else(|| id.strip_suffix("/2")).unwrap_or(&id).to_string()
+}
+
+// whether a record passes --min-mean-qual, --subsample and the length limits;
+// records skipped for their length are counted
+fn record_passes(id: &[u8], length: usize, qual: Option<&[u8]>, options: &SketchOptions, seed: u64) -> bool {
+    if qual.is_some_and(|q| mean_quality(q) < options.min_mean_qual as f64) {
+        return false;
+    }
+    if qual.is_some() && options.read_fraction.is_some_and(|f| !read_sampled(id, f, seed)) {
+        return false;
+    }
+    if length < options.min_seq_len || options.max_seq_len.is_some_and(|max| length > max) {
+        options.skipped_records.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        return false;
+    }
+    true
+}
+
+// whether a read is among the --subsample fraction; drawn from its name without a
+// /1 or /2 suffix, so both reads of a pair are kept or left out together
+fn read_sampled(header: &[u8], fraction: f64, seed: u64) -> bool {
+    let name = mate_name(header);
+    fraction >= 1.0 || (xxh3_64_with_seed(name.as_bytes(), seed ^ 0x7265_6164_7300_0000) as f64) < fraction * u64::MAX as f64
+}
+
+// k-mers seen so far, of FASTQ records for --min-count or of any record for --min-copies
+enum KmerCounter {
+    Bloom(BloomFilter),
+    #[cfg(feature = "exact-counts")]
+    Exact(crate::hashcount::HashCounter, u32),
+    Copies(HashMap<u64, u32, Xxh3Builder>, u32),
+}
+
+impl KmerCounter {
+    fn new(options: &SketchOptions, seed: u64) -> Self {
+        if options.min_copies > 1 {
+            return KmerCounter::Copies(HashMap::with_hasher(Xxh3Builder { seed }), options.min_copies);
+        }
+        #[cfg(feature = "exact-counts")]
+        if options.exact_counts {
+            return KmerCounter::Exact(crate::hashcount::HashCounter::new(seed), options.min_count);
+        }
+        KmerCounter::Bloom(BloomFilter::for_input(options.input_bytes, seed))
+    }
+
+    // count a k-mer, true once it has been seen --min-count times; the Bloom
+    // filter only tells first occurrences apart
+    fn admit(&mut self, masked: u64) -> bool {
+        match self {
+            KmerCounter::Bloom(bloom) => bloom.insert(masked),
+            #[cfg(feature = "exact-counts")]
+            KmerCounter::Exact(counter, min_count) => counter.increment(masked) >= *min_count,
+            KmerCounter::Copies(counts, min_copies) => {
+                let count = counts.entry(masked).or_insert(0);
+                *count = count.saturating_add(1);
+                *count >= *min_copies
+            }
+        }
+    }
+}
+
+// id, sequence and qualities of a record kept past the parser's next record
+type OwnedRecord = (Vec<u8>, Vec<u8>, Option<Vec<u8>>);
+
+// sketches of a whole file (both files of a read pair), or with `options.per_contig`
+// of each record under its id (the header up to the first space), as `sketch_reader_counted`;
+// malformed records are skipped, a read error (a corrupt compressed stream, a failed
+// checksum, a dropped download) fails the whole input rather than sketch part of it
+pub fn sketch_sequences<S: KmerSketch>(
+    readers: Vec<Box<dyn FastxReader>>,
+    precision: Option<u32>,
+    kmer_lengths: &[usize],
+    seed: u64,
+    aa: bool,
+    options: &SketchOptions,
+) -> Result<Vec<(String, Sketched<S>)>, ParseError> {
+    let per_record = options.per_contig;
+    let empty = || -> Sketched<S> {
+        kmer_lengths
+            .iter()
+            .map(|_| (S::new(precision), options.count_min.map(CountMin::new)))
+            .collect()
+    };
+    let mut entries: Vec<(String, Sketched<S>)> = Vec::new();
+    if !per_record {
+        entries.push((String::new(), empty()));
+    }
+    // k-mers seen so far, per k-mer length, once FASTQ records come with --min-count
+    let mut seen: Vec<Option<KmerCounter>> = kmer_lengths.iter().map(|_| None).collect();
+    // minimizer windows per k-mer length, restarted with every record
+    let mut minimizers: Vec<Option<Minimizers>> = kmer_lengths
+        .iter()
+        .map(|_| options.minimizer_window.map(|w| Minimizers::new(w, seed)))
+        .collect();
+    // hashed apart from the sketches' own hashes, whose leading bits pick registers
+    let below = options.subsample.map(|scaled| u64::MAX / scaled);
+    let syncmers: Vec<Option<Syncmers>> = kmer_lengths
+        .iter()
+        .map(|&k| options.syncmer.clone().map(|scheme| Syncmers::new(scheme, k, seed)))
+        .collect();
+
+    // one record into the sketches; `filtered` once its read pair was let through as a whole
+    let mut sketch_record = |id: &[u8], seq: Cow<[u8]>, qual: Option<&[u8]>, filtered: bool| {
+        let reads = (options.min_count > 1 && qual.is_some()) || options.min_copies > 1;
+        let length = seq.len();
+        if !filtered && !record_passes(id, length, qual, options, seed) {
+            return;
+        }
+        // the regions sketched: --include-bed intervals, within what read trimming leaves
+        let (first, last) = match qual {
+            Some(_) => options.trimmed(length),
+            None => (0, length),
+        };
+        let targets: Vec<(usize, usize)> = match &options.include_bed {
+            Some(regions) => regions.of_record(&record_id(id), length),
+            None => vec![(0, length)],
+        }
+        .into_iter()
+        .map(|(start, end)| (start.max(first), end.min(last)))
+        .filter(|(start, end)| end > start)
+        .collect();
+        if targets.is_empty() && length > 0 {
+            return;
+        }
+        if per_record {
+            entries.push((record_id(id), empty()));
+        }
+        let sketched = &mut entries.last_mut().expect("entry for the record").1;
+        let mut record = seq;
+        // RNA: U would otherwise be dropped as a base other than ACGT
+        if (!aa || options.translate) && record.iter().any(|&c| c == b'U' || c == b'u') {
+            let converted = rna_to_dna(record.to_mut());
+            options.rna_bases.fetch_add(converted, std::sync::atomic::Ordering::Relaxed);
+        }
+        let mut runs: Vec<&[u8]> = targets
+            .iter()
+            .flat_map(|&(start, end)| {
+                quality_runs(&record[start..end], qual.and_then(|q| q.get(start..end)), options.min_qual)
+            })
+            .collect();
+        if options.exclude_softmasked {
+            runs = runs
+                .into_iter()
+                .flat_map(|run| run.split(|c| c.is_ascii_lowercase()))
+                .filter(|run| !run.is_empty())
+                .collect();
+        }
+        let seqs: Vec<Vec<u8>> = if options.translate {
+            runs.iter().flat_map(|run| six_frames(&run.to_ascii_uppercase())).collect()
+        } else if aa {
+            runs.iter().map(|run| run.to_ascii_uppercase()).collect()
+        } else {
+            if options.ambiguous != AmbiguityPolicy::Drop {
+                let count = ambiguous::count_ambiguous(record.as_ref());
+                options.ambiguous_bases.fetch_add(count, std::sync::atomic::Ordering::Relaxed);
+            }
+            runs.iter()
+                .flat_map(|run| ambiguous::resolve(run, options.ambiguous, seed))
+                .map(|stretch| if options.hpc { homopolymer_compress(&stretch) } else { stretch })
+                .collect()
+        };
+        let per_k = kmer_lengths.iter().zip(sketched.iter_mut()).zip(&mut seen).zip(&mut minimizers).zip(&syncmers);
+        for ((((&kmer_length, (sketch, counts)), seen), minimizers), syncmers) in per_k {
+            let mut seen = reads.then(|| seen.get_or_insert_with(|| KmerCounter::new(options, seed)));
+            // kept k-mers reach the sketch hashed a batch at a time
+            let mut batch = HashBatch::new();
+            let mut keep = |masked: u64| {
+                if below.is_some_and(|below| xxh3_64_with_seed(&masked.to_le_bytes(), seed ^ 0x7363_616C_6564_0000) > below) {
+                    return;
+                }
+                if syncmers.as_ref().is_some_and(|syncmers| !syncmers.select(masked)) {
+                    return;
+                }
+                if seen.as_mut().is_none_or(|seen| seen.admit(masked)) {
+                    batch.push(masked, seed, |masked, hash| sketch.add_hashed(masked, hash, seed));
+                }
+            };
+            if let Some(minimizers) = minimizers.as_mut() {
+                minimizers.reset();
+            }
+            let blocked = options.blocklist.as_ref().and_then(|blocklist| blocklist.filter(kmer_length));
+            let included = options.include_fasta.as_ref().and_then(|include| include.filter(kmer_length));
+            // Count-Min sketches count every k-mer the lists let through, so spectra keep their singletons
+            let mut add = |masked: u64| {
+                if blocked.is_some_and(|blocked| blocked.contains(masked)) {
+                    return;
+                }
+                if included.is_some_and(|included| !included.contains(masked)) {
+                    return;
+                }
+                if let Some(counts) = counts.as_mut() {
+                    counts.add_kmer(masked, seed);
+                }
+                if options.filter_low_complexity && low_complexity(masked, kmer_length) {
+                    return;
+                }
+                match minimizers.as_mut() {
+                    Some(minimizers) => minimizers.push(masked, &mut keep),
+                    None => keep(masked),
+                }
+            };
+            let expanded: Vec<Vec<u8>> = match options.ambiguous {
+                AmbiguityPolicy::Expand => runs.iter().flat_map(|run| ambiguous::expanded_windows(run, kmer_length)).collect(),
+                _ => Vec::new(),
+            };
+            // --circular: the k - 1 k-mers across the end/start junction of the record
+            let junction: Vec<Vec<u8>> = match (options.circular, seqs.first(), seqs.last()) {
+                (true, Some(first), Some(last)) => {
+                    let overlap = kmer_length - 1;
+                    vec![[&last[last.len().saturating_sub(overlap)..], &first[..first.len().min(overlap)]].concat()]
+                }
+                _ => Vec::new(),
+            };
+            for seq in seqs.iter().chain(&expanded).chain(&junction) {
+                if aa {
+                    for_each_aa_kmer(seq, kmer_length, &mut add);
+                } else if let Some(mask) = &options.seed_mask {
+                    mask.for_each_kmer(seq, &mut add);
+                } else if let Some(scheme) = &options.strobemer {
+                    scheme.for_each_strobemer(seq, kmer_length, seed, &mut add);
+                } else {
+                    options.for_each_dna_kmer(seq, kmer_length, &mut add);
+                }
+            }
+            if let Some(minimizers) = minimizers.as_mut() {
+                minimizers.finish(&mut keep);
+            }
+            batch.flush(seed, |masked, hash| sketch.add_hashed(masked, hash, seed));
+        }
+    };
+
+    // looping through each sequence of each file, R2 after R1 for read pairs
+    for mut reader in readers {
+        // with --interleaved, the first mate of the pair being read
+        let mut first: Option<OwnedRecord> = None;
+        let mut mismatch_reported = false;
+        while let Some(res) = reader.next() {
+            let seqrec = match res {
+                Ok(seqrec) => seqrec,
+                Err(e) if e.kind == ParseErrorKind::Io => return Err(e),
+                Err(_) => continue,
+            };
+            let Some(mates) = options.interleaved else {
+                sketch_record(seqrec.id(), seqrec.seq(), seqrec.qual(), false);
+                continue;
+            };
+            let Some((id, seq, qual)) = first.take() else {
+                first = Some((seqrec.id().to_vec(), seqrec.seq().into_owned(), seqrec.qual().map(<[u8]>::to_vec)));
+                continue;
+            };
+            if !mismatch_reported && mate_name(&id) != mate_name(seqrec.id()) {
+                warn!("interleaved mates {} and {} have different names, is the file interleaved?", record_id(&id), record_id(seqrec.id()));
+                mismatch_reported = true;
+            }
+            // both mates pass the read filters or neither is sketched
+            let second = seqrec.seq();
+            let first_passes = record_passes(&id, seq.len(), qual.as_deref(), options, seed);
+            let second_passes = record_passes(&id, second.len(), seqrec.qual(), options, seed);
+            if !(first_passes && second_passes) {
+                continue;
+            }
+            if mates != Mates::Second {
+                sketch_record(&id, Cow::Owned(seq), qual.as_deref(), true);
+            }
+            if mates != Mates::First {
+                sketch_record(seqrec.id(), second, seqrec.qual(), true);
+            }
+        }
+        // a last read without its mate
+        if let Some((id, seq, qual)) = first {
+            warn!("interleaved read {} has no mate", record_id(&id));
+            if options.interleaved != Some(Mates::Second) {
+                sketch_record(&id, Cow::Owned(seq), qual.as_deref(), false);
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+// general sketching function, writing one sketch set per (k-mer length, output name);
+// with `options.per_contig` every record gets its own sketch, named <file>:<record id>
+// Each file is processed to completion in its own task (no inner parallelism / no channels).
+// This is simple, avoids stack overflows, and matches the “parallel by sample” request.
+pub fn sketch_files <S: KmerSketch> (
+    precision: Option<u32>,
+    samples: Vec<Sample>,
+    sets: &[(usize, String)],
+    threads: u32,
+    seed: u64,
+    aa: bool,
+    options: &SketchOptions,
+) -> Result<(), Box<dyn Error>> {
+
+    let kmer_lengths: Vec<usize> = sets.iter().map(|(k, _)| *k).collect();
+
+    // finished samples checkpointed next to the first set, for --resume
+    let mut run = serde_json::json!({ "sketch": std::any::type_name::<S>(), "precision": precision, "seed": seed, "aa": aa });
+    options.record(&mut run);
+    run["sets"] = serde_json::json!(sets);
+    run["samples"] = samples.iter().map(|s| serde_json::json!([s.name, s.path, s.more])).collect();
+    let (checkpoint, done) = Checkpoint::open(&sets[0].1, xxh3_64(run.to_string().as_bytes()), options.resume)?;
+    if done > 0 {
+        println!("{} of {} samples read back from the checkpoint.", done, samples.len());
+    }
+
+    let sketched_samples: Vec<(Vec<_>, (u64, u64, u64))> = samples
+        .par_iter()
+        .enumerate()
+        .map(|(index, sample)| {
+            if let Some((entries, [ambiguous, rna, skipped])) = checkpoint.load::<S>(index, kmer_lengths.len()) {
+                return Ok((entries, (ambiguous, rna, skipped)));
+            }
+            let mut options = options.for_input(&sample.path);
+            let mut readers = Vec::new();
+            for path in std::iter::once(&sample.path).chain(&sample.more) {
+                readers.push(open_fastx(path).map_err(|e| format!("{}: {}", path, e))?);
+                if path != &sample.path {
+                    options.input_bytes = options.input_bytes.zip(fs::metadata(path).ok()).map(|(bytes, more)| bytes + more.len());
+                }
+            }
+            let entries = sketch_sequences(readers, precision, &kmer_lengths, seed, aa, &options)
+                .map_err(|e| format!("{}: {}", sample.name, e))?;
+            let count = |counter: &std::sync::atomic::AtomicU64| counter.load(std::sync::atomic::Ordering::Relaxed);
+            let counters = [count(&options.ambiguous_bases), count(&options.rna_bases), count(&options.skipped_records)];
+            checkpoint.save(index, &entries, counters);
+            Ok((entries, (counters[0], counters[1], counters[2])))
+        })
+        .collect::<Result<_, String>>()?;
+    let (by_sample, counted): (Vec<Vec<_>>, Vec<(u64, u64, u64)>) = sketched_samples.into_iter().unzip();
+    for (sample, (_, rna, skipped)) in samples.iter().zip(&counted) {
+        if *rna > 0 {
+            println!("{}: {} U bases read as T", sample.name, rna);
+        }
+        if *skipped > 0 {
+            println!("{}: {} records skipped outside --min-seq-len/--max-seq-len", sample.name, skipped);
+        }
+    }
+
+    // samples named apart from their path (a manifest) or read from several files
+    // keep the paths next to the name
+    let read_from = |sample: &Sample| sample.source.clone().unwrap_or_else(|| sample.path.clone());
+    let named = samples
+        .iter()
+        .any(|sample| (sample.name != read_from(sample) || !sample.more.is_empty()) && sample.path != STDIN);
+    let mut names: Vec<Entry> = Vec::new();
+    let mut sketched: Vec<Sketched<S>> = Vec::new();
+    for (sample, entries) in samples.iter().zip(by_sample) {
+        for (id, per_k) in entries {
+            let name = match options.per_contig {
+                true => format!("{}:{}", sample.name, id),
+                false => sample.name.clone(),
+            };
+            names.push(Entry { name, path: named.then(|| read_from(sample)), more: sample.more.clone() });
+            sketched.push(per_k);
+        }
+    }
+
+    // sets in reverse so each sketch's k-mer lengths can be popped off the end
+    for (_, output_name) in sets.iter().rev() {
+        let (sketches, counts): (Vec<S>, Vec<Option<CountMin>>) = sketched
+            .iter_mut()
+            .map(|per_k| per_k.pop().expect("one sketch per k-mer length"))
+            .unzip();
 
-    // write names
-    to_writer_pretty(
-        &File::create(format!("{}_files.json", output_name))?,
-        &files,
-    )?;
+        // write sketches
+        let writer = BufWriter::new(File::create(format!("{}_sketches.bin", output_name))?);
+        let mut encoder = Encoder::new(writer, 3)?;
+        encoder.multithread(threads)?;
+
+        for sketch in sketches {
+            sketch.save(&mut encoder)?;
+        }
+        encoder.finish()?;
+
+        // Count-Min sketches next to them
+        if options.count_min.is_some() {
+            let counts: Vec<CountMin> = counts.into_iter().flatten().collect();
+            countmin::write_counts(output_name, &counts, threads)?;
+        }
+
+        // write names
+        write_entries(&format!("{}_files.json", output_name), &names)?;
+
+        // bases other than ACGT per input, once a policy handles them
+        if options.ambiguous != AmbiguityPolicy::Drop {
+            let mut report = BufWriter::new(File::create(format!("{}_ambiguous.tsv", output_name))?);
+            writeln!(report, "Genome\tAmbiguousBases")?;
+            for (sample, (count, _, _)) in samples.iter().zip(&counted) {
+                writeln!(report, "{}\t{}", sample.name, count)?;
+            }
+        }
+    }
+    checkpoint.finish()?;
 
     Ok(())
 }
+
+// a directory in the temporary directory for tests, removed when dropped
+#[cfg(test)]
+pub struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("lash_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    pub fn path(&self, name: &str) -> String {
+        self.0.join(name).to_string_lossy().into_owned()
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// k-mers rolled by roll_kmers against a reference that packs every window of k
+// bases from scratch, for each k a u64 holds (k = 15 used to panic in the
+// kmerutils split between 32- and 64-bit k-mers). The sequence is longer than
+// an encoding block and has lowercase bases and Ns, so block boundaries, case
+// and restarts after an ambiguous base are covered.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(base: u8) -> Option<u64> {
+        match base.to_ascii_uppercase() {
+            b'A' => Some(0),
+            b'C' => Some(1),
+            b'G' => Some(2),
+            b'T' => Some(3),
+            _ => None,
+        }
+    }
+
+    // every window without an ambiguous base, packed first base highest, and its
+    // reverse complement packed the same way
+    fn naive_kmers(seq: &[u8], k: usize, canonical: bool) -> Vec<u64> {
+        seq.windows(k)
+            .filter_map(|window| {
+                let codes: Option<Vec<u64>> = window.iter().map(|&b| code(b)).collect();
+                let codes = codes?;
+                let forward = codes.iter().fold(0u64, |acc, &c| (acc << 2) | c);
+                let reverse = codes.iter().rev().fold(0u64, |acc, &c| (acc << 2) | (3 - c));
+                Some(if canonical { forward.min(reverse) } else { forward })
+            })
+            .collect()
+    }
+
+    fn fixed_sequence() -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        (0..700)
+            .map(|i| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let base = b"ACGT"[(state >> 32) as usize % 4];
+                match i {
+                    100 | 301 | 302 => b'N',
+                    400..=450 => base.to_ascii_lowercase(),
+                    _ => base,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn kmers_match_naive_encoding_for_every_k() {
+        let seq = fixed_sequence();
+        for k in 1..=32 {
+            let mut canonical = Vec::new();
+            for_each_kmer(&seq, k, |kmer| canonical.push(kmer));
+            assert_eq!(canonical, naive_kmers(&seq, k, true), "canonical k = {}", k);
+            let mut stranded = Vec::new();
+            for_each_stranded_kmer(&seq, k, |kmer| stranded.push(kmer));
+            assert_eq!(stranded, naive_kmers(&seq, k, false), "stranded k = {}", k);
+        }
+    }
+
+    // a FASTA file of one record in the temporary directory, removed by the caller
+    fn fasta(name: &str, seq: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("lash_{}_{}.fa", name, std::process::id()));
+        fs::write(&path, [b">record\n", seq, b"\n"].concat()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn params(options: &[(&str, &str)]) -> SketchParams {
+        let mut map: HashMap<String, String> = [("k", "16"), ("algorithm", "hll"), ("seed", "42")]
+            .iter()
+            .chain(options)
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        map.entry("molecule".to_string()).or_insert_with(|| "nucleotide".to_string());
+        SketchParams::from_map(&map).unwrap()
+    }
+
+    #[test]
+    fn kmer_set_translates() {
+        let seq = fixed_sequence();
+        let path = fasta("translate", &seq);
+        let params = params(&[("k", "7"), ("molecule", "amino_acid"), ("translate", "true")]);
+        let kmers = kmer_set(&Entry::named(path.clone()), &params).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut expected = HashSet::new();
+        for frame in six_frames(&seq.to_ascii_uppercase()) {
+            for_each_aa_kmer(&frame, 7, |kmer| { expected.insert(kmer); });
+        }
+        assert!(!expected.is_empty());
+        assert_eq!(kmers, expected);
+    }
+
+    #[test]
+    fn kmer_set_compresses_homopolymers() {
+        let seq = b"AAAACCCGGTTTTACGTTTTGCAAAAC";
+        let path = fasta("hpc", seq);
+        let kmers = kmer_set(&Entry::named(path.clone()), &params(&[("k", "5"), ("hpc", "true")])).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut expected = HashSet::new();
+        for_each_kmer(&homopolymer_compress(seq), 5, |kmer| { expected.insert(kmer); });
+        assert_eq!(kmers, expected);
+        // AAAAC only exists before compression
+        let mut uncompressed = HashSet::new();
+        for_each_kmer(seq, 5, |kmer| { uncompressed.insert(kmer); });
+        assert_ne!(kmers, uncompressed);
+    }
+
+    #[test]
+    fn kmer_set_keeps_strands() {
+        // without its Ns, which the default --ambiguous drop deletes from records
+        let seq = filter_out_n(&fixed_sequence());
+        let path = fasta("stranded", &seq);
+        let stranded = kmer_set(&Entry::named(path.clone()), &params(&[("canonical", "false")])).unwrap();
+        let canonical = kmer_set(&Entry::named(path.clone()), &params(&[])).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(stranded, naive_kmers(&seq, 16, false).into_iter().collect());
+        assert_eq!(canonical, naive_kmers(&seq, 16, true).into_iter().collect());
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let path = std::env::temp_dir().join(format!("lash_entries_{}_files.json", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let named = vec![Entry::named("g0.fa".to_string()), Entry::named("g1.fa".to_string())];
+        write_entries(&path, &named).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().split_whitespace().collect::<String>(), r#"["g0.fa","g1.fa"]"#);
+        assert_eq!(read_entries(&path).unwrap(), named);
+        let paired = vec![
+            Entry { name: "s1".to_string(), path: Some("s1_R1.fq".to_string()), more: vec!["s1_R2.fq".to_string()] },
+            Entry::named("g1.fa".to_string()),
+        ];
+        write_entries(&path, &paired).unwrap();
+        let read = read_entries(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read[0], paired[0]);
+        assert_eq!(read[0].files(), vec!["s1_R1.fq", "s1_R2.fq"]);
+        // a genome named by its file is written with its path next to the others
+        assert_eq!(read[1].name, "g1.fa");
+        assert_eq!(read[1].files(), vec!["g1.fa"]);
+    }
+
+    #[test]
+    fn kmer_packing_k15() {
+        // AAAAAAAAAAAAAAC packs to 1, its reverse complement GTTTTTTTTTTTTTT is larger
+        let mut kmers = Vec::new();
+        for_each_kmer(b"AAAAAAAAAAAAAAC", 15, |kmer| kmers.push(kmer));
+        assert_eq!(kmers, vec![1]);
+        // the reverse complement of GTTTTTTTTTTTTTT is the same canonical k-mer
+        kmers.clear();
+        for_each_kmer(b"GTTTTTTTTTTTTTT", 15, |kmer| kmers.push(kmer));
+        assert_eq!(kmers, vec![1]);
+        kmers.clear();
+        for_each_stranded_kmer(b"GTTTTTTTTTTTTTT", 15, |kmer| kmers.push(kmer));
+        assert_eq!(kmers, vec![(2 << 28) | ((1 << 28) - 1)]);
+    }
+}
@@ -0,0 +1,158 @@
+// src/watch.rs
+// `lash watch`: poll a directory, sketch FASTA/FASTQ files as they appear and
+// append their distances to a reference sketch set to a running output
+
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hashbrown::{HashMap, HashSet};
+use log::{info, warn};
+use rayon::prelude::*;
+use serde_json::json;
+
+use crate::notify::{run_report, Notifier};
+use crate::pairwise::sketch_distance;
+use crate::utils::{
+    find_files, load_sketches, metadata_header, read_names, read_params, sketch_file,
+    is_sequence_file, with_sketch_type, KmerSketch, SketchParams,
+};
+
+pub struct WatchOptions {
+    pub dir: String,
+    pub reference: String,
+    pub output: String,
+    pub interval: u64,
+    pub top: Option<usize>,
+    pub equation: u8,
+    pub once: bool,
+    pub metadata: bool,
+}
+
+// files that kept the same size over two polls are considered complete
+fn poll(
+    dir: &str,
+    seen: &HashSet<PathBuf>,
+    sizes: &mut HashMap<PathBuf, u64>,
+) -> std::io::Result<Vec<PathBuf>> {
+    let mut ready = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() || !is_sequence_file(&path) || seen.contains(&path) {
+            continue;
+        }
+        let size = fs::metadata(&path)?.len();
+        if size > 0 && sizes.get(&path) == Some(&size) {
+            ready.push(path);
+        } else {
+            sizes.insert(path, size);
+        }
+    }
+    ready.sort();
+    Ok(ready)
+}
+
+fn watch_with<S: KmerSketch + Sync>(
+    opts: &WatchOptions,
+    notifier: &Notifier,
+    param_map: &HashMap<String, String>,
+    params: &SketchParams,
+    sketch_file_name: &str,
+    reference_names: &[String],
+) -> Result<(), Box<dyn Error>> {
+    let references: Vec<S> = load_sketches(sketch_file_name, reference_names.len())?;
+    info!("{} reference sketches loaded", references.len());
+
+    let write_header = fs::metadata(&opts.output).map(|m| m.len() == 0).unwrap_or(true);
+    let mut output = OpenOptions::new().create(true).append(true).open(&opts.output)?;
+    if write_header {
+        if opts.metadata {
+            writeln!(output, "{}", metadata_header(param_map, opts.equation))?;
+        }
+        writeln!(output, "Reference\tQuery\tDistance")?;
+    }
+
+    // files already in the running output are not compared again after a restart
+    let mut seen: HashSet<PathBuf> = fs::read_to_string(&opts.output)?
+        .lines()
+        .filter(|l| !l.starts_with('#') && !l.starts_with("Reference\t"))
+        .filter_map(|l| l.split('\t').nth(1))
+        .map(PathBuf::from)
+        .collect();
+    let mut sizes: HashMap<PathBuf, u64> = HashMap::new();
+    // a single pass needs no stability check, take what is there now
+    if opts.once {
+        for entry in fs::read_dir(&opts.dir)? {
+            let path = entry?.path();
+            if path.is_file() && is_sequence_file(&path) {
+                sizes.insert(path.clone(), fs::metadata(&path)?.len());
+            }
+        }
+    }
+
+    loop {
+        for path in poll(&opts.dir, &seen, &mut sizes)? {
+            seen.insert(path.clone());
+            let query_name = path.to_string_lossy().to_string();
+            let start = Instant::now();
+            let query: S = match sketch_file(&query_name, params) {
+                Ok(sketch) => sketch,
+                Err(e) => {
+                    warn!("could not sketch {}, skipped: {}", query_name, e);
+                    let mut report = run_report(
+                        "watch",
+                        Some(format!("could not sketch {}: {}", query_name, e)),
+                        start.elapsed().as_secs_f64(),
+                    );
+                    report["query"] = json!(query_name);
+                    notifier.notify(&report);
+                    continue;
+                }
+            };
+
+            let mut rows: Vec<(&String, f64)> = references
+                .par_iter()
+                .zip(reference_names.par_iter())
+                .map(|(reference, name)| (name, sketch_distance(reference, &query, params.kmer_length, opts.equation)))
+                .collect();
+            rows.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+            if let Some(top) = opts.top {
+                rows.truncate(top);
+            }
+
+            for (r_name, d) in &rows {
+                writeln!(output, "{}\t{}\t{:.6}", r_name, query_name, d)?;
+            }
+            output.flush()?;
+            println!("{} compared against {}.", query_name, opts.reference);
+
+            let mut report = run_report("watch", None, start.elapsed().as_secs_f64());
+            report["query"] = json!(query_name);
+            report["hits"] = rows
+                .iter()
+                .map(|(r_name, d)| json!({"reference": r_name, "distance": d}))
+                .collect();
+            notifier.notify(&report);
+        }
+
+        if opts.once {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_secs(opts.interval));
+    }
+}
+
+pub fn watch(opts: &WatchOptions, notifier: &Notifier) -> Result<(), Box<dyn Error>> {
+    let files = find_files(&opts.reference)?;
+    let param_map = read_params(&files["params"])?;
+    let params = SketchParams::from_map(&param_map)?;
+    let names = read_names(&files["files"])?;
+
+    with_sketch_type!(
+        params.algorithm.as_str(),
+        watch_with(opts, notifier, &param_map, &params, &files["sketches"], &names)
+    )
+}
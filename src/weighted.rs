@@ -0,0 +1,257 @@
+// src/weighted.rs
+// Abundance-weighted sketches. K-mer multiplicities are counted while a file is
+// sketched and folded into the signature the first time it is needed, so two
+// samples with the same k-mers in different proportions are told apart.
+//
+// Both keep `sketch_size` registers and compare by the share of registers holding
+// the same point:
+// - ProbMinHash (`probminhash`, Ertl 2020) estimates the probability Jaccard of the
+//   two abundance profiles, which does not depend on sequencing depth.
+// - DartMinHash (`dartminhash`, Christiani 2020) estimates the weighted Jaccard,
+//   sum of min counts / sum of max counts, so depth differences count as
+//   differences. It throws darts at the area under each count in dyadic blocks,
+//   a k-mer costs O(log count).
+//
+// Until the signature is folded, every distinct k-mer of the file is held with its
+// count, about 16 bytes each plus the table's slack: a metagenome of 10^9 distinct
+// k-mers needs some 20 GB. `--scaled` counts only the k-mers hashing below
+// 2^64/scaled, dividing that memory by `scaled`.
+
+use std::error::Error;
+use std::sync::OnceLock;
+
+use hashbrown::HashMap;
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+use crate::format;
+use crate::utils::KmerSketch;
+
+// register count used when none is given
+pub const DEFAULT_SKETCH_SIZE: usize = 1000;
+
+// splitmix64 seeded by the k-mer hash, every sample draws the same sequence for a k-mer
+pub struct KmerRng(pub u64);
+
+impl KmerRng {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    pub fn next_unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // standard exponential, from a uniform in (0, 1]
+    fn next_exp(&mut self) -> f64 {
+        let u = ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64;
+        -u.ln()
+    }
+}
+
+// per register the smallest point seen and the k-mer hash it came from
+#[derive(Clone)]
+pub struct Signature {
+    pub values: Vec<f64>,
+    pub hashes: Vec<u64>,
+}
+
+impl Signature {
+    pub fn empty(size: usize) -> Self {
+        Signature { values: vec![f64::INFINITY; size], hashes: vec![0; size] }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.values.iter().all(|v| v.is_infinite())
+    }
+
+    // ProbMinHash1: the k-mer emits points of a Poisson process of rate `weight`,
+    // each on a random register, until no register can improve
+    fn add(&mut self, hash: u64, weight: f64) {
+        let m = self.values.len();
+        let mut max = self.values.iter().copied().fold(0.0, f64::max);
+        let mut rng = KmerRng(hash);
+        let mut point = rng.next_exp() / weight;
+        while point < max {
+            let k = (rng.next_u64() % m as u64) as usize;
+            if point < self.values[k] {
+                let was_max = self.values[k] == max;
+                self.values[k] = point;
+                self.hashes[k] = hash;
+                if was_max {
+                    max = self.values.iter().copied().fold(0.0, f64::max);
+                }
+            }
+            point += rng.next_exp() / weight;
+        }
+    }
+
+    // darts of a unit-rate Poisson process over (weight, rank, register): the k-mer
+    // keeps those with weight below its count. The weight axis is cut into blocks
+    // [0, 1), [1, 2), [2, 4), ..., each with darts of its own, so a k-mer draws the
+    // same darts whatever its count and two samples share a register's dart with
+    // probability sum of min counts / sum of max counts
+    fn add_darts(&mut self, hash: u64, count: f64) {
+        let m = self.values.len();
+        let mut max = self.values.iter().copied().fold(0.0, f64::max);
+        let mut block = 0u64;
+        let mut low = 0.0;
+        while low < count {
+            let width = if block == 0 { 1.0 } else { low };
+            let covered = (count - low).min(width);
+            let mut rng = KmerRng(hash ^ block.wrapping_mul(0xA076_1D64_78BD_642F));
+            let mut rank = rng.next_exp() / width;
+            while rank < max {
+                let k = (rng.next_u64() % m as u64) as usize;
+                let weight = rng.next_unit() * width;
+                if weight < covered && rank < self.values[k] {
+                    let was_max = self.values[k] == max;
+                    self.values[k] = rank;
+                    // the dart, not the k-mer: other darts of the k-mer are other units
+                    self.hashes[k] = KmerRng(hash ^ rank.to_bits()).next_u64();
+                    if was_max {
+                        max = self.values.iter().copied().fold(0.0, f64::max);
+                    }
+                }
+                rank += rng.next_exp() / width;
+            }
+            low += width;
+            block += 1;
+        }
+    }
+
+    fn min(&self, other: &Self) -> Self {
+        let (values, hashes) = self
+            .values
+            .iter()
+            .zip(&self.hashes)
+            .zip(other.values.iter().zip(&other.hashes))
+            .map(|((&va, &ha), (&vb, &hb))| if vb < va { (vb, hb) } else { (va, ha) })
+            .unzip();
+        Signature { values, hashes }
+    }
+}
+
+// how k-mer counts are folded into a signature, the one difference between the
+// weighted sketches
+pub trait Weighting: Clone + Send + Sync {
+    fn fold(signature: &mut Signature, hash: u64, count: u32);
+}
+
+#[derive(Clone)]
+pub struct Prob;
+
+impl Weighting for Prob {
+    fn fold(signature: &mut Signature, hash: u64, count: u32) {
+        signature.add(hash, count as f64);
+    }
+}
+
+#[derive(Clone)]
+pub struct Dart;
+
+impl Weighting for Dart {
+    fn fold(signature: &mut Signature, hash: u64, count: u32) {
+        signature.add_darts(hash, count as f64);
+    }
+}
+
+pub type ProbMinHash = WeightedMinHash<Prob>;
+pub type DartMinHash = WeightedMinHash<Dart>;
+
+#[derive(Clone)]
+pub struct WeightedMinHash<W> {
+    pub sketch_size: usize,
+    // signature of loaded or merged sketches, k-mers added later are folded on top
+    base: Signature,
+    // count of every distinct k-mer hash added, until the signature is folded
+    counts: HashMap<u64, u32>,
+    folded: OnceLock<Signature>,
+    weighting: std::marker::PhantomData<W>,
+}
+
+impl<W: Weighting> WeightedMinHash<W> {
+    pub fn from_signature(signature: Signature) -> Self {
+        WeightedMinHash {
+            sketch_size: signature.values.len(),
+            base: signature,
+            counts: HashMap::new(),
+            folded: OnceLock::new(),
+            weighting: std::marker::PhantomData,
+        }
+    }
+
+    pub fn signature(&self) -> &Signature {
+        self.folded.get_or_init(|| {
+            let mut signature = self.base.clone();
+            for (&hash, &count) in &self.counts {
+                W::fold(&mut signature, hash, count);
+            }
+            signature
+        })
+    }
+}
+
+impl<W: Weighting> KmerSketch for WeightedMinHash<W> {
+    fn new(precision: Option<u32>) -> Self {
+        let sketch_size = precision.map_or(DEFAULT_SKETCH_SIZE, |s| s as usize);
+        assert!(sketch_size > 1, "weighted sketch size must be at least 2");
+        WeightedMinHash::from_signature(Signature::empty(sketch_size))
+    }
+
+    fn add_kmer(&mut self, masked: u64, seed: u64) {
+        self.add_hashed(masked, xxh3_64_with_seed(&masked.to_le_bytes(), seed), seed);
+    }
+
+    fn add_hashed(&mut self, _masked: u64, hash: u64, _seed: u64) {
+        *self.counts.entry(hash).or_insert(0) += 1;
+        self.folded.take();
+    }
+
+    fn save<W2: std::io::Write>(&self, writer: &mut W2) -> Result<(), Box<dyn Error>> {
+        Ok(format::write_weighted(writer, self.signature())?)
+    }
+
+    fn load<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        Ok(WeightedMinHash::from_signature(format::read_weighted(reader)?))
+    }
+
+    // total k-mer count, abundances included: each register is exponential with
+    // rate total / m
+    fn cardinality(&self) -> f64 {
+        let signature = self.signature();
+        if signature.is_empty() {
+            return 0.0;
+        }
+        let m = signature.values.len() as f64;
+        m * (m - 1.0) / signature.values.iter().sum::<f64>()
+    }
+
+    fn merged(&self, other: &Self) -> Self {
+        assert_eq!(self.sketch_size, other.sketch_size, "weighted sketches with different sizes");
+        WeightedMinHash::from_signature(self.signature().min(other.signature()))
+    }
+
+    fn jaccard(&self, other: &Self) -> f64 {
+        let (a, b) = (self.signature(), other.signature());
+        if a.is_empty() || b.is_empty() {
+            return 0.0;
+        }
+        let same = a.hashes.iter().zip(&b.hashes).filter(|(x, y)| x == y).count();
+        same as f64 / a.hashes.len() as f64
+    }
+
+    fn registers(&self) -> Vec<u16> {
+        let signature = self.signature();
+        signature
+            .values
+            .iter()
+            .zip(&signature.hashes)
+            .map(|(v, h)| if v.is_finite() { ((h >> 48) as u16).max(1) } else { 0 })
+            .collect()
+    }
+}